@@ -2,25 +2,49 @@ use crate::{native::NativeCommand, AppError};
 use axum::Router;
 use bodhicore::{
   cli::{Cli, Command, ServeCommand},
-  service::{AppService, EnvService, EnvServiceFn, HfHubService, LocalDataService},
-  CreateCommand, DefaultStdoutWriter, EnvCommand, ListCommand, ManageAliasCommand, PullCommand,
-  RunCommand,
+  server::with_ui_dir,
+  service::{
+    AppService, EnvService, EnvServiceFn, HfHubService, LocalDataService, SettingsWatcher,
+    LOG_FORMAT_JSON,
+  },
+  BundleCommand, CreateCommand, DefaultStdoutWriter, EnvCommand, LintCommand, ListCommand,
+  ManageAliasCommand, PullCommand, RunCommand, ScanCommand,
 };
 use clap::Parser;
 use include_dir::{include_dir, Dir};
-use std::{env, path::Path, sync::Arc};
+use std::{
+  env,
+  path::{Path, PathBuf},
+  sync::Arc,
+  time::Duration,
+};
 use tower_serve_static::ServeDir;
 use tracing_appender::non_blocking::WorkerGuard;
-use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use tracing_subscriber::{
+  fmt, layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Registry,
+};
 
 static ASSETS: Dir<'static> = include_dir!("$CARGO_MANIFEST_DIR/../out");
+static SETTINGS_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+pub type LogFilterHandle = reload::Handle<EnvFilter, Registry>;
 
-pub fn main_internal(env_service: Arc<EnvService>) -> super::Result<()> {
+// the settings are only watched for the server and the native app, the other commands exit before
+// a change would matter
+pub fn main_internal(
+  env_service: Arc<EnvService>,
+  log_filter_handle: Option<LogFilterHandle>,
+) -> super::Result<()> {
   let bodhi_home = env_service.bodhi_home();
   let hf_cache = env_service.hf_cache();
+  let ui_dir = env_service.ui_dir();
   let data_service = LocalDataService::new(bodhi_home);
   let hub_service = HfHubService::new_from_hf_cache(hf_cache, true);
-  let service = Arc::new(AppService::new(env_service, hub_service, data_service));
+  let service = Arc::new(AppService::new(
+    env_service.clone(),
+    hub_service,
+    data_service,
+  ));
 
   let args = env::args().collect::<Vec<_>>();
   if args.len() == 1
@@ -30,7 +54,8 @@ pub fn main_internal(env_service: Arc<EnvService>) -> super::Result<()> {
       .contains(".app/Contents/MacOS/")
   {
     // the app was launched using Bodhi.app, launch the native app with system tray
-    NativeCommand::new(service, true).execute(Some(static_router()))?;
+    watch_settings(env_service, log_filter_handle);
+    NativeCommand::new(service, true).execute(Some(static_router(ui_dir)))?;
     return Ok(());
   }
 
@@ -42,7 +67,8 @@ pub fn main_internal(env_service: Arc<EnvService>) -> super::Result<()> {
       EnvCommand::new(service).execute()?;
     }
     Command::App { ui } => {
-      NativeCommand::new(service, ui).execute(Some(static_router()))?;
+      watch_settings(env_service, log_filter_handle);
+      NativeCommand::new(service, ui).execute(Some(static_router(ui_dir)))?;
     }
     list @ Command::List { .. } => {
       let list_command = ListCommand::try_from(list)?;
@@ -50,6 +76,7 @@ pub fn main_internal(env_service: Arc<EnvService>) -> super::Result<()> {
     }
     serve @ Command::Serve { .. } => {
       let serve_command = ServeCommand::try_from(serve)?;
+      watch_settings(env_service, log_filter_handle);
       serve_command.execute(service)?;
     }
     pull @ Command::Pull { .. } => {
@@ -80,23 +107,93 @@ pub fn main_internal(env_service: Arc<EnvService>) -> super::Result<()> {
       let rm = ManageAliasCommand::try_from(rm)?;
       rm.execute(service, &mut DefaultStdoutWriter::default())?;
     }
+    Command::Lint {} => {
+      LintCommand::new(service).execute(&mut DefaultStdoutWriter::default())?;
+    }
+    scan @ Command::Scan { .. } => {
+      let scan = ScanCommand::try_from(scan)?;
+      scan.execute(service, &mut DefaultStdoutWriter::default())?;
+    }
+    bundle @ (Command::Export { .. } | Command::Import { .. }) => {
+      let bundle = BundleCommand::try_from(bundle)?;
+      bundle.execute(service, &mut DefaultStdoutWriter::default())?;
+    }
   }
   Ok(())
 }
 
-pub fn setup_logs(logs_dir: &Path) -> super::Result<WorkerGuard> {
+// RUST_LOG takes precedence over the log level, for the per module filters
+fn log_filter(log_level: &str) -> EnvFilter {
+  let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(log_level));
+  filter.add_directive("hf_hub=error".parse().unwrap())
+}
+
+pub fn setup_logs(
+  logs_dir: &Path,
+  log_format: &str,
+  log_level: &str,
+) -> super::Result<(WorkerGuard, LogFilterHandle)> {
   let file_appender = tracing_appender::rolling::daily(logs_dir, "bodhi.log");
   let (non_blocking, guard) = tracing_appender::non_blocking(file_appender);
-  let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
-  let filter = filter.add_directive("hf_hub=error".parse().unwrap());
-  tracing_subscriber::registry()
-    .with(filter)
-    .with(fmt::layer().with_writer(non_blocking))
-    .init();
-  Ok(guard)
+  let (filter, handle) = reload::Layer::new(log_filter(log_level));
+  let registry = tracing_subscriber::registry().with(filter);
+  if log_format == LOG_FORMAT_JSON {
+    // one json object per line, with the fields of the request span, like the request id
+    registry
+      .with(fmt::layer().json().with_writer(non_blocking))
+      .init();
+  } else {
+    registry.with(fmt::layer().with_writer(non_blocking)).init();
+  }
+  Ok((guard, handle))
+}
+
+// the watcher runs on a thread of its own, as the commands build their runtime when they need one
+fn watch_settings(env_service: Arc<EnvService>, log_filter_handle: Option<LogFilterHandle>) {
+  let watcher = SettingsWatcher::new(env_service, SETTINGS_POLL_INTERVAL);
+  let mut settings = watcher.subscribe();
+  let spawned = std::thread::Builder::new()
+    .name("settings-watcher".to_string())
+    .spawn(move || {
+      let runtime = match tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+      {
+        Ok(runtime) => runtime,
+        Err(err) => {
+          tracing::warn!(
+            ?err,
+            "settings watcher could not start, settings will not be reloaded"
+          );
+          return;
+        }
+      };
+      runtime.block_on(async move {
+        tokio::spawn(watcher.watch());
+        while settings.changed().await.is_ok() {
+          let log_level = settings.borrow_and_update().log_level.clone();
+          // RUST_LOG takes precedence over the log level, so there is nothing to reload
+          if env::var("RUST_LOG").is_ok() {
+            tracing::debug!("RUST_LOG is set, the log level is not reloaded");
+            continue;
+          }
+          if let Some(handle) = &log_filter_handle {
+            if let Err(err) = handle.reload(log_filter(&log_level)) {
+              tracing::warn!(?err, "failed to reload the log level");
+            }
+          }
+        }
+      });
+    });
+  if let Err(err) = spawned {
+    tracing::warn!(
+      ?err,
+      "settings watcher could not start, settings will not be reloaded"
+    );
+  }
 }
 
-fn static_router() -> Router {
+fn static_router(ui_dir: Option<PathBuf>) -> Router {
   let static_service = ServeDir::new(&ASSETS).append_index_html_on_directories(true);
-  Router::new().fallback_service(static_service)
+  with_ui_dir(ui_dir, Router::new().fallback_service(static_service))
 }
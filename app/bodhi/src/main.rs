@@ -4,8 +4,7 @@
 use std::sync::Arc;
 
 use bodhi::{main_internal, setup_logs, AppError};
-use bodhicore::service::{env_wrapper::EnvWrapper, EnvService};
-use tracing_appender::non_blocking::WorkerGuard;
+use bodhicore::service::{env_wrapper::EnvWrapper, EnvService, EnvServiceFn};
 
 pub fn main() {
   let mut env_service = EnvService::new(EnvWrapper::default());
@@ -17,6 +16,17 @@ pub fn main() {
     }
   };
   env_service.load_dotenv();
+  env_service.load_settings();
+  let issues = env_service.validate();
+  if !issues.is_empty() {
+    let issues = issues
+      .iter()
+      .map(|issue| format!("  {issue}"))
+      .collect::<Vec<_>>()
+      .join("\n");
+    eprintln!("fatal error: invalid settings\n{issues}\nexiting...");
+    std::process::exit(1);
+  }
   match env_service.setup_hf_cache() {
     Ok(hf_cache) => hf_cache,
     Err(err) => {
@@ -24,14 +34,23 @@ pub fn main() {
       std::process::exit(1);
     }
   };
-  let _guard = match env_service.setup_logs_dir() {
-    Ok(logs_dir) => setup_logs(&logs_dir),
-    Err(err) => Err::<WorkerGuard, AppError>(err.into()),
+  let logs = match env_service.setup_logs_dir() {
+    Ok(logs_dir) => setup_logs(
+      &logs_dir,
+      &env_service.log_format(),
+      &env_service.log_level(),
+    ),
+    Err(err) => Err::<_, AppError>(err.into()),
   };
-  if _guard.is_err() {
-    eprintln!("failed to configure logging, will be skipped");
+  let (_guard, log_filter_handle) = match logs {
+    Ok((guard, handle)) => (Some(guard), Some(handle)),
+    Err(_) => {
+      eprintln!("failed to configure logging, will be skipped");
+      (None, None)
+    }
   };
-  let result = main_internal(Arc::new(env_service));
+  let env_service = Arc::new(env_service);
+  let result = main_internal(env_service, log_filter_handle);
   if let Err(err) = result {
     tracing::warn!(?err, "application exited with error");
     eprintln!("fatal error: {}\nexiting...", err);
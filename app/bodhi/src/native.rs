@@ -27,7 +27,8 @@ impl NativeCommand {
   async fn aexecute(&self, static_router: Option<Router>) -> crate::error::Result<()> {
     let host = self.service.env_service().host();
     let port = self.service.env_service().port();
-    let addr = format!("http://{host}:{port}/");
+    let scheme = self.service.env_service().scheme();
+    let addr = format!("{scheme}://{host}:{port}/");
     let addr_clone = addr.clone();
     let cmd = ServeCommand::ByParams { host, port };
     let server_handle = cmd.aexecute(self.service.clone(), static_router).await?;
@@ -1,4 +1,6 @@
-use crate::{error::Common, service::AppServiceFn, CliError, Command, StdoutWriter};
+use crate::{
+  error::Common, objs::OAIRequestParams, service::AppServiceFn, CliError, Command, StdoutWriter,
+};
 use std::{env, sync::Arc};
 
 pub enum ManageAliasCommand {
@@ -82,7 +84,9 @@ impl ManageAliasCommand {
     service: Arc<dyn AppServiceFn>,
     stdout: &mut dyn StdoutWriter,
   ) -> crate::error::Result<()> {
-    service.data_service().copy_alias(alias, new_alias)?;
+    service
+      .data_service()
+      .copy_alias(alias, new_alias, &OAIRequestParams::default())?;
     stdout
       .write(&format!(
         "created new alias '{new_alias}' from '{alias}'.\n"
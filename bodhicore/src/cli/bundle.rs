@@ -0,0 +1,427 @@
+use super::{CliError, Command, PullCommand};
+use crate::{
+  error::{BodhiError, Common, Result},
+  objs::{Alias, REFS_MAIN, TOKENIZER_CONFIG_JSON},
+  service::AppServiceFn,
+  Repo, StdoutWriter,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+  collections::HashSet,
+  fs,
+  path::{Path, PathBuf},
+  sync::Arc,
+};
+
+pub static BUNDLE_FORMAT: &str = "bodhi-bundle";
+pub static BUNDLE_VERSION: u32 = 1;
+
+// a yaml file with the manifest of the models referred to, and the alias configs, the model
+// files are not included and are pulled from huggingface on import
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bundle {
+  pub manifest: BundleManifest,
+  pub aliases: Vec<Alias>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleManifest {
+  pub format: String,
+  pub version: u32,
+  pub created_at: DateTime<Utc>,
+  pub models: Vec<ModelRef>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelRef {
+  pub alias: String,
+  pub repo: Repo,
+  pub filename: String,
+  pub snapshot: String,
+}
+
+impl From<&Alias> for ModelRef {
+  fn from(alias: &Alias) -> Self {
+    ModelRef {
+      alias: alias.alias.clone(),
+      repo: alias.repo.clone(),
+      filename: alias.filename.clone(),
+      snapshot: alias.snapshot.clone(),
+    }
+  }
+}
+
+impl Bundle {
+  pub fn new(aliases: Vec<Alias>, created_at: DateTime<Utc>) -> Self {
+    Bundle {
+      manifest: BundleManifest {
+        format: BUNDLE_FORMAT.to_string(),
+        version: BUNDLE_VERSION,
+        created_at,
+        models: aliases.iter().map(ModelRef::from).collect(),
+      },
+      aliases,
+    }
+  }
+
+  // the manifest should refer to the models of the alias configs in the bundle, one each
+  pub fn validate(&self) -> Result<()> {
+    let invalid = |message: String| Err(BodhiError::InvalidBundle(message));
+    if self.manifest.format != BUNDLE_FORMAT {
+      return invalid(format!(
+        "format '{}' is not '{BUNDLE_FORMAT}'",
+        self.manifest.format
+      ));
+    }
+    if self.manifest.version != BUNDLE_VERSION {
+      return invalid(format!(
+        "version {} is not supported, supported version is {BUNDLE_VERSION}",
+        self.manifest.version
+      ));
+    }
+    let mut names = HashSet::new();
+    for alias in &self.aliases {
+      if !names.insert(alias.alias.as_str()) {
+        return invalid(format!("alias '{}' is repeated", alias.alias));
+      }
+      if alias.model_file.is_some() {
+        return invalid(format!(
+          "alias '{}' uses a local model file, which cannot be shared",
+          alias.alias
+        ));
+      }
+      if !self.manifest.models.contains(&ModelRef::from(alias)) {
+        return invalid(format!(
+          "alias '{}' does not match a model in the manifest",
+          alias.alias
+        ));
+      }
+    }
+    if self.manifest.models.len() != self.aliases.len() {
+      return invalid(format!(
+        "manifest has {} models for {} aliases",
+        self.manifest.models.len(),
+        self.aliases.len()
+      ));
+    }
+    Ok(())
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BundleCommand {
+  Export {
+    file: PathBuf,
+    aliases: Vec<String>,
+  },
+  Import {
+    file: PathBuf,
+    pull: bool,
+    force: bool,
+  },
+}
+
+impl TryFrom<Command> for BundleCommand {
+  type Error = CliError;
+
+  fn try_from(value: Command) -> std::result::Result<Self, Self::Error> {
+    match value {
+      Command::Export { file, aliases } => Ok(BundleCommand::Export {
+        file: PathBuf::from(file),
+        aliases,
+      }),
+      Command::Import { file, pull, force } => Ok(BundleCommand::Import {
+        file: PathBuf::from(file),
+        pull,
+        force,
+      }),
+      cmd => Err(CliError::ConvertCommand(
+        cmd.to_string(),
+        "export".to_string(),
+      )),
+    }
+  }
+}
+
+impl BundleCommand {
+  pub fn execute(
+    &self,
+    service: Arc<dyn AppServiceFn>,
+    stdout: &mut dyn StdoutWriter,
+  ) -> Result<()> {
+    match self {
+      BundleCommand::Export { file, aliases } => self.export(file, aliases, service, stdout),
+      BundleCommand::Import { file, pull, force } => {
+        self.import(file, *pull, *force, service, stdout)
+      }
+    }
+  }
+
+  // exports all the aliases if none are given, skipping the aliases with a local model file
+  fn export(
+    &self,
+    file: &Path,
+    aliases: &[String],
+    service: Arc<dyn AppServiceFn>,
+    stdout: &mut dyn StdoutWriter,
+  ) -> Result<()> {
+    let aliases = if aliases.is_empty() {
+      service.data_service().list_aliases()?
+    } else {
+      aliases
+        .iter()
+        .map(|alias| {
+          service
+            .data_service()
+            .find_alias(alias)
+            .ok_or_else(|| BodhiError::LocalAliasNotFound(alias.clone()))
+        })
+        .collect::<Result<Vec<_>>>()?
+    };
+    let (local, aliases): (Vec<_>, Vec<_>) = aliases
+      .into_iter()
+      .partition(|alias| alias.model_file.is_some());
+    let mut out = String::new();
+    for alias in &local {
+      out.push_str(&format!(
+        "skipped: '{}', uses a local model file\n",
+        alias.alias
+      ));
+    }
+    let bundle = Bundle::new(aliases, Utc::now());
+    let content = serde_yaml::to_string(&bundle).map_err(|source| Common::SerdeYamlSerialize {
+      source,
+      filename: file.display().to_string(),
+    })?;
+    fs::write(file, content).map_err(|source| Common::IoFile {
+      source,
+      path: file.display().to_string(),
+    })?;
+    out.push_str(&format!(
+      "exported {} aliases to '{}'\n",
+      bundle.aliases.len(),
+      file.display()
+    ));
+    stdout.write(&out).map_err(Common::from)?;
+    Ok(())
+  }
+
+  // an existing alias is skipped unless forced, with pull the model and the tokenizer files
+  // are downloaded and the alias refers to the downloaded snapshot
+  fn import(
+    &self,
+    file: &Path,
+    pull: bool,
+    force: bool,
+    service: Arc<dyn AppServiceFn>,
+    stdout: &mut dyn StdoutWriter,
+  ) -> Result<()> {
+    let content = fs::read_to_string(file).map_err(|source| Common::IoFile {
+      source,
+      path: file.display().to_string(),
+    })?;
+    let bundle: Bundle =
+      serde_yaml::from_str(&content).map_err(|err| BodhiError::InvalidBundle(err.to_string()))?;
+    bundle.validate()?;
+    let mut out = String::new();
+    let mut imported = 0;
+    for mut alias in bundle.aliases {
+      if !force && service.data_service().find_alias(&alias.alias).is_some() {
+        out.push_str(&format!("skipped: '{}', already exists\n", alias.alias));
+        continue;
+      }
+      if pull {
+        let model_file = PullCommand::download_file_if_missing(
+          service.clone(),
+          &alias.repo,
+          &alias.filename,
+          REFS_MAIN,
+          force,
+        )?;
+        _ = PullCommand::download_file_if_missing(
+          service.clone(),
+          &Repo::try_from(alias.chat_template.clone())?,
+          TOKENIZER_CONFIG_JSON,
+          REFS_MAIN,
+          force,
+        )?;
+        alias.snapshot = model_file.snapshot;
+      }
+      service.data_service().save_alias(&alias)?;
+      out.push_str(&format!("imported: '{}'\n", alias.alias));
+      imported += 1;
+    }
+    out.push_str(&format!(
+      "imported {imported} aliases from '{}'\n",
+      file.display()
+    ));
+    stdout.write(&out).map_err(Common::from)?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{Bundle, BundleCommand};
+  use crate::{
+    objs::{Alias, HubFile, Repo, REFS_MAIN, TOKENIZER_CONFIG_JSON},
+    service::{MockDataService, MockEnvServiceFn, MockHubService},
+    test_utils::AppServiceStubMock,
+    MockStdoutWriter,
+  };
+  use chrono::{TimeZone, Utc};
+  use mockall::predicate::{always, eq};
+  use rstest::rstest;
+  use std::{
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+  };
+
+  fn stdout() -> MockStdoutWriter {
+    let mut stdout = MockStdoutWriter::default();
+    stdout
+      .expect_write()
+      .with(always())
+      .returning(|s| Ok(s.len()));
+    stdout
+  }
+
+  #[rstest]
+  fn test_bundle_export_import_round_trips_aliases() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let file = temp_dir.path().join("setup.bodhi");
+    let local = Alias {
+      alias: "local".to_string(),
+      model_file: Some(PathBuf::from("/models/local.gguf")),
+      ..Alias::testalias()
+    };
+    let mut mock_data_service = MockDataService::default();
+    mock_data_service
+      .expect_list_aliases()
+      .return_once(move || Ok(vec![Alias::testalias(), Alias::llama3(), local]));
+    let service = AppServiceStubMock::new(
+      MockEnvServiceFn::new(),
+      MockHubService::new(),
+      mock_data_service,
+    );
+    BundleCommand::Export {
+      file: file.clone(),
+      aliases: vec![],
+    }
+    .execute(Arc::new(service), &mut stdout())?;
+
+    let mut mock_data_service = MockDataService::default();
+    mock_data_service
+      .expect_find_alias()
+      .with(eq("testalias:instruct"))
+      .return_once(|_| Some(Alias::testalias()));
+    mock_data_service
+      .expect_find_alias()
+      .with(eq("llama3:instruct"))
+      .return_once(|_| None);
+    let saved = Arc::new(Mutex::new(Vec::new()));
+    let saved_clone = saved.clone();
+    mock_data_service
+      .expect_save_alias()
+      .times(1)
+      .returning(move |alias| {
+        saved_clone.lock().unwrap().push(alias.clone());
+        Ok(PathBuf::from("ignored"))
+      });
+    let service = AppServiceStubMock::new(
+      MockEnvServiceFn::new(),
+      MockHubService::new(),
+      mock_data_service,
+    );
+    BundleCommand::Import {
+      file,
+      pull: false,
+      force: false,
+    }
+    .execute(Arc::new(service), &mut stdout())?;
+    assert_eq!(vec![Alias::llama3()], *saved.lock().unwrap());
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_bundle_import_with_pull_downloads_model_files() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let file = temp_dir.path().join("setup.bodhi");
+    let bundle = Bundle::new(vec![Alias::testalias()], Utc::now());
+    fs::write(&file, serde_yaml::to_string(&bundle)?)?;
+    let testalias = Alias::testalias();
+    let mut mock_hub_service = MockHubService::new();
+    mock_hub_service
+      .expect_find_local_file()
+      .with(
+        eq(testalias.repo.clone()),
+        eq(testalias.filename.clone()),
+        eq(REFS_MAIN),
+      )
+      .return_once(|_, _, _| Ok(None));
+    mock_hub_service
+      .expect_download()
+      .with(eq(testalias.repo), eq(testalias.filename), eq(false))
+      .return_once(|_, _, _| {
+        Ok(HubFile {
+          snapshot: "9ff8b00464fc439a64bb374769dec3dd627be1c2".to_string(),
+          ..HubFile::testalias()
+        })
+      });
+    mock_hub_service
+      .expect_find_local_file()
+      .with(eq(Repo::llama3()), eq(TOKENIZER_CONFIG_JSON), eq(REFS_MAIN))
+      .return_once(|_, _, _| Ok(Some(HubFile::llama3_tokenizer())));
+    let mut mock_data_service = MockDataService::default();
+    mock_data_service.expect_find_alias().return_once(|_| None);
+    mock_data_service
+      .expect_save_alias()
+      .with(eq(Alias {
+        snapshot: "9ff8b00464fc439a64bb374769dec3dd627be1c2".to_string(),
+        ..Alias::testalias()
+      }))
+      .return_once(|_| Ok(PathBuf::from("ignored")));
+    let service =
+      AppServiceStubMock::new(MockEnvServiceFn::new(), mock_hub_service, mock_data_service);
+    BundleCommand::Import {
+      file,
+      pull: true,
+      force: false,
+    }
+    .execute(Arc::new(service), &mut stdout())?;
+    Ok(())
+  }
+
+  #[rstest]
+  #[case::format(
+    |bundle: &mut Bundle| bundle.manifest.format = "zip".to_string(),
+    "invalid bundle file: format 'zip' is not 'bodhi-bundle'"
+  )]
+  #[case::version(
+    |bundle: &mut Bundle| bundle.manifest.version = 2,
+    "invalid bundle file: version 2 is not supported, supported version is 1"
+  )]
+  #[case::model_mismatch(
+    |bundle: &mut Bundle| bundle.manifest.models[0].filename = "other.gguf".to_string(),
+    "invalid bundle file: alias 'testalias:instruct' does not match a model in the manifest"
+  )]
+  #[case::extra_model(
+    |bundle: &mut Bundle| bundle.aliases.pop().map(|_| ()).unwrap(),
+    "invalid bundle file: manifest has 2 models for 1 aliases"
+  )]
+  fn test_bundle_validate_rejects_invalid_manifest(
+    #[case] update: fn(&mut Bundle),
+    #[case] expected: &str,
+  ) -> anyhow::Result<()> {
+    let mut bundle = Bundle::new(
+      vec![Alias::testalias(), Alias::llama3()],
+      Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+    );
+    assert!(bundle.validate().is_ok());
+    update(&mut bundle);
+    assert_eq!(expected, bundle.validate().unwrap_err().to_string());
+    Ok(())
+  }
+}
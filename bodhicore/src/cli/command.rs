@@ -66,18 +66,23 @@ pub enum Command {
 
   /// Create a new model alias
   #[clap(group = ArgGroup::new("template").required(true))]
+  #[clap(group = ArgGroup::new("model").required(true))]
   Create {
-    /// Unique name of the model alias. E.g. llama3:8b-instruct, model alias should not be present, 
+    /// Unique name of the model alias. E.g. llama3:8b-instruct, model alias should not be present,
     /// run `bodhi list` to list the existing model aliases
     alias: String,
 
     /// The hugging face repo to pull the model from, e.g. `TheBloke/TinyLlama-1.1B-Chat-v1.0-GGUF`
-    #[clap(long, short = 'r', value_parser = repo_parser)]
-    repo: String,
+    #[clap(long, short = 'r', requires = "filename", group = "model", value_parser = repo_parser)]
+    repo: Option<String>,
 
     /// The gguf model file to pull from the repo, e.g. `tinyllama-1.1b-chat-v1.0.Q4_0.gguf`,
-    #[clap(long, short = 'f', value_parser = gguf_filename_parser)]
-    filename: String,
+    #[clap(long, short = 'f', requires = "repo", value_parser = gguf_filename_parser)]
+    filename: Option<String>,
+
+    /// The gguf model file on the local filesystem to use instead of pulling from huggingface, e.g. `/models/mymodel.Q4_0.gguf`
+    #[clap(long, group = "model", value_parser = gguf_filename_parser)]
+    model_file: Option<String>,
 
     /// In-built chat template mapping to use to convert chat messages to LLM prompt
     #[clap(long, group = "template")]
@@ -95,6 +100,10 @@ pub enum Command {
     #[clap(long)]
     force: bool,
 
+    /// Tag to group and filter the model aliases, e.g. `code`, can be repeated
+    #[clap(long = "tag", number_of_values = 1)]
+    tags: Vec<String>,
+
     #[clap(flatten, next_help_heading = "OpenAI Compatible Request defaults")]
     oai_request_params: OAIRequestParams,
 
@@ -128,6 +137,43 @@ pub enum Command {
     /// Model alias to delete, run `bodhi list` to list the existing model aliases
     alias: String,
   },
+  /// Check all the model aliases for missing or invalid model files and chat templates,
+  /// without loading the models
+  Lint {},
+  /// Create model aliases for the GGUF files in a directory on the local filesystem,
+  /// skipping the files already used by a model alias
+  #[clap(group = ArgGroup::new("template").required(true))]
+  Scan {
+    /// Directory to scan for GGUF files, including its sub-directories
+    dir: String,
+
+    /// In-built chat template mapping to use for the created model aliases
+    #[clap(long, group = "template")]
+    chat_template: Option<ChatTemplateId>,
+
+    /// Repo containing tokenizer_config.json file to use for the created model aliases. e.g. `TinyLlama/TinyLlama-1.1B-Chat-v1.0`
+    #[clap(long, group = "template", value_parser = repo_parser)]
+    tokenizer_config: Option<String>,
+  },
+  /// Export the model aliases to a bundle file to share the setup with another instance,
+  /// the bundle refers to the model files without including them
+  Export {
+    /// Bundle file to write, e.g. `setup.bodhi`
+    file: String,
+    /// Model aliases to export, exports all the model aliases if none given
+    aliases: Vec<String>,
+  },
+  /// Import the model aliases from a bundle file created using `bodhi export`
+  Import {
+    /// Bundle file to import, e.g. `setup.bodhi`
+    file: String,
+    /// Pull the model files referred to by the bundle from huggingface
+    #[clap(long)]
+    pull: bool,
+    /// Overwrite the existing model aliases, and with --pull, download the files again
+    #[clap(long)]
+    force: bool,
+  },
 }
 
 fn repo_parser(repo: &str) -> Result<String, String> {
@@ -269,6 +315,13 @@ For more information, try '--help'.
     Ok(())
   }
 
+  #[test]
+  fn test_cli_lint() -> anyhow::Result<()> {
+    let cli = Cli::try_parse_from(vec!["bodhi", "lint"])?;
+    assert_eq!(Command::Lint {}, cli.command);
+    Ok(())
+  }
+
   #[rstest]
   #[case(vec!["bodhi", "pull", "llama3:instruct"], Some(String::from("llama3:instruct")), None, None, false)]
   #[case(vec!["bodhi",
@@ -397,7 +450,8 @@ For more information, try '--help'.
       stop: vec!["\n".to_string(), "\n\n".to_string()],
       temperature: Some(0.8),
       top_p: Some(0.9),
-      user: Some("testuser".to_string())
+      user: Some("testuser".to_string()),
+      system_prompt: None,
     },
     GptContextParams {
       n_seed: None,
@@ -422,12 +476,14 @@ For more information, try '--help'.
     let actual = Cli::try_parse_from(args)?.command;
     let expected = Command::Create {
       alias,
-      repo,
-      filename,
+      repo: Some(repo),
+      filename: Some(filename),
+      model_file: None,
       chat_template: Some(chat_template),
       tokenizer_config: None,
       family: Some(family),
       force: false,
+      tags: vec![],
       oai_request_params,
       context_params,
     };
@@ -435,6 +491,39 @@ For more information, try '--help'.
     Ok(())
   }
 
+  #[rstest]
+  fn test_cli_create_with_model_file() -> anyhow::Result<()> {
+    let args = vec![
+      "bodhi",
+      "create",
+      "mymodel:instruct",
+      "--model-file",
+      "/models/mymodel.Q4_0.gguf",
+      "--chat-template",
+      "llama3",
+      "--tag",
+      "code",
+      "--tag",
+      "chat",
+    ];
+    let actual = Cli::try_parse_from(args)?.command;
+    let expected = Command::Create {
+      alias: "mymodel:instruct".to_string(),
+      repo: None,
+      filename: None,
+      model_file: Some("/models/mymodel.Q4_0.gguf".to_string()),
+      chat_template: Some(ChatTemplateId::Llama3),
+      tokenizer_config: None,
+      family: None,
+      force: false,
+      tags: vec!["code".to_string(), "chat".to_string()],
+      oai_request_params: OAIRequestParams::default(),
+      context_params: GptContextParams::default(),
+    };
+    assert_eq!(expected, actual);
+    Ok(())
+  }
+
   #[rstest]
   #[case(vec![
     "bodhi", "create",
@@ -445,7 +534,7 @@ For more information, try '--help'.
     "--tokenizer-config", "MyFactory/testalias-gguf",
   ], r#"error: the argument '--chat-template <CHAT_TEMPLATE>' cannot be used with '--tokenizer-config <TOKENIZER_CONFIG>'
 
-Usage: bodhi create --repo <REPO> --filename <FILENAME> <--chat-template <CHAT_TEMPLATE>|--tokenizer-config <TOKENIZER_CONFIG>> <ALIAS>
+Usage: bodhi create --filename <FILENAME> <--chat-template <CHAT_TEMPLATE>|--tokenizer-config <TOKENIZER_CONFIG>> <--repo <REPO>|--model-file <MODEL_FILE>> <ALIAS>
 
 For more information, try '--help'.
 "#)]
@@ -480,6 +569,19 @@ For more information, try '--help'.
     "--tokenizer-config", "MyFactory/testalias-gguf",
   ], r#"error: invalid value 'MyFactory$testalias-gguf' for '--repo <REPO>': does not match huggingface repo format - `owner/repo`
 
+For more information, try '--help'.
+"#)]
+  #[case(vec![
+    "bodhi", "create",
+    "testalias:instruct",
+    "--repo", "MyFactory/testalias-gguf",
+    "--filename", "testalias.Q8_0.gguf",
+    "--model-file", "/models/testalias.Q8_0.gguf",
+    "--chat-template", "llama3",
+  ], r#"error: the argument '--repo <REPO>' cannot be used with '--model-file <MODEL_FILE>'
+
+Usage: bodhi create --filename <FILENAME> <--chat-template <CHAT_TEMPLATE>|--tokenizer-config <TOKENIZER_CONFIG>> <--repo <REPO>|--model-file <MODEL_FILE>> <ALIAS>
+
 For more information, try '--help'.
 "#)]
   fn test_cli_create_invalid(
@@ -501,10 +603,12 @@ For more information, try '--help'.
       alias: Default::default(),
       repo: Default::default(),
       filename: Default::default(),
+      model_file: None,
       chat_template: None,
       tokenizer_config: None,
       family: None,
       force: false,
+      tags: vec![],
       oai_request_params: OAIRequestParams::default(),
       context_params: GptContextParams::default(),
     }, "create")]
@@ -513,4 +617,45 @@ For more information, try '--help'.
     assert_eq!(expected, cmd.to_string());
     Ok(())
   }
+
+  #[rstest]
+  #[case(vec!["bodhi", "scan", "/models", "--chat-template", "llama3"], Some(ChatTemplateId::Llama3), None)]
+  #[case(vec!["bodhi", "scan", "/models", "--tokenizer-config", "TinyLlama/TinyLlama-1.1B-Chat-v1.0"], None, Some("TinyLlama/TinyLlama-1.1B-Chat-v1.0".to_string()))]
+  fn test_cli_scan(
+    #[case] args: Vec<&str>,
+    #[case] chat_template: Option<ChatTemplateId>,
+    #[case] tokenizer_config: Option<String>,
+  ) -> anyhow::Result<()> {
+    let actual = Cli::try_parse_from(args)?.command;
+    let expected = Command::Scan {
+      dir: "/models".to_string(),
+      chat_template,
+      tokenizer_config,
+    };
+    assert_eq!(expected, actual);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(vec!["bodhi", "export", "setup.bodhi"], Command::Export {
+    file: "setup.bodhi".to_string(),
+    aliases: vec![],
+  })]
+  #[case(vec!["bodhi", "export", "setup.bodhi", "llama3:instruct", "phi3:mini"], Command::Export {
+    file: "setup.bodhi".to_string(),
+    aliases: vec!["llama3:instruct".to_string(), "phi3:mini".to_string()],
+  })]
+  #[case(vec!["bodhi", "import", "setup.bodhi", "--pull"], Command::Import {
+    file: "setup.bodhi".to_string(),
+    pull: true,
+    force: false,
+  })]
+  fn test_cli_export_import(
+    #[case] args: Vec<&str>,
+    #[case] expected: Command,
+  ) -> anyhow::Result<()> {
+    let actual = Cli::try_parse_from(args)?.command;
+    assert_eq!(expected, actual);
+    Ok(())
+  }
 }
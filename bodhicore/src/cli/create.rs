@@ -7,7 +7,7 @@ use crate::{
   },
   service::AppServiceFn,
 };
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 
 #[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(test, derive(derive_new::new, derive_builder::Builder))]
@@ -16,9 +16,12 @@ pub struct CreateCommand {
   alias: String,
   repo: Repo,
   filename: String,
+  // a local model file skips the huggingface download, the repo is left empty
+  model_file: Option<PathBuf>,
   chat_template: ChatTemplate,
   family: Option<String>,
   force: bool,
+  tags: Vec<String>,
   oai_request_params: OAIRequestParams,
   context_params: GptContextParams,
 }
@@ -32,13 +35,31 @@ impl TryFrom<Command> for CreateCommand {
         alias,
         repo,
         filename,
+        model_file,
         chat_template,
         tokenizer_config,
         family,
         force,
+        tags,
         oai_request_params,
         context_params,
       } => {
+        let (repo, filename, model_file) = match (repo, filename, model_file) {
+          (None, None, Some(model_file)) => {
+            let model_file = PathBuf::from(model_file);
+            let filename = model_file
+              .file_name()
+              .map(|filename| filename.to_string_lossy().into_owned())
+              .unwrap_or_default();
+            (Repo::default(), filename, Some(model_file))
+          }
+          (Some(repo), Some(filename), None) => (Repo::try_from(repo)?, filename, None),
+          (repo, filename, model_file) => {
+            return Err(CliError::BadRequest(format!(
+              "cannot initialize create command with invalid state. repo: '{repo:?}', filename: '{filename:?}', model_file: '{model_file:?}'"
+            )))
+          }
+        };
         let chat_template = match chat_template {
           Some(chat_template) => ChatTemplate::Id(chat_template),
           None => match tokenizer_config {
@@ -52,11 +73,13 @@ impl TryFrom<Command> for CreateCommand {
         };
         let result = CreateCommand {
           alias,
-          repo: Repo::try_from(repo)?,
+          repo,
           filename,
+          model_file,
           chat_template,
           family,
           force,
+          tags,
           oai_request_params,
           context_params,
         };
@@ -76,21 +99,33 @@ impl CreateCommand {
     if !self.force && service.data_service().find_alias(&self.alias).is_some() {
       return Err(BodhiError::AliasExists(self.alias.clone()));
     }
-    let local_model_file =
-      service
-        .hub_service()
-        .find_local_file(&self.repo, &self.filename, REFS_MAIN)?;
-    let local_model_file = match local_model_file {
-      Some(local_model_file) => {
-        println!(
-          "repo: '{}', filename: '{}' already exists in $HF_HOME",
-          &self.repo, &self.filename
-        );
-        local_model_file
+    let (snapshot, model_file) = match self.model_file {
+      Some(model_file) => {
+        let model_file = model_file
+          .canonicalize()
+          .map_err(|_| BodhiError::ModelFileMissing(model_file.display().to_string()))?;
+        println!("model file: '{}' found", model_file.display());
+        (String::new(), Some(model_file))
+      }
+      None => {
+        let local_model_file =
+          service
+            .hub_service()
+            .find_local_file(&self.repo, &self.filename, REFS_MAIN)?;
+        let local_model_file = match local_model_file {
+          Some(local_model_file) => {
+            println!(
+              "repo: '{}', filename: '{}' already exists in $HF_HOME",
+              &self.repo, &self.filename
+            );
+            local_model_file
+          }
+          None => service
+            .hub_service()
+            .download(&self.repo, &self.filename, self.force)?,
+        };
+        (local_model_file.snapshot, None)
       }
-      None => service
-        .hub_service()
-        .download(&self.repo, &self.filename, self.force)?,
     };
     let chat_template_repo = Repo::try_from(self.chat_template.clone())?;
     let tokenizer_file = service.hub_service().find_local_file(
@@ -115,17 +150,21 @@ impl CreateCommand {
         );
       }
     }
-    let alias: Alias = Alias::new(
-      self.alias,
-      self.family,
-      self.repo,
-      self.filename,
-      local_model_file.snapshot.clone(),
-      default_features(),
-      self.chat_template,
-      self.oai_request_params,
-      self.context_params,
-    );
+    let alias: Alias = Alias {
+      model_file,
+      tags: self.tags,
+      ..Alias::new(
+        self.alias,
+        self.family,
+        self.repo,
+        self.filename,
+        snapshot,
+        default_features(),
+        self.chat_template,
+        self.oai_request_params,
+        self.context_params,
+      )
+    };
     service.data_service().save_alias(&alias)?;
     println!(
       "model alias: '{}' saved to $BODHI_HOME/aliases",
@@ -156,12 +195,14 @@ mod test {
   #[case(
   Command::Create {
     alias: "testalias:instruct".to_string(),
-    repo: "MyFactory/testalias-gguf".to_string(),
-    filename: "testalias.Q8_0.gguf".to_string(),
+    repo: Some("MyFactory/testalias-gguf".to_string()),
+    filename: Some("testalias.Q8_0.gguf".to_string()),
+    model_file: None,
     chat_template: Some(ChatTemplateId::Llama3),
     tokenizer_config: None,
     family: Some("testalias".to_string()),
     force: false,
+    tags: vec![],
     oai_request_params: OAIRequestParams::default(),
     context_params: GptContextParams::default(),
   },
@@ -169,9 +210,37 @@ mod test {
     alias: "testalias:instruct".to_string(),
     repo: Repo::try_from("MyFactory/testalias-gguf".to_string())?,
     filename: "testalias.Q8_0.gguf".to_string(),
+    model_file: None,
     chat_template: ChatTemplate::Id(ChatTemplateId::Llama3),
     family: Some("testalias".to_string()),
     force: false,
+    tags: vec![],
+    oai_request_params: OAIRequestParams::default(),
+    context_params: GptContextParams::default(),
+  })]
+  #[case(
+  Command::Create {
+    alias: "mymodel:instruct".to_string(),
+    repo: None,
+    filename: None,
+    model_file: Some("/models/mymodel.Q4_0.gguf".to_string()),
+    chat_template: Some(ChatTemplateId::Llama3),
+    tokenizer_config: None,
+    family: None,
+    force: false,
+    tags: vec![],
+    oai_request_params: OAIRequestParams::default(),
+    context_params: GptContextParams::default(),
+  },
+  CreateCommand {
+    alias: "mymodel:instruct".to_string(),
+    repo: Repo::default(),
+    filename: "mymodel.Q4_0.gguf".to_string(),
+    model_file: Some(PathBuf::from("/models/mymodel.Q4_0.gguf")),
+    chat_template: ChatTemplate::Id(ChatTemplateId::Llama3),
+    family: None,
+    force: false,
+    tags: vec![],
     oai_request_params: OAIRequestParams::default(),
     context_params: GptContextParams::default(),
   })]
@@ -203,9 +272,11 @@ mod test {
       alias: "testalias:instruct".to_string(),
       repo: Repo::try_from("MyFactory/testalias-gguf".to_string())?,
       filename: "testalias.Q8_0.gguf".to_string(),
+      model_file: None,
       chat_template: ChatTemplate::Id(ChatTemplateId::Llama3),
       family: None,
       force: false,
+      tags: vec![],
       oai_request_params: OAIRequestParams::default(),
       context_params: GptContextParams::default(),
     };
@@ -303,7 +374,11 @@ mod test {
       .return_once(|_, _, _| Ok(HubFile::testalias()));
     mock_hub_service
       .expect_find_local_file()
-      .with(eq(tokenizer_repo.clone()), eq(TOKENIZER_CONFIG_JSON), eq(REFS_MAIN))
+      .with(
+        eq(tokenizer_repo.clone()),
+        eq(TOKENIZER_CONFIG_JSON),
+        eq(REFS_MAIN),
+      )
       .return_once(|_, _, _| Ok(None));
     mock_hub_service
       .expect_download()
@@ -322,4 +397,41 @@ mod test {
     create.execute(Arc::new(service))?;
     Ok(())
   }
+
+  #[rstest]
+  fn test_create_execute_with_model_file_skips_download_saves_alias() -> anyhow::Result<()> {
+    let model_file = PathBuf::from("tests/data/tinyllama-15m-q8_0.gguf");
+    let create = CreateCommand::testalias_builder()
+      .repo(Repo::default())
+      .filename("tinyllama-15m-q8_0.gguf".to_string())
+      .model_file(Some(model_file.clone()))
+      .build()
+      .unwrap();
+    let mut mock_data_service = MockDataService::default();
+    mock_data_service
+      .expect_find_alias()
+      .with(eq(create.alias.clone()))
+      .return_once(|_| None);
+    let mut mock_hub_service = MockHubService::default();
+    mock_hub_service.expect_download().never();
+    mock_hub_service
+      .expect_find_local_file()
+      .with(eq(Repo::llama3()), eq(TOKENIZER_CONFIG_JSON), eq(REFS_MAIN))
+      .return_once(|_, _, _| Ok(Some(HubFile::llama3_tokenizer())));
+    let alias = Alias {
+      repo: Repo::default(),
+      filename: "tinyllama-15m-q8_0.gguf".to_string(),
+      snapshot: String::new(),
+      model_file: Some(model_file.canonicalize()?),
+      ..Alias::testalias()
+    };
+    mock_data_service
+      .expect_save_alias()
+      .with(eq(alias))
+      .return_once(|_| Ok(PathBuf::from("ignored")));
+    let service =
+      AppServiceStubMock::new(MockEnvServiceFn::new(), mock_hub_service, mock_data_service);
+    create.execute(Arc::new(service))?;
+    Ok(())
+  }
 }
@@ -0,0 +1,329 @@
+use crate::{
+  error::Common,
+  gguf::{GgufError, GgufMetadata, GgufReader},
+  objs::{Alias, REFS_MAIN, TOKENIZER_CONFIG_JSON},
+  service::{estimated_load_bytes, find_model_file, AppServiceFn, MemoryService, MemoryServiceFn},
+  tokenizer_config::TokenizerConfig,
+  utils::human_size,
+  BodhiError, Repo, StdoutWriter,
+};
+use async_openai::types::ChatCompletionRequestMessage;
+use prettytable::{format, row, Cell, Row, Table};
+use serde_json::json;
+use std::{
+  fs::File,
+  io::BufReader,
+  path::{Path, PathBuf},
+  sync::Arc,
+};
+use validator::Validate;
+
+#[derive(Debug, PartialEq)]
+pub struct AliasLint {
+  pub alias: String,
+  // estimated size of the model weights, from the tensor infos of the GGUF file
+  pub size_bytes: Option<u64>,
+  pub problems: Vec<String>,
+}
+
+impl AliasLint {
+  pub fn is_healthy(&self) -> bool {
+    self.problems.is_empty()
+  }
+}
+
+impl From<&AliasLint> for Row {
+  fn from(value: &AliasLint) -> Self {
+    let status = if value.is_healthy() { "ok" } else { "broken" };
+    let human_size = value
+      .size_bytes
+      .map(|size| format!("{:.2} GB", size as f64 / 2_f64.powf(30.0)))
+      .unwrap_or_default();
+    Row::from(vec![
+      Cell::new(&value.alias),
+      Cell::new(status),
+      Cell::new(&human_size),
+      Cell::new(&value.problems.join("\n")),
+    ])
+  }
+}
+
+#[derive(Debug, derive_new::new)]
+pub struct LintCommand {
+  service: Arc<dyn AppServiceFn>,
+  #[new(value = "Arc::new(MemoryService)")]
+  pub(crate) memory_service: Arc<dyn MemoryServiceFn>,
+}
+
+impl LintCommand {
+  pub fn execute(&self, stdout: &mut dyn StdoutWriter) -> crate::error::Result<()> {
+    let lints = self.lint()?;
+    let mut table = Table::new();
+    table.add_row(row!["ALIAS", "STATUS", "SIZE", "PROBLEMS"]);
+    for lint in &lints {
+      table.add_row(Row::from(lint));
+    }
+    table.set_format(format::FormatBuilder::default().padding(2, 2).build());
+    let broken = lints.iter().filter(|lint| !lint.is_healthy()).count();
+    stdout
+      .write(&format!(
+        "{}\n{} healthy, {} broken\n",
+        table,
+        lints.len() - broken,
+        broken
+      ))
+      .map_err(Common::from)?;
+    if broken > 0 {
+      return Err(BodhiError::AliasesBroken(broken));
+    }
+    Ok(())
+  }
+
+  // checks every alias using only the files on disk, the models are not loaded
+  pub fn lint(&self) -> crate::error::Result<Vec<AliasLint>> {
+    let aliases = self.service.data_service().list_aliases()?;
+    Ok(aliases.iter().map(|alias| self.lint_alias(alias)).collect())
+  }
+
+  fn lint_alias(&self, alias: &Alias) -> AliasLint {
+    let mut problems = Vec::new();
+    let size_bytes = match self.check_model_file(alias) {
+      Ok((model_file, size_bytes, metadata)) => {
+        problems.extend(check_stop_tokens(&alias.stop_tokens, &metadata));
+        problems.extend(self.check_memory(alias, &model_file));
+        Some(size_bytes)
+      }
+      Err(problem) => {
+        problems.push(problem);
+        None
+      }
+    };
+    if let Err(problem) = self.check_chat_template(alias) {
+      problems.push(problem);
+    }
+    AliasLint {
+      alias: alias.alias.clone(),
+      size_bytes,
+      problems,
+    }
+  }
+
+  fn check_model_file(&self, alias: &Alias) -> Result<(PathBuf, u64, GgufMetadata), String> {
+    let model_file = find_model_file(self.service.hub_service().as_ref(), alias)
+      .map_err(|err| err.to_string())?
+      .ok_or_else(|| match &alias.model_file {
+        Some(model_file) => {
+          BodhiError::ModelFileMissing(model_file.display().to_string()).to_string()
+        }
+        None => format!(
+          "model file '{}' from repo '{}' not found in huggingface cache",
+          alias.filename, alias.repo
+        ),
+      })?;
+    let file = File::open(&model_file).map_err(|err| format!("io: {err}"))?;
+    let reader = GgufReader::from_reader(BufReader::new(file)).map_err(|err| err.to_string())?;
+    let size_bytes = reader
+      .estimated_size_bytes()
+      .ok_or_else(|| GgufError::Truncated.to_string())?;
+    Ok((model_file, size_bytes, reader.into_metadata()))
+  }
+
+  // the same estimate the server refuses a model load with, against the memory watermark with no
+  // model loaded, as the memory used by the other apps changes till the model is loaded
+  fn check_memory(&self, alias: &Alias, model_file: &Path) -> Option<String> {
+    let memory = self.memory_service.system_memory()?;
+    let required = estimated_load_bytes(model_file, alias.context_params.n_ctx)?;
+    let watermark = self.service.env_service().memory_watermark();
+    let watermark_bytes = memory.watermark_bytes(watermark);
+    (required > watermark_bytes).then(|| {
+      format!(
+        "needs {} of memory, over the {watermark}% watermark of {}, use a smaller quantization of the model, or a smaller n_ctx",
+        human_size(required),
+        human_size(watermark_bytes)
+      )
+    })
+  }
+
+  fn check_chat_template(&self, alias: &Alias) -> Result<(), String> {
+    let tokenizer_repo =
+      Repo::try_from(alias.chat_template.clone()).map_err(|err| err.to_string())?;
+    let tokenizer_file = self
+      .service
+      .hub_service()
+      .find_local_file(&tokenizer_repo, TOKENIZER_CONFIG_JSON, REFS_MAIN)
+      .map_err(|err| err.to_string())?
+      .ok_or_else(|| {
+        format!(
+          "{} from repo '{}' not found in huggingface cache",
+          TOKENIZER_CONFIG_JSON, tokenizer_repo
+        )
+      })?;
+    let tokenizer_config =
+      TokenizerConfig::try_from(tokenizer_file).map_err(|err| err.to_string())?;
+    tokenizer_config.validate().map_err(|err| err.to_string())?;
+    let messages = vec![
+      serde_json::from_value::<ChatCompletionRequestMessage>(json! {{
+        "role": "user",
+        "content": "What day comes after Monday?"
+      }})
+      .map_err(|err| err.to_string())?,
+    ];
+    tokenizer_config
+      .apply_chat_template(&messages)
+      .map_err(|err| err.to_string())?;
+    Ok(())
+  }
+}
+
+// a stop string can span many tokens, only the ones written as a special token, like
+// `<|im_end|>`, are expected in the vocab
+fn check_stop_tokens(stop_tokens: &[String], metadata: &GgufMetadata) -> Vec<String> {
+  let Some(tokens) = metadata.tokens() else {
+    return vec![];
+  };
+  stop_tokens
+    .iter()
+    .filter(|token| token.len() > 2 && token.starts_with('<') && token.ends_with('>'))
+    .filter(|token| !tokens.contains(&token.as_str()))
+    .map(|token| format!("stop token '{token}' not in the vocab of the model"))
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+  use super::{check_stop_tokens, AliasLint, LintCommand};
+  use crate::{
+    gguf::{GgufMetadata, GgufValue},
+    objs::{Alias, ChatTemplate},
+    service::{AppServiceFn, MockMemoryServiceFn, SystemMemory},
+    test_utils::{app_service_stub, AppServiceTuple, SNAPSHOT},
+    MockStdoutWriter, Repo,
+  };
+  use rstest::rstest;
+  use std::{collections::BTreeMap, fs, sync::Arc};
+
+  #[rstest]
+  fn test_lint_reports_healthy_and_broken_aliases(
+    app_service_stub: AppServiceTuple,
+  ) -> anyhow::Result<()> {
+    let AppServiceTuple(_temp_bodhi_home, _temp_hf_home, _, hf_cache, service) = app_service_stub;
+    fs::copy(
+      "tests/data/tinyllama-15m-q8_0.gguf",
+      hf_cache.join(format!(
+        "models--MyFactory--testalias-gguf/snapshots/{SNAPSHOT}/testalias.Q8_0.gguf"
+      )),
+    )?;
+    service.data_service().save_alias(&Alias::testalias())?;
+    service.data_service().save_alias(&Alias {
+      alias: "fakemodel:instruct".to_string(),
+      repo: Repo::fakemodel(),
+      filename: "fakemodel.Q4_0.gguf".to_string(),
+      snapshot: SNAPSHOT.to_string(),
+      chat_template: ChatTemplate::Repo(Repo::testalias()),
+      ..Alias::default()
+    })?;
+    let lints = LintCommand::new(Arc::new(service)).lint()?;
+    let lint = |alias: &str| lints.iter().find(|lint| lint.alias == alias).unwrap();
+    assert_eq!(
+      &AliasLint {
+        alias: "testalias:instruct".to_string(),
+        size_bytes: Some(25944192),
+        problems: vec![],
+      },
+      lint("testalias:instruct")
+    );
+    assert_eq!(
+      vec!["gguf_invalid_magic: not a GGUF file, found magic bytes 0x73696874".to_string()],
+      lint("fakemodel:instruct").problems
+    );
+    assert_eq!(
+      vec![
+        "model file 'tinyllama-1.1b-chat-v0.3.Q2_K.gguf' from repo 'TheBloke/TinyLlama-1.1B-Chat-v0.3-GGUF' not found in huggingface cache".to_string(),
+        "tokenizer_config.json from repo 'TinyLlama/TinyLlama-1.1B-Chat-v1.0' not found in huggingface cache".to_string(),
+      ],
+      lint("tinyllama:instruct").problems
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(1024 * 1024 * 1024, vec![])]
+  #[case(
+    16 * 1024 * 1024,
+    vec!["over the 90% watermark of 0.01 GB, use a smaller quantization of the model, or a smaller n_ctx"]
+  )]
+  fn test_lint_checks_memory_feasibility(
+    app_service_stub: AppServiceTuple,
+    #[case] total_bytes: u64,
+    #[case] expected: Vec<&str>,
+  ) -> anyhow::Result<()> {
+    let AppServiceTuple(_temp_bodhi_home, _temp_hf_home, _, hf_cache, service) = app_service_stub;
+    fs::copy(
+      "tests/data/tinyllama-15m-q8_0.gguf",
+      hf_cache.join(format!(
+        "models--MyFactory--testalias-gguf/snapshots/{SNAPSHOT}/testalias.Q8_0.gguf"
+      )),
+    )?;
+    let mut memory_service = MockMemoryServiceFn::new();
+    memory_service.expect_system_memory().returning(move || {
+      Some(SystemMemory {
+        total_bytes,
+        available_bytes: total_bytes,
+      })
+    });
+    let mut command = LintCommand::new(Arc::new(service));
+    command.memory_service = Arc::new(memory_service);
+    let problems = command.lint_alias(&Alias::testalias()).problems;
+    assert_eq!(expected.len(), problems.len(), "{problems:?}");
+    for (expected, problem) in expected.iter().zip(&problems) {
+      assert!(problem.ends_with(expected), "{problem}");
+    }
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(vec!["</s>", "\nUser:", "<>"], vec![])]
+  #[case(
+    vec!["<|im_end|>", "</s>"],
+    vec!["stop token '<|im_end|>' not in the vocab of the model"]
+  )]
+  fn test_lint_checks_stop_tokens_in_vocab(
+    #[case] stop_tokens: Vec<&str>,
+    #[case] expected: Vec<&str>,
+  ) {
+    let metadata = GgufMetadata {
+      version: 3,
+      tensor_count: 0,
+      kv: BTreeMap::from([(
+        "tokenizer.ggml.tokens".to_string(),
+        GgufValue::Array(vec![
+          GgufValue::String("<s>".to_string()),
+          GgufValue::String("</s>".to_string()),
+        ]),
+      )]),
+    };
+    let stop_tokens = stop_tokens
+      .into_iter()
+      .map(str::to_string)
+      .collect::<Vec<_>>();
+    assert_eq!(expected, check_stop_tokens(&stop_tokens, &metadata));
+  }
+
+  #[rstest]
+  fn test_lint_execute_fails_if_any_alias_broken(
+    app_service_stub: AppServiceTuple,
+  ) -> anyhow::Result<()> {
+    let AppServiceTuple(_temp_bodhi_home, _temp_hf_home, _, _, service) = app_service_stub;
+    let mut mock = MockStdoutWriter::default();
+    mock
+      .expect_write()
+      .withf(|output| output.ends_with("0 healthy, 3 broken\n"))
+      .return_once(|output| Ok(output.len()));
+    let result = LintCommand::new(Arc::new(service)).execute(&mut mock);
+    assert_eq!(
+      "3 model aliases failed the lint checks, run `bodhi show <ALIAS>` to review the alias config",
+      result.unwrap_err().to_string()
+    );
+    Ok(())
+  }
+}
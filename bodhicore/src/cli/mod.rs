@@ -1,3 +1,4 @@
+mod bundle;
 mod command;
 #[cfg(not(test))]
 mod create;
@@ -5,20 +6,25 @@ mod create;
 pub mod create;
 mod envs;
 mod error;
+mod lint;
 mod list;
 mod out_writer;
 mod pull;
 mod run;
+mod scan;
 mod serve;
 mod alias;
 
+pub use bundle::{Bundle, BundleCommand, BundleManifest, ModelRef};
 pub use command::*;
 pub use create::CreateCommand;
 pub use envs::EnvCommand;
 pub use error::CliError;
+pub use lint::{AliasLint, LintCommand};
 pub use list::ListCommand;
 pub use out_writer::*;
 pub use pull::PullCommand;
 pub use run::RunCommand;
+pub use scan::{ScanCommand, ScanReport};
 pub use serve::*;
 pub use alias::ManageAliasCommand;
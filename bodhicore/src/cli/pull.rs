@@ -120,7 +120,7 @@ impl PullCommand {
     }
   }
 
-  fn download_file_if_missing(
+  pub(crate) fn download_file_if_missing(
     service: Arc<dyn AppServiceFn>,
     repo: &Repo,
     filename: &str,
@@ -0,0 +1,284 @@
+use super::{CliError, Command};
+use crate::{
+  error::{Common, Result},
+  gguf::GgufMetadata,
+  objs::{
+    default_features, Alias, ChatTemplate, GptContextParams, OAIRequestParams, Repo, REFS_MAIN,
+    TOKENIZER_CONFIG_JSON,
+  },
+  service::AppServiceFn,
+  StdoutWriter,
+};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::{
+  path::{Path, PathBuf},
+  sync::Arc,
+};
+use walkdir::WalkDir;
+
+// llama.cpp loads the other shards of a split model from the first shard
+static REGEX_SHARD: Lazy<Regex> =
+  Lazy::new(|| Regex::new(r"^(.+)-(\d{5})-of-(\d{5})\.gguf$").unwrap());
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanCommand {
+  dir: PathBuf,
+  chat_template: ChatTemplate,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ScanReport {
+  pub created: Vec<String>,
+  pub skipped: Vec<String>,
+  pub failed: Vec<(String, String)>,
+}
+
+impl TryFrom<Command> for ScanCommand {
+  type Error = CliError;
+
+  fn try_from(value: Command) -> std::result::Result<Self, Self::Error> {
+    match value {
+      Command::Scan {
+        dir,
+        chat_template,
+        tokenizer_config,
+      } => {
+        let chat_template = match (chat_template, tokenizer_config) {
+          (Some(chat_template), None) => ChatTemplate::Id(chat_template),
+          (None, Some(tokenizer_config)) => ChatTemplate::Repo(Repo::try_from(tokenizer_config)?),
+          (chat_template, tokenizer_config) => {
+            return Err(CliError::BadRequest(format!(
+              "cannot initialize scan command with invalid state. chat_template: '{chat_template:?}', tokenizer_config: '{tokenizer_config:?}'"
+            )))
+          }
+        };
+        Ok(ScanCommand {
+          dir: PathBuf::from(dir),
+          chat_template,
+        })
+      }
+      cmd => Err(CliError::ConvertCommand(
+        cmd.to_string(),
+        "scan".to_string(),
+      )),
+    }
+  }
+}
+
+impl ScanCommand {
+  pub fn execute(
+    &self,
+    service: Arc<dyn AppServiceFn>,
+    stdout: &mut dyn StdoutWriter,
+  ) -> Result<()> {
+    let report = self.scan(service)?;
+    let mut out = String::new();
+    for alias in &report.created {
+      out.push_str(&format!("created: '{alias}'\n"));
+    }
+    for alias in &report.skipped {
+      out.push_str(&format!("skipped: '{alias}', already exists\n"));
+    }
+    for (model_file, err) in &report.failed {
+      out.push_str(&format!("failed: '{model_file}', {err}\n"));
+    }
+    out.push_str(&format!(
+      "{} created, {} skipped, {} failed\n",
+      report.created.len(),
+      report.skipped.len(),
+      report.failed.len()
+    ));
+    stdout.write(&out).map_err(Common::from)?;
+    Ok(())
+  }
+
+  // creates an alias named after the file for every GGUF file in the dir not already used by an
+  // alias, the alias refers to the file in place
+  pub fn scan(&self, service: Arc<dyn AppServiceFn>) -> Result<ScanReport> {
+    let chat_template_repo = Repo::try_from(self.chat_template.clone())?;
+    if service
+      .hub_service()
+      .find_local_file(&chat_template_repo, TOKENIZER_CONFIG_JSON, REFS_MAIN)?
+      .is_none()
+    {
+      service
+        .hub_service()
+        .download(&chat_template_repo, TOKENIZER_CONFIG_JSON, false)?;
+    }
+    let existing = service.data_service().list_aliases()?;
+    let mut report = ScanReport::default();
+    for model_file in self.model_files() {
+      let Some((alias, filename)) = alias_name(&model_file) else {
+        continue;
+      };
+      let model_file = model_file.canonicalize().unwrap_or(model_file);
+      if existing.iter().any(|existing| {
+        existing.alias == alias || existing.model_file.as_ref() == Some(&model_file)
+      }) || report.created.contains(&alias)
+      {
+        report.skipped.push(alias);
+        continue;
+      }
+      let metadata = match GgufMetadata::from_file(&model_file) {
+        Ok(metadata) => metadata,
+        Err(err) => {
+          report
+            .failed
+            .push((model_file.display().to_string(), err.to_string()));
+          continue;
+        }
+      };
+      let alias = Alias {
+        model_file: Some(model_file.clone()),
+        ..Alias::new(
+          alias,
+          metadata.architecture().map(str::to_string),
+          Repo::default(),
+          filename,
+          String::new(),
+          default_features(),
+          self.chat_template.clone(),
+          OAIRequestParams::default(),
+          GptContextParams::default(),
+        )
+      };
+      match service.data_service().save_alias(&alias) {
+        Ok(_) => report.created.push(alias.alias),
+        Err(err) => report
+          .failed
+          .push((model_file.display().to_string(), err.to_string())),
+      }
+    }
+    Ok(report)
+  }
+
+  fn model_files(&self) -> Vec<PathBuf> {
+    let mut model_files = WalkDir::new(&self.dir)
+      .follow_links(true)
+      .into_iter()
+      .filter_map(|entry| entry.ok())
+      .filter(|entry| entry.path().is_file())
+      .map(|entry| entry.into_path())
+      .filter(|path| {
+        path
+          .extension()
+          .map(|extension| extension.eq_ignore_ascii_case("gguf"))
+          .unwrap_or(false)
+      })
+      .collect::<Vec<_>>();
+    model_files.sort();
+    model_files
+  }
+}
+
+// the alias is the filename without the extension and the shard suffix, the shards other than
+// the first are not a model of their own
+fn alias_name(model_file: &Path) -> Option<(String, String)> {
+  let filename = model_file.file_name()?.to_string_lossy().into_owned();
+  match REGEX_SHARD.captures(&filename) {
+    Some(captures) if &captures[2] != "00001" => None,
+    Some(captures) => Some((captures[1].to_string(), filename.clone())),
+    None => {
+      let stem = model_file.file_stem()?.to_string_lossy().into_owned();
+      Some((stem, filename))
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{ScanCommand, ScanReport};
+  use crate::{
+    cli::Command,
+    objs::{Alias, ChatTemplate, ChatTemplateId, HubFile, Repo, REFS_MAIN, TOKENIZER_CONFIG_JSON},
+    service::{MockDataService, MockEnvServiceFn, MockHubService},
+    test_utils::{write_gguf, AppServiceStubMock},
+  };
+  use mockall::predicate::eq;
+  use rstest::rstest;
+  use std::{
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+  };
+
+  #[rstest]
+  fn test_scan_try_from_command() -> anyhow::Result<()> {
+    let command = Command::Scan {
+      dir: "/models".to_string(),
+      chat_template: Some(ChatTemplateId::Llama3),
+      tokenizer_config: None,
+    };
+    assert_eq!(
+      ScanCommand {
+        dir: PathBuf::from("/models"),
+        chat_template: ChatTemplate::Id(ChatTemplateId::Llama3),
+      },
+      ScanCommand::try_from(command)?
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_scan_creates_aliases_skips_existing_reports_invalid() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let dir = temp_dir.path().canonicalize()?;
+    write_gguf(&dir.join("mymodel.Q4_0.gguf"), &[])?;
+    write_gguf(&dir.join("existing.Q8_0.gguf"), &[])?;
+    fs::create_dir(dir.join("split"))?;
+    write_gguf(&dir.join("split").join("bigmodel-00001-of-00002.gguf"), &[])?;
+    write_gguf(&dir.join("split").join("bigmodel-00002-of-00002.gguf"), &[])?;
+    fs::write(dir.join("broken.gguf"), "this is not a gguf")?;
+    fs::write(dir.join("README.md"), "my models")?;
+
+    let mut mock_hub_service = MockHubService::default();
+    mock_hub_service
+      .expect_find_local_file()
+      .with(eq(Repo::llama3()), eq(TOKENIZER_CONFIG_JSON), eq(REFS_MAIN))
+      .return_once(|_, _, _| Ok(Some(HubFile::llama3_tokenizer())));
+    mock_hub_service.expect_download().never();
+    let mut mock_data_service = MockDataService::default();
+    mock_data_service.expect_list_aliases().return_once(|| {
+      Ok(vec![Alias {
+        alias: "existing.Q8_0".to_string(),
+        ..Alias::testalias()
+      }])
+    });
+    let saved = Arc::new(Mutex::new(Vec::new()));
+    let saved_clone = saved.clone();
+    mock_data_service
+      .expect_save_alias()
+      .times(2)
+      .returning(move |alias| {
+        saved_clone.lock().unwrap().push(alias.clone());
+        Ok(PathBuf::from("ignored"))
+      });
+    let service =
+      AppServiceStubMock::new(MockEnvServiceFn::new(), mock_hub_service, mock_data_service);
+    let command = ScanCommand {
+      dir: dir.clone(),
+      chat_template: ChatTemplate::Id(ChatTemplateId::Llama3),
+    };
+    let report = command.scan(Arc::new(service))?;
+    assert_eq!(
+      ScanReport {
+        created: vec!["mymodel.Q4_0".to_string(), "bigmodel".to_string()],
+        skipped: vec!["existing.Q8_0".to_string()],
+        failed: vec![(
+          dir.join("broken.gguf").display().to_string(),
+          "gguf_invalid_magic: not a GGUF file, found magic bytes 0x73696874".to_string()
+        )],
+      },
+      report
+    );
+    let saved = saved.lock().unwrap();
+    assert_eq!(
+      Some(dir.join("split").join("bigmodel-00001-of-00002.gguf")),
+      saved[1].model_file
+    );
+    assert_eq!(Repo::default(), saved[1].repo);
+    assert_eq!("bigmodel-00001-of-00002.gguf", saved[1].filename);
+    Ok(())
+  }
+}
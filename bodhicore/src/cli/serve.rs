@@ -1,13 +1,16 @@
-use super::{CliError, Command};
+use super::{CliError, Command, LintCommand};
 use crate::{
   db::{DbPool, DbService, DbServiceFn, TimeService},
   error::Common,
-  server::{build_routes, build_server_handle, shutdown_signal, ServerHandle, ShutdownCallback},
+  server::{
+    build_routes, build_server_handle, load_tls_config, shutdown_signal, RouterState,
+    RouterStateFn, ServerHandle, ShutdownCallback,
+  },
   service::AppServiceFn,
   BodhiError, SharedContextRw, SharedContextRwFn,
 };
 use axum::Router;
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 use tokio::{runtime::Builder, sync::oneshot::Sender, task::JoinHandle};
 
 #[derive(Debug, Clone, PartialEq)]
@@ -117,6 +120,20 @@ impl ServeCommand {
     service: Arc<dyn AppServiceFn>,
     static_router: Option<Router>,
   ) -> crate::error::Result<ServerShutdownHandle> {
+    // a bad certificate fails the start, before the database and the server are set up
+    let tls = match (
+      service.env_service().tls_cert(),
+      service.env_service().tls_key(),
+    ) {
+      (Some(cert), Some(key)) => Some(load_tls_config(&cert, &key).await?),
+      _ => None,
+    };
+    let scheme = service.env_service().scheme();
+    let uds = service.env_service().bind_uds();
+    let uds_mode = service.env_service().bind_uds_mode();
+    if service.env_service().lint_on_start() {
+      lint_on_start(service.clone());
+    }
     let dbpath = service.env_service().db_path();
     let pool = DbPool::connect(&format!("sqlite:{}", dbpath.display())).await?;
     let db_service = DbService::new(pool, Arc::new(TimeService));
@@ -130,7 +147,25 @@ impl ServeCommand {
 
     let ctx = SharedContextRw::new_shared_rw(None).await?;
     let ctx: Arc<dyn SharedContextRwFn> = Arc::new(ctx);
-    let app = build_routes(ctx.clone(), service, Arc::new(db_service), static_router);
+    let grace = Duration::from_secs(service.env_service().shutdown_grace_secs());
+    let paused = service.env_service().inference_paused();
+    let state = RouterState::new(ctx.clone(), service, Arc::new(db_service));
+    if paused {
+      tracing::warn!(
+        "starting with inference paused, resume it with the inference resume endpoint"
+      );
+      state.set_paused(true);
+    }
+    let server = server.with_drain(grace, Arc::new(state.clone()));
+    let server = match tls {
+      Some(tls) => server.with_tls(tls),
+      None => server,
+    };
+    let server = match &uds {
+      Some(path) => server.with_uds(path.clone(), uds_mode),
+      None => server,
+    };
+    let app = build_routes(state, static_router);
 
     let join_handle = tokio::spawn(async move {
       let callback = Box::new(ShutdownContextCallback { ctx });
@@ -143,9 +178,10 @@ impl ServeCommand {
       }
     });
     match ready_rx.await {
-      Ok(()) => {
-        println!("server started on http://{host}:{port}");
-      }
+      Ok(()) => match &uds {
+        Some(path) => println!("server started on unix:{}", path.display()),
+        None => println!("server started on {scheme}://{host}:{port}"),
+      },
       Err(err) => tracing::warn!(?err, "ready channel closed before could receive signal"),
     }
     Ok(ServerShutdownHandle {
@@ -155,6 +191,33 @@ impl ServeCommand {
   }
 }
 
+// the broken aliases are logged, the server starts regardless, and they fail on use
+fn lint_on_start(service: Arc<dyn AppServiceFn>) {
+  let lints = match LintCommand::new(service).lint() {
+    Ok(lints) => lints,
+    Err(err) => {
+      tracing::warn!(?err, "error linting the model aliases");
+      return;
+    }
+  };
+  let broken = lints
+    .iter()
+    .filter(|lint| !lint.is_healthy())
+    .collect::<Vec<_>>();
+  for lint in &broken {
+    tracing::warn!(
+      alias = lint.alias,
+      problems = lint.problems.join(", "),
+      "model alias failed the lint checks"
+    );
+  }
+  tracing::info!(
+    healthy = lints.len() - broken.len(),
+    broken = broken.len(),
+    "linted the model aliases"
+  );
+}
+
 #[cfg(test)]
 mod test {
   use super::{Command, ServeCommand};
@@ -23,6 +23,23 @@ Run `bodhi list -r` to see list of pre-configured model aliases
   AliasExists(String),
   #[error("$HOME directory not found, set home directory using $HOME")]
   HomeDirectory,
+  #[error(
+    "{0} model aliases failed the lint checks, run `bodhi show <ALIAS>` to review the alias config"
+  )]
+  AliasesBroken(usize),
+  #[error("model file '{0}' not found, check the model_file of the model alias")]
+  ModelFileMissing(String),
+  #[error("model alias '{0}' not found, run `bodhi list` to list the existing model aliases")]
+  LocalAliasNotFound(String),
+  #[error("invalid bundle file: {0}")]
+  InvalidBundle(String),
+  #[error("failed to load the TLS certificate '{cert}' and key '{key}': {source}")]
+  TlsConfig {
+    #[source]
+    source: io::Error,
+    cert: String,
+    key: String,
+  },
 
   #[error(transparent)]
   Common(#[from] Common),
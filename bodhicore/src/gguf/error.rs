@@ -0,0 +1,26 @@
+use std::{io, path::PathBuf};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum GgufError {
+  #[error("gguf_invalid_magic: not a GGUF file, found magic bytes {0:#010x}")]
+  InvalidMagic(u32),
+  #[error("gguf_unsupported_version: GGUF version {0} is not supported")]
+  UnsupportedVersion(u32),
+  #[error("gguf_invalid_value_type: unknown metadata value type {0}")]
+  InvalidValueType(u32),
+  #[error("gguf_invalid_string: metadata string is not valid utf-8")]
+  InvalidString,
+  #[error("gguf_truncated: file ends before the GGUF metadata is complete, the file may be partially downloaded")]
+  Truncated,
+  #[error("gguf_io: {0}")]
+  Io(#[from] io::Error),
+  #[error("gguf_io: {source}\npath='{path}'")]
+  IoWithPath {
+    #[source]
+    source: io::Error,
+    path: PathBuf,
+  },
+}
+
+pub type Result<T> = std::result::Result<T, GgufError>;
@@ -0,0 +1,155 @@
+use super::{
+  error::{GgufError, Result},
+  reader::GgufReader,
+  tensor::TensorInfo,
+};
+use crate::objs::is_default;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{
+  collections::HashMap,
+  fs::{self, File},
+  io::{BufReader, Read, Seek},
+  path::{Path, PathBuf},
+  sync::Mutex,
+  time::SystemTime,
+};
+
+// parsed model infos by model file, a file modified since it was parsed is parsed again
+static MODEL_INFO_CACHE: Lazy<Mutex<HashMap<PathBuf, (SystemTime, GgufModelInfo)>>> =
+  Lazy::new(Default::default);
+
+// the model details shown by the model management UIs, a key missing in the model file is left
+// out instead of failing the whole info
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GgufModelInfo {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub architecture: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub parameter_count: Option<u64>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub quantization: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub context_length: Option<u64>,
+  #[serde(default, skip_serializing_if = "is_default")]
+  pub embedding: bool,
+}
+
+impl GgufModelInfo {
+  pub fn from_reader<R: Read + Seek>(reader: &GgufReader<R>) -> GgufModelInfo {
+    let metadata = reader.metadata();
+    let parameter_count = metadata.parameter_count().or_else(|| {
+      let count = reader
+        .tensors()?
+        .iter()
+        .map(TensorInfo::n_elements)
+        .sum::<u64>();
+      (count > 0).then_some(count)
+    });
+    GgufModelInfo {
+      architecture: metadata.architecture().map(str::to_string),
+      parameter_count,
+      quantization: metadata.file_type().map(str::to_string),
+      context_length: metadata.context_length(),
+      embedding: metadata.pooling_type().is_some(),
+    }
+  }
+
+  pub fn from_file(path: &Path) -> Result<GgufModelInfo> {
+    let file = File::open(path).map_err(|source| GgufError::IoWithPath {
+      source,
+      path: path.to_path_buf(),
+    })?;
+    let reader = GgufReader::from_reader(BufReader::new(file))?;
+    Ok(GgufModelInfo::from_reader(&reader))
+  }
+
+  // parses the model file only on the first call, or once the file is modified
+  pub fn cached(path: &Path) -> Result<GgufModelInfo> {
+    let modified = fs::metadata(path)
+      .and_then(|metadata| metadata.modified())
+      .map_err(|source| GgufError::IoWithPath {
+        source,
+        path: path.to_path_buf(),
+      })?;
+    if let Some((cached_at, info)) = MODEL_INFO_CACHE.lock().unwrap().get(path) {
+      if *cached_at == modified {
+        return Ok(info.clone());
+      }
+    }
+    let info = GgufModelInfo::from_file(path)?;
+    MODEL_INFO_CACHE
+      .lock()
+      .unwrap()
+      .insert(path.to_path_buf(), (modified, info.clone()));
+    Ok(info)
+  }
+
+  // the parameter count the way Ollama shows it, like 8.0B
+  pub fn parameter_size(&self) -> Option<String> {
+    let count = self.parameter_count? as f64;
+    let size = if count >= 1e9 {
+      format!("{:.1}B", count / 1e9)
+    } else if count >= 1e6 {
+      format!("{:.1}M", count / 1e6)
+    } else {
+      format!("{:.1}K", count / 1e3)
+    };
+    Some(size)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::GgufModelInfo;
+  use rstest::rstest;
+  use std::{fs, path::Path};
+
+  #[rstest]
+  fn test_gguf_model_info_from_model_file() -> anyhow::Result<()> {
+    let info = GgufModelInfo::from_file(Path::new("tests/data/tinyllama-15m-q8_0.gguf"))?;
+    assert_eq!(
+      GgufModelInfo {
+        architecture: Some("llama".to_string()),
+        parameter_count: Some(24_407_712),
+        quantization: Some("Q8_0".to_string()),
+        context_length: Some(256),
+        embedding: false,
+      },
+      info
+    );
+    assert_eq!(Some("24.4M".to_string()), info.parameter_size());
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_gguf_model_info_cached_parses_modified_file_again() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let model_file = temp_dir.path().join("model.gguf");
+    fs::copy("tests/data/tinyllama-15m-q8_0.gguf", &model_file)?;
+    let info = GgufModelInfo::cached(&model_file)?;
+    assert_eq!(Some(256), info.context_length);
+    assert_eq!(info, GgufModelInfo::cached(&model_file)?);
+    fs::write(&model_file, "not a gguf file")?;
+    let file = fs::File::options().write(true).open(&model_file)?;
+    file.set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(60))?;
+    assert!(GgufModelInfo::cached(&model_file).is_err());
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(Some(8_030_261_248), Some("8.0B"))]
+  #[case(Some(137_000_000), Some("137.0M"))]
+  #[case(Some(512_000), Some("512.0K"))]
+  #[case(None, None)]
+  fn test_gguf_model_info_parameter_size(
+    #[case] parameter_count: Option<u64>,
+    #[case] expected: Option<&str>,
+  ) {
+    let info = GgufModelInfo {
+      parameter_count,
+      ..Default::default()
+    };
+    assert_eq!(expected.map(str::to_string), info.parameter_size());
+  }
+}
@@ -0,0 +1,317 @@
+use super::{error::GgufError, reader::GgufReader};
+use std::{collections::BTreeMap, fs::File, io::BufReader, path::Path};
+
+pub static GENERAL_ARCHITECTURE: &str = "general.architecture";
+pub static GENERAL_FILE_TYPE: &str = "general.file_type";
+pub static GENERAL_PARAMETER_COUNT: &str = "general.parameter_count";
+pub static TOKENIZER_CHAT_TEMPLATE: &str = "tokenizer.chat_template";
+pub static TOKENIZER_TOKENS: &str = "tokenizer.ggml.tokens";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GgufValue {
+  U8(u8),
+  I8(i8),
+  U16(u16),
+  I16(i16),
+  U32(u32),
+  I32(i32),
+  U64(u64),
+  I64(i64),
+  F32(f32),
+  F64(f64),
+  Bool(bool),
+  String(String),
+  Array(Vec<GgufValue>),
+}
+
+impl GgufValue {
+  pub fn as_str(&self) -> Option<&str> {
+    match self {
+      GgufValue::String(value) => Some(value),
+      _ => None,
+    }
+  }
+
+  pub fn as_u64(&self) -> Option<u64> {
+    match self {
+      GgufValue::U8(value) => Some(*value as u64),
+      GgufValue::U16(value) => Some(*value as u64),
+      GgufValue::U32(value) => Some(*value as u64),
+      GgufValue::U64(value) => Some(*value),
+      GgufValue::I8(value) => u64::try_from(*value).ok(),
+      GgufValue::I16(value) => u64::try_from(*value).ok(),
+      GgufValue::I32(value) => u64::try_from(*value).ok(),
+      GgufValue::I64(value) => u64::try_from(*value).ok(),
+      _ => None,
+    }
+  }
+
+  pub fn as_f64(&self) -> Option<f64> {
+    match self {
+      GgufValue::F32(value) => Some(*value as f64),
+      GgufValue::F64(value) => Some(*value),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GgufMetadata {
+  pub version: u32,
+  pub tensor_count: u64,
+  pub kv: BTreeMap<String, GgufValue>,
+}
+
+impl GgufMetadata {
+  // reads the header and metadata from the start of the file, the tensor data is not read
+  pub fn from_file(path: &Path) -> Result<GgufMetadata, GgufError> {
+    let file = File::open(path).map_err(|source| GgufError::IoWithPath {
+      source,
+      path: path.to_path_buf(),
+    })?;
+    let reader = GgufReader::from_reader(BufReader::new(file))?;
+    Ok(reader.into_metadata())
+  }
+
+  pub fn get(&self, key: &str) -> Option<&GgufValue> {
+    self.kv.get(key)
+  }
+
+  pub fn architecture(&self) -> Option<&str> {
+    self.get(GENERAL_ARCHITECTURE).and_then(GgufValue::as_str)
+  }
+
+  // the llama.cpp name of the quantization of the model file, like Q4_K_M
+  pub fn file_type(&self) -> Option<&'static str> {
+    let file_type = match self.get(GENERAL_FILE_TYPE).and_then(GgufValue::as_u64)? {
+      0 => "F32",
+      1 => "F16",
+      2 => "Q4_0",
+      3 => "Q4_1",
+      7 => "Q8_0",
+      8 => "Q5_0",
+      9 => "Q5_1",
+      10 => "Q2_K",
+      11 => "Q3_K_S",
+      12 => "Q3_K_M",
+      13 => "Q3_K_L",
+      14 => "Q4_K_S",
+      15 => "Q4_K_M",
+      16 => "Q5_K_S",
+      17 => "Q5_K_M",
+      18 => "Q6_K",
+      19 => "IQ2_XXS",
+      20 => "IQ2_XS",
+      21 => "Q2_K_S",
+      22 => "IQ3_XS",
+      23 => "IQ3_XXS",
+      24 => "IQ1_S",
+      25 => "IQ4_NL",
+      26 => "IQ3_S",
+      27 => "IQ3_M",
+      28 => "IQ2_S",
+      29 => "IQ2_M",
+      30 => "IQ4_XS",
+      31 => "IQ1_M",
+      32 => "BF16",
+      _ => return None,
+    };
+    Some(file_type)
+  }
+
+  // written by the newer converters only, the tensor infos have it otherwise
+  pub fn parameter_count(&self) -> Option<u64> {
+    self
+      .get(GENERAL_PARAMETER_COUNT)
+      .and_then(GgufValue::as_u64)
+  }
+
+  pub fn chat_template(&self) -> Option<String> {
+    match self.get(TOKENIZER_CHAT_TEMPLATE)? {
+      GgufValue::String(template) => Some(template.clone()),
+      // some converters write the template split over an array of strings
+      GgufValue::Array(parts) => parts
+        .iter()
+        .map(|part| part.as_str())
+        .collect::<Option<String>>(),
+      _ => None,
+    }
+  }
+
+  // the vocab of the model, None if the file does not have the tokenizer
+  pub fn tokens(&self) -> Option<Vec<&str>> {
+    match self.get(TOKENIZER_TOKENS)? {
+      GgufValue::Array(tokens) => tokens.iter().map(GgufValue::as_str).collect(),
+      _ => None,
+    }
+  }
+
+  pub fn context_length(&self) -> Option<u64> {
+    self
+      .arch_value("context_length")
+      .and_then(GgufValue::as_u64)
+  }
+
+  pub fn rope_freq_base(&self) -> Option<f64> {
+    self
+      .arch_value("rope.freq_base")
+      .and_then(GgufValue::as_f64)
+  }
+
+  pub fn rope_scaling_type(&self) -> Option<String> {
+    self
+      .arch_value("rope.scaling.type")
+      .and_then(GgufValue::as_str)
+      .map(str::to_string)
+  }
+
+  // only the embedding models, like the bert models, pool the token embeddings
+  pub fn pooling_type(&self) -> Option<u64> {
+    self.arch_value("pooling_type").and_then(GgufValue::as_u64)
+  }
+
+  pub fn block_count(&self) -> Option<u64> {
+    self.arch_value("block_count").and_then(GgufValue::as_u64)
+  }
+
+  pub fn embedding_length(&self) -> Option<u64> {
+    self
+      .arch_value("embedding_length")
+      .and_then(GgufValue::as_u64)
+  }
+
+  pub fn head_count(&self) -> Option<u64> {
+    self
+      .arch_value("attention.head_count")
+      .and_then(GgufValue::as_u64)
+  }
+
+  // models without grouped-query attention do not have the key, and use the head count
+  pub fn head_count_kv(&self) -> Option<u64> {
+    self
+      .arch_value("attention.head_count_kv")
+      .and_then(GgufValue::as_u64)
+      .or_else(|| self.head_count())
+  }
+
+  // memory of the f16 K and V caches llama.cpp allocates for a context of n_ctx tokens
+  pub fn kv_cache_bytes(&self, n_ctx: u64) -> Option<u64> {
+    let head_count = self.head_count().filter(|head_count| *head_count > 0)?;
+    let n_embd_kv = self.embedding_length()? * self.head_count_kv()? / head_count;
+    Some(2 * self.block_count()? * n_ctx * n_embd_kv * 2)
+  }
+
+  fn arch_value(&self, key: &str) -> Option<&GgufValue> {
+    let architecture = self.architecture()?;
+    self.get(&format!("{architecture}.{key}"))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{GgufMetadata, GgufValue};
+  use rstest::rstest;
+  use std::collections::BTreeMap;
+
+  fn metadata(kv: Vec<(&str, GgufValue)>) -> GgufMetadata {
+    GgufMetadata {
+      version: 3,
+      tensor_count: 0,
+      kv: kv
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect::<BTreeMap<_, _>>(),
+    }
+  }
+
+  #[rstest]
+  fn test_gguf_metadata_typed_accessors() {
+    let metadata = metadata(vec![
+      (
+        "general.architecture",
+        GgufValue::String("llama".to_string()),
+      ),
+      (
+        "tokenizer.chat_template",
+        GgufValue::String("{{ messages }}".to_string()),
+      ),
+      ("llama.context_length", GgufValue::U32(8192)),
+      ("general.file_type", GgufValue::U32(15)),
+      ("general.parameter_count", GgufValue::U64(8_030_261_248)),
+      ("llama.rope.freq_base", GgufValue::F32(500000.0)),
+      (
+        "llama.rope.scaling.type",
+        GgufValue::String("linear".to_string()),
+      ),
+      (
+        "tokenizer.ggml.tokens",
+        GgufValue::Array(vec![
+          GgufValue::String("<s>".to_string()),
+          GgufValue::String("</s>".to_string()),
+        ]),
+      ),
+    ]);
+    assert_eq!(Some("llama"), metadata.architecture());
+    assert_eq!(Some("{{ messages }}".to_string()), metadata.chat_template());
+    assert_eq!(Some(8192), metadata.context_length());
+    assert_eq!(Some("Q4_K_M"), metadata.file_type());
+    assert_eq!(Some(8_030_261_248), metadata.parameter_count());
+    assert_eq!(Some(500000.0), metadata.rope_freq_base());
+    assert_eq!(Some("linear".to_string()), metadata.rope_scaling_type());
+    assert_eq!(Some(vec!["<s>", "</s>"]), metadata.tokens());
+  }
+
+  #[rstest]
+  fn test_gguf_metadata_chat_template_from_array() {
+    let metadata = metadata(vec![(
+      "tokenizer.chat_template",
+      GgufValue::Array(vec![
+        GgufValue::String("{% for message in messages %}".to_string()),
+        GgufValue::String("{% endfor %}".to_string()),
+      ]),
+    )]);
+    assert_eq!(
+      Some("{% for message in messages %}{% endfor %}".to_string()),
+      metadata.chat_template()
+    );
+  }
+
+  #[rstest]
+  fn test_gguf_metadata_missing_keys_return_none() {
+    let metadata = metadata(vec![(
+      "llama.context_length",
+      GgufValue::String("not-a-number".to_string()),
+    )]);
+    assert_eq!(None, metadata.architecture());
+    assert_eq!(None, metadata.chat_template());
+    assert_eq!(None, metadata.context_length());
+    assert_eq!(None, metadata.file_type());
+    assert_eq!(None, metadata.parameter_count());
+    assert_eq!(None, metadata.rope_freq_base());
+    assert_eq!(None, metadata.rope_scaling_type());
+    assert_eq!(None, metadata.tokens());
+    assert_eq!(None, metadata.kv_cache_bytes(512));
+  }
+
+  #[rstest]
+  #[case(None, 2 * 32 * 512 * 4096 * 2)]
+  #[case(Some(8), 2 * 32 * 512 * 1024 * 2)]
+  fn test_gguf_metadata_kv_cache_bytes(#[case] head_count_kv: Option<u32>, #[case] expected: u64) {
+    let mut kv = vec![
+      (
+        "general.architecture",
+        GgufValue::String("llama".to_string()),
+      ),
+      ("llama.block_count", GgufValue::U32(32)),
+      ("llama.embedding_length", GgufValue::U32(4096)),
+      ("llama.attention.head_count", GgufValue::U32(32)),
+    ];
+    if let Some(head_count_kv) = head_count_kv {
+      kv.push((
+        "llama.attention.head_count_kv",
+        GgufValue::U32(head_count_kv),
+      ));
+    }
+    assert_eq!(Some(expected), metadata(kv).kv_cache_bytes(512));
+  }
+}
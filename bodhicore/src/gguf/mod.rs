@@ -0,0 +1,11 @@
+mod error;
+mod info;
+mod metadata;
+mod reader;
+mod tensor;
+
+pub use error::*;
+pub use info::GgufModelInfo;
+pub use metadata::*;
+pub use reader::{GgufReader, GGUF_MAGIC, GGUF_SUPPORTED_VERSIONS};
+pub use tensor::{GgmlType, TensorInfo};
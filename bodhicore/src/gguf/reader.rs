@@ -0,0 +1,356 @@
+use super::{
+  error::{GgufError, Result},
+  metadata::{GgufMetadata, GgufValue},
+  tensor::TensorInfo,
+};
+use std::{
+  collections::BTreeMap,
+  io::{ErrorKind, Read, Seek, SeekFrom},
+};
+
+pub static GGUF_MAGIC: u32 = 0x46554747;
+pub static GGUF_SUPPORTED_VERSIONS: [u32; 2] = [2, 3];
+
+// parses the header and metadata from the start of the reader and stops there, so a partially
+// downloaded file or a ranged read of the first few hundred KB of the model file is enough. The
+// tensor infos following the metadata are parsed too, when the reader has all of them
+#[derive(Debug)]
+pub struct GgufReader<R> {
+  reader: R,
+  metadata: GgufMetadata,
+  tensors: Option<Vec<TensorInfo>>,
+}
+
+impl<R: Read + Seek> GgufReader<R> {
+  pub fn from_reader(mut reader: R) -> Result<Self> {
+    reader.seek(SeekFrom::Start(0))?;
+    let metadata = read_metadata(&mut reader).map_err(truncated)?;
+    let tensors_start = reader.stream_position()?;
+    let tensors = match read_tensor_infos(&mut reader, metadata.tensor_count).map_err(truncated) {
+      Ok(tensors) => Some(tensors),
+      Err(GgufError::Truncated) => {
+        reader.seek(SeekFrom::Start(tensors_start))?;
+        None
+      }
+      Err(err) => return Err(err),
+    };
+    Ok(Self {
+      reader,
+      metadata,
+      tensors,
+    })
+  }
+
+  pub fn metadata(&self) -> &GgufMetadata {
+    &self.metadata
+  }
+
+  pub fn into_metadata(self) -> GgufMetadata {
+    self.metadata
+  }
+
+  // None when the tensor infos are cut off in a partial file
+  pub fn tensors(&self) -> Option<&[TensorInfo]> {
+    self.tensors.as_deref()
+  }
+
+  // sum of the tensor data sizes, roughly the memory needed to load the model weights,
+  // tensors of a type without a known layout are not counted
+  pub fn estimated_size_bytes(&self) -> Option<u64> {
+    let tensors = self.tensors.as_ref()?;
+    Some(tensors.iter().filter_map(TensorInfo::size_bytes).sum())
+  }
+
+  // the reader is positioned right after the tensor infos, before the alignment padding, or
+  // right after the metadata when the tensor infos are cut off
+  pub fn into_inner(self) -> R {
+    self.reader
+  }
+}
+
+fn truncated(err: GgufError) -> GgufError {
+  match err {
+    GgufError::Io(err) if err.kind() == ErrorKind::UnexpectedEof => GgufError::Truncated,
+    err => err,
+  }
+}
+
+fn read_tensor_infos<R: Read>(reader: &mut R, tensor_count: u64) -> Result<Vec<TensorInfo>> {
+  let mut tensors = Vec::with_capacity(tensor_count.min(u16::MAX as u64) as usize);
+  for _ in 0..tensor_count {
+    tensors.push(read_tensor_info(reader)?);
+  }
+  Ok(tensors)
+}
+
+fn read_metadata<R: Read>(reader: &mut R) -> Result<GgufMetadata> {
+  let magic = read_u32(reader)?;
+  if magic != GGUF_MAGIC {
+    return Err(GgufError::InvalidMagic(magic));
+  }
+  let version = read_u32(reader)?;
+  if !GGUF_SUPPORTED_VERSIONS.contains(&version) {
+    return Err(GgufError::UnsupportedVersion(version));
+  }
+  let tensor_count = read_u64(reader)?;
+  let kv_count = read_u64(reader)?;
+  let mut kv = BTreeMap::new();
+  for _ in 0..kv_count {
+    let key = read_string(reader)?;
+    let value_type = read_u32(reader)?;
+    let value = read_value(reader, value_type)?;
+    kv.insert(key, value);
+  }
+  Ok(GgufMetadata {
+    version,
+    tensor_count,
+    kv,
+  })
+}
+
+fn read_tensor_info<R: Read>(reader: &mut R) -> Result<TensorInfo> {
+  let name = read_string(reader)?;
+  let n_dimensions = read_u32(reader)?;
+  let mut dimensions = Vec::with_capacity(n_dimensions.min(8) as usize);
+  for _ in 0..n_dimensions {
+    dimensions.push(read_u64(reader)?);
+  }
+  let ggml_type = read_u32(reader)?.into();
+  let offset = read_u64(reader)?;
+  Ok(TensorInfo {
+    name,
+    dimensions,
+    ggml_type,
+    offset,
+  })
+}
+
+fn read_value<R: Read>(reader: &mut R, value_type: u32) -> Result<GgufValue> {
+  let value = match value_type {
+    0 => GgufValue::U8(u8::from_le_bytes(read_bytes(reader)?)),
+    1 => GgufValue::I8(i8::from_le_bytes(read_bytes(reader)?)),
+    2 => GgufValue::U16(u16::from_le_bytes(read_bytes(reader)?)),
+    3 => GgufValue::I16(i16::from_le_bytes(read_bytes(reader)?)),
+    4 => GgufValue::U32(read_u32(reader)?),
+    5 => GgufValue::I32(i32::from_le_bytes(read_bytes(reader)?)),
+    6 => GgufValue::F32(f32::from_le_bytes(read_bytes(reader)?)),
+    7 => GgufValue::Bool(u8::from_le_bytes(read_bytes(reader)?) != 0),
+    8 => GgufValue::String(read_string(reader)?),
+    9 => {
+      let item_type = read_u32(reader)?;
+      let len = read_u64(reader)?;
+      // do not trust the length for pre-allocation, a corrupt file can claim any size
+      let mut items = Vec::with_capacity(len.min(u16::MAX as u64) as usize);
+      for _ in 0..len {
+        items.push(read_value(reader, item_type)?);
+      }
+      GgufValue::Array(items)
+    }
+    10 => GgufValue::U64(read_u64(reader)?),
+    11 => GgufValue::I64(i64::from_le_bytes(read_bytes(reader)?)),
+    12 => GgufValue::F64(f64::from_le_bytes(read_bytes(reader)?)),
+    unknown => return Err(GgufError::InvalidValueType(unknown)),
+  };
+  Ok(value)
+}
+
+fn read_bytes<R: Read, const N: usize>(reader: &mut R) -> Result<[u8; N]> {
+  let mut buf = [0u8; N];
+  reader.read_exact(&mut buf)?;
+  Ok(buf)
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32> {
+  Ok(u32::from_le_bytes(read_bytes(reader)?))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64> {
+  Ok(u64::from_le_bytes(read_bytes(reader)?))
+}
+
+fn read_string<R: Read>(reader: &mut R) -> Result<String> {
+  let len = read_u64(reader)?;
+  let mut buf = Vec::new();
+  reader.take(len).read_to_end(&mut buf)?;
+  if buf.len() as u64 != len {
+    return Err(std::io::Error::from(ErrorKind::UnexpectedEof).into());
+  }
+  String::from_utf8(buf).map_err(|_| GgufError::InvalidString)
+}
+
+#[cfg(test)]
+mod test {
+  use super::{read_metadata, GgufReader};
+  use crate::{
+    gguf::{GgmlType, GgufError, GgufMetadata, GgufValue, TensorInfo},
+    test_utils::GgufBytes,
+  };
+  use rstest::rstest;
+  use std::{
+    fs::File,
+    io::{Cursor, Read},
+    path::Path,
+  };
+
+  fn model_file_prefix(len: u64) -> anyhow::Result<Cursor<Vec<u8>>> {
+    let mut buf = Vec::new();
+    File::open("tests/data/tinyllama-15m-q8_0.gguf")?
+      .take(len)
+      .read_to_end(&mut buf)?;
+    Ok(Cursor::new(buf))
+  }
+
+  #[rstest]
+  fn test_gguf_reader_from_partial_file() -> anyhow::Result<()> {
+    // metadata of the test model ends at 723569 bytes, the tensor infos and data are not needed
+    let reader = GgufReader::from_reader(model_file_prefix(723569)?)?;
+    assert_eq!(Some("llama"), reader.metadata().architecture());
+    assert_eq!(Some(256), reader.metadata().context_length());
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_gguf_reader_fails_on_truncated_metadata() -> anyhow::Result<()> {
+    let result = GgufReader::from_reader(model_file_prefix(1024)?);
+    assert!(matches!(result, Err(GgufError::Truncated)));
+    Ok(())
+  }
+
+  // tensor infos of the test model end at 726903 bytes
+  #[rstest]
+  #[case(723569, None)]
+  #[case(726903 - 1, None)]
+  #[case(726903, Some(57))]
+  fn test_gguf_reader_tensors_from_partial_file(
+    #[case] len: u64,
+    #[case] expected: Option<usize>,
+  ) -> anyhow::Result<()> {
+    let reader = GgufReader::from_reader(model_file_prefix(len)?)?;
+    assert_eq!(expected, reader.tensors().map(<[_]>::len));
+    assert_eq!(expected.is_some(), reader.estimated_size_bytes().is_some());
+    assert_eq!(Some("llama"), reader.metadata().architecture());
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_gguf_reader_tensors_from_model_file() -> anyhow::Result<()> {
+    let reader = GgufReader::from_reader(File::open("tests/data/tinyllama-15m-q8_0.gguf")?)?;
+    let tensors = reader.tensors().unwrap();
+    assert_eq!(57, tensors.len());
+    assert_eq!(
+      TensorInfo {
+        name: "token_embd.weight".to_string(),
+        dimensions: vec![288, 32000],
+        ggml_type: GgmlType::Q8_0,
+        offset: 0,
+      },
+      tensors[0]
+    );
+    // the first tensor size lines up with the offset of the second one
+    assert_eq!(tensors[0].size_bytes(), Some(tensors[1].offset));
+    let q8_0 = tensors
+      .iter()
+      .filter(|tensor| tensor.ggml_type == GgmlType::Q8_0)
+      .count();
+    let f32 = tensors
+      .iter()
+      .filter(|tensor| tensor.ggml_type == GgmlType::F32)
+      .count();
+    assert_eq!((44, 13), (q8_0, f32));
+    let last = tensors.last().unwrap();
+    assert_eq!(
+      Some(last.offset + last.size_bytes().unwrap()),
+      reader.estimated_size_bytes()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_gguf_reader_tensors_with_unknown_type() -> anyhow::Result<()> {
+    let bytes = GgufBytes::default()
+      .tensor("output.weight", &[288, 32000], 14, 0)
+      .tensor("future.weight", &[288], 1000, 7680000)
+      .build();
+    let reader = GgufReader::from_reader(Cursor::new(bytes))?;
+    let tensors = reader.tensors().unwrap();
+    assert_eq!(GgmlType::Q6K, tensors[0].ggml_type);
+    assert_eq!(GgmlType::Unknown(1000), tensors[1].ggml_type);
+    assert_eq!(None, tensors[1].size_bytes());
+    assert_eq!(Some(288 * 32000 / 256 * 210), reader.estimated_size_bytes());
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_gguf_reader_fails_on_invalid_magic() -> anyhow::Result<()> {
+    let result = GgufReader::from_reader(Cursor::new(b"this is not a gguf file".to_vec()));
+    assert_eq!(
+      "gguf_invalid_magic: not a GGUF file, found magic bytes 0x73696874",
+      result.unwrap_err().to_string()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_gguf_read_metadata_from_model_file() -> anyhow::Result<()> {
+    let metadata = GgufMetadata::from_file(Path::new("tests/data/tinyllama-15m-q8_0.gguf"))?;
+    assert_eq!(3, metadata.version);
+    assert_eq!(57, metadata.tensor_count);
+    assert_eq!(15, metadata.kv.len());
+    assert_eq!(Some("llama"), metadata.architecture());
+    assert_eq!(Some(256), metadata.context_length());
+    assert_eq!(None, metadata.chat_template());
+    assert_eq!(None, metadata.rope_freq_base());
+    let Some(GgufValue::Array(tokens)) = metadata.get("tokenizer.ggml.tokens") else {
+      panic!("tokenizer.ggml.tokens should be an array");
+    };
+    assert_eq!(32000, tokens.len());
+    assert_eq!(Some("<s>"), tokens[1].as_str());
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_gguf_read_metadata_chat_template_and_rope() -> anyhow::Result<()> {
+    let bytes = GgufBytes::default()
+      .kv(
+        "general.architecture",
+        GgufValue::String("llama".to_string()),
+      )
+      .kv(
+        "tokenizer.chat_template",
+        GgufValue::String("{{ bos_token }}".to_string()),
+      )
+      .kv("llama.context_length", GgufValue::U64(8192))
+      .kv("llama.rope.freq_base", GgufValue::F32(500000.0))
+      .kv(
+        "llama.rope.scaling.type",
+        GgufValue::String("yarn".to_string()),
+      )
+      .build();
+    let metadata = read_metadata(&mut bytes.as_slice())?;
+    assert_eq!(
+      Some("{{ bos_token }}".to_string()),
+      metadata.chat_template()
+    );
+    assert_eq!(Some(8192), metadata.context_length());
+    assert_eq!(Some(500000.0), metadata.rope_freq_base());
+    assert_eq!(Some("yarn".to_string()), metadata.rope_scaling_type());
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_gguf_read_metadata_fails_on_invalid_magic() -> anyhow::Result<()> {
+    let result = GgufMetadata::from_file(Path::new(
+      "tests/data/huggingface/hub/models--MyFactory--testalias-gguf/snapshots/5007652f7a641fe7170e0bad4f63839419bd9213/testalias.Q8_0.gguf",
+    ));
+    assert!(matches!(result, Err(GgufError::InvalidMagic(_))));
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_gguf_read_metadata_fails_on_unsupported_version() -> anyhow::Result<()> {
+    let bytes = GgufBytes::default().version(1).build();
+    let result = read_metadata(&mut bytes.as_slice());
+    assert!(matches!(result, Err(GgufError::UnsupportedVersion(1))));
+    Ok(())
+  }
+}
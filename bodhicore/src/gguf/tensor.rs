@@ -0,0 +1,194 @@
+use std::fmt;
+
+// tensor types from ggml.h, the type codes are stable across ggml versions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GgmlType {
+  F32,
+  F16,
+  Q4_0,
+  Q4_1,
+  Q5_0,
+  Q5_1,
+  Q8_0,
+  Q8_1,
+  Q2K,
+  Q3K,
+  Q4K,
+  Q5K,
+  Q6K,
+  Q8K,
+  IQ2XXS,
+  IQ2XS,
+  IQ3XXS,
+  IQ1S,
+  IQ4NL,
+  IQ3S,
+  IQ2S,
+  IQ4XS,
+  I8,
+  I16,
+  I32,
+  I64,
+  F64,
+  IQ1M,
+  BF16,
+  Unknown(u32),
+}
+
+impl From<u32> for GgmlType {
+  fn from(value: u32) -> Self {
+    match value {
+      0 => GgmlType::F32,
+      1 => GgmlType::F16,
+      2 => GgmlType::Q4_0,
+      3 => GgmlType::Q4_1,
+      6 => GgmlType::Q5_0,
+      7 => GgmlType::Q5_1,
+      8 => GgmlType::Q8_0,
+      9 => GgmlType::Q8_1,
+      10 => GgmlType::Q2K,
+      11 => GgmlType::Q3K,
+      12 => GgmlType::Q4K,
+      13 => GgmlType::Q5K,
+      14 => GgmlType::Q6K,
+      15 => GgmlType::Q8K,
+      16 => GgmlType::IQ2XXS,
+      17 => GgmlType::IQ2XS,
+      18 => GgmlType::IQ3XXS,
+      19 => GgmlType::IQ1S,
+      20 => GgmlType::IQ4NL,
+      21 => GgmlType::IQ3S,
+      22 => GgmlType::IQ2S,
+      23 => GgmlType::IQ4XS,
+      24 => GgmlType::I8,
+      25 => GgmlType::I16,
+      26 => GgmlType::I32,
+      27 => GgmlType::I64,
+      28 => GgmlType::F64,
+      29 => GgmlType::IQ1M,
+      30 => GgmlType::BF16,
+      unknown => GgmlType::Unknown(unknown),
+    }
+  }
+}
+
+impl GgmlType {
+  // (number of elements in a block, size of the block in bytes)
+  fn block_layout(&self) -> Option<(u64, u64)> {
+    let layout = match self {
+      GgmlType::F32 => (1, 4),
+      GgmlType::F16 => (1, 2),
+      GgmlType::Q4_0 => (32, 18),
+      GgmlType::Q4_1 => (32, 20),
+      GgmlType::Q5_0 => (32, 22),
+      GgmlType::Q5_1 => (32, 24),
+      GgmlType::Q8_0 => (32, 34),
+      GgmlType::Q8_1 => (32, 36),
+      GgmlType::Q2K => (256, 84),
+      GgmlType::Q3K => (256, 110),
+      GgmlType::Q4K => (256, 144),
+      GgmlType::Q5K => (256, 176),
+      GgmlType::Q6K => (256, 210),
+      GgmlType::Q8K => (256, 292),
+      GgmlType::IQ2XXS => (256, 66),
+      GgmlType::IQ2XS => (256, 74),
+      GgmlType::IQ3XXS => (256, 98),
+      GgmlType::IQ1S => (256, 50),
+      GgmlType::IQ4NL => (32, 18),
+      GgmlType::IQ3S => (256, 110),
+      GgmlType::IQ2S => (256, 82),
+      GgmlType::IQ4XS => (256, 136),
+      GgmlType::I8 => (1, 1),
+      GgmlType::I16 => (1, 2),
+      GgmlType::I32 => (1, 4),
+      GgmlType::I64 => (1, 8),
+      GgmlType::F64 => (1, 8),
+      GgmlType::IQ1M => (256, 56),
+      GgmlType::BF16 => (1, 2),
+      GgmlType::Unknown(_) => return None,
+    };
+    Some(layout)
+  }
+}
+
+impl fmt::Display for GgmlType {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      GgmlType::Q2K => write!(f, "Q2_K"),
+      GgmlType::Q3K => write!(f, "Q3_K"),
+      GgmlType::Q4K => write!(f, "Q4_K"),
+      GgmlType::Q5K => write!(f, "Q5_K"),
+      GgmlType::Q6K => write!(f, "Q6_K"),
+      GgmlType::Q8K => write!(f, "Q8_K"),
+      GgmlType::IQ2XXS => write!(f, "IQ2_XXS"),
+      GgmlType::IQ2XS => write!(f, "IQ2_XS"),
+      GgmlType::IQ3XXS => write!(f, "IQ3_XXS"),
+      GgmlType::IQ1S => write!(f, "IQ1_S"),
+      GgmlType::IQ4NL => write!(f, "IQ4_NL"),
+      GgmlType::IQ3S => write!(f, "IQ3_S"),
+      GgmlType::IQ2S => write!(f, "IQ2_S"),
+      GgmlType::IQ4XS => write!(f, "IQ4_XS"),
+      GgmlType::IQ1M => write!(f, "IQ1_M"),
+      GgmlType::Unknown(code) => write!(f, "UNKNOWN({code})"),
+      other => write!(f, "{other:?}"),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TensorInfo {
+  pub name: String,
+  pub dimensions: Vec<u64>,
+  pub ggml_type: GgmlType,
+  // offset of the tensor data, relative to the start of the tensor data section
+  pub offset: u64,
+}
+
+impl TensorInfo {
+  pub fn n_elements(&self) -> u64 {
+    self.dimensions.iter().product()
+  }
+
+  // None for the tensor types this version does not know the layout of
+  pub fn size_bytes(&self) -> Option<u64> {
+    let (block_size, block_bytes) = self.ggml_type.block_layout()?;
+    Some(self.n_elements().div_ceil(block_size) * block_bytes)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{GgmlType, TensorInfo};
+  use rstest::rstest;
+
+  #[rstest]
+  #[case(0, GgmlType::F32, "F32")]
+  #[case(8, GgmlType::Q8_0, "Q8_0")]
+  #[case(12, GgmlType::Q4K, "Q4_K")]
+  #[case(30, GgmlType::BF16, "BF16")]
+  #[case(99, GgmlType::Unknown(99), "UNKNOWN(99)")]
+  fn test_ggml_type_from_code(
+    #[case] code: u32,
+    #[case] expected: GgmlType,
+    #[case] display: &str,
+  ) {
+    let ggml_type = GgmlType::from(code);
+    assert_eq!(expected, ggml_type);
+    assert_eq!(display, ggml_type.to_string());
+  }
+
+  #[rstest]
+  #[case(GgmlType::F16, Some(2 * 288 * 288))]
+  #[case(GgmlType::Q8_0, Some(288 * 288 / 32 * 34))]
+  #[case(GgmlType::Q4K, Some(324 * 144))]
+  #[case(GgmlType::Unknown(99), None)]
+  fn test_tensor_info_size_bytes(#[case] ggml_type: GgmlType, #[case] expected: Option<u64>) {
+    let tensor = TensorInfo {
+      name: "blk.0.attn_q.weight".to_string(),
+      dimensions: vec![288, 288],
+      ggml_type,
+      offset: 0,
+    };
+    assert_eq!(expected, tensor.size_bytes());
+  }
+}
@@ -3,7 +3,7 @@ use crate::{
   error::{BodhiError, Common},
   objs::{Alias, ObjError},
   server::{RouterState, RouterStateFn},
-  service::{AppServiceFn, HubServiceError},
+  service::{find_model_file, AppServiceFn, HubServiceError},
   SharedContextRw,
 };
 use async_openai::types::{
@@ -46,31 +46,33 @@ pub struct Interactive {
 impl Interactive {
   pub async fn execute(self, service: Arc<dyn AppServiceFn>) -> crate::error::Result<()> {
     let alias = self.alias.clone();
-    let model = service
-      .hub_service()
-      .find_local_file(&alias.repo, &alias.filename, &alias.snapshot)?
-      .ok_or_else(|| {
-        let filepath = &service
-          .hub_service()
-          .model_file_path(&alias.repo, &alias.filename, &alias.snapshot)
-          .display()
-          .to_string();
-        let (dirname, filename) = match filepath.rsplit_once('/') {
-          Some((dir, file)) => (dir.to_string(), file.to_string()),
-          None => ("".to_string(), filepath.to_string()),
-        };
-        let relative_dir = dirname
-          .strip_prefix(&service.env_service().hf_home().display().to_string())
-          .unwrap_or(&dirname)
-          .to_string();
-        HubServiceError::FileMissing {
-          filename,
-          dirname: relative_dir,
-        }
-      })?;
+    if let Some(model_file) = alias.model_file.as_ref().filter(|path| !path.is_file()) {
+      return Err(BodhiError::ModelFileMissing(
+        model_file.display().to_string(),
+      ));
+    }
+    let model = find_model_file(service.hub_service().as_ref(), &alias)?.ok_or_else(|| {
+      let filepath = &service
+        .hub_service()
+        .model_file_path(&alias.repo, &alias.filename, &alias.snapshot)
+        .display()
+        .to_string();
+      let (dirname, filename) = match filepath.rsplit_once('/') {
+        Some((dir, file)) => (dir.to_string(), file.to_string()),
+        None => ("".to_string(), filepath.to_string()),
+      };
+      let relative_dir = dirname
+        .strip_prefix(&service.env_service().hf_home().display().to_string())
+        .unwrap_or(&dirname)
+        .to_string();
+      HubServiceError::FileMissing {
+        filename,
+        dirname: relative_dir,
+      }
+    })?;
     let pb = infinite_loading(String::from("Loading..."));
     let mut gpt_params = GptParamsBuilder::default()
-      .model(model.path().display().to_string())
+      .model(model.display().to_string())
       .build()
       .map_err(ObjError::from)?;
     alias.context_params.update(&mut gpt_params);
@@ -171,7 +173,7 @@ impl Interactive {
         ));
         Ok(())
       });
-    let result = router_state.chat_completions(request, tx).await;
+    let result = router_state.chat_completions(request.into(), tx).await;
     (handle.await.map_err(|err| Common::Stdlib(Arc::new(err)))?)?;
     match result {
       Ok(()) => {}
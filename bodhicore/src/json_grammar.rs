@@ -0,0 +1,266 @@
+use serde_json::{Map, Value};
+
+// the json grammar of llama.cpp, the rules generated for a schema are built on top of these
+static JSON_RULES: &str = r#"value ::= object | array | string | number | ("true" | "false" | "null") ws
+object ::= "{" ws ( string ":" ws value ( "," ws string ":" ws value )* )? "}" ws
+array ::= "[" ws ( value ( "," ws value )* )? "]" ws
+string ::= "\"" ( [^"\\\x7F\x00-\x1F] | "\\" (["\\bfnrt] | "u" [0-9a-fA-F] [0-9a-fA-F] [0-9a-fA-F] [0-9a-fA-F]) )* "\"" ws
+number ::= ("-"? ([0-9] | [1-9] [0-9]*)) ("." [0-9]+)? ([eE] [-+]? [0-9]+)? ws
+integer ::= ("-"? ([0-9] | [1-9] [0-9]*)) ws
+boolean ::= ("true" | "false") ws
+null ::= "null" ws
+ws ::= ([ \t\n] ws)?
+"#;
+
+// the keywords the grammar cannot enforce, a schema using them falls back to asking the model
+// for the json and checking that it parses
+static UNSUPPORTED_KEYWORDS: &[&str] = &[
+  "$ref",
+  "allOf",
+  "not",
+  "if",
+  "prefixItems",
+  "pattern",
+  "patternProperties",
+  "minLength",
+  "maxLength",
+  "minItems",
+  "maxItems",
+  "minimum",
+  "maximum",
+  "exclusiveMinimum",
+  "exclusiveMaximum",
+  "multipleOf",
+];
+
+// any json object, for `response_format: json_object`
+pub(crate) fn json_object_grammar() -> String {
+  format!("root ::= object\n{JSON_RULES}")
+}
+
+// the grammar of the json matching the schema, the error has the part of the schema that is not
+// supported
+pub(crate) fn schema_grammar(schema: &Value) -> Result<String, String> {
+  let mut converter = Converter::default();
+  let root = converter.expression(schema, "root")?;
+  if root != "root" {
+    converter.rules.insert(0, ("root".to_string(), root));
+  }
+  let mut grammar = String::new();
+  for (name, rule) in converter.rules {
+    grammar.push_str(&format!("{name} ::= {rule}\n"));
+  }
+  grammar.push_str(JSON_RULES);
+  Ok(grammar)
+}
+
+#[derive(Debug, Default)]
+struct Converter {
+  rules: Vec<(String, String)>,
+}
+
+impl Converter {
+  // the grammar expression matching the schema, the objects and arrays get rules of their own
+  fn expression(&mut self, schema: &Value, name: &str) -> Result<String, String> {
+    let schema = match schema {
+      Value::Object(schema) => schema,
+      Value::Bool(true) => return Ok("value".to_string()),
+      other => return Err(format!("schema {other} is not supported")),
+    };
+    if let Some(keyword) = UNSUPPORTED_KEYWORDS
+      .iter()
+      .find(|keyword| schema.contains_key(**keyword))
+    {
+      return Err(format!("'{keyword}' is not supported"));
+    }
+    if let Some(value) = schema.get("const") {
+      return Ok(literal(value));
+    }
+    if let Some(values) = schema.get("enum") {
+      let values = values
+        .as_array()
+        .ok_or_else(|| "'enum' should be a list".to_string())?;
+      return Ok(alternatives(values.iter().map(literal).collect()));
+    }
+    if let Some(schemas) = schema.get("anyOf").or_else(|| schema.get("oneOf")) {
+      let schemas = schemas
+        .as_array()
+        .ok_or_else(|| "'anyOf' should be a list".to_string())?;
+      let expressions = schemas
+        .iter()
+        .enumerate()
+        .map(|(i, schema)| self.expression(schema, &format!("{name}-{i}")))
+        .collect::<Result<Vec<_>, _>>()?;
+      return Ok(alternatives(expressions));
+    }
+    match schema.get("type") {
+      Some(Value::Array(types)) => {
+        let expressions = types
+          .iter()
+          .enumerate()
+          .map(|(i, r#type)| {
+            let mut schema = schema.clone();
+            schema.insert("type".to_string(), r#type.clone());
+            self.expression(&Value::Object(schema), &format!("{name}-{i}"))
+          })
+          .collect::<Result<Vec<_>, _>>()?;
+        Ok(alternatives(expressions))
+      }
+      Some(Value::String(r#type)) => match r#type.as_str() {
+        "object" => self.object(schema, name),
+        "array" => self.array(schema, name),
+        "string" | "number" | "integer" | "boolean" | "null" => Ok(r#type.clone()),
+        other => Err(format!("type '{other}' is not supported")),
+      },
+      None if schema.contains_key("properties") => self.object(schema, name),
+      None => Ok("value".to_string()),
+      Some(other) => Err(format!("type {other} is not supported")),
+    }
+  }
+
+  // the required properties come first, followed by the optional ones, as an optional property
+  // cannot start the object without the comma rules getting ambiguous
+  fn object(&mut self, schema: &Map<String, Value>, name: &str) -> Result<String, String> {
+    let Some(properties) = schema
+      .get("properties")
+      .and_then(Value::as_object)
+      .filter(|properties| !properties.is_empty())
+    else {
+      return Ok("object".to_string());
+    };
+    let required = schema
+      .get("required")
+      .and_then(Value::as_array)
+      .map(|required| {
+        required
+          .iter()
+          .filter_map(Value::as_str)
+          .collect::<Vec<_>>()
+      })
+      .unwrap_or_default();
+    let (required, optional): (Vec<_>, Vec<_>) = properties
+      .iter()
+      .partition(|(key, _)| required.contains(&key.as_str()));
+    if required.is_empty() {
+      return Err(format!(
+        "object '{name}' without required properties is not supported"
+      ));
+    }
+    let mut members = vec![];
+    for (i, (key, property)) in required.into_iter().enumerate() {
+      let value = self.expression(property, &format!("{name}-{}", rule_name(key)))?;
+      let separator = if i == 0 { "" } else { "\",\" ws " };
+      members.push(format!(
+        "{separator}{} \":\" ws {value}",
+        literal(&json_key(key))
+      ));
+    }
+    for (key, property) in optional {
+      let value = self.expression(property, &format!("{name}-{}", rule_name(key)))?;
+      members.push(format!(
+        "( \",\" ws {} \":\" ws {value} )?",
+        literal(&json_key(key))
+      ));
+    }
+    Ok(self.add_rule(name, format!("\"{{\" ws {} \"}}\" ws", members.join(" "))))
+  }
+
+  fn array(&mut self, schema: &Map<String, Value>, name: &str) -> Result<String, String> {
+    let Some(items) = schema.get("items") else {
+      return Ok("array".to_string());
+    };
+    let item = self.expression(items, &format!("{name}-item"))?;
+    Ok(self.add_rule(
+      name,
+      format!("\"[\" ws ( {item} ( \",\" ws {item} )* )? \"]\" ws"),
+    ))
+  }
+
+  fn add_rule(&mut self, name: &str, rule: String) -> String {
+    let mut unique = name.to_string();
+    let mut suffix = 1;
+    while self.rules.iter().any(|(existing, _)| existing == &unique) {
+      suffix += 1;
+      unique = format!("{name}{suffix}");
+    }
+    self.rules.push((unique.clone(), rule));
+    unique
+  }
+}
+
+fn json_key(key: &str) -> Value {
+  Value::String(key.to_string())
+}
+
+// the json of the value as a grammar string literal
+fn literal(value: &Value) -> String {
+  let json = value.to_string().replace('\\', "\\\\").replace('"', "\\\"");
+  format!("\"{json}\" ws")
+}
+
+fn alternatives(expressions: Vec<String>) -> String {
+  format!("( {} )", expressions.join(" | "))
+}
+
+// the rule names of the grammar only allow letters, digits and dashes
+fn rule_name(key: &str) -> String {
+  key
+    .chars()
+    .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+  use super::{json_object_grammar, schema_grammar};
+  use rstest::rstest;
+  use serde_json::{json, Value};
+
+  #[rstest]
+  fn test_json_object_grammar_starts_at_object() {
+    assert!(json_object_grammar().starts_with("root ::= object\nvalue ::= "));
+  }
+
+  #[rstest]
+  fn test_schema_grammar_for_object() -> anyhow::Result<()> {
+    let schema = json! {{
+      "type": "object",
+      "properties": {
+        "city": {"type": "string"},
+        "unit": {"enum": ["celsius", "fahrenheit"]},
+        "days": {"type": "array", "items": {"type": "integer"}},
+      },
+      "required": ["city", "days"],
+    }};
+    let grammar = schema_grammar(&schema).map_err(anyhow::Error::msg)?;
+    let rules = grammar.lines().take(2).collect::<Vec<_>>();
+    assert_eq!(
+      vec![
+        r#"root-days ::= "[" ws ( integer ( "," ws integer )* )? "]" ws"#,
+        r#"root ::= "{" ws "\"city\"" ws ":" ws string "," ws "\"days\"" ws ":" ws root-days ( "," ws "\"unit\"" ws ":" ws ( "\"celsius\"" ws | "\"fahrenheit\"" ws ) )? "}" ws"#,
+      ],
+      rules
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(json! {{"type": "string"}}, "root ::= string")]
+  #[case(json! {{"type": ["string", "null"]}}, "root ::= ( string | null )")]
+  #[case(json! {{"anyOf": [{"type": "number"}, {"const": "none"}]}}, r#"root ::= ( number | "\"none\"" ws )"#)]
+  fn test_schema_grammar_root_rule(#[case] schema: Value, #[case] expected: &str) {
+    let grammar = schema_grammar(&schema).unwrap();
+    assert_eq!(expected, grammar.lines().next().unwrap());
+  }
+
+  #[rstest]
+  #[case(json! {{"$ref": "#/$defs/city"}}, "'$ref' is not supported")]
+  #[case(json! {{"type": "string", "pattern": "^[A-Z]"}}, "'pattern' is not supported")]
+  #[case(
+    json! {{"type": "object", "properties": {"city": {"type": "string"}}}},
+    "object 'root' without required properties is not supported"
+  )]
+  fn test_schema_grammar_unsupported(#[case] schema: Value, #[case] expected: &str) {
+    assert_eq!(Err(expected.to_string()), schema_grammar(&schema));
+  }
+}
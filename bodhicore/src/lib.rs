@@ -2,15 +2,19 @@ pub mod bindings;
 pub mod cli;
 pub mod db;
 mod error;
+pub mod gguf;
 pub mod interactive;
+mod json_grammar;
 mod oai;
 pub mod objs;
 pub mod server;
 pub mod service;
 mod shared_rw;
+mod slot_affinity;
 #[cfg(test)]
 mod test_utils;
 mod tokenizer_config;
+mod tool_calls;
 mod utils;
 
 // TODO: remove exposing of cli methods, rename cli to command package
@@ -1,8 +1,27 @@
-use crate::shared_rw::ContextError;
-use axum::{http::StatusCode, response::IntoResponse, Json};
+use crate::{
+  json_grammar::{json_object_grammar, schema_grammar},
+  shared_rw::ContextError,
+  utils::human_size,
+};
+use async_openai::types::{CreateChatCompletionRequest, Stop};
+use axum::{
+  http::{header::RETRY_AFTER, StatusCode},
+  response::IntoResponse,
+  Json,
+};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
 use thiserror::Error;
 
+pub static INFERENCE_PAUSED_RETRY_AFTER_SECS: &str = "60";
+pub static QUEUE_FULL_RETRY_AFTER_SECS: &str = "5";
+pub static METADATA_MAX_KEYS: usize = 16;
+pub static METADATA_MAX_KEY_LEN: usize = 64;
+pub static METADATA_MAX_VALUE_LEN: usize = 512;
+pub static STOP_MAX_SEQUENCES: usize = 4;
+pub static LOGIT_BIAS_MAX: f64 = 100.0;
+
 #[derive(Debug, Error)]
 pub enum OpenAIApiError {
   #[error("{0}")]
@@ -11,6 +30,25 @@ pub enum OpenAIApiError {
   InternalServer(String),
   #[error(transparent)]
   ContextError(#[from] ContextError),
+  #[error("inference is paused for maintenance")]
+  InferencePaused,
+  #[error("too many requests queued for model '{0}'")]
+  QueueFull(String),
+  #[error("{0}")]
+  BadRequest(String),
+  #[error("{0}")]
+  Conflict(String),
+  #[error(
+    "insufficient memory to load model '{model}': needs {}, {} available, {suggestion}",
+    human_size(*.required),
+    human_size(*.available)
+  )]
+  InsufficientMemory {
+    model: String,
+    required: u64,
+    available: u64,
+    suggestion: String,
+  },
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
@@ -42,6 +80,36 @@ impl From<&OpenAIApiError> for ApiError {
       },
       OpenAIApiError::ContextError(err) => ApiError::internal_server(err.to_string()),
       OpenAIApiError::InternalServer(err) => ApiError::internal_server(err.to_string()),
+      OpenAIApiError::InferencePaused => ApiError {
+        message: "Inference is paused for maintenance, retry after some time".to_string(),
+        r#type: "service_unavailable".to_string(),
+        param: None,
+        code: "inference_paused".to_string(),
+      },
+      OpenAIApiError::QueueFull(model) => ApiError {
+        message: format!("Too many requests queued for model '{model}', retry after some time"),
+        r#type: "service_unavailable".to_string(),
+        param: None,
+        code: "queue_full".to_string(),
+      },
+      OpenAIApiError::BadRequest(message) => ApiError {
+        message: message.clone(),
+        r#type: "invalid_request_error".to_string(),
+        param: None,
+        code: "invalid_request_error".to_string(),
+      },
+      OpenAIApiError::Conflict(message) => ApiError {
+        message: message.clone(),
+        r#type: "invalid_request_error".to_string(),
+        param: None,
+        code: "conflict".to_string(),
+      },
+      OpenAIApiError::InsufficientMemory { .. } => ApiError {
+        message: value.to_string(),
+        r#type: "service_unavailable".to_string(),
+        param: None,
+        code: "insufficient_memory".to_string(),
+      },
     }
   }
 }
@@ -53,14 +121,531 @@ impl From<&OpenAIApiError> for StatusCode {
       OpenAIApiError::ContextError(_) | OpenAIApiError::InternalServer(_) => {
         StatusCode::INTERNAL_SERVER_ERROR
       }
+      OpenAIApiError::InferencePaused
+      | OpenAIApiError::QueueFull(_)
+      | OpenAIApiError::InsufficientMemory { .. } => StatusCode::SERVICE_UNAVAILABLE,
+      OpenAIApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+      OpenAIApiError::Conflict(_) => StatusCode::CONFLICT,
     }
   }
 }
 
 impl IntoResponse for OpenAIApiError {
   fn into_response(self) -> axum::response::Response {
-    (StatusCode::from(&self), Json(ApiError::from(&self))).into_response()
+    let status = StatusCode::from(&self);
+    let body = Json(ApiError::from(&self));
+    match self {
+      OpenAIApiError::InferencePaused => (
+        status,
+        [(RETRY_AFTER, INFERENCE_PAUSED_RETRY_AFTER_SECS)],
+        body,
+      )
+        .into_response(),
+      OpenAIApiError::QueueFull(_) => {
+        (status, [(RETRY_AFTER, QUEUE_FULL_RETRY_AFTER_SECS)], body).into_response()
+      }
+      _ => (status, body).into_response(),
+    }
   }
 }
 
 pub type Result<T> = std::result::Result<T, OpenAIApiError>;
+
+// OpenAI chat completion request, along with the fields bodhi supports on top of the spec
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BodhiChatRequest {
+  #[serde(flatten)]
+  pub request: CreateChatCompletionRequest,
+  // requests with the same key are scheduled on the same llama.cpp slot to reuse its prompt cache
+  #[serde(default, alias = "cache_key", skip_serializing_if = "Option::is_none")]
+  pub prompt_cache_key: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub stream_options: Option<StreamOptions>,
+  // taken here instead of the request, to also accept the `json_schema` format
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub response_format: Option<ResponseFormat>,
+  #[serde(flatten)]
+  pub sampling_params: SamplingParams,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub profile: Option<SamplingProfile>,
+  // overrides the keep alive of the alias for the model loaded by this request
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub keep_alive_secs: Option<u64>,
+  // client tags like a trace or user id, logged with the request and never sent to llama.cpp
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub metadata: Option<BTreeMap<String, String>>,
+  // raw prompt of a legacy text completion, sent to llama.cpp without applying the chat template
+  #[serde(skip)]
+  pub prompt: Option<String>,
+}
+
+// the top level fields of the request, along with the ones bodhi supports on top of the spec,
+// when strict, a request with any other field is rejected instead of ignoring the field
+pub static CHAT_REQUEST_FIELDS: &[&str] = &[
+  "messages",
+  "model",
+  "frequency_penalty",
+  "logit_bias",
+  "logprobs",
+  "top_logprobs",
+  "max_tokens",
+  "n",
+  "presence_penalty",
+  "response_format",
+  "seed",
+  "stop",
+  "stream",
+  "temperature",
+  "top_p",
+  "tools",
+  "tool_choice",
+  "user",
+  "function_call",
+  "functions",
+  "prompt_cache_key",
+  "cache_key",
+  "stream_options",
+  "top_k",
+  "min_p",
+  "repeat_penalty",
+  "tfs_z",
+  "profile",
+  "keep_alive_secs",
+  "metadata",
+];
+
+// the fields of the json object not in the known fields
+pub fn unknown_fields(value: &Value, known: &[&str]) -> Vec<String> {
+  value
+    .as_object()
+    .map(|object| {
+      object
+        .keys()
+        .filter(|key| !known.contains(&key.as_str()))
+        .cloned()
+        .collect()
+    })
+    .unwrap_or_default()
+}
+
+impl From<CreateChatCompletionRequest> for BodhiChatRequest {
+  fn from(request: CreateChatCompletionRequest) -> Self {
+    Self {
+      request,
+      prompt_cache_key: None,
+      stream_options: None,
+      response_format: None,
+      sampling_params: SamplingParams::default(),
+      profile: None,
+      keep_alive_secs: None,
+      metadata: None,
+      prompt: None,
+    }
+  }
+}
+
+impl BodhiChatRequest {
+  // same limits as the metadata of the OpenAI API
+  pub fn validate_metadata(&self) -> Result<()> {
+    let Some(metadata) = &self.metadata else {
+      return Ok(());
+    };
+    if metadata.len() > METADATA_MAX_KEYS {
+      return Err(OpenAIApiError::BadRequest(format!(
+        "metadata can have at most {METADATA_MAX_KEYS} keys, found {}",
+        metadata.len()
+      )));
+    }
+    for (key, value) in metadata {
+      if key.chars().count() > METADATA_MAX_KEY_LEN {
+        return Err(OpenAIApiError::BadRequest(format!(
+          "metadata key '{key}' is longer than {METADATA_MAX_KEY_LEN} characters"
+        )));
+      }
+      if value.chars().count() > METADATA_MAX_VALUE_LEN {
+        return Err(OpenAIApiError::BadRequest(format!(
+          "metadata value of key '{key}' is longer than {METADATA_MAX_VALUE_LEN} characters"
+        )));
+      }
+    }
+    Ok(())
+  }
+
+  // same limits as the stop and logit_bias of the OpenAI API, the stop tokens of the alias are
+  // merged in later, and are not counted
+  pub fn validate_generation_params(&self) -> Result<()> {
+    let stop = match &self.request.stop {
+      Some(Stop::String(stop)) => vec![stop],
+      Some(Stop::StringArray(stop)) => stop.iter().collect(),
+      None => vec![],
+    };
+    if stop.len() > STOP_MAX_SEQUENCES {
+      return Err(OpenAIApiError::BadRequest(format!(
+        "stop can have at most {STOP_MAX_SEQUENCES} sequences, found {}",
+        stop.len()
+      )));
+    }
+    if stop.iter().any(|stop| stop.is_empty()) {
+      return Err(OpenAIApiError::BadRequest(
+        "stop sequences cannot be empty".to_string(),
+      ));
+    }
+    for (token, bias) in self.request.logit_bias.iter().flatten() {
+      if token.parse::<u32>().is_err() {
+        return Err(OpenAIApiError::BadRequest(format!(
+          "logit_bias key '{token}' is not a token id"
+        )));
+      }
+      if !bias
+        .as_f64()
+        .is_some_and(|bias| (-LOGIT_BIAS_MAX..=LOGIT_BIAS_MAX).contains(&bias))
+      {
+        return Err(OpenAIApiError::BadRequest(format!(
+          "logit_bias of token '{token}' should be a number between -{LOGIT_BIAS_MAX} and \
+           {LOGIT_BIAS_MAX}, found {bias}"
+        )));
+      }
+    }
+    Ok(())
+  }
+
+  pub fn metadata_json(&self) -> Option<String> {
+    self
+      .metadata
+      .as_ref()
+      .and_then(|metadata| serde_json::to_string(metadata).ok())
+  }
+}
+
+// llama.cpp takes the logit bias as the [token id, bias] pairs, instead of the map of token ids
+// of the OpenAI API
+pub(crate) fn logit_bias_pairs(logit_bias: &Map<String, Value>) -> Value {
+  logit_bias
+    .iter()
+    .filter_map(|(token, bias)| {
+      let token = token.parse::<u32>().ok()?;
+      Some(Value::Array(vec![Value::from(token), bias.clone()]))
+    })
+    .collect()
+}
+
+// the format the generated text is constrained to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseFormat {
+  Text,
+  JsonObject,
+  JsonSchema { json_schema: JsonSchema },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonSchema {
+  #[serde(default)]
+  pub name: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub description: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub schema: Option<Value>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub strict: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum OutputConstraint {
+  // the grammar llama.cpp samples the generated text with
+  Grammar(String),
+  // for a schema the grammar cannot be generated for, the json is asked for in the prompt, and
+  // checked to parse once generated
+  Instructions(String),
+}
+
+impl ResponseFormat {
+  pub(crate) fn constraint(&self) -> Option<OutputConstraint> {
+    match self {
+      ResponseFormat::Text => None,
+      ResponseFormat::JsonObject => Some(OutputConstraint::Grammar(json_object_grammar())),
+      ResponseFormat::JsonSchema { json_schema } => {
+        let Some(schema) = &json_schema.schema else {
+          return Some(OutputConstraint::Grammar(json_object_grammar()));
+        };
+        match schema_grammar(schema) {
+          Ok(grammar) => Some(OutputConstraint::Grammar(grammar)),
+          Err(reason) => {
+            tracing::debug!(
+              name = json_schema.name,
+              reason,
+              "no grammar for the json schema, asking for the json in the prompt"
+            );
+            Some(OutputConstraint::Instructions(format!(
+              "Respond only with JSON matching the following JSON schema, without any other \
+               text:\n{schema}"
+            )))
+          }
+        }
+      }
+    }
+  }
+
+  // the json asked for in the prompt is not guaranteed, so it is checked once generated
+  pub(crate) fn unconstrained(&self) -> bool {
+    matches!(self.constraint(), Some(OutputConstraint::Instructions(_)))
+  }
+}
+
+// llama.cpp sampling params outside the OpenAI spec, sent as is to llama.cpp along with the
+// request, the fields not listed here are ignored
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SamplingParams {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub top_k: Option<i32>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub min_p: Option<f32>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub repeat_penalty: Option<f32>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub tfs_z: Option<f32>,
+}
+
+// named set of sampling params, the params set in the request take precedence over the profile,
+// and the profile takes precedence over the request params of the alias
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SamplingProfile {
+  Deterministic,
+  Precise,
+  Creative,
+}
+
+impl SamplingProfile {
+  pub fn update(
+    &self,
+    request: &mut CreateChatCompletionRequest,
+    sampling_params: &mut SamplingParams,
+  ) {
+    let (temperature, top_p, top_k) = match self {
+      SamplingProfile::Deterministic => (0.0, 1.0, 1),
+      SamplingProfile::Precise => (0.2, 0.5, 20),
+      SamplingProfile::Creative => (1.1, 0.95, 100),
+    };
+    request.temperature.get_or_insert(temperature);
+    request.top_p.get_or_insert(top_p);
+    sampling_params.top_k.get_or_insert(top_k);
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StreamOptions {
+  // sends the token usage as a last chunk with no choices
+  #[serde(default)]
+  pub include_usage: bool,
+}
+
+#[cfg(test)]
+mod test {
+  use super::{
+    logit_bias_pairs, unknown_fields, BodhiChatRequest, OutputConstraint, ResponseFormat,
+    SamplingParams, SamplingProfile, CHAT_REQUEST_FIELDS,
+  };
+  use rstest::rstest;
+  use serde_json::json;
+
+  #[rstest]
+  #[case("prompt_cache_key")]
+  #[case("cache_key")]
+  fn test_bodhi_chat_request_parses_prompt_cache_key(#[case] field: &str) -> anyhow::Result<()> {
+    let mut value = json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+    }};
+    value[field] = json!("session-1");
+    let request = serde_json::from_value::<BodhiChatRequest>(value)?;
+    assert_eq!(Some("session-1".to_string()), request.prompt_cache_key);
+    assert_eq!("testalias:instruct", request.request.model);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(json! {{"type": "text"}}, false, false)]
+  #[case(json! {{"type": "json_object"}}, true, false)]
+  #[case(
+    json! {{"type": "json_schema", "json_schema": {"name": "weather", "schema": {"type": "object", "properties": {"city": {"type": "string"}}, "required": ["city"]}}}},
+    true,
+    false
+  )]
+  #[case(
+    json! {{"type": "json_schema", "json_schema": {"name": "weather", "schema": {"type": "string", "pattern": "^[A-Z]"}}}},
+    false,
+    true
+  )]
+  fn test_bodhi_chat_request_parses_response_format(
+    #[case] response_format: serde_json::Value,
+    #[case] grammar: bool,
+    #[case] instructions: bool,
+  ) -> anyhow::Result<()> {
+    let request = serde_json::from_value::<BodhiChatRequest>(json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What is the weather in Paris?"}],
+      "response_format": response_format,
+    }})?;
+    assert_eq!(None, request.request.response_format);
+    let constraint = request
+      .response_format
+      .as_ref()
+      .and_then(ResponseFormat::constraint);
+    assert_eq!(
+      grammar,
+      matches!(constraint, Some(OutputConstraint::Grammar(_)))
+    );
+    assert_eq!(
+      instructions,
+      matches!(constraint, Some(OutputConstraint::Instructions(_)))
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_bodhi_chat_request_parses_sampling_params() -> anyhow::Result<()> {
+    let request = serde_json::from_value::<BodhiChatRequest>(json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+      "seed": 42,
+      "top_k": 40,
+      "min_p": 0.05,
+      "repeat_penalty": 1.1,
+      "tfs_z": 1.0,
+      "mirostat": 2,
+    }})?;
+    assert_eq!(
+      SamplingParams {
+        top_k: Some(40),
+        min_p: Some(0.05),
+        repeat_penalty: Some(1.1),
+        tfs_z: Some(1.0),
+      },
+      request.sampling_params
+    );
+    assert_eq!(Some(42), request.request.seed);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(json!{{}}, Some(0.0), Some(1.0), Some(1))]
+  #[case(json!{{"temperature": 0.7, "top_k": 10}}, Some(0.7), Some(1.0), Some(10))]
+  fn test_bodhi_chat_request_profile_expands_to_sampling_params(
+    #[case] params: serde_json::Value,
+    #[case] temperature: Option<f32>,
+    #[case] top_p: Option<f32>,
+    #[case] top_k: Option<i32>,
+  ) -> anyhow::Result<()> {
+    let mut value = json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+      "profile": "deterministic",
+    }};
+    for (field, param) in params.as_object().into_iter().flatten() {
+      value[field] = param.clone();
+    }
+    let mut request = serde_json::from_value::<BodhiChatRequest>(value)?;
+    assert_eq!(Some(SamplingProfile::Deterministic), request.profile);
+    SamplingProfile::Deterministic.update(&mut request.request, &mut request.sampling_params);
+    assert_eq!(temperature, request.request.temperature);
+    assert_eq!(top_p, request.request.top_p);
+    assert_eq!(top_k, request.sampling_params.top_k);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(json!{{"trace_id": "trace-1"}}, None)]
+  #[case(
+    json!((0..17).map(|i| (format!("key{i}"), json!("value"))).collect::<serde_json::Map<_, _>>()),
+    Some("metadata can have at most 16 keys, found 17")
+  )]
+  #[case(
+    json!({"k".repeat(65): "value"}),
+    Some("is longer than 64 characters")
+  )]
+  #[case(
+    json!({"trace_id": "v".repeat(513)}),
+    Some("metadata value of key 'trace_id' is longer than 512 characters")
+  )]
+  fn test_bodhi_chat_request_validate_metadata(
+    #[case] metadata: serde_json::Value,
+    #[case] error: Option<&str>,
+  ) -> anyhow::Result<()> {
+    let request = serde_json::from_value::<BodhiChatRequest>(json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+      "metadata": metadata,
+    }})?;
+    match (request.validate_metadata(), error) {
+      (Ok(()), None) => {}
+      (Err(err), Some(error)) => assert!(err.to_string().contains(error), "{err}"),
+      (result, error) => panic!("expected {error:?}, got {result:?}"),
+    }
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(json! {{"stop": "\n", "logit_bias": {"15043": -100, "2": 5.5}}}, None)]
+  #[case(json! {{"stop": ["\n", "User:", "###", "</s>"]}}, None)]
+  #[case(
+    json! {{"stop": ["a", "b", "c", "d", "e"]}},
+    Some("stop can have at most 4 sequences, found 5")
+  )]
+  #[case(json! {{"stop": ["\n", ""]}}, Some("stop sequences cannot be empty"))]
+  #[case(json! {{"stop": ""}}, Some("stop sequences cannot be empty"))]
+  #[case(
+    json! {{"logit_bias": {"hello": -100}}},
+    Some("logit_bias key 'hello' is not a token id")
+  )]
+  #[case(
+    json! {{"logit_bias": {"15043": 101}}},
+    Some("logit_bias of token '15043' should be a number between -100 and 100, found 101")
+  )]
+  fn test_bodhi_chat_request_validate_generation_params(
+    #[case] params: serde_json::Value,
+    #[case] error: Option<&str>,
+  ) -> anyhow::Result<()> {
+    let mut request = json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+    }};
+    for (key, value) in params.as_object().unwrap() {
+      request[key] = value.clone();
+    }
+    let request = serde_json::from_value::<BodhiChatRequest>(request)?;
+    match (request.validate_generation_params(), error) {
+      (Ok(()), None) => {}
+      (Err(err), Some(error)) => assert_eq!(error, err.to_string()),
+      (result, error) => panic!("expected {error:?}, got {result:?}"),
+    }
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_logit_bias_pairs() {
+    let logit_bias = json! {{"15043": -100, "2": 5.5}};
+    assert_eq!(
+      json! {[[15043, -100], [2, 5.5]]},
+      logit_bias_pairs(logit_bias.as_object().unwrap())
+    );
+  }
+
+  #[rstest]
+  #[case(json! {{"model": "testalias:instruct", "messages": [], "top_k": 40}}, vec![])]
+  #[case(
+    json! {{"model": "testalias:instruct", "service_tier": "auto", "messages": [], "parallel_tool_calls": true}},
+    vec!["service_tier", "parallel_tool_calls"]
+  )]
+  #[case(json! {["not", "an", "object"]}, vec![])]
+  fn test_bodhi_chat_request_unknown_fields(
+    #[case] value: serde_json::Value,
+    #[case] expected: Vec<&str>,
+  ) {
+    let mut unknown = unknown_fields(&value, CHAT_REQUEST_FIELDS);
+    let mut expected = expected;
+    unknown.sort();
+    expected.sort();
+    assert_eq!(expected, unknown);
+  }
+}
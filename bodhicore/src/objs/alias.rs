@@ -2,9 +2,11 @@
 use super::{is_default, BuilderError};
 use super::{ChatTemplate, GptContextParams, OAIRequestParams, Repo};
 use crate::utils::to_safe_filename;
+use async_openai::types::{CreateChatCompletionRequest, Stop};
 use derive_new::new;
 use prettytable::{Cell, Row};
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 
 #[allow(clippy::too_many_arguments)]
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, new)]
@@ -18,8 +20,11 @@ pub struct Alias {
   pub alias: String,
   #[serde(skip_serializing_if = "Option::is_none")]
   pub family: Option<String>,
+  // repo and snapshot are left empty for an alias with a local model file
+  #[serde(default, skip_serializing_if = "is_default")]
   pub repo: Repo,
   pub filename: String,
+  #[serde(default, skip_serializing_if = "String::is_empty")]
   pub snapshot: String,
   pub features: Vec<String>,
   pub chat_template: ChatTemplate,
@@ -27,6 +32,23 @@ pub struct Alias {
   pub request_params: OAIRequestParams,
   #[serde(default, skip_serializing_if = "is_default")]
   pub context_params: GptContextParams,
+  // overrides BODHI_KEEP_ALIVE_SECS for this model, 0 keeps the model loaded
+  #[new(default)]
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub keep_alive_secs: Option<u64>,
+  // stop strings always applied along with the request stop, for models with a missing or wrong
+  // EOS token in the GGUF file
+  #[new(default)]
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub stop_tokens: Vec<String>,
+  // GGUF file outside the huggingface cache, used in place of the repo and snapshot
+  #[new(default)]
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub model_file: Option<PathBuf>,
+  // freeform labels to group and filter the aliases, e.g. `code` or `chat`
+  #[new(default)]
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub tags: Vec<String>,
 }
 
 impl Alias {
@@ -35,6 +57,32 @@ impl Alias {
     let filename = to_safe_filename(&filename);
     format!("{}.yaml", filename)
   }
+
+  // unlike the request params stop, which is a default for the request, the stop tokens are
+  // merged with the request stop
+  pub fn merge_stop_tokens(&self, request: &mut CreateChatCompletionRequest) {
+    if self.stop_tokens.is_empty() {
+      return;
+    }
+    let mut stop = match request.stop.take() {
+      Some(Stop::String(stop)) => vec![stop],
+      Some(Stop::StringArray(stop)) => stop,
+      None => vec![],
+    };
+    for token in &self.stop_tokens {
+      if !stop.contains(token) {
+        stop.push(token.clone());
+      }
+    }
+    request.stop = Some(Stop::StringArray(stop));
+  }
+
+  pub fn has_tag(&self, tag: &str) -> bool {
+    self
+      .tags
+      .iter()
+      .any(|alias_tag| alias_tag.eq_ignore_ascii_case(tag.trim()))
+  }
 }
 
 impl From<Alias> for Row {
@@ -42,7 +90,12 @@ impl From<Alias> for Row {
     Row::from(vec![
       Cell::new(&value.alias),
       Cell::new(&value.family.unwrap_or_default()),
-      Cell::new(&value.repo.to_string()),
+      Cell::new(
+        &value
+          .model_file
+          .map(|model_file| model_file.display().to_string())
+          .unwrap_or_else(|| value.repo.to_string()),
+      ),
       Cell::new(&value.filename),
       Cell::new(&value.features.join(",")),
       Cell::new(&value.chat_template.to_string()),
@@ -64,8 +117,11 @@ mod test {
     },
     Repo,
   };
+  use async_openai::types::{CreateChatCompletionRequest, Stop};
   use prettytable::{Cell, Row};
   use rstest::rstest;
+  use serde_json::json;
+  use std::path::PathBuf;
 
   fn tinyllama_builder() -> AliasBuilder {
     AliasBuilder::default()
@@ -150,9 +206,7 @@ context_params:
   #[case(
     Alias::default(),
     r#"alias: ''
-repo: ''
 filename: ''
-snapshot: ''
 features: []
 chat_template: llama3
 "#
@@ -174,6 +228,43 @@ chat_template: llama3
     tinyllama_chat_template_repo()
   )]
   #[case(tinyllama_chat_template_id_serialized(), tinyllama_chat_template_id())]
+  #[case(
+    r#"alias: mymodel:instruct
+filename: mymodel.Q4_0.gguf
+features:
+- chat
+chat_template: llama3
+model_file: /models/mymodel.Q4_0.gguf
+"#.to_string(),
+    Alias {
+      alias: "mymodel:instruct".to_string(),
+      filename: "mymodel.Q4_0.gguf".to_string(),
+      features: vec!["chat".to_string()],
+      model_file: Some(PathBuf::from("/models/mymodel.Q4_0.gguf")),
+      ..Alias::default()
+    }
+  )]
+  #[case(
+    format!("{}keep_alive_secs: 600\n", tinyllama_chat_template_id_serialized()),
+    Alias {
+      keep_alive_secs: Some(600),
+      ..tinyllama_chat_template_id()
+    }
+  )]
+  #[case(
+    format!("{}tags:\n- code\n- chat\n", tinyllama_chat_template_id_serialized()),
+    Alias {
+      tags: vec!["code".to_string(), "chat".to_string()],
+      ..tinyllama_chat_template_id()
+    }
+  )]
+  #[case(
+    format!("{}stop_tokens:\n- <|im_end|>\n", tinyllama_chat_template_id_serialized()),
+    Alias {
+      stop_tokens: vec!["<|im_end|>".to_string()],
+      ..tinyllama_chat_template_id()
+    }
+  )]
   fn test_alias_deserialized(
     #[case] serialized: String,
     #[case] expected: Alias,
@@ -183,6 +274,52 @@ chat_template: llama3
     Ok(())
   }
 
+  #[rstest]
+  #[case(vec![], None, None)]
+  #[case(vec!["</s>"], None, Some(vec!["</s>"]))]
+  #[case(vec!["</s>"], Some(Stop::String("\n".to_string())), Some(vec!["\n", "</s>"]))]
+  #[case(
+    vec!["</s>", "<|im_end|>"],
+    Some(Stop::StringArray(vec!["<|im_end|>".to_string(), "User:".to_string()])),
+    Some(vec!["<|im_end|>", "User:", "</s>"])
+  )]
+  fn test_alias_merge_stop_tokens(
+    #[case] stop_tokens: Vec<&str>,
+    #[case] stop: Option<Stop>,
+    #[case] expected: Option<Vec<&str>>,
+  ) -> anyhow::Result<()> {
+    let alias = Alias {
+      stop_tokens: stop_tokens.into_iter().map(str::to_string).collect(),
+      ..Alias::testalias()
+    };
+    let mut request = serde_json::from_value::<CreateChatCompletionRequest>(json! {{
+      "model": "testalias:instruct",
+      "messages": []
+    }})?;
+    request.stop = stop.clone();
+    alias.merge_stop_tokens(&mut request);
+    let expected = match expected {
+      None => stop,
+      Some(expected) => Some(Stop::StringArray(
+        expected.into_iter().map(str::to_string).collect(),
+      )),
+    };
+    assert_eq!(expected, request.stop);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("code", true)]
+  #[case(" Code ", true)]
+  #[case("chat", false)]
+  fn test_alias_has_tag(#[case] tag: &str, #[case] expected: bool) {
+    let alias = Alias {
+      tags: vec!["code".to_string()],
+      ..Alias::testalias()
+    };
+    assert_eq!(expected, alias.has_tag(tag));
+  }
+
   #[test]
   fn test_alias_to_row() -> anyhow::Result<()> {
     let alias = Alias::testalias();
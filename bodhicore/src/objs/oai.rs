@@ -1,6 +1,9 @@
 #[allow(unused_imports)]
 use crate::objs::BuilderError;
-use async_openai::types::{CreateChatCompletionRequest, Stop};
+use async_openai::types::{
+  ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs,
+  CreateChatCompletionRequest, Role, Stop,
+};
 use clap::Args;
 use serde::{Deserialize, Serialize};
 
@@ -66,6 +69,13 @@ default: 1.0 (disabled)"#)]
   )]
   #[serde(default, skip_serializing_if = "Option::is_none")]
   pub user: Option<String>,
+
+  #[arg(
+    long,
+    help = r#"System message added at the start of the conversation, if the request does not have a system message."#
+  )]
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub system_prompt: Option<String>,
 }
 
 fn validate_range_neg_to_pos_2(s: &str) -> Result<f32, String> {
@@ -110,6 +120,45 @@ impl OAIRequestParams {
     if !self.stop.is_empty() && request.stop.is_none() {
       request.stop = Some(Stop::StringArray(self.stop.clone()));
     }
+    self.update_system_prompt(&mut request.messages);
+  }
+
+  // the params set in the overrides, falling back to these params for the rest
+  pub fn merge(&self, overrides: &OAIRequestParams) -> OAIRequestParams {
+    let mut merged = overrides.clone();
+    update_if_none(&self.frequency_penalty, &mut merged.frequency_penalty);
+    update_if_none(&self.max_tokens, &mut merged.max_tokens);
+    update_if_none(&self.presence_penalty, &mut merged.presence_penalty);
+    update_if_none(&self.seed, &mut merged.seed);
+    update_if_none(&self.temperature, &mut merged.temperature);
+    update_if_none(&self.top_p, &mut merged.top_p);
+    update_if_none(&self.user, &mut merged.user);
+    update_if_none(&self.system_prompt, &mut merged.system_prompt);
+    if merged.stop.is_empty() {
+      merged.stop.clone_from(&self.stop);
+    }
+    merged
+  }
+
+  // a prompt request has no messages, the system prompt is left to the prompt
+  fn update_system_prompt(&self, messages: &mut Vec<ChatCompletionRequestMessage>) {
+    let Some(system_prompt) = &self.system_prompt else {
+      return;
+    };
+    // the messages are untagged, a plain text user message also parses as a system message
+    let has_system = messages.iter().any(|message| match message {
+      ChatCompletionRequestMessage::System(message) => message.role == Role::System,
+      _ => false,
+    });
+    if messages.is_empty() || has_system {
+      return;
+    }
+    if let Ok(message) = ChatCompletionRequestSystemMessageArgs::default()
+      .content(system_prompt.clone())
+      .build()
+    {
+      messages.insert(0, message.into());
+    }
   }
 }
 
@@ -118,3 +167,65 @@ fn update_if_none<T: Clone>(self_param: &Option<T>, request_param: &mut Option<T
     request_param.clone_from(self_param);
   }
 }
+
+#[cfg(test)]
+mod test {
+  use super::OAIRequestParams;
+  use async_openai::types::CreateChatCompletionRequest;
+  use rstest::rstest;
+  use serde_json::{json, Value};
+
+  #[rstest]
+  #[case(
+    json! {[{"role": "user", "content": "Hello"}]},
+    json! {[{"role": "system", "content": "You are a coding assistant."}, {"role": "user", "content": "Hello"}]}
+  )]
+  #[case(
+    json! {[{"role": "system", "content": "You are a poet."}, {"role": "user", "content": "Hello"}]},
+    json! {[{"role": "system", "content": "You are a poet."}, {"role": "user", "content": "Hello"}]}
+  )]
+  #[case(json! {[]}, json! {[]})]
+  fn test_oai_request_params_update_system_prompt(
+    #[case] messages: Value,
+    #[case] expected: Value,
+  ) -> anyhow::Result<()> {
+    let params = OAIRequestParams {
+      temperature: Some(0.2),
+      system_prompt: Some("You are a coding assistant.".to_string()),
+      ..Default::default()
+    };
+    let mut request = serde_json::from_value::<CreateChatCompletionRequest>(json! {{
+      "model": "testalias:instruct",
+      "messages": messages,
+      "temperature": 0.9,
+    }})?;
+    params.update(&mut request);
+    assert_eq!(Some(0.9), request.temperature);
+    assert_eq!(expected, serde_json::to_value(&request.messages)?);
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_oai_request_params_merge_prefers_overrides() {
+    let params = OAIRequestParams {
+      temperature: Some(0.2),
+      max_tokens: Some(512),
+      stop: vec!["<|im_end|>".to_string()],
+      ..Default::default()
+    };
+    let overrides = OAIRequestParams {
+      temperature: Some(0.8),
+      system_prompt: Some("You are a poet.".to_string()),
+      ..Default::default()
+    };
+    let expected = OAIRequestParams {
+      temperature: Some(0.8),
+      max_tokens: Some(512),
+      stop: vec!["<|im_end|>".to_string()],
+      system_prompt: Some("You are a poet.".to_string()),
+      ..Default::default()
+    };
+    assert_eq!(expected, params.merge(&overrides));
+    assert_eq!(params, params.merge(&OAIRequestParams::default()));
+  }
+}
@@ -0,0 +1,284 @@
+use std::{
+  collections::{BTreeMap, BTreeSet},
+  fmt::Write,
+  sync::Mutex,
+  time::Duration,
+};
+
+// upper bounds in seconds, from the first token of a small model to a long generation
+static LATENCY_BUCKETS: [f64; 11] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0];
+
+// the model label of the aliases past the limit, and of the models not matching an alias
+pub static OTHER_MODEL: &str = "other";
+static MAX_MODEL_LABELS: usize = 32;
+
+type Labels = Vec<(&'static str, String)>;
+
+#[derive(Debug, Default, Clone)]
+struct Histogram {
+  buckets: [u64; LATENCY_BUCKETS.len()],
+  sum: f64,
+  count: u64,
+}
+
+impl Histogram {
+  fn observe(&mut self, value: f64) {
+    for (bucket, le) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+      if value <= le {
+        *bucket += 1;
+      }
+    }
+    self.sum += value;
+    self.count += 1;
+  }
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+  counters: BTreeMap<&'static str, BTreeMap<Labels, u64>>,
+  gauges: BTreeMap<&'static str, BTreeMap<Labels, i64>>,
+  histograms: BTreeMap<&'static str, BTreeMap<Labels, Histogram>>,
+  models: BTreeSet<String>,
+}
+
+// the metrics of the server, rendered in the Prometheus text format at /metrics
+#[derive(Debug, Default)]
+pub struct Metrics {
+  registry: Mutex<Registry>,
+}
+
+pub static HTTP_REQUESTS_TOTAL: &str = "bodhi_http_requests_total";
+pub static PROMPT_TOKENS_TOTAL: &str = "bodhi_prompt_tokens_total";
+pub static COMPLETION_TOKENS_TOTAL: &str = "bodhi_completion_tokens_total";
+pub static TIME_TO_FIRST_TOKEN_SECONDS: &str = "bodhi_time_to_first_token_seconds";
+pub static GENERATION_SECONDS: &str = "bodhi_generation_seconds";
+pub static MODEL_REQUESTS_TOTAL: &str = "bodhi_model_requests_total";
+pub static MODEL_LOADS_TOTAL: &str = "bodhi_model_loads_total";
+pub static MODEL_EVICTIONS_TOTAL: &str = "bodhi_model_evictions_total";
+pub static QUEUED_REQUESTS: &str = "bodhi_queued_requests";
+
+fn help(name: &str) -> &'static str {
+  match name {
+    "bodhi_http_requests_total" => "HTTP requests by route and status",
+    "bodhi_prompt_tokens_total" => "prompt tokens evaluated by model",
+    "bodhi_completion_tokens_total" => "completion tokens generated by model",
+    "bodhi_time_to_first_token_seconds" => "time from the request to the first generated message",
+    "bodhi_generation_seconds" => "time from the request to the end of the generation",
+    "bodhi_model_requests_total" => "chat completion requests by model",
+    "bodhi_model_loads_total" => {
+      "model loads by model, the model loaded at startup is counted on its first request"
+    }
+    "bodhi_model_evictions_total" => "model evictions by model and reason",
+    "bodhi_queued_requests" => "chat completion requests waiting for a free slot by model",
+    _ => "",
+  }
+}
+
+impl Metrics {
+  // the first aliases seen keep their own label, so a client cycling through aliases, e.g. a
+  // script creating one per run, cannot grow the series without bound
+  pub fn model_label(&self, alias: &str) -> String {
+    let Ok(mut registry) = self.registry.lock() else {
+      return OTHER_MODEL.to_string();
+    };
+    if registry.models.contains(alias) {
+      return alias.to_string();
+    }
+    if registry.models.len() < MAX_MODEL_LABELS {
+      registry.models.insert(alias.to_string());
+      return alias.to_string();
+    }
+    OTHER_MODEL.to_string()
+  }
+
+  pub fn increment(&self, name: &'static str, labels: Labels, value: u64) {
+    if let Ok(mut registry) = self.registry.lock() {
+      *registry
+        .counters
+        .entry(name)
+        .or_default()
+        .entry(labels)
+        .or_default() += value;
+    }
+  }
+
+  // moves the gauge up or down, so the models sharing the `other` label add up
+  pub fn add(&self, name: &'static str, labels: Labels, delta: i64) {
+    if let Ok(mut registry) = self.registry.lock() {
+      *registry
+        .gauges
+        .entry(name)
+        .or_default()
+        .entry(labels)
+        .or_default() += delta;
+    }
+  }
+
+  pub fn observe(&self, name: &'static str, labels: Labels, value: Duration) {
+    if let Ok(mut registry) = self.registry.lock() {
+      registry
+        .histograms
+        .entry(name)
+        .or_default()
+        .entry(labels)
+        .or_default()
+        .observe(value.as_secs_f64());
+    }
+  }
+
+  pub fn render(&self) -> String {
+    let Ok(registry) = self.registry.lock() else {
+      return String::new();
+    };
+    let mut output = String::new();
+    for (name, series) in &registry.counters {
+      _ = writeln!(output, "# HELP {name} {}", help(name));
+      _ = writeln!(output, "# TYPE {name} counter");
+      for (labels, value) in series {
+        _ = writeln!(output, "{name}{} {value}", format_labels(labels, None));
+      }
+    }
+    for (name, series) in &registry.gauges {
+      _ = writeln!(output, "# HELP {name} {}", help(name));
+      _ = writeln!(output, "# TYPE {name} gauge");
+      for (labels, value) in series {
+        _ = writeln!(output, "{name}{} {value}", format_labels(labels, None));
+      }
+    }
+    for (name, series) in &registry.histograms {
+      _ = writeln!(output, "# HELP {name} {}", help(name));
+      _ = writeln!(output, "# TYPE {name} histogram");
+      for (labels, histogram) in series {
+        for (bucket, le) in histogram.buckets.iter().zip(LATENCY_BUCKETS) {
+          let le = le.to_string();
+          _ = writeln!(
+            output,
+            "{name}_bucket{} {bucket}",
+            format_labels(labels, Some(&le))
+          );
+        }
+        _ = writeln!(
+          output,
+          "{name}_bucket{} {}",
+          format_labels(labels, Some("+Inf")),
+          histogram.count
+        );
+        _ = writeln!(
+          output,
+          "{name}_sum{} {}",
+          format_labels(labels, None),
+          histogram.sum
+        );
+        _ = writeln!(
+          output,
+          "{name}_count{} {}",
+          format_labels(labels, None),
+          histogram.count
+        );
+      }
+    }
+    output
+  }
+}
+
+fn format_labels(labels: &Labels, le: Option<&str>) -> String {
+  let mut labels = labels
+    .iter()
+    .map(|(name, value)| format!("{name}=\"{}\"", escape(value)))
+    .collect::<Vec<_>>();
+  if let Some(le) = le {
+    labels.push(format!("le=\"{le}\""));
+  }
+  if labels.is_empty() {
+    String::new()
+  } else {
+    format!("{{{}}}", labels.join(","))
+  }
+}
+
+fn escape(value: &str) -> String {
+  value
+    .replace('\\', "\\\\")
+    .replace('"', "\\\"")
+    .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod test {
+  use super::{
+    Metrics, GENERATION_SECONDS, HTTP_REQUESTS_TOTAL, MAX_MODEL_LABELS, OTHER_MODEL,
+    PROMPT_TOKENS_TOTAL, QUEUED_REQUESTS,
+  };
+  use rstest::rstest;
+  use std::time::Duration;
+
+  #[rstest]
+  fn test_metrics_render_counters() {
+    let metrics = Metrics::default();
+    let labels = vec![
+      ("route", "/v1/chat/completions".to_string()),
+      ("status", "200".to_string()),
+    ];
+    metrics.increment(HTTP_REQUESTS_TOTAL, labels.clone(), 1);
+    metrics.increment(HTTP_REQUESTS_TOTAL, labels, 1);
+    metrics.increment(
+      PROMPT_TOKENS_TOTAL,
+      vec![("model", "my \"quoted\" alias".to_string())],
+      15,
+    );
+    let output = metrics.render();
+    assert!(output.contains("# TYPE bodhi_http_requests_total counter\n"));
+    assert!(output
+      .contains("bodhi_http_requests_total{route=\"/v1/chat/completions\",status=\"200\"} 2\n"));
+    assert!(output.contains("bodhi_prompt_tokens_total{model=\"my \\\"quoted\\\" alias\"} 15\n"));
+  }
+
+  #[rstest]
+  fn test_metrics_render_gauges() {
+    let metrics = Metrics::default();
+    let labels = vec![("model", "testalias:instruct".to_string())];
+    metrics.add(QUEUED_REQUESTS, labels.clone(), 1);
+    metrics.add(QUEUED_REQUESTS, labels.clone(), 1);
+    metrics.add(QUEUED_REQUESTS, labels, -1);
+    let output = metrics.render();
+    assert!(output.contains("# TYPE bodhi_queued_requests gauge\n"));
+    assert!(output.contains("bodhi_queued_requests{model=\"testalias:instruct\"} 1\n"));
+  }
+
+  #[rstest]
+  fn test_metrics_render_histogram_buckets() {
+    let metrics = Metrics::default();
+    let labels = vec![("model", "testalias:instruct".to_string())];
+    metrics.observe(
+      GENERATION_SECONDS,
+      labels.clone(),
+      Duration::from_millis(300),
+    );
+    metrics.observe(GENERATION_SECONDS, labels, Duration::from_secs(200));
+    let output = metrics.render();
+    assert!(output.contains("# TYPE bodhi_generation_seconds histogram\n"));
+    for line in [
+      "bodhi_generation_seconds_bucket{model=\"testalias:instruct\",le=\"0.25\"} 0\n",
+      "bodhi_generation_seconds_bucket{model=\"testalias:instruct\",le=\"0.5\"} 1\n",
+      "bodhi_generation_seconds_bucket{model=\"testalias:instruct\",le=\"120\"} 1\n",
+      "bodhi_generation_seconds_bucket{model=\"testalias:instruct\",le=\"+Inf\"} 2\n",
+      "bodhi_generation_seconds_sum{model=\"testalias:instruct\"} 200.3\n",
+      "bodhi_generation_seconds_count{model=\"testalias:instruct\"} 2\n",
+    ] {
+      assert!(output.contains(line), "{line} not in {output}");
+    }
+  }
+
+  #[rstest]
+  fn test_metrics_model_label_bounds_cardinality() {
+    let metrics = Metrics::default();
+    for i in 0..MAX_MODEL_LABELS {
+      assert_eq!(
+        format!("alias-{i}"),
+        metrics.model_label(&format!("alias-{i}"))
+      );
+    }
+    assert_eq!(OTHER_MODEL, metrics.model_label("one-too-many"));
+    assert_eq!("alias-0", metrics.model_label("alias-0"));
+  }
+}
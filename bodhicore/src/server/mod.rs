@@ -1,14 +1,31 @@
+mod metrics;
+mod request_queue;
+mod response_cache;
 mod router_state;
 mod routes;
 mod routes_chat;
+mod routes_completions;
+mod routes_health;
+mod routes_inference;
+mod routes_messages;
+mod routes_metrics;
+mod routes_mock;
 mod routes_models;
+mod routes_ollama;
+mod routes_server;
 mod routes_ui;
 #[allow(clippy::module_inception)]
 mod server;
 mod shutdown;
+#[cfg(unix)]
+mod uds;
 mod utils;
-pub use crate::server::router_state::{RouterState, RouterStateFn};
+pub use crate::server::metrics::Metrics;
+pub use crate::server::router_state::{
+  ActiveRequest, Eviction, EvictionReason, LoadedModel, RouterState, RouterStateFn,
+};
 pub use crate::server::routes::build_routes;
+pub use crate::server::routes_ui::with_ui_dir;
 pub use crate::server::server::*;
 pub use crate::server::shutdown::shutdown_signal;
 pub use crate::server::utils::AxumRequestExt;
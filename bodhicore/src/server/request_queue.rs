@@ -0,0 +1,157 @@
+use super::metrics::{Metrics, QUEUED_REQUESTS};
+use crate::oai::OpenAIApiError;
+use std::{
+  collections::HashMap,
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+  },
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+// the requests running on a model are limited to its slots, the requests over the limit wait in
+// the queue of the model, and once the queue is full the requests are turned away
+#[derive(Debug, Default)]
+pub struct RequestQueue {
+  models: Mutex<HashMap<String, Arc<ModelQueue>>>,
+  metrics: Arc<Metrics>,
+}
+
+#[derive(Debug)]
+struct ModelQueue {
+  limit: usize,
+  semaphore: Arc<Semaphore>,
+  waiting: AtomicUsize,
+}
+
+// counts the request in the queue of the model till it gets a permit or is dropped
+struct WaitingGuard<'a> {
+  waiting: &'a AtomicUsize,
+  metrics: &'a Metrics,
+  model_label: String,
+}
+
+impl Drop for WaitingGuard<'_> {
+  fn drop(&mut self) {
+    self.waiting.fetch_sub(1, Ordering::SeqCst);
+    self.metrics.add(
+      QUEUED_REQUESTS,
+      vec![("model", self.model_label.clone())],
+      -1,
+    );
+  }
+}
+
+impl RequestQueue {
+  pub fn new(metrics: Arc<Metrics>) -> Self {
+    Self {
+      models: Mutex::new(HashMap::new()),
+      metrics,
+    }
+  }
+
+  // waits for a free slot of the model, the permit frees the slot when dropped
+  pub async fn acquire(
+    &self,
+    model: &str,
+    limit: usize,
+    max_queued: usize,
+  ) -> crate::oai::Result<OwnedSemaphorePermit> {
+    let queue = self.model_queue(model, limit.max(1));
+    if let Ok(permit) = queue.semaphore.clone().try_acquire_owned() {
+      return Ok(permit);
+    }
+    if queue.waiting.fetch_add(1, Ordering::SeqCst) >= max_queued {
+      queue.waiting.fetch_sub(1, Ordering::SeqCst);
+      return Err(OpenAIApiError::QueueFull(model.to_string()));
+    }
+    let model_label = self.metrics.model_label(model);
+    self
+      .metrics
+      .add(QUEUED_REQUESTS, vec![("model", model_label.clone())], 1);
+    let _waiting = WaitingGuard {
+      waiting: &queue.waiting,
+      metrics: &self.metrics,
+      model_label,
+    };
+    tracing::debug!(model, "request queued for a free slot");
+    queue
+      .semaphore
+      .clone()
+      .acquire_owned()
+      .await
+      .map_err(|err| OpenAIApiError::InternalServer(err.to_string()))
+  }
+
+  // a changed limit, e.g. after editing the n_parallel of the alias, starts a new queue, the
+  // requests still holding a permit of the old one finish as they are
+  fn model_queue(&self, model: &str, limit: usize) -> Arc<ModelQueue> {
+    let mut models = self.models.lock().unwrap();
+    match models.get(model) {
+      Some(queue) if queue.limit == limit => queue.clone(),
+      _ => {
+        let queue = Arc::new(ModelQueue {
+          limit,
+          semaphore: Arc::new(Semaphore::new(limit)),
+          waiting: AtomicUsize::new(0),
+        });
+        models.insert(model.to_string(), queue.clone());
+        queue
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::RequestQueue;
+  use crate::{oai::OpenAIApiError, server::metrics::Metrics};
+  use rstest::rstest;
+  use std::{sync::Arc, time::Duration};
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_request_queue_waits_for_a_free_slot() -> anyhow::Result<()> {
+    let metrics = Arc::new(Metrics::default());
+    let queue = Arc::new(RequestQueue::new(metrics.clone()));
+    let permit = queue.acquire("testalias:instruct", 1, 1).await?;
+    let waiting = {
+      let queue = queue.clone();
+      tokio::spawn(async move { queue.acquire("testalias:instruct", 1, 1).await.is_ok() })
+    };
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert!(metrics
+      .render()
+      .contains("bodhi_queued_requests{model=\"testalias:instruct\"} 1\n"));
+    drop(permit);
+    assert!(waiting.await?);
+    assert!(metrics
+      .render()
+      .contains("bodhi_queued_requests{model=\"testalias:instruct\"} 0\n"));
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_request_queue_full_turns_away_the_request() -> anyhow::Result<()> {
+    let queue = Arc::new(RequestQueue::default());
+    let _permit = queue.acquire("testalias:instruct", 1, 0).await?;
+    let result = queue.acquire("testalias:instruct", 1, 0).await;
+    assert!(
+      matches!(result, Err(OpenAIApiError::QueueFull(model)) if model == "testalias:instruct")
+    );
+    // the other models have their own slots
+    let _other = queue.acquire("llama3:instruct", 1, 0).await?;
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_request_queue_runs_up_to_the_limit() -> anyhow::Result<()> {
+    let queue = RequestQueue::default();
+    let _first = queue.acquire("testalias:instruct", 2, 0).await?;
+    let _second = queue.acquire("testalias:instruct", 2, 0).await?;
+    assert!(queue.acquire("testalias:instruct", 2, 0).await.is_err());
+    Ok(())
+  }
+}
@@ -0,0 +1,437 @@
+use super::{routes::MAX_REQUEST_BODY_BYTES, routes_chat::accepts_text_plain, RouterStateFn};
+use crate::{oai::OpenAIApiError, objs::Alias};
+use axum::{
+  body::{to_bytes, Body, Bytes},
+  extract::State,
+  http::{header, HeaderValue, Request, StatusCode},
+  middleware::{from_fn_with_state, Next},
+  response::{IntoResponse, Response},
+  routing::MethodRouter,
+};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{
+  collections::{BTreeMap, HashMap},
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+
+pub static X_CACHE: &str = "x-cache";
+
+// the fields that do not change the generated completion, left out of the cache key
+static KEY_IGNORED_FIELDS: &[&str] = &[
+  "stream",
+  "stream_options",
+  "metadata",
+  "keep_alive_secs",
+  "prompt_cache_key",
+  "cache_key",
+  "user",
+];
+
+// completions of the identical deterministic chat requests, the oldest is dropped once full
+#[derive(Debug)]
+pub struct ResponseCache {
+  ttl: Duration,
+  max_entries: usize,
+  entries: Mutex<HashMap<String, (Instant, Bytes)>>,
+}
+
+impl ResponseCache {
+  pub fn new(ttl: Duration, max_entries: usize) -> Self {
+    Self {
+      ttl,
+      max_entries,
+      entries: Mutex::new(HashMap::new()),
+    }
+  }
+
+  pub fn get(&self, key: &str) -> Option<Bytes> {
+    let mut entries = self.entries.lock().unwrap();
+    match entries.get(key) {
+      Some((cached_at, body)) if cached_at.elapsed() < self.ttl => Some(body.clone()),
+      Some(_) => {
+        entries.remove(key);
+        None
+      }
+      None => None,
+    }
+  }
+
+  pub fn insert(&self, key: String, body: Bytes) {
+    if self.max_entries == 0 {
+      return;
+    }
+    let mut entries = self.entries.lock().unwrap();
+    entries.retain(|_, (cached_at, _)| cached_at.elapsed() < self.ttl);
+    if entries.len() >= self.max_entries && !entries.contains_key(&key) {
+      let oldest = entries
+        .iter()
+        .min_by_key(|(_, (cached_at, _))| *cached_at)
+        .map(|(key, _)| key.clone());
+      if let Some(oldest) = oldest {
+        entries.remove(&oldest);
+      }
+    }
+    entries.insert(key, (Instant::now(), body));
+  }
+}
+
+// the key of a request that generates the same completion every time, greedy with temperature 0
+// or sampled with a fixed seed, none for a streamed or a non deterministic request. The alias of
+// the model is part of the key, so editing its request params, stop tokens or chat template does
+// not serve the completions generated with the old ones
+pub(crate) fn cache_key(
+  body: &[u8],
+  find_alias: impl FnOnce(&str) -> Option<Alias>,
+) -> Option<String> {
+  let request = serde_json::from_slice::<Value>(body).ok()?;
+  let request = request.as_object()?;
+  if request
+    .get("stream")
+    .and_then(Value::as_bool)
+    .unwrap_or(false)
+  {
+    return None;
+  }
+  let greedy = request.get("temperature").and_then(Value::as_f64) == Some(0.0);
+  let seeded = request.get("seed").is_some_and(|seed| !seed.is_null());
+  if !greedy && !seeded {
+    return None;
+  }
+  let alias = find_alias(request.get("model").and_then(Value::as_str)?)?;
+  let fields = request
+    .iter()
+    .filter(|(field, _)| !KEY_IGNORED_FIELDS.contains(&field.as_str()))
+    .collect::<BTreeMap<_, _>>();
+  let mut hasher = Sha256::new();
+  hasher.update(serde_json::to_vec(&fields).ok()?);
+  hasher.update(serde_json::to_vec(&alias).ok()?);
+  Some(format!("{:x}", hasher.finalize()))
+}
+
+// when the cache is enabled, serves the stored completion of an identical deterministic request
+// instead of running it, `X-Cache` tells a cached response (`HIT`) from a stored one (`MISS`)
+pub(crate) fn with_response_cache<S: Clone + Send + Sync + 'static>(
+  route: MethodRouter<S>,
+  cache: Option<Arc<ResponseCache>>,
+  state: Arc<dyn RouterStateFn>,
+) -> MethodRouter<S> {
+  match cache {
+    Some(cache) => route.route_layer(from_fn_with_state((cache, state), serve_cached)),
+    None => route,
+  }
+}
+
+async fn serve_cached(
+  State((cache, state)): State<(Arc<ResponseCache>, Arc<dyn RouterStateFn>)>,
+  request: Request<Body>,
+  next: Next,
+) -> Response {
+  // a paused server serves no completions, not even the cached ones
+  if state.is_paused() {
+    return OpenAIApiError::InferencePaused.into_response();
+  }
+  let (parts, body) = request.into_parts();
+  let bytes = match to_bytes(body, MAX_REQUEST_BODY_BYTES).await {
+    Ok(bytes) => bytes,
+    Err(err) => return OpenAIApiError::BadRequest(err.to_string()).into_response(),
+  };
+  // the text/plain response is built from the json, so only the json response is cached
+  let key = (!accepts_text_plain(&parts.headers))
+    .then(|| cache_key(&bytes, |model| state.find_alias(model)))
+    .flatten();
+  let request = Request::from_parts(parts, Body::from(bytes));
+  let Some(key) = key else {
+    return next.run(request).await;
+  };
+  if let Some(cached) = cache.get(&key) {
+    tracing::debug!(key, "serving the cached chat completion");
+    return Response::builder()
+      .status(StatusCode::OK)
+      .header(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(mime::APPLICATION_JSON.as_ref()),
+      )
+      .header(X_CACHE, "HIT")
+      .body(Body::from(cached))
+      .unwrap_or_else(|err| OpenAIApiError::InternalServer(err.to_string()).into_response());
+  }
+  let response = next.run(request).await;
+  if response.status() != StatusCode::OK {
+    return response;
+  }
+  let (mut parts, body) = response.into_parts();
+  let bytes = match to_bytes(body, usize::MAX).await {
+    Ok(bytes) => bytes,
+    Err(err) => return OpenAIApiError::InternalServer(err.to_string()).into_response(),
+  };
+  cache.insert(key, bytes.clone());
+  parts
+    .headers
+    .insert(X_CACHE, HeaderValue::from_static("MISS"));
+  Response::from_parts(parts, Body::from(bytes))
+}
+
+#[cfg(test)]
+mod test {
+  use super::{cache_key, with_response_cache, ResponseCache, X_CACHE};
+  use crate::{
+    objs::Alias,
+    test_utils::{MockRouterState, RequestTestExt, ResponseTestExt},
+  };
+  use anyhow_trace::anyhow_trace;
+  use axum::{
+    body::Bytes,
+    extract::{Request, State},
+    routing::post,
+    Json, Router,
+  };
+  use reqwest::StatusCode;
+  use rstest::rstest;
+  use serde_json::{json, Value};
+  use std::{
+    sync::{
+      atomic::{AtomicBool, AtomicUsize, Ordering},
+      Arc, Mutex,
+    },
+    time::Duration,
+  };
+  use tower::ServiceExt;
+
+  async fn counting_handler(State(calls): State<Arc<AtomicUsize>>) -> Json<Value> {
+    let call = calls.fetch_add(1, Ordering::SeqCst) + 1;
+    Json(json! {{"id": format!("chatcmpl-{call}"), "choices": []}})
+  }
+
+  fn app(calls: Arc<AtomicUsize>, cache: Option<Arc<ResponseCache>>) -> Router {
+    let alias = Arc::new(Mutex::new(Alias::default()));
+    app_with_state(calls, cache, Arc::new(AtomicBool::new(false)), alias)
+  }
+
+  // the paused flag and the alias of the model can be changed between the requests
+  fn app_with_state(
+    calls: Arc<AtomicUsize>,
+    cache: Option<Arc<ResponseCache>>,
+    paused: Arc<AtomicBool>,
+    alias: Arc<Mutex<Alias>>,
+  ) -> Router {
+    let mut router_state = MockRouterState::new();
+    router_state
+      .expect_is_paused()
+      .returning(move || paused.load(Ordering::SeqCst));
+    router_state
+      .expect_find_alias()
+      .returning(move |_| Some(alias.lock().unwrap().clone()));
+    Router::new()
+      .route(
+        "/v1/chat/completions",
+        with_response_cache(post(counting_handler), cache, Arc::new(router_state)),
+      )
+      .with_state(calls)
+  }
+
+  async fn x_cache(app: Router, request: &Value) -> anyhow::Result<(StatusCode, Option<String>)> {
+    let response = app
+      .oneshot(Request::post("/v1/chat/completions").json(request.clone())?)
+      .await?;
+    let x_cache = response
+      .headers()
+      .get(X_CACHE)
+      .and_then(|value| value.to_str().ok())
+      .map(str::to_string);
+    Ok((response.status(), x_cache))
+  }
+
+  fn chat_request(params: Value) -> Value {
+    let mut request = json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+    }};
+    for (key, value) in params.as_object().unwrap() {
+      request[key] = value.clone();
+    }
+    request
+  }
+
+  #[rstest]
+  #[case(json! {{"temperature": 0.0}}, true)]
+  #[case(json! {{"seed": 42, "temperature": 0.8}}, true)]
+  #[case(json! {{"temperature": 0.8}}, false)]
+  #[case(json! {{}}, false)]
+  #[case(json! {{"temperature": 0.0, "stream": true}}, false)]
+  #[case(json! {{"seed": null}}, false)]
+  fn test_response_cache_key_only_for_deterministic_requests(
+    #[case] params: Value,
+    #[case] cached: bool,
+  ) -> anyhow::Result<()> {
+    let key = cache_key(&serde_json::to_vec(&chat_request(params))?, |_| {
+      Some(Alias::default())
+    });
+    assert_eq!(cached, key.is_some());
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_response_cache_key_ignores_metadata_and_sorts_fields() -> anyhow::Result<()> {
+    let request = chat_request(json! {{"temperature": 0.0, "max_tokens": 10}});
+    let reordered = json! {{
+      "max_tokens": 10,
+      "temperature": 0.0,
+      "messages": request["messages"],
+      "model": "testalias:instruct",
+      "metadata": {"trace_id": "abc"},
+    }};
+    let other_model =
+      chat_request(json! {{"temperature": 0.0, "max_tokens": 10, "model": "llama3:instruct"}});
+    let alias = |_: &str| Some(Alias::default());
+    let key = cache_key(&serde_json::to_vec(&request)?, alias);
+    assert_eq!(key, cache_key(&serde_json::to_vec(&reordered)?, alias));
+    assert_ne!(key, cache_key(&serde_json::to_vec(&other_model)?, alias));
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_response_cache_key_includes_the_alias() -> anyhow::Result<()> {
+    let request = serde_json::to_vec(&chat_request(json! {{"temperature": 0.0}}))?;
+    let key = cache_key(&request, |_| Some(Alias::default()));
+    let edited = cache_key(&request, |_| {
+      Some(Alias {
+        stop_tokens: vec!["<|eot_id|>".to_string()],
+        ..Alias::default()
+      })
+    });
+    assert!(key.is_some());
+    assert_ne!(key, edited);
+    assert_eq!(None, cache_key(&request, |_| None));
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_response_cache_misses_after_alias_is_edited() -> anyhow::Result<()> {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let cache = Arc::new(ResponseCache::new(Duration::from_secs(60), 10));
+    let paused = Arc::new(AtomicBool::new(false));
+    let alias = Arc::new(Mutex::new(Alias::default()));
+    let app = || {
+      app_with_state(
+        calls.clone(),
+        Some(cache.clone()),
+        paused.clone(),
+        alias.clone(),
+      )
+    };
+    let request = chat_request(json! {{"temperature": 0.0}});
+    assert_eq!(Some("MISS".to_string()), x_cache(app(), &request).await?.1);
+    assert_eq!(Some("HIT".to_string()), x_cache(app(), &request).await?.1);
+    alias.lock().unwrap().stop_tokens = vec!["<|eot_id|>".to_string()];
+    assert_eq!(Some("MISS".to_string()), x_cache(app(), &request).await?.1);
+    assert_eq!(2, calls.load(Ordering::SeqCst));
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_response_cache_not_served_when_paused() -> anyhow::Result<()> {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let cache = Arc::new(ResponseCache::new(Duration::from_secs(60), 10));
+    let paused = Arc::new(AtomicBool::new(false));
+    let alias = Arc::new(Mutex::new(Alias::default()));
+    let app = || {
+      app_with_state(
+        calls.clone(),
+        Some(cache.clone()),
+        paused.clone(),
+        alias.clone(),
+      )
+    };
+    let request = chat_request(json! {{"temperature": 0.0}});
+    assert_eq!(
+      (StatusCode::OK, Some("MISS".to_string())),
+      x_cache(app(), &request).await?
+    );
+    paused.store(true, Ordering::SeqCst);
+    assert_eq!(
+      (StatusCode::SERVICE_UNAVAILABLE, None),
+      x_cache(app(), &request).await?
+    );
+    assert_eq!(1, calls.load(Ordering::SeqCst));
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_response_cache_serves_identical_deterministic_request() -> anyhow::Result<()> {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let cache = Arc::new(ResponseCache::new(Duration::from_secs(60), 10));
+    let request = chat_request(json! {{"temperature": 0.0}});
+    let mut responses = vec![];
+    for _ in 0..2 {
+      let response = app(calls.clone(), Some(cache.clone()))
+        .oneshot(Request::post("/v1/chat/completions").json(request.clone())?)
+        .await?;
+      assert_eq!(StatusCode::OK, response.status());
+      let x_cache = response
+        .headers()
+        .get(X_CACHE)
+        .unwrap()
+        .to_str()?
+        .to_string();
+      let body: Value = response.json().await?;
+      responses.push((x_cache, body["id"].as_str().unwrap().to_string()));
+    }
+    assert_eq!(
+      vec![
+        ("MISS".to_string(), "chatcmpl-1".to_string()),
+        ("HIT".to_string(), "chatcmpl-1".to_string()),
+      ],
+      responses
+    );
+    assert_eq!(1, calls.load(Ordering::SeqCst));
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(Some(Arc::new(ResponseCache::new(Duration::from_secs(60), 10))), json! {{"temperature": 0.8}})]
+  #[case(None, json! {{"temperature": 0.0}})]
+  #[case(Some(Arc::new(ResponseCache::new(Duration::ZERO, 10))), json! {{"temperature": 0.0}})]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_response_cache_bypassed(
+    #[case] cache: Option<Arc<ResponseCache>>,
+    #[case] params: Value,
+  ) -> anyhow::Result<()> {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let request = chat_request(params);
+    for _ in 0..2 {
+      let response = app(calls.clone(), cache.clone())
+        .oneshot(Request::post("/v1/chat/completions").json(request.clone())?)
+        .await?;
+      assert_eq!(StatusCode::OK, response.status());
+      assert_ne!(
+        Some("HIT"),
+        response
+          .headers()
+          .get(X_CACHE)
+          .and_then(|value| value.to_str().ok())
+      );
+    }
+    assert_eq!(2, calls.load(Ordering::SeqCst));
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_response_cache_drops_oldest_entry_when_full() {
+    let cache = ResponseCache::new(Duration::from_secs(60), 2);
+    cache.insert("first".to_string(), Bytes::from("1"));
+    cache.insert("second".to_string(), Bytes::from("2"));
+    cache.insert("third".to_string(), Bytes::from("3"));
+    assert_eq!(None, cache.get("first"));
+    assert_eq!(Some(Bytes::from("2")), cache.get("second"));
+    assert_eq!(Some(Bytes::from("3")), cache.get("third"));
+  }
+}
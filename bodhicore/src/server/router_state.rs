@@ -1,15 +1,35 @@
 use crate::{
-  db::DbServiceFn,
-  oai::OpenAIApiError,
-  objs::{REFS_MAIN, TOKENIZER_CONFIG_JSON},
-  service::AppServiceFn,
+  db::{DbServiceFn, TimeService, TimeServiceFn},
+  oai::{BodhiChatRequest, OpenAIApiError},
+  objs::{Alias, REFS_MAIN, TOKENIZER_CONFIG_JSON},
+  server::{
+    metrics::{
+      Metrics, COMPLETION_TOKENS_TOTAL, GENERATION_SECONDS, MODEL_EVICTIONS_TOTAL,
+      MODEL_LOADS_TOTAL, MODEL_REQUESTS_TOTAL, OTHER_MODEL, PROMPT_TOKENS_TOTAL,
+      TIME_TO_FIRST_TOKEN_SECONDS,
+    },
+    request_queue::RequestQueue,
+    shutdown::request_shutdown,
+  },
+  service::{estimated_load_bytes, find_model_file, AppServiceFn, MemoryService, MemoryServiceFn},
   shared_rw::SharedContextRwFn,
-  Repo,
+  tool_calls::{ChatTools, ToolCallParser},
+  BodhiError, Repo,
 };
-use async_openai::types::CreateChatCompletionRequest;
 use axum::async_trait;
-use std::sync::Arc;
-use tokio::sync::mpsc::Sender;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+  collections::{HashMap, VecDeque},
+  path::{Path, PathBuf},
+  sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc, Mutex,
+  },
+  time::{Duration, Instant},
+};
+use tokio::sync::mpsc::{self, Receiver, Sender};
 
 #[async_trait]
 pub trait RouterStateFn: Send + Sync {
@@ -17,9 +37,33 @@ pub trait RouterStateFn: Send + Sync {
 
   fn db_service(&self) -> Arc<dyn DbServiceFn>;
 
+  fn is_paused(&self) -> bool;
+
+  fn set_paused(&self, paused: bool);
+
+  fn active_requests(&self) -> Vec<ActiveRequest>;
+
+  fn uptime_secs(&self) -> i64;
+
+  // stops the server the same way as SIGTERM, waiting for the in-flight requests to complete
+  fn shutdown(&self);
+
+  async fn loaded_model(&self) -> crate::oai::Result<Option<LoadedModel>>;
+
+  // unloads the model if it was loaded for the alias, returns true if the model was unloaded
+  async fn unload_alias(&self, alias: &str) -> crate::oai::Result<bool>;
+
+  // the most recent evictions first
+  fn evictions(&self) -> Vec<Eviction>;
+
+  // the alias of the model in the request
+  fn find_alias(&self, model: &str) -> Option<Alias>;
+
+  fn metrics(&self) -> Arc<Metrics>;
+
   async fn chat_completions(
     &self,
-    request: CreateChatCompletionRequest,
+    request: BodhiChatRequest,
     userdata: Sender<String>,
   ) -> crate::oai::Result<()>;
 }
@@ -29,6 +73,65 @@ pub struct RouterState {
   pub(crate) ctx: Arc<dyn SharedContextRwFn>,
   pub(crate) app_service: Arc<dyn AppServiceFn>,
   pub(crate) db_service: Arc<dyn DbServiceFn>,
+  pub(crate) time_service: Arc<dyn TimeServiceFn>,
+  pub(crate) memory_service: Arc<dyn MemoryServiceFn>,
+  pub(crate) paused: Arc<AtomicBool>,
+  pub(crate) active_requests: Arc<Mutex<HashMap<usize, ActiveRequest>>>,
+  pub(crate) next_request_id: Arc<AtomicUsize>,
+  pub(crate) loaded_model: Arc<Mutex<Option<LoadedModel>>>,
+  pub(crate) evictions: Arc<Mutex<VecDeque<Eviction>>>,
+  pub(crate) metrics: Arc<Metrics>,
+  pub(crate) request_queue: Arc<RequestQueue>,
+  pub(crate) started_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ActiveRequest {
+  pub model: String,
+  pub started_at: DateTime<Utc>,
+}
+
+// the model of the last completed request, it is unloaded at expires_at if no request uses it
+// till then, a model without a keep alive stays loaded till another model is requested
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LoadedModel {
+  pub alias: String,
+  pub model_file: PathBuf,
+  pub expires_at: Option<DateTime<Utc>>,
+}
+
+// only a single model is loaded at a time, so a model is evicted either on its keep alive expiry,
+// when a request for another model replaces it, or when its alias is deleted
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EvictionReason {
+  KeepAlive,
+  Replaced,
+  Deleted,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Eviction {
+  pub alias: String,
+  pub model_file: PathBuf,
+  pub reason: EvictionReason,
+  pub evicted_at: DateTime<Utc>,
+}
+
+const MAX_EVICTIONS: usize = 100;
+
+// keeps the request in the active requests till the completion is done
+struct ActiveRequestGuard {
+  id: usize,
+  active_requests: Arc<Mutex<HashMap<usize, ActiveRequest>>>,
+}
+
+impl Drop for ActiveRequestGuard {
+  fn drop(&mut self) {
+    if let Ok(mut active_requests) = self.active_requests.lock() {
+      active_requests.remove(&self.id);
+    }
+  }
 }
 
 impl RouterState {
@@ -37,14 +140,173 @@ impl RouterState {
     app_service: Arc<dyn AppServiceFn>,
     db_service: Arc<dyn DbServiceFn>,
   ) -> Self {
+    let time_service = Arc::new(TimeService);
+    let metrics = Arc::new(Metrics::default());
     Self {
       ctx,
       app_service,
       db_service,
+      started_at: time_service.utc_now(),
+      time_service,
+      memory_service: Arc::new(MemoryService),
+      paused: Arc::new(AtomicBool::new(false)),
+      active_requests: Arc::new(Mutex::new(HashMap::new())),
+      next_request_id: Arc::new(AtomicUsize::new(0)),
+      loaded_model: Arc::new(Mutex::new(None)),
+      evictions: Arc::new(Mutex::new(VecDeque::new())),
+      request_queue: Arc::new(RequestQueue::new(metrics.clone())),
+      metrics,
+    }
+  }
+
+  fn record_eviction(&self, loaded_model: LoadedModel, reason: EvictionReason, at: DateTime<Utc>) {
+    tracing::info!(
+      model = loaded_model.alias,
+      reason = ?reason,
+      "model evicted"
+    );
+    let reason_label = match reason {
+      EvictionReason::KeepAlive => "keep_alive",
+      EvictionReason::Replaced => "replaced",
+      EvictionReason::Deleted => "deleted",
+    };
+    self.metrics.increment(
+      MODEL_EVICTIONS_TOTAL,
+      vec![
+        ("model", self.metrics.model_label(&loaded_model.alias)),
+        ("reason", reason_label.to_string()),
+      ],
+      1,
+    );
+    if let Ok(mut evictions) = self.evictions.lock() {
+      if evictions.len() == MAX_EVICTIONS {
+        evictions.pop_back();
+      }
+      evictions.push_front(Eviction {
+        alias: loaded_model.alias,
+        model_file: loaded_model.model_file,
+        reason,
+        evicted_at: at,
+      });
     }
   }
+
+  fn track_request(&self, model: &str, started_at: DateTime<Utc>) -> ActiveRequestGuard {
+    let id = self.next_request_id.fetch_add(1, Ordering::SeqCst);
+    if let Ok(mut active_requests) = self.active_requests.lock() {
+      active_requests.insert(
+        id,
+        ActiveRequest {
+          model: model.to_string(),
+          started_at,
+        },
+      );
+    }
+    ActiveRequestGuard {
+      id,
+      active_requests: self.active_requests.clone(),
+    }
+  }
+
+  fn record_load(&self, model: &str) {
+    self.metrics.increment(
+      MODEL_LOADS_TOTAL,
+      vec![("model", self.metrics.model_label(model))],
+      1,
+    );
+  }
+
+  fn record_completion_metrics(&self, model: &str, stats: &CompletionStats) {
+    let model = self.metrics.model_label(model);
+    let labels = || vec![("model", model.clone())];
+    if let Some(first_message_after) = stats.first_message_after {
+      self
+        .metrics
+        .observe(TIME_TO_FIRST_TOKEN_SECONDS, labels(), first_message_after);
+    }
+    self
+      .metrics
+      .observe(GENERATION_SECONDS, labels(), stats.elapsed);
+    if let Some(prompt_tokens) = stats.prompt_tokens {
+      self
+        .metrics
+        .increment(PROMPT_TOKENS_TOTAL, labels(), prompt_tokens);
+    }
+    if let Some(completion_tokens) = stats.completion_tokens {
+      self
+        .metrics
+        .increment(COMPLETION_TOKENS_TOTAL, labels(), completion_tokens);
+    }
+  }
+
+  // refuses to load a model that would take the memory in use over the watermark, the loaded
+  // model is unloaded before the load, so its memory is counted as free
+  async fn check_memory(&self, alias: &Alias, model_file: &Path) -> crate::oai::Result<()> {
+    let watermark = self.app_service.env_service().memory_watermark();
+    if watermark == 0 {
+      return Ok(());
+    }
+    let loaded_params = self
+      .ctx
+      .get_gpt_params()
+      .await
+      .map_err(|err| OpenAIApiError::InternalServer(err.to_string()))?;
+    if loaded_params.as_ref().map(|params| params.model.as_str())
+      == Some(model_file.display().to_string().as_str())
+    {
+      return Ok(());
+    }
+    let Some(memory) = self.memory_service.system_memory() else {
+      return Ok(());
+    };
+    // a file that is not a valid GGUF fails on load, with the error from llama.cpp
+    let Some(required) = estimated_load_bytes(model_file, alias.context_params.n_ctx) else {
+      return Ok(());
+    };
+    let loaded_bytes = loaded_params
+      .and_then(|params| estimated_load_bytes(Path::new(&params.model), params.n_ctx))
+      .unwrap_or_default();
+    let watermark_bytes = memory.watermark_bytes(watermark);
+    // the memory left under the watermark once the loaded model is unloaded
+    let available = watermark_bytes.saturating_sub(memory.projected_bytes(loaded_bytes, 0));
+    if required > available {
+      tracing::warn!(
+        model = alias.alias,
+        required,
+        available,
+        watermark_bytes,
+        "refused model load over the memory watermark"
+      );
+      // a model that does not fit even on an idle system needs a smaller load, not more free memory
+      let suggestion = if required > watermark_bytes {
+        "use a smaller quantization of the model, or a smaller n_ctx".to_string()
+      } else {
+        format!(
+          "the memory in use would go over the {watermark}% watermark, unload a model or close other apps and retry"
+        )
+      };
+      return Err(OpenAIApiError::InsufficientMemory {
+        model: alias.alias.clone(),
+        required,
+        available,
+        suggestion,
+      });
+    }
+    // the estimate leaves out the compute buffers, so a load close to the limit may still fail
+    if required > available / 100 * CLOSE_TO_LIMIT_PERCENT {
+      tracing::warn!(
+        model = alias.alias,
+        required,
+        available,
+        "model load is close to the memory watermark, it may fail to allocate its buffers"
+      );
+    }
+    Ok(())
+  }
 }
 
+const CLOSE_TO_LIMIT_PERCENT: u64 = 90;
+
 #[async_trait]
 impl RouterStateFn for RouterState {
   fn app_service(&self) -> Arc<dyn AppServiceFn> {
@@ -55,24 +317,160 @@ impl RouterStateFn for RouterState {
     self.db_service.clone()
   }
 
+  fn is_paused(&self) -> bool {
+    self.paused.load(Ordering::SeqCst)
+  }
+
+  fn set_paused(&self, paused: bool) {
+    self.paused.store(paused, Ordering::SeqCst);
+  }
+
+  fn active_requests(&self) -> Vec<ActiveRequest> {
+    let mut active_requests = self
+      .active_requests
+      .lock()
+      .map(|active_requests| active_requests.values().cloned().collect::<Vec<_>>())
+      .unwrap_or_default();
+    active_requests.sort_by_key(|request| request.started_at);
+    active_requests
+  }
+
+  fn uptime_secs(&self) -> i64 {
+    (self.time_service.utc_now() - self.started_at).num_seconds()
+  }
+
+  fn shutdown(&self) {
+    request_shutdown();
+  }
+
+  async fn loaded_model(&self) -> crate::oai::Result<Option<LoadedModel>> {
+    let gpt_params = self
+      .ctx
+      .get_gpt_params()
+      .await
+      .map_err(|err| OpenAIApiError::InternalServer(err.to_string()))?;
+    let Some(gpt_params) = gpt_params else {
+      return Ok(None);
+    };
+    let model_file = PathBuf::from(gpt_params.model);
+    let loaded_model = self
+      .loaded_model
+      .lock()
+      .ok()
+      .and_then(|loaded_model| loaded_model.clone());
+    match loaded_model {
+      Some(loaded_model) if loaded_model.model_file == model_file => Ok(Some(loaded_model)),
+      // loaded on startup, before any request
+      _ => Ok(Some(LoadedModel {
+        alias: model_file
+          .file_stem()
+          .map(|stem| stem.to_string_lossy().into_owned())
+          .unwrap_or_default(),
+        model_file,
+        expires_at: None,
+      })),
+    }
+  }
+
+  async fn unload_alias(&self, alias: &str) -> crate::oai::Result<bool> {
+    let Some(loaded_model) = self.loaded_model().await? else {
+      return Ok(false);
+    };
+    if loaded_model.alias != alias {
+      return Ok(false);
+    }
+    // waits for the in-flight requests holding the model
+    self.ctx.try_stop().await?;
+    let unloaded = self
+      .loaded_model
+      .lock()
+      .ok()
+      .and_then(|mut loaded_model| loaded_model.take())
+      .unwrap_or(loaded_model);
+    let evicted_at = self.time_service.utc_now();
+    self.record_eviction(unloaded, EvictionReason::Deleted, evicted_at);
+    Ok(true)
+  }
+
+  fn evictions(&self) -> Vec<Eviction> {
+    self
+      .evictions
+      .lock()
+      .map(|evictions| evictions.iter().cloned().collect())
+      .unwrap_or_default()
+  }
+
+  // falls back to matching the alias ignoring case and surrounding whitespace, unless
+  // BODHI_STRICT_ALIAS is set, a model matching more than one alias is not resolved
+  fn find_alias(&self, model: &str) -> Option<Alias> {
+    let data_service = self.app_service.data_service();
+    if let Some(alias) = data_service.find_alias(model) {
+      return Some(alias);
+    }
+    if self.app_service.env_service().strict_alias() {
+      return None;
+    }
+    let normalized = model.trim().to_lowercase();
+    let mut matches = data_service
+      .list_aliases()
+      .unwrap_or_default()
+      .into_iter()
+      .filter(|alias| alias.alias.to_lowercase() == normalized);
+    let alias = matches.next()?;
+    if matches.next().is_some() {
+      tracing::warn!(model, "model matches more than one alias ignoring case");
+      return None;
+    }
+    tracing::info!(
+      model,
+      alias = alias.alias,
+      "model resolved to alias ignoring case"
+    );
+    Some(alias)
+  }
+
+  fn metrics(&self) -> Arc<Metrics> {
+    self.metrics.clone()
+  }
+
   async fn chat_completions(
     &self,
-    request: CreateChatCompletionRequest,
+    mut request: BodhiChatRequest,
     userdata: Sender<String>,
   ) -> crate::oai::Result<()> {
-    let Some(alias) = self.app_service.data_service().find_alias(&request.model) else {
-      return Err(crate::oai::OpenAIApiError::ModelNotFound(request.model));
+    let started_at = self.time_service.utc_now();
+    request.validate_metadata()?;
+    request.validate_generation_params()?;
+    let metadata = request.metadata_json();
+    let _active = self.track_request(&request.request.model, started_at);
+    let Some(alias) = self.find_alias(&request.request.model) else {
+      self.metrics.increment(
+        MODEL_REQUESTS_TOTAL,
+        vec![("model", OTHER_MODEL.to_string())],
+        1,
+      );
+      return Err(crate::oai::OpenAIApiError::ModelNotFound(
+        request.request.model,
+      ));
     };
-    let model_file = self
-      .app_service
-      .hub_service()
-      .find_local_file(&alias.repo, &alias.filename, &alias.snapshot)
+    self.metrics.increment(
+      MODEL_REQUESTS_TOTAL,
+      vec![("model", self.metrics.model_label(&alias.alias))],
+      1,
+    );
+    request.request.model = alias.alias.clone();
+    let model_file = find_model_file(self.app_service.hub_service().as_ref(), &alias)
       .map_err(|err| OpenAIApiError::InternalServer(err.to_string()))?;
     let Some(model_file) = model_file else {
-      return Err(OpenAIApiError::InternalServer(format!(
-        "file required by LLM model not found in huggingface cache: filename: '{}', repo: '{}'",
-        alias.filename, alias.repo
-      )));
+      return Err(OpenAIApiError::InternalServer(match &alias.model_file {
+        Some(model_file) => {
+          BodhiError::ModelFileMissing(model_file.display().to_string()).to_string()
+        }
+        None => format!(
+          "file required by LLM model not found in huggingface cache: filename: '{}', repo: '{}'",
+          alias.filename, alias.repo
+        ),
+      }));
     };
     let tokenizer_repo = Repo::try_from(alias.chat_template.clone())
       .map_err(|err| OpenAIApiError::InternalServer(err.to_string()))?;
@@ -87,15 +485,165 @@ impl RouterStateFn for RouterState {
         TOKENIZER_CONFIG_JSON, tokenizer_repo
       )));
     };
-    self
+    self.check_memory(&alias, &model_file).await?;
+    // the requests over the slots of the model wait here, instead of all hitting the backend
+    let max_concurrent = match self.app_service.env_service().max_concurrent_requests() {
+      0 => alias.context_params.n_parallel.unwrap_or(1).max(1) as usize,
+      max_concurrent => max_concurrent,
+    };
+    let max_queued = self.app_service.env_service().max_queued_requests();
+    let _permit = self
+      .request_queue
+      .acquire(&alias.alias, max_concurrent, max_queued)
+      .await?;
+    let model = request.request.model.clone();
+    tracing::info!(model, metadata, "chat completion request");
+    let keep_alive = keep_alive(
+      request.keep_alive_secs,
+      alias.keep_alive_secs,
+      self.app_service.env_service().keep_alive_secs(),
+    );
+    let tool_calls =
+      ChatTools::from_request(&request.request).map(|tools| ToolCallParser::new(&tools));
+    let (tx, rx) = mpsc::channel::<String>(100);
+    let forwarder = tokio::spawn(forward_completion(rx, userdata, Instant::now(), tool_calls));
+    let result = self
       .ctx
-      .chat_completions(request, alias, model_file, tokenizer_file, userdata)
-      .await
-      .map_err(OpenAIApiError::ContextError)?;
+      .chat_completions(request, alias, model_file.clone(), tokenizer_file, tx)
+      .await;
+    let stats = forwarder.await.unwrap_or_default();
+    let finished_at = self.time_service.utc_now();
+    if result.is_ok() {
+      let previous = self.loaded_model.lock().ok().map(|mut loaded_model| {
+        loaded_model.replace(LoadedModel {
+          alias: model.clone(),
+          model_file: model_file.clone(),
+          expires_at: keep_alive
+            .and_then(|keep_alive| chrono::Duration::from_std(keep_alive).ok())
+            .map(|keep_alive| finished_at + keep_alive),
+        })
+      });
+      match previous {
+        Some(Some(replaced)) if replaced.model_file != model_file => {
+          self.record_eviction(replaced, EvictionReason::Replaced, finished_at);
+          self.record_load(&model);
+        }
+        // also the first request on the model loaded at startup, it is not tracked until then
+        Some(None) => self.record_load(&model),
+        _ => {}
+      }
+      self.record_completion_metrics(&model, &stats);
+    }
+    let elapsed_secs = (finished_at - started_at).num_seconds();
+    let threshold_secs = self.app_service.env_service().slow_request_secs();
+    if threshold_secs > 0 && elapsed_secs > threshold_secs as i64 {
+      tracing::warn!(
+        model,
+        request_id = stats.id,
+        metadata,
+        prompt_tokens = stats.prompt_tokens,
+        completion_tokens = stats.completion_tokens,
+        elapsed_secs,
+        threshold_secs,
+        "slow chat completion request"
+      );
+    }
+    if let Some(keep_alive) = keep_alive {
+      tokio::spawn(unload_when_idle(self.clone(), model, keep_alive));
+    }
+    result.map_err(OpenAIApiError::ContextError)?;
     Ok(())
   }
 }
 
+#[derive(Debug, Default)]
+struct CompletionStats {
+  id: Option<String>,
+  prompt_tokens: Option<u64>,
+  completion_tokens: Option<u64>,
+  first_message_after: Option<Duration>,
+  elapsed: Duration,
+}
+
+// forwards the generated messages to the client, picking up the request id and token usage
+// from the message carrying the usage, the last chunk when streaming
+async fn forward_completion(
+  mut rx: Receiver<String>,
+  userdata: Sender<String>,
+  started: Instant,
+  mut tool_calls: Option<ToolCallParser>,
+) -> CompletionStats {
+  let mut stats = CompletionStats::default();
+  while let Some(msg) = rx.recv().await {
+    if stats.first_message_after.is_none() {
+      stats.first_message_after = Some(started.elapsed());
+    }
+    if msg.contains("\"usage\"") {
+      let data = msg.strip_prefix("data: ").unwrap_or(&msg).trim();
+      if let Ok(value) = serde_json::from_str::<Value>(data) {
+        stats.id = value["id"].as_str().map(str::to_string);
+        stats.prompt_tokens = value["usage"]["prompt_tokens"].as_u64();
+        stats.completion_tokens = value["usage"]["completion_tokens"].as_u64();
+      }
+    }
+    // the tool calls in the generated text are sent as the `tool_calls` of the response
+    let msg = match tool_calls.as_mut() {
+      Some(tool_calls) => match tool_calls.rewrite(msg) {
+        Some(msg) => msg,
+        None => continue,
+      },
+      None => msg,
+    };
+    if userdata.send(msg).await.is_err() {
+      break;
+    }
+  }
+  stats.elapsed = started.elapsed();
+  stats
+}
+
+// the request setting overrides the alias setting, which overrides the server wide setting,
+// 0 disables unloading the idle model
+fn keep_alive(
+  request_keep_alive_secs: Option<u64>,
+  alias_keep_alive_secs: Option<u64>,
+  keep_alive_secs: u64,
+) -> Option<Duration> {
+  match request_keep_alive_secs
+    .or(alias_keep_alive_secs)
+    .unwrap_or(keep_alive_secs)
+  {
+    0 => None,
+    secs => Some(Duration::from_secs(secs)),
+  }
+}
+
+// every request schedules an idle check after its keep alive, the check skips the unload if
+// another request used the model since, the next request loads the model again
+async fn unload_when_idle(state: RouterState, model: String, keep_alive: Duration) {
+  tokio::time::sleep(keep_alive).await;
+  match state.ctx.unload_if_idle(keep_alive).await {
+    Ok(true) => {
+      tracing::info!(
+        model,
+        keep_alive_secs = keep_alive.as_secs(),
+        "unloaded idle model"
+      );
+      let unloaded = state
+        .loaded_model
+        .lock()
+        .ok()
+        .and_then(|mut loaded_model| loaded_model.take());
+      if let Some(unloaded) = unloaded {
+        let evicted_at = state.time_service.utc_now();
+        state.record_eviction(unloaded, EvictionReason::KeepAlive, evicted_at);
+      }
+    }
+    Ok(false) => {}
+    Err(err) => tracing::warn!(?err, model, "error unloading idle model"),
+  }
+}
+
 impl RouterState {
   pub async fn try_stop(&self) -> crate::error::Result<()> {
     self.ctx.try_stop().await?;
@@ -105,26 +653,35 @@ impl RouterState {
 
 #[cfg(test)]
 mod test {
-  use super::RouterState;
+  use super::{
+    keep_alive, unload_when_idle, ActiveRequest, Eviction, EvictionReason, LoadedModel, RouterState,
+  };
   use crate::{
-    oai::ApiError,
+    oai::{ApiError, BodhiChatRequest},
     objs::{Alias, HubFile, REFS_MAIN, TOKENIZER_CONFIG_JSON},
     server::RouterStateFn,
-    service::{MockDataService, MockEnvServiceFn, MockHubService},
+    service::{
+      MockAppServiceFn, MockDataService, MockEnvServiceFn, MockHubService, MockMemoryServiceFn,
+      SystemMemory, DEFAULT_KEEP_ALIVE_SECS, DEFAULT_MAX_CONCURRENT_REQUESTS,
+      DEFAULT_MAX_QUEUED_REQUESTS, DEFAULT_MEMORY_WATERMARK, DEFAULT_SLOW_REQUEST_SECS,
+      DEFAULT_STRICT_ALIAS,
+    },
     shared_rw::ContextError,
     test_utils::{
-      test_channel, AppServiceStubMock, MockDbService, MockSharedContext, ResponseTestExt,
+      capture_warn_logs, test_channel, write_gguf, AppServiceStubMock, MockDbService,
+      MockSharedContext, MockTimeService, ResponseTestExt,
     },
     Repo,
   };
   use async_openai::types::CreateChatCompletionRequest;
   use axum::http::StatusCode;
   use axum::response::{IntoResponse, Response};
-  use llama_server_bindings::LlamaCppError;
+  use chrono::{Duration, TimeZone, Utc};
+  use llama_server_bindings::{GptParamsBuilder, LlamaCppError};
   use mockall::predicate::{always, eq};
   use rstest::rstest;
-  use serde_json::json;
-  use std::sync::Arc;
+  use serde_json::{json, Value};
+  use std::{path::PathBuf, sync::Arc};
 
   #[rstest]
   #[tokio::test]
@@ -134,12 +691,16 @@ mod test {
       .expect_find_alias()
       .with(eq("not-found"))
       .return_once(|_| None);
+    mock_data_service
+      .expect_list_aliases()
+      .return_once(|| Ok(vec![Alias::testalias()]));
+    let mut mock_env_service = MockEnvServiceFn::new();
+    mock_env_service
+      .expect_strict_alias()
+      .return_const(DEFAULT_STRICT_ALIAS);
     let mock_ctx = MockSharedContext::default();
-    let service = AppServiceStubMock::new(
-      MockEnvServiceFn::new(),
-      MockHubService::new(),
-      mock_data_service,
-    );
+    let service =
+      AppServiceStubMock::new(mock_env_service, MockHubService::new(), mock_data_service);
     let state = RouterState::new(
       Arc::new(mock_ctx),
       Arc::new(service),
@@ -152,10 +713,14 @@ mod test {
       ]
     }})?;
     let (tx, _rx) = test_channel();
-    let result = state.chat_completions(request, tx).await;
+    let result = state.chat_completions(request.into(), tx).await;
     assert!(result.is_err());
     let response: Response = result.unwrap_err().into_response();
     assert_eq!(StatusCode::NOT_FOUND, response.status());
+    assert!(state
+      .metrics()
+      .render()
+      .contains("bodhi_model_requests_total{model=\"other\"} 1\n"));
     let response: ApiError = response.json_obj().await?;
     let expected = ApiError {
       message: "The model 'not-found' does not exist".to_string(),
@@ -167,6 +732,143 @@ mod test {
     Ok(())
   }
 
+  #[rstest]
+  #[tokio::test]
+  async fn test_router_state_chat_completions_rejects_oversized_metadata() -> anyhow::Result<()> {
+    let service = AppServiceStubMock::new(
+      MockEnvServiceFn::new(),
+      MockHubService::new(),
+      MockDataService::default(),
+    );
+    let state = RouterState::new(
+      Arc::new(MockSharedContext::default()),
+      Arc::new(service),
+      Arc::new(MockDbService::new()),
+    );
+    let request = serde_json::from_value::<BodhiChatRequest>(json! {{
+      "model": "testalias:instruct",
+      "messages": [
+        {"role": "user", "content": "What day comes after Monday?"}
+      ],
+      "metadata": {"trace_id": "v".repeat(513)}
+    }})?;
+    let (tx, _rx) = test_channel();
+    let result = state.chat_completions(request, tx).await;
+    let response: Response = result.unwrap_err().into_response();
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    let response: ApiError = response.json_obj().await?;
+    assert_eq!("invalid_request_error", response.code);
+    assert!(state.active_requests().is_empty());
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(None, 100_000, 14_096, None)]
+  #[case(None, 100_000, 14_095, Some("unload a model or close other apps"))]
+  #[case(Some("loaded.gguf"), 100_000, 14_096 - 4096, None)]
+  #[case(Some("model.gguf"), 100_000, 0, None)]
+  #[case(None, 4_000, 4_000, Some("use a smaller quantization of the model"))]
+  #[tokio::test]
+  async fn test_router_state_check_memory_refuses_load_over_watermark(
+    #[case] loaded: Option<&str>,
+    #[case] total_bytes: u64,
+    #[case] available_bytes: u64,
+    #[case] suggestion: Option<&str>,
+  ) -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let model_file = temp_dir.path().join("model.gguf");
+    // a single F32 tensor of 1024 elements, estimated at 4096 bytes
+    write_gguf(&model_file, &[&[1024]])?;
+    write_gguf(&temp_dir.path().join("loaded.gguf"), &[&[1024]])?;
+    let loaded_params = loaded
+      .map(|loaded| {
+        GptParamsBuilder::default()
+          .model(temp_dir.path().join(loaded).display().to_string())
+          .build()
+      })
+      .transpose()?;
+    let mut mock_ctx = MockSharedContext::default();
+    mock_ctx
+      .expect_get_gpt_params()
+      .return_once(move || Ok(loaded_params));
+    let mut mock_env_service = MockEnvServiceFn::new();
+    mock_env_service
+      .expect_memory_watermark()
+      .return_const(DEFAULT_MEMORY_WATERMARK);
+    let mut mock_memory_service = MockMemoryServiceFn::new();
+    mock_memory_service
+      .expect_system_memory()
+      .returning(move || {
+        Some(SystemMemory {
+          total_bytes,
+          available_bytes,
+        })
+      });
+    let service = AppServiceStubMock::new(
+      mock_env_service,
+      MockHubService::new(),
+      MockDataService::default(),
+    );
+    let mut state = RouterState::new(
+      Arc::new(mock_ctx),
+      Arc::new(service),
+      Arc::new(MockDbService::new()),
+    );
+    state.memory_service = Arc::new(mock_memory_service);
+    let result = state.check_memory(&Alias::testalias(), &model_file).await;
+    match suggestion {
+      Some(suggestion) => {
+        let err = result.unwrap_err();
+        assert!(err.to_string().contains(suggestion), "{err}");
+        assert_eq!(
+          StatusCode::SERVICE_UNAVAILABLE,
+          err.into_response().status()
+        );
+      }
+      None => assert!(result.is_ok()),
+    }
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("testalias:instruct", false, Some("testalias:instruct"))]
+  #[case("TestAlias:Instruct", false, Some("testalias:instruct"))]
+  #[case(" testalias:instruct\n", false, Some("testalias:instruct"))]
+  #[case("TestAlias:Instruct", true, None)]
+  #[case("Llama3:Instruct", false, None)]
+  fn test_router_state_find_alias_ignores_case_unless_strict(
+    #[case] model: &str,
+    #[case] strict: bool,
+    #[case] expected: Option<&str>,
+  ) -> anyhow::Result<()> {
+    let mut mock_data_service = MockDataService::default();
+    mock_data_service
+      .expect_find_alias()
+      .returning(|alias| (alias == "testalias:instruct").then(Alias::testalias));
+    mock_data_service.expect_list_aliases().returning(|| {
+      Ok(vec![
+        Alias::testalias(),
+        Alias::llama3(),
+        Alias {
+          alias: "LLAMA3:instruct".to_string(),
+          ..Alias::llama3()
+        },
+      ])
+    });
+    let mut mock_env_service = MockEnvServiceFn::new();
+    mock_env_service.expect_strict_alias().return_const(strict);
+    let service =
+      AppServiceStubMock::new(mock_env_service, MockHubService::new(), mock_data_service);
+    let state = RouterState::new(
+      Arc::new(MockSharedContext::default()),
+      Arc::new(service),
+      Arc::new(MockDbService::new()),
+    );
+    let alias = state.find_alias(model);
+    assert_eq!(expected, alias.as_ref().map(|alias| alias.alias.as_str()));
+    Ok(())
+  }
+
   #[rstest]
   #[tokio::test]
   async fn test_router_state_chat_completions_delegate_to_context_with_alias() -> anyhow::Result<()>
@@ -200,22 +902,234 @@ mod test {
     mock_ctx
       .expect_chat_completions()
       .with(
-        eq(request.clone()),
+        eq(BodhiChatRequest::from(request.clone())),
         eq(Alias::testalias()),
-        eq(HubFile::testalias()),
+        eq(HubFile::testalias().path()),
         eq(HubFile::llama3_tokenizer()),
         always(),
       )
       .return_once(|_, _, _, _, _| Ok(()));
-    let service =
-      AppServiceStubMock::new(MockEnvServiceFn::new(), mock_hub_service, mock_data_service);
-    let state = RouterState::new(
+    let mut mock_env_service = MockEnvServiceFn::new();
+    mock_env_service
+      .expect_slow_request_secs()
+      .return_const(DEFAULT_SLOW_REQUEST_SECS);
+    mock_env_service
+      .expect_keep_alive_secs()
+      .return_const(DEFAULT_KEEP_ALIVE_SECS);
+    mock_env_service.expect_memory_watermark().return_const(0u8);
+    mock_env_service
+      .expect_max_concurrent_requests()
+      .return_const(DEFAULT_MAX_CONCURRENT_REQUESTS);
+    mock_env_service
+      .expect_max_queued_requests()
+      .return_const(DEFAULT_MAX_QUEUED_REQUESTS);
+    let service = AppServiceStubMock::new(mock_env_service, mock_hub_service, mock_data_service);
+    let evicted_at = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+    let mut mock_time_service = MockTimeService::new();
+    mock_time_service.expect_utc_now().return_const(evicted_at);
+    let mut state = RouterState::new(
       Arc::new(mock_ctx),
       Arc::new(service),
       Arc::new(MockDbService::new()),
     );
+    state.time_service = Arc::new(mock_time_service);
+    let replaced = LoadedModel {
+      alias: "llama3:instruct".to_string(),
+      model_file: PathBuf::from("/models/llama3.gguf"),
+      expires_at: None,
+    };
+    *state.loaded_model.lock().unwrap() = Some(replaced.clone());
     let (tx, _rx) = test_channel();
-    state.chat_completions(request, tx).await?;
+    state.chat_completions(request.into(), tx).await?;
+    assert_eq!(
+      Some(LoadedModel {
+        alias: "testalias:instruct".to_string(),
+        model_file: HubFile::testalias().path(),
+        expires_at: None,
+      }),
+      *state.loaded_model.lock().unwrap()
+    );
+    assert_eq!(
+      vec![Eviction {
+        alias: replaced.alias,
+        model_file: replaced.model_file,
+        reason: EvictionReason::Replaced,
+        evicted_at,
+      }],
+      state.evictions()
+    );
+    let metrics = state.metrics().render();
+    for line in [
+      "bodhi_model_requests_total{model=\"testalias:instruct\"} 1\n",
+      "bodhi_model_loads_total{model=\"testalias:instruct\"} 1\n",
+      "bodhi_model_evictions_total{model=\"llama3:instruct\",reason=\"replaced\"} 1\n",
+    ] {
+      assert!(metrics.contains(line), "{line} not in {metrics}");
+    }
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(true, vec![EvictionReason::KeepAlive])]
+  #[case(false, vec![])]
+  #[tokio::test]
+  async fn test_router_state_unload_when_idle_records_keep_alive_eviction(
+    #[case] unloaded: bool,
+    #[case] expected: Vec<EvictionReason>,
+  ) -> anyhow::Result<()> {
+    let mut mock_ctx = MockSharedContext::default();
+    mock_ctx
+      .expect_unload_if_idle()
+      .return_once(move |_| Ok(unloaded));
+    let mut state = RouterState::new(
+      Arc::new(mock_ctx),
+      Arc::new(MockAppServiceFn::new()),
+      Arc::new(MockDbService::new()),
+    );
+    let mut mock_time_service = MockTimeService::new();
+    mock_time_service.expect_utc_now().return_const(Utc::now());
+    state.time_service = Arc::new(mock_time_service);
+    *state.loaded_model.lock().unwrap() = Some(LoadedModel {
+      alias: "testalias:instruct".to_string(),
+      model_file: PathBuf::from("/models/testalias.gguf"),
+      expires_at: None,
+    });
+    unload_when_idle(
+      state.clone(),
+      "testalias:instruct".to_string(),
+      std::time::Duration::from_millis(1),
+    )
+    .await;
+    assert_eq!(
+      expected,
+      state
+        .evictions()
+        .iter()
+        .map(|eviction| eviction.reason)
+        .collect::<Vec<_>>()
+    );
+    assert_eq!(!unloaded, state.loaded_model.lock().unwrap().is_some());
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("testalias:instruct", true)]
+  #[case("llama3:instruct", false)]
+  #[tokio::test]
+  async fn test_router_state_unload_alias_records_deleted_eviction(
+    #[case] alias: &str,
+    #[case] unloaded: bool,
+  ) -> anyhow::Result<()> {
+    let mut mock_ctx = MockSharedContext::default();
+    mock_ctx.expect_get_gpt_params().returning(|| {
+      Ok(Some(
+        GptParamsBuilder::default()
+          .model("/models/testalias.gguf".to_string())
+          .build()
+          .unwrap(),
+      ))
+    });
+    mock_ctx
+      .expect_try_stop()
+      .times(unloaded as usize)
+      .returning(|| Ok(()));
+    let mut state = RouterState::new(
+      Arc::new(mock_ctx),
+      Arc::new(MockAppServiceFn::new()),
+      Arc::new(MockDbService::new()),
+    );
+    let mut mock_time_service = MockTimeService::new();
+    mock_time_service.expect_utc_now().return_const(Utc::now());
+    state.time_service = Arc::new(mock_time_service);
+    *state.loaded_model.lock().unwrap() = Some(LoadedModel {
+      alias: "testalias:instruct".to_string(),
+      model_file: PathBuf::from("/models/testalias.gguf"),
+      expires_at: None,
+    });
+    assert_eq!(unloaded, state.unload_alias(alias).await?);
+    let expected = if unloaded {
+      vec![EvictionReason::Deleted]
+    } else {
+      vec![]
+    };
+    assert_eq!(
+      expected,
+      state
+        .evictions()
+        .iter()
+        .map(|eviction| eviction.reason)
+        .collect::<Vec<_>>()
+    );
+    assert_eq!(!unloaded, state.loaded_model.lock().unwrap().is_some());
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(None, None, None)]
+  #[case(
+    Some("/models/testalias.gguf"),
+    None,
+    Some(LoadedModel {
+      alias: "testalias".to_string(),
+      model_file: PathBuf::from("/models/testalias.gguf"),
+      expires_at: None,
+    })
+  )]
+  #[case(
+    Some("/models/testalias.gguf"),
+    Some(LoadedModel {
+      alias: "testalias:instruct".to_string(),
+      model_file: PathBuf::from("/models/testalias.gguf"),
+      expires_at: Some(Utc.with_ymd_and_hms(2024, 1, 1, 0, 5, 0).unwrap()),
+    }),
+    Some(LoadedModel {
+      alias: "testalias:instruct".to_string(),
+      model_file: PathBuf::from("/models/testalias.gguf"),
+      expires_at: Some(Utc.with_ymd_and_hms(2024, 1, 1, 0, 5, 0).unwrap()),
+    })
+  )]
+  #[case(
+    Some("/models/other.gguf"),
+    Some(LoadedModel {
+      alias: "testalias:instruct".to_string(),
+      model_file: PathBuf::from("/models/testalias.gguf"),
+      expires_at: None,
+    }),
+    Some(LoadedModel {
+      alias: "other".to_string(),
+      model_file: PathBuf::from("/models/other.gguf"),
+      expires_at: None,
+    })
+  )]
+  #[tokio::test]
+  async fn test_router_state_loaded_model(
+    #[case] loaded: Option<&str>,
+    #[case] last_completed: Option<LoadedModel>,
+    #[case] expected: Option<LoadedModel>,
+  ) -> anyhow::Result<()> {
+    let gpt_params = loaded
+      .map(|loaded| {
+        GptParamsBuilder::default()
+          .model(loaded.to_string())
+          .build()
+      })
+      .transpose()?;
+    let mut mock_ctx = MockSharedContext::default();
+    mock_ctx
+      .expect_get_gpt_params()
+      .return_once(move || Ok(gpt_params));
+    let service = AppServiceStubMock::new(
+      MockEnvServiceFn::new(),
+      MockHubService::new(),
+      MockDataService::default(),
+    );
+    let state = RouterState::new(
+      Arc::new(mock_ctx),
+      Arc::new(service),
+      Arc::new(MockDbService::new()),
+    );
+    *state.loaded_model.lock().unwrap() = last_completed;
+    assert_eq!(expected, state.loaded_model().await?);
     Ok(())
   }
 
@@ -252,9 +1166,9 @@ mod test {
     mock_ctx
       .expect_chat_completions()
       .with(
-        eq(request.clone()),
+        eq(BodhiChatRequest::from(request.clone())),
         eq(Alias::testalias()),
-        eq(HubFile::testalias()),
+        eq(HubFile::testalias().path()),
         eq(HubFile::llama3_tokenizer()),
         always(),
       )
@@ -263,14 +1177,27 @@ mod test {
           LlamaCppError::BodhiServerChatCompletion("test error".to_string()),
         ))
       });
-    let service =
-      AppServiceStubMock::new(MockEnvServiceFn::new(), mock_hub_service, mock_data_service);
+    let mut mock_env_service = MockEnvServiceFn::new();
+    mock_env_service
+      .expect_slow_request_secs()
+      .return_const(DEFAULT_SLOW_REQUEST_SECS);
+    mock_env_service
+      .expect_keep_alive_secs()
+      .return_const(DEFAULT_KEEP_ALIVE_SECS);
+    mock_env_service.expect_memory_watermark().return_const(0u8);
+    mock_env_service
+      .expect_max_concurrent_requests()
+      .return_const(DEFAULT_MAX_CONCURRENT_REQUESTS);
+    mock_env_service
+      .expect_max_queued_requests()
+      .return_const(DEFAULT_MAX_QUEUED_REQUESTS);
+    let service = AppServiceStubMock::new(mock_env_service, mock_hub_service, mock_data_service);
     let state = RouterState::new(
       Arc::new(mock_ctx),
       Arc::new(service),
       Arc::new(MockDbService::new()),
     );
-    let result = state.chat_completions(request, tx).await;
+    let result = state.chat_completions(request.into(), tx).await;
     assert!(result.is_err());
     let response = result.unwrap_err().into_response();
     assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
@@ -285,4 +1212,307 @@ mod test {
     );
     Ok(())
   }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_router_state_chat_completions_turns_away_request_when_queue_full(
+  ) -> anyhow::Result<()> {
+    let mut mock_data_service = MockDataService::new();
+    mock_data_service
+      .expect_find_alias()
+      .with(eq("testalias:instruct"))
+      .return_once(|_| Some(Alias::testalias()));
+    let testalias = Alias::testalias();
+    let mut mock_hub_service = MockHubService::new();
+    mock_hub_service
+      .expect_find_local_file()
+      .with(
+        eq(testalias.repo),
+        eq(testalias.filename),
+        eq(testalias.snapshot),
+      )
+      .return_once(|_, _, _| Ok(Some(HubFile::testalias())));
+    mock_hub_service
+      .expect_find_local_file()
+      .with(eq(Repo::llama3()), eq(TOKENIZER_CONFIG_JSON), eq(REFS_MAIN))
+      .return_once(|_, _, _| Ok(Some(HubFile::llama3_tokenizer())));
+    let mut mock_env_service = MockEnvServiceFn::new();
+    mock_env_service.expect_memory_watermark().return_const(0u8);
+    mock_env_service
+      .expect_max_concurrent_requests()
+      .return_const(1usize);
+    mock_env_service
+      .expect_max_queued_requests()
+      .return_const(0usize);
+    let service = AppServiceStubMock::new(mock_env_service, mock_hub_service, mock_data_service);
+    let state = RouterState::new(
+      Arc::new(MockSharedContext::default()),
+      Arc::new(service),
+      Arc::new(MockDbService::new()),
+    );
+    let _running = state
+      .request_queue
+      .acquire("testalias:instruct", 1, 0)
+      .await?;
+    let request = serde_json::from_value::<CreateChatCompletionRequest>(json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}]
+    }})?;
+    let (tx, _rx) = test_channel();
+    let response = state
+      .chat_completions(request.into(), tx)
+      .await
+      .unwrap_err()
+      .into_response();
+    assert_eq!(StatusCode::SERVICE_UNAVAILABLE, response.status());
+    assert_eq!("5", response.headers().get("retry-after").unwrap());
+    assert_eq!("queue_full", response.json::<ApiError>().await?.code);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_router_state_chat_completions_returns_tool_calls() -> anyhow::Result<()> {
+    let mut mock_data_service = MockDataService::new();
+    mock_data_service
+      .expect_find_alias()
+      .with(eq("testalias:instruct"))
+      .return_once(|_| Some(Alias::testalias()));
+    let testalias = Alias::testalias();
+    let mut mock_hub_service = MockHubService::new();
+    mock_hub_service
+      .expect_find_local_file()
+      .with(
+        eq(testalias.repo),
+        eq(testalias.filename),
+        eq(testalias.snapshot),
+      )
+      .return_once(|_, _, _| Ok(Some(HubFile::testalias())));
+    mock_hub_service
+      .expect_find_local_file()
+      .with(eq(Repo::llama3()), eq(TOKENIZER_CONFIG_JSON), eq(REFS_MAIN))
+      .return_once(|_, _, _| Ok(Some(HubFile::llama3_tokenizer())));
+    let mut mock_env_service = MockEnvServiceFn::new();
+    mock_env_service
+      .expect_slow_request_secs()
+      .return_const(DEFAULT_SLOW_REQUEST_SECS);
+    mock_env_service
+      .expect_keep_alive_secs()
+      .return_const(DEFAULT_KEEP_ALIVE_SECS);
+    mock_env_service.expect_memory_watermark().return_const(0u8);
+    mock_env_service
+      .expect_max_concurrent_requests()
+      .return_const(DEFAULT_MAX_CONCURRENT_REQUESTS);
+    mock_env_service
+      .expect_max_queued_requests()
+      .return_const(DEFAULT_MAX_QUEUED_REQUESTS);
+    let mut mock_ctx = MockSharedContext::default();
+    mock_ctx.expect_chat_completions().return_once(
+      |_, _, _, _, userdata: tokio::sync::mpsc::Sender<String>| {
+        let response = json! {{
+          "id": "chatcmpl-tools",
+          "choices": [{
+            "index": 0,
+            "finish_reason": "stop",
+            "message": {
+              "role": "assistant",
+              "content": "{\"name\": \"get_weather\", \"parameters\": {\"city\": \"Paris\"}}",
+            },
+          }],
+        }};
+        userdata.try_send(response.to_string()).unwrap();
+        Ok(())
+      },
+    );
+    let service = AppServiceStubMock::new(mock_env_service, mock_hub_service, mock_data_service);
+    let state = RouterState::new(
+      Arc::new(mock_ctx),
+      Arc::new(service),
+      Arc::new(MockDbService::new()),
+    );
+    let request = serde_json::from_value::<BodhiChatRequest>(json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What is the weather in Paris?"}],
+      "tools": [{"type": "function", "function": {"name": "get_weather"}}],
+    }})?;
+    let (tx, mut rx) = test_channel();
+    state.chat_completions(request, tx).await?;
+    let response = serde_json::from_str::<Value>(&rx.recv().await.unwrap())?;
+    let choice = &response["choices"][0];
+    assert_eq!("tool_calls", choice["finish_reason"]);
+    assert_eq!(Value::Null, choice["message"]["content"]);
+    assert_eq!(
+      json! {{"name": "get_weather", "arguments": "{\"city\": \"Paris\"}"}},
+      choice["message"]["tool_calls"][0]["function"]
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(120, true)]
+  #[case(1, false)]
+  #[tokio::test]
+  async fn test_router_state_chat_completions_logs_slow_request(
+    #[case] elapsed_secs: i64,
+    #[case] logged: bool,
+  ) -> anyhow::Result<()> {
+    let mut mock_data_service = MockDataService::new();
+    mock_data_service
+      .expect_find_alias()
+      .with(eq("testalias:instruct"))
+      .return_once(|_| Some(Alias::testalias()));
+    let testalias = Alias::testalias();
+    let mut mock_hub_service = MockHubService::new();
+    mock_hub_service
+      .expect_find_local_file()
+      .with(
+        eq(testalias.repo),
+        eq(testalias.filename),
+        eq(testalias.snapshot),
+      )
+      .return_once(|_, _, _| Ok(Some(HubFile::testalias())));
+    mock_hub_service
+      .expect_find_local_file()
+      .with(eq(Repo::llama3()), eq(TOKENIZER_CONFIG_JSON), eq(REFS_MAIN))
+      .return_once(|_, _, _| Ok(Some(HubFile::llama3_tokenizer())));
+    let mut mock_env_service = MockEnvServiceFn::new();
+    mock_env_service
+      .expect_slow_request_secs()
+      .return_const(DEFAULT_SLOW_REQUEST_SECS);
+    mock_env_service
+      .expect_keep_alive_secs()
+      .return_const(DEFAULT_KEEP_ALIVE_SECS);
+    mock_env_service.expect_memory_watermark().return_const(0u8);
+    mock_env_service
+      .expect_max_concurrent_requests()
+      .return_const(DEFAULT_MAX_CONCURRENT_REQUESTS);
+    mock_env_service
+      .expect_max_queued_requests()
+      .return_const(DEFAULT_MAX_QUEUED_REQUESTS);
+    let mut mock_ctx = MockSharedContext::default();
+    mock_ctx.expect_chat_completions().return_once(
+      |_, _, _, _, userdata: tokio::sync::mpsc::Sender<String>| {
+        let response = json! {{
+          "id": "chatcmpl-slow",
+          "usage": {"prompt_tokens": 15, "completion_tokens": 13, "total_tokens": 28},
+        }};
+        userdata.try_send(response.to_string()).unwrap();
+        Ok(())
+      },
+    );
+    let started_at = Utc::now();
+    let mut mock_time_service = MockTimeService::new();
+    let mut times = vec![started_at + Duration::seconds(elapsed_secs), started_at];
+    mock_time_service
+      .expect_utc_now()
+      .times(2)
+      .returning(move || times.pop().unwrap());
+    let service = AppServiceStubMock::new(mock_env_service, mock_hub_service, mock_data_service);
+    let mut state = RouterState::new(
+      Arc::new(mock_ctx),
+      Arc::new(service),
+      Arc::new(MockDbService::new()),
+    );
+    state.time_service = Arc::new(mock_time_service);
+    let request = serde_json::from_value::<BodhiChatRequest>(json! {{
+      "model": "testalias:instruct",
+      "messages": [
+        {"role": "user", "content": "What day comes after Monday?"}
+      ],
+      "metadata": {"trace_id": "trace-1"}
+    }})?;
+    let (tx, mut rx) = test_channel();
+    let (logs, _guard) = capture_warn_logs();
+    state.chat_completions(request, tx).await?;
+    assert!(rx.recv().await.unwrap().contains("chatcmpl-slow"));
+    let logs = logs.contents();
+    assert_eq!(logged, logs.contains("slow chat completion request"));
+    if logged {
+      assert!(logs.contains(r#"model="testalias:instruct""#));
+      assert!(logs.contains(r#"request_id="chatcmpl-slow""#));
+      assert!(logs.contains("trace-1"));
+      assert!(logs.contains("prompt_tokens=15"));
+      assert!(logs.contains("completion_tokens=13"));
+      assert!(logs.contains("elapsed_secs=120"));
+    }
+    let metrics = state.metrics().render();
+    assert!(metrics.contains("bodhi_prompt_tokens_total{model=\"testalias:instruct\"} 15\n"));
+    assert!(metrics.contains("bodhi_completion_tokens_total{model=\"testalias:instruct\"} 13\n"));
+    assert!(
+      metrics.contains("bodhi_time_to_first_token_seconds_count{model=\"testalias:instruct\"} 1\n")
+    );
+    assert!(metrics.contains("bodhi_generation_seconds_count{model=\"testalias:instruct\"} 1\n"));
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(None, None, 0, None)]
+  #[case(None, None, 300, Some(300))]
+  #[case(None, Some(60), 300, Some(60))]
+  #[case(None, Some(0), 300, None)]
+  #[case(None, Some(600), 0, Some(600))]
+  #[case(Some(30), Some(60), 300, Some(30))]
+  #[case(Some(30), None, 300, Some(30))]
+  #[case(Some(0), Some(60), 300, None)]
+  #[case(Some(900), Some(0), 0, Some(900))]
+  fn test_router_state_keep_alive(
+    #[case] request_keep_alive_secs: Option<u64>,
+    #[case] alias_keep_alive_secs: Option<u64>,
+    #[case] keep_alive_secs: u64,
+    #[case] expected: Option<u64>,
+  ) {
+    assert_eq!(
+      expected.map(std::time::Duration::from_secs),
+      keep_alive(
+        request_keep_alive_secs,
+        alias_keep_alive_secs,
+        keep_alive_secs
+      )
+    );
+  }
+
+  #[rstest]
+  fn test_router_state_uptime_secs() {
+    let mut router_state = RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(MockAppServiceFn::new()),
+      Arc::new(MockDbService::new()),
+    );
+    let mut mock_time_service = MockTimeService::new();
+    mock_time_service
+      .expect_utc_now()
+      .return_const(router_state.started_at + Duration::seconds(90));
+    router_state.time_service = Arc::new(mock_time_service);
+    assert_eq!(90, router_state.uptime_secs());
+  }
+
+  #[rstest]
+  fn test_router_state_tracks_active_requests_till_done() {
+    let router_state = RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(MockAppServiceFn::new()),
+      Arc::new(MockDbService::new()),
+    );
+    let started_at = Utc::now();
+    let first = router_state.track_request("testalias:instruct", started_at);
+    let second = router_state.track_request("llama3:instruct", started_at + Duration::seconds(1));
+    assert_eq!(
+      vec!["testalias:instruct", "llama3:instruct"],
+      router_state
+        .active_requests()
+        .iter()
+        .map(|request| request.model.as_str())
+        .collect::<Vec<_>>()
+    );
+    drop(first);
+    assert_eq!(
+      vec![ActiveRequest {
+        model: "llama3:instruct".to_string(),
+        started_at: started_at + Duration::seconds(1),
+      }],
+      router_state.active_requests()
+    );
+    drop(second);
+    assert!(router_state.active_requests().is_empty());
+  }
 }
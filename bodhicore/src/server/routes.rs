@@ -1,45 +1,531 @@
 use super::{
-  super::{db::DbServiceFn, service::AppServiceFn, SharedContextRwFn},
+  response_cache::{with_response_cache, ResponseCache, X_CACHE},
   router_state::RouterState,
-  routes_chat::chat_completions_handler,
-  routes_models::{oai_model_handler, oai_models_handler},
+  routes_chat::{
+    chat_completions_handler, chat_stream_route, conversation_id, X_BODHI_COMPLETION_TOKENS,
+    X_BODHI_CONVERSATION_ID, X_BODHI_FINISH_REASON, X_BODHI_PROMPT_TOKENS, X_BODHI_TOTAL_TOKENS,
+    X_CONVERSATION_ID,
+  },
+  routes_completions::{completions_handler, COMPLETION_REQUEST_FIELDS},
+  routes_health::{health_handler, ready_handler},
+  routes_inference::inference_router,
+  routes_messages::messages_handler,
+  routes_metrics::{metrics_handler, track_requests},
+  routes_mock::{mock_router, MockConfig},
+  routes_models::{models_router, oai_model_handler, oai_models_handler},
+  routes_ollama::{generate_handler, ps_handler, show_handler, tags_handler},
+  routes_server::server_router,
   routes_ui::chats_router,
+  RouterStateFn,
 };
+use crate::oai::{unknown_fields, OpenAIApiError, CHAT_REQUEST_FIELDS};
 use axum::{
-  routing::{get, post},
+  body::{to_bytes, Body},
+  http::{
+    header::{AUTHORIZATION, CACHE_CONTROL, CONTENT_TYPE, RETRY_AFTER},
+    HeaderName, HeaderValue, Method, Request,
+  },
+  middleware::{from_fn, from_fn_with_state, Next},
+  response::{IntoResponse, Response},
+  routing::{get, post, MethodRouter},
   Router,
 };
-use std::sync::Arc;
-use tower_http::cors::{Any, CorsLayer};
+use serde_json::Value;
+use std::{sync::Arc, time::Duration};
+use tower_http::compression::{
+  predicate::{DefaultPredicate, NotForContentType, Predicate},
+  CompressionLayer,
+};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
 use tower_http::trace::TraceLayer;
+use tracing::Span;
+
+pub(crate) static X_REQUEST_ID: &str = "x-request-id";
+// same as the default body limit of the axum json extractor
+pub(crate) static MAX_REQUEST_BODY_BYTES: usize = 2 * 1024 * 1024;
 
-pub fn build_routes(
-  ctx: Arc<dyn SharedContextRwFn>,
-  app_service: Arc<dyn AppServiceFn>,
-  db_service: Arc<dyn DbServiceFn>,
-  static_router: Option<Router>,
-) -> Router {
-  let state = RouterState::new(ctx, app_service, db_service);
-  let api_router = Router::new().merge(chats_router());
+// the state is shared with the server, to drain its in-flight requests on shutdown
+pub fn build_routes(state: RouterState, static_router: Option<Router>) -> Router {
+  let env_service = state.app_service.env_service();
+  let cors = cors_layer(&env_service.cors_allowed_origins());
+  let strict_params = env_service.strict_params();
+  let compression_enabled = env_service.compression_enabled();
+  let response_cache = env_service.response_cache_enabled().then(|| {
+    Arc::new(ResponseCache::new(
+      Duration::from_secs(env_service.response_cache_ttl_secs()),
+      env_service.response_cache_max_entries(),
+    ))
+  });
+  let state: Arc<dyn RouterStateFn> = Arc::new(state);
+  let api_router = Router::new()
+    .merge(chats_router())
+    .merge(inference_router())
+    .merge(models_router())
+    .merge(server_router());
   let router = Router::new()
     .route("/ping", get(|| async { "pong" }))
+    .route("/health", get(health_handler))
+    .route("/ready", get(ready_handler))
     .nest("/api/ui", api_router)
-    .route("/v1/models", get(oai_models_handler))
-    .route("/v1/models/:id", get(oai_model_handler))
-    .route("/v1/chat/completions", post(chat_completions_handler))
-    .layer(
-      CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods(Any)
-        .allow_headers(Any)
-        .allow_credentials(false),
-    )
-    .layer(TraceLayer::new_for_http())
-    .with_state(Arc::new(state));
+    .route("/v1/messages", post(messages_handler))
+    .route("/api/tags", get(tags_handler))
+    .route("/api/ps", get(ps_handler))
+    .route("/api/show", post(show_handler))
+    .route("/api/generate", post(generate_handler));
+  let router = if env_service.mock() {
+    tracing::warn!(
+      "mock mode enabled, the OpenAI chat, completions and models endpoints return canned responses"
+    );
+    router.merge(mock_router(MockConfig {
+      text: env_service.mock_text(),
+      token_delay: Duration::from_millis(env_service.mock_token_delay_ms()),
+    }))
+  } else {
+    router
+      .route("/v1/models", get(oai_models_handler))
+      .route("/v1/models/:id", get(oai_model_handler))
+      .route(
+        "/v1/chat/completions",
+        known_fields(
+          with_response_cache(
+            post(chat_completions_handler),
+            response_cache,
+            state.clone(),
+          ),
+          CHAT_REQUEST_FIELDS,
+          strict_params,
+        ),
+      )
+      .route("/v1/chat/stream", chat_stream_route(strict_params))
+      .route(
+        "/v1/completions",
+        known_fields(
+          post(completions_handler),
+          COMPLETION_REQUEST_FIELDS,
+          strict_params,
+        ),
+      )
+  };
+  let router = if env_service.metrics_enabled() {
+    router.route("/metrics", get(metrics_handler))
+  } else {
+    router
+  };
+  let router = router
+    .route_layer(from_fn_with_state(state.clone(), track_requests))
+    .layer(cors);
+  let router = with_request_id(router).with_state(state);
   let router = if let Some(static_router) = static_router {
     router.merge(static_router)
   } else {
     router
   };
+  with_compression(router, compression_enabled)
+}
+
+// compresses the UI files and the json responses with gzip or brotli, as accepted by the client.
+// The SSE and the Ollama json lines are streamed as generated, compressing them would hold back
+// the chunks till the encoder flushes
+fn with_compression(router: Router, enabled: bool) -> Router {
+  if !enabled {
+    return router;
+  }
+  let predicate = DefaultPredicate::new()
+    .and(NotForContentType::new("text/event-stream"))
+    .and(NotForContentType::new("application/x-ndjson"));
+  router.layer(
+    CompressionLayer::new()
+      .gzip(true)
+      .br(true)
+      .compress_when(predicate),
+  )
+}
+
+// when strict, rejects the requests with fields outside the known fields, listing them in the
+// error, otherwise the unknown fields are ignored when parsing the request
+fn known_fields<S: Clone + Send + Sync + 'static>(
+  route: MethodRouter<S>,
+  known: &'static [&'static str],
+  strict: bool,
+) -> MethodRouter<S> {
+  if !strict {
+    return route;
+  }
+  route.route_layer(from_fn(move |request, next| {
+    reject_unknown_fields(known, request, next)
+  }))
+}
+
+async fn reject_unknown_fields(
+  known: &'static [&'static str],
+  request: Request<Body>,
+  next: Next,
+) -> Response {
+  let (parts, body) = request.into_parts();
+  let bytes = match to_bytes(body, MAX_REQUEST_BODY_BYTES).await {
+    Ok(bytes) => bytes,
+    Err(err) => return OpenAIApiError::BadRequest(err.to_string()).into_response(),
+  };
+  // an invalid json body is left to the handler to report
+  if let Ok(value) = serde_json::from_slice::<Value>(&bytes) {
+    let unknown = unknown_fields(&value, known);
+    if !unknown.is_empty() {
+      return OpenAIApiError::BadRequest(format!(
+        "unsupported fields in the request: {}",
+        unknown.join(", ")
+      ))
+      .into_response();
+    }
+  }
+  next
+    .run(Request::from_parts(parts, Body::from(bytes)))
+    .await
+}
+
+// the request id from the `X-Request-Id` header, or a generated uuid, is recorded on the span of
+// the request, so all its log lines carry the id, and is sent back in the response header
+fn with_request_id<S: Clone + Send + Sync + 'static>(router: Router<S>) -> Router<S> {
   router
+    .layer(PropagateRequestIdLayer::x_request_id())
+    .layer(TraceLayer::new_for_http().make_span_with(request_span))
+    .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+}
+
+// the conversation id is only recorded when the client sends it
+fn request_span(request: &Request<Body>) -> Span {
+  let request_id = request
+    .headers()
+    .get(X_REQUEST_ID)
+    .and_then(|value| value.to_str().ok())
+    .unwrap_or_default();
+  let span = tracing::info_span!(
+    "request",
+    method = %request.method(),
+    uri = %request.uri(),
+    request_id = %request_id,
+    conversation_id = tracing::field::Empty,
+  );
+  if let Some(conversation_id) = conversation_id(request.headers()) {
+    span.record("conversation_id", tracing::field::display(&conversation_id));
+  }
+  span
+}
+
+// without allowed origins, no CORS headers are sent and the browser only allows the same origin,
+// the token and finish reason headers are exposed along with the SSE headers for EventSource
+fn cors_layer(allowed_origins: &[String]) -> CorsLayer {
+  let cors = CorsLayer::new()
+    .allow_methods([
+      Method::GET,
+      Method::POST,
+      Method::PUT,
+      Method::PATCH,
+      Method::DELETE,
+    ])
+    .allow_headers([
+      AUTHORIZATION,
+      CONTENT_TYPE,
+      HeaderName::from_static(X_CONVERSATION_ID),
+      HeaderName::from_static(X_BODHI_CONVERSATION_ID),
+    ])
+    .expose_headers([
+      CONTENT_TYPE,
+      CACHE_CONTROL,
+      RETRY_AFTER,
+      HeaderName::from_static(X_BODHI_PROMPT_TOKENS),
+      HeaderName::from_static(X_BODHI_COMPLETION_TOKENS),
+      HeaderName::from_static(X_BODHI_TOTAL_TOKENS),
+      HeaderName::from_static(X_BODHI_FINISH_REASON),
+      HeaderName::from_static(X_CACHE),
+      HeaderName::from_static(X_REQUEST_ID),
+    ])
+    .allow_credentials(false);
+  if allowed_origins.iter().any(|origin| origin == "*") {
+    return cors.allow_origin(AllowOrigin::any());
+  }
+  let origins = allowed_origins
+    .iter()
+    .filter_map(|origin| match HeaderValue::from_str(origin) {
+      Ok(origin) => Some(origin),
+      Err(_) => {
+        tracing::warn!(origin, "ignoring invalid CORS allowed origin");
+        None
+      }
+    })
+    .collect::<Vec<_>>();
+  cors.allow_origin(AllowOrigin::list(origins))
+}
+
+#[cfg(test)]
+mod test {
+  use super::{cors_layer, known_fields, with_compression, with_request_id, X_REQUEST_ID};
+  use crate::{
+    oai::{ApiError, CHAT_REQUEST_FIELDS},
+    test_utils::{capture_logs, RequestTestExt, ResponseTestExt},
+  };
+  use axum::{
+    body::Body,
+    http::{
+      header::{
+        ACCEPT_ENCODING, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_ORIGIN,
+        ACCESS_CONTROL_EXPOSE_HEADERS, ACCESS_CONTROL_REQUEST_HEADERS,
+        ACCESS_CONTROL_REQUEST_METHOD, CONTENT_ENCODING, CONTENT_TYPE, ORIGIN,
+      },
+      Method, Request, StatusCode,
+    },
+    response::sse::{Event, Sse},
+    routing::{get, post},
+    Json, Router,
+  };
+  use rstest::rstest;
+  use serde_json::json;
+  use std::convert::Infallible;
+  use tower::ServiceExt;
+  use tracing::Level;
+
+  fn cors_router(allowed_origins: &[&str]) -> Router {
+    let allowed_origins = allowed_origins
+      .iter()
+      .map(|origin| origin.to_string())
+      .collect::<Vec<_>>();
+    Router::new()
+      .route("/v1/chat/completions", post(|| async { "ok" }))
+      .layer(cors_layer(&allowed_origins))
+  }
+
+  #[rstest]
+  #[case(&[], "https://chat.example.com", None)]
+  #[case(&["*"], "https://chat.example.com", Some("*"))]
+  #[case(
+    &["https://chat.example.com", "http://localhost:3000"],
+    "https://chat.example.com",
+    Some("https://chat.example.com")
+  )]
+  #[case(&["https://chat.example.com"], "https://evil.example.com", None)]
+  #[tokio::test]
+  async fn test_routes_cors_preflight(
+    #[case] allowed_origins: &[&str],
+    #[case] origin: &str,
+    #[case] expected: Option<&str>,
+  ) -> anyhow::Result<()> {
+    let response = cors_router(allowed_origins)
+      .oneshot(
+        Request::builder()
+          .method(Method::OPTIONS)
+          .uri("/v1/chat/completions")
+          .header(ORIGIN, origin)
+          .header(ACCESS_CONTROL_REQUEST_METHOD, "POST")
+          .header(ACCESS_CONTROL_REQUEST_HEADERS, "authorization,content-type")
+          .body(Body::empty())?,
+      )
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let allow_origin = response
+      .headers()
+      .get(ACCESS_CONTROL_ALLOW_ORIGIN)
+      .map(|value| value.to_str())
+      .transpose()?;
+    assert_eq!(expected, allow_origin);
+    if expected.is_some() {
+      let allow_headers = response.headers()[ACCESS_CONTROL_ALLOW_HEADERS].to_str()?;
+      assert!(allow_headers.contains("authorization"));
+      assert!(allow_headers.contains("content-type"));
+    }
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_routes_cors_exposes_headers() -> anyhow::Result<()> {
+    let response = cors_router(&["https://chat.example.com"])
+      .oneshot(
+        Request::post("/v1/chat/completions")
+          .header(ORIGIN, "https://chat.example.com")
+          .body(Body::empty())?,
+      )
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let expose_headers = response.headers()[ACCESS_CONTROL_EXPOSE_HEADERS].to_str()?;
+    for header in [
+      "content-type",
+      "cache-control",
+      "retry-after",
+      "x-bodhi-total-tokens",
+      "x-request-id",
+    ] {
+      assert!(expose_headers.contains(header), "{header} not exposed");
+    }
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(Some("req-123"))]
+  #[case(None)]
+  #[tokio::test]
+  async fn test_routes_request_id_in_response_and_logs(
+    #[case] request_id: Option<&str>,
+  ) -> anyhow::Result<()> {
+    let router = with_request_id(Router::new().route(
+      "/v1/chat/completions",
+      post(|| async {
+        tracing::info!("handling chat completion");
+        "ok"
+      }),
+    ));
+    let mut request = Request::post("/v1/chat/completions");
+    if let Some(request_id) = request_id {
+      request = request.header(X_REQUEST_ID, request_id);
+    }
+    let (logs, _guard) = capture_logs(Level::INFO);
+    let response = router.oneshot(request.body(Body::empty())?).await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response_id = response.headers()[X_REQUEST_ID].to_str()?;
+    match request_id {
+      Some(request_id) => assert_eq!(request_id, response_id),
+      None => assert_eq!(36, response_id.len()),
+    }
+    let logs = logs.contents();
+    let log_line = logs
+      .lines()
+      .find(|line| line.contains("handling chat completion"))
+      .unwrap();
+    assert!(
+      log_line.contains(&format!("request_id={response_id}")),
+      "{log_line}"
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(Some("convo-1"), Some("conversation_id=convo-1"))]
+  #[case(None, None)]
+  #[tokio::test]
+  async fn test_routes_conversation_id_in_logs(
+    #[case] conversation_id: Option<&str>,
+    #[case] expected: Option<&str>,
+  ) -> anyhow::Result<()> {
+    let router = with_request_id(Router::new().route(
+      "/v1/chat/completions",
+      post(|| async {
+        tracing::info!("handling chat completion");
+        "ok"
+      }),
+    ));
+    let mut request = Request::post("/v1/chat/completions");
+    if let Some(conversation_id) = conversation_id {
+      request = request.header("X-Conversation-Id", conversation_id);
+    }
+    let (logs, _guard) = capture_logs(Level::INFO);
+    let response = router.oneshot(request.body(Body::empty())?).await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let logs = logs.contents();
+    let log_line = logs
+      .lines()
+      .find(|line| line.contains("handling chat completion"))
+      .unwrap();
+    match expected {
+      Some(expected) => assert!(log_line.contains(expected), "{log_line}"),
+      None => assert!(!log_line.contains("conversation_id"), "{log_line}"),
+    }
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(false, json! {{"model": "testalias:instruct", "messages": [], "service_tier": "auto"}}, None)]
+  #[case(true, json! {{"model": "testalias:instruct", "messages": [], "top_k": 40}}, None)]
+  #[case(
+    true,
+    json! {{"model": "testalias:instruct", "messages": [], "service_tier": "auto"}},
+    Some("unsupported fields in the request: service_tier")
+  )]
+  #[tokio::test]
+  async fn test_routes_known_fields_policy(
+    #[case] strict: bool,
+    #[case] body: serde_json::Value,
+    #[case] expected: Option<&str>,
+  ) -> anyhow::Result<()> {
+    let router = Router::new().route(
+      "/v1/chat/completions",
+      known_fields(
+        post(|body: String| async move { body }),
+        CHAT_REQUEST_FIELDS,
+        strict,
+      ),
+    );
+    let response = router
+      .oneshot(Request::post("/v1/chat/completions").json(&body)?)
+      .await?;
+    match expected {
+      None => {
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(body, response.json::<serde_json::Value>().await?);
+      }
+      Some(message) => {
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        assert_eq!(message, response.json::<ApiError>().await?.message);
+      }
+    }
+    Ok(())
+  }
+
+  fn compression_router(enabled: bool) -> Router {
+    let models = json! {{"object": "list", "data": vec![json! {{"id": "testalias:instruct", "object": "model"}}; 50]}};
+    let router = Router::new()
+      .route("/v1/models", get(move || async move { Json(models) }))
+      .route(
+        "/v1/chat/completions",
+        post(|| async {
+          let events = futures_util::stream::iter(
+            (0..50).map(|i| Ok::<_, Infallible>(Event::default().data(format!("chunk {i}")))),
+          );
+          Sse::new(events)
+        }),
+      )
+      .route(
+        "/api/generate",
+        post(|| async {
+          (
+            [(CONTENT_TYPE, "application/x-ndjson")],
+            "{\"response\": \"Tuesday\", \"done\": false}\n".repeat(50),
+          )
+        }),
+      );
+    with_compression(router, enabled)
+  }
+
+  #[rstest]
+  #[case(true, Method::GET, "/v1/models", "gzip", Some("gzip"))]
+  #[case(true, Method::GET, "/v1/models", "br", Some("br"))]
+  #[case(true, Method::GET, "/v1/models", "identity", None)]
+  #[case(false, Method::GET, "/v1/models", "gzip", None)]
+  #[case(true, Method::POST, "/v1/chat/completions", "gzip", None)]
+  #[case(true, Method::POST, "/api/generate", "gzip", None)]
+  #[tokio::test]
+  async fn test_routes_compression_skips_streamed_responses(
+    #[case] enabled: bool,
+    #[case] method: Method,
+    #[case] uri: &str,
+    #[case] accept_encoding: &str,
+    #[case] expected: Option<&str>,
+  ) -> anyhow::Result<()> {
+    let response = compression_router(enabled)
+      .oneshot(
+        Request::builder()
+          .method(method)
+          .uri(uri)
+          .header(ACCEPT_ENCODING, accept_encoding)
+          .body(Body::empty())?,
+      )
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(
+      expected,
+      response
+        .headers()
+        .get(CONTENT_ENCODING)
+        .map(|value| value.to_str().unwrap())
+    );
+    Ok(())
+  }
 }
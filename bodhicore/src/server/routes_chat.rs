@@ -1,73 +1,351 @@
 use super::RouterStateFn;
-use crate::oai::OpenAIApiError;
-use async_openai::types::CreateChatCompletionRequest;
+use crate::oai::{
+  unknown_fields, ApiError, BodhiChatRequest, OpenAIApiError, ResponseFormat, CHAT_REQUEST_FIELDS,
+};
 use axum::{
   body::Body,
-  extract::State,
-  http::{header, HeaderValue, StatusCode},
+  extract::{
+    ws::{Message, WebSocket, WebSocketUpgrade},
+    State,
+  },
+  http::{header, HeaderMap, HeaderValue, StatusCode},
   response::{sse::Event, IntoResponse, Response, Sse},
+  routing::{get, MethodRouter},
   Json,
 };
 use futures_util::StreamExt;
+use serde_json::{json, Value};
 use std::{convert::Infallible, sync::Arc};
 use tokio_stream::wrappers::ReceiverStream;
+use tracing::Instrument;
+
+pub static X_BODHI_PROMPT_TOKENS: &str = "x-bodhi-prompt-tokens";
+pub static X_BODHI_COMPLETION_TOKENS: &str = "x-bodhi-completion-tokens";
+pub static X_BODHI_TOTAL_TOKENS: &str = "x-bodhi-total-tokens";
+pub static X_BODHI_FINISH_REASON: &str = "x-bodhi-finish-reason";
+// the conversation of a multi-turn chat, used as the prompt cache key when the request has none,
+// and recorded on the request span
+pub static X_CONVERSATION_ID: &str = "x-conversation-id";
+pub static X_BODHI_CONVERSATION_ID: &str = "x-bodhi-conversation-id";
 
 // TODO: custom Json extractor to dispatch OpenAIError response for bad request
 pub(crate) async fn chat_completions_handler(
   State(state): State<Arc<dyn RouterStateFn>>,
-  Json(request): Json<CreateChatCompletionRequest>,
+  headers: HeaderMap,
+  Json(mut request): Json<BodhiChatRequest>,
 ) -> Result<Response, OpenAIApiError> {
-  let stream = request.stream.unwrap_or(false);
+  if state.is_paused() {
+    return Err(OpenAIApiError::InferencePaused);
+  }
+  if request.prompt_cache_key.is_none() {
+    request.prompt_cache_key = conversation_id(&headers);
+  }
+  let stream = request.request.stream.unwrap_or(false);
+  let include_usage = request
+    .stream_options
+    .as_ref()
+    .map(|options| options.include_usage)
+    .unwrap_or(false);
+  let check_json = request
+    .response_format
+    .as_ref()
+    .is_some_and(ResponseFormat::unconstrained);
   let (tx, mut rx) = tokio::sync::mpsc::channel::<String>(100);
-  let handle = tokio::spawn(async move { state.chat_completions(request, tx).await });
-  if !stream {
-    if let Some(message) = rx.recv().await {
-      drop(rx);
-      _ = handle.await;
-      let response = Response::builder()
-        .status(StatusCode::OK)
-        .header(
-          header::CONTENT_TYPE,
-          HeaderValue::from_static(mime::APPLICATION_JSON.as_ref()),
-        )
-        .body(Body::from(message))
-        .map_err(|err| OpenAIApiError::InternalServer(err.to_string()))?;
-      Ok(response)
-    } else {
-      Err(OpenAIApiError::InternalServer(
+  let handle =
+    tokio::spawn(async move { state.chat_completions(request, tx).await }.in_current_span());
+  // the request fails before the first message, e.g. an invalid request or a full queue, with
+  // the error response instead of an empty stream
+  let Some(message) = rx.recv().await else {
+    return match handle.await {
+      Ok(Err(err)) => Err(err),
+      _ => Err(OpenAIApiError::InternalServer(
         "receiver stream abruptly closed".to_string(),
-      ))
+      )),
+    };
+  };
+  if !stream {
+    drop(rx);
+    _ = handle.await;
+    if check_json {
+      check_json_content(&message)?;
+    }
+    if accepts_text_plain(&headers) {
+      return text_response(&message);
     }
+    let response = Response::builder()
+      .status(StatusCode::OK)
+      .header(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(mime::APPLICATION_JSON.as_ref()),
+      )
+      .body(Body::from(message))
+      .map_err(|err| OpenAIApiError::InternalServer(err.to_string()))?;
+    Ok(response)
   } else {
     // TODO: not open up the response, but proxy it directly
-    let stream = ReceiverStream::new(rx).map::<Result<Event, Infallible>, _>(move |msg| {
-      let data = if msg.starts_with("data: ") {
-        msg
-          .strip_prefix("data: ")
-          .unwrap()
-          .strip_suffix("\n\n")
-          .unwrap()
-      } else if msg.starts_with("error: ") {
-        msg
-          .strip_prefix("error: ")
-          .unwrap()
-          .strip_suffix("\n\n")
-          .unwrap()
-      } else {
-        tracing::error!(msg, "unknown event type raised from bodhi_server");
-        &msg
-      };
-      Ok(Event::default().data(data))
-    });
+    let stream = futures_util::stream::once(async move { message })
+      .chain(ReceiverStream::new(rx))
+      .flat_map(move |msg| {
+        futures_util::stream::iter(
+          stream_events(&msg, include_usage)
+            .into_iter()
+            .map(|data| Ok::<_, Infallible>(Event::default().data(data))),
+        )
+      });
     Ok(Sse::new(stream).into_response())
   }
 }
 
+// the same chunks as the SSE of the chat completions, sent as websocket text frames for the
+// clients behind proxies buffering the SSE. The first frame from the client is the chat request,
+// and closing the socket stops the generation. When strict, a request with unknown fields is
+// rejected, the same as over http
+pub(crate) fn chat_stream_route(strict_params: bool) -> MethodRouter<Arc<dyn RouterStateFn>> {
+  get(
+    move |State(state): State<Arc<dyn RouterStateFn>>, headers: HeaderMap, ws: WebSocketUpgrade| async move {
+      ws.on_upgrade(move |socket| {
+        chat_stream(state, headers, socket, strict_params).in_current_span()
+      })
+    },
+  )
+}
+
+async fn chat_stream(
+  state: Arc<dyn RouterStateFn>,
+  headers: HeaderMap,
+  mut socket: WebSocket,
+  strict_params: bool,
+) {
+  let bytes = loop {
+    match socket.recv().await {
+      Some(Ok(Message::Text(text))) => break text.into_bytes(),
+      Some(Ok(Message::Binary(bytes))) => break bytes,
+      Some(Ok(Message::Ping(_) | Message::Pong(_))) => continue,
+      Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+    }
+  };
+  let mut request = match parse_chat_request(&bytes, strict_params) {
+    Ok(request) => request,
+    Err(err) => return close_with_error(socket, &err).await,
+  };
+  if state.is_paused() {
+    return close_with_error(socket, &OpenAIApiError::InferencePaused).await;
+  }
+  if request.prompt_cache_key.is_none() {
+    request.prompt_cache_key = conversation_id(&headers);
+  }
+  request.request.stream = Some(true);
+  let include_usage = request
+    .stream_options
+    .as_ref()
+    .map(|options| options.include_usage)
+    .unwrap_or(false);
+  let (tx, mut rx) = tokio::sync::mpsc::channel::<String>(100);
+  let handle =
+    tokio::spawn(async move { state.chat_completions(request, tx).await }.in_current_span());
+  loop {
+    tokio::select! {
+      msg = rx.recv() => {
+        let Some(msg) = msg else { break };
+        for data in stream_events(&msg, include_usage) {
+          // the client is gone, dropping the receiver stops the generation
+          if socket.send(Message::Text(data)).await.is_err() {
+            return;
+          }
+        }
+      }
+      frame = socket.recv() => match frame {
+        Some(Ok(Message::Close(_))) | Some(Err(_)) | None => {
+          tracing::debug!("chat stream closed by the client, stopping the generation");
+          return;
+        }
+        Some(Ok(_)) => {}
+      },
+    }
+  }
+  match handle.await {
+    Ok(Err(err)) => close_with_error(socket, &err).await,
+    Ok(Ok(())) => {
+      _ = socket.send(Message::Close(None)).await;
+    }
+    Err(err) => {
+      let err = OpenAIApiError::InternalServer(err.to_string());
+      close_with_error(socket, &err).await
+    }
+  }
+}
+
+fn parse_chat_request(
+  bytes: &[u8],
+  strict_params: bool,
+) -> Result<BodhiChatRequest, OpenAIApiError> {
+  let invalid =
+    |err: serde_json::Error| OpenAIApiError::BadRequest(format!("invalid chat request: {err}"));
+  let value = serde_json::from_slice::<Value>(bytes).map_err(invalid)?;
+  if strict_params {
+    let unknown = unknown_fields(&value, CHAT_REQUEST_FIELDS);
+    if !unknown.is_empty() {
+      return Err(OpenAIApiError::BadRequest(format!(
+        "unsupported fields in the request: {}",
+        unknown.join(", ")
+      )));
+    }
+  }
+  serde_json::from_value(value).map_err(invalid)
+}
+
+// the error, with the same body as the http error response, is the last frame before the close
+async fn close_with_error(mut socket: WebSocket, err: &OpenAIApiError) {
+  if let Ok(error) = serde_json::to_string(&ApiError::from(err)) {
+    _ = socket.send(Message::Text(error)).await;
+  }
+  _ = socket.send(Message::Close(None)).await;
+}
+
+// the data of a message from llama.cpp, split in two for `stream_options.include_usage`
+fn stream_events(msg: &str, include_usage: bool) -> Vec<String> {
+  let data = if let Some(data) = msg.strip_prefix("data: ") {
+    data.strip_suffix("\n\n").unwrap_or(data)
+  } else if let Some(data) = msg.strip_prefix("error: ") {
+    data.strip_suffix("\n\n").unwrap_or(data)
+  } else {
+    tracing::error!(msg, "unknown event type raised from bodhi_server");
+    msg
+  };
+  match include_usage.then(|| split_usage(data)).flatten() {
+    Some((chunk, usage)) => vec![chunk, usage],
+    None => vec![data.to_string()],
+  }
+}
+
+// for `stream_options.include_usage`, the usage llama.cpp sends on the last chunk is moved to
+// an extra chunk with no choices, as expected by the OpenAI clients
+fn split_usage(data: &str) -> Option<(String, String)> {
+  let mut chunk = serde_json::from_str::<Value>(data).ok()?;
+  let usage = chunk.as_object_mut()?.remove("usage")?;
+  if usage.is_null() {
+    return None;
+  }
+  let usage_chunk = json! {{
+    "id": chunk["id"],
+    "object": chunk["object"],
+    "created": chunk["created"],
+    "model": chunk["model"],
+    "choices": [],
+    "usage": usage,
+  }};
+  Some((chunk.to_string(), usage_chunk.to_string()))
+}
+
+// the turns of a conversation share their leading messages, so scheduling them on the same slot
+// lets llama.cpp reuse the kv cache of the prefix instead of evaluating it again
+pub(crate) fn conversation_id(headers: &HeaderMap) -> Option<String> {
+  [X_CONVERSATION_ID, X_BODHI_CONVERSATION_ID]
+    .into_iter()
+    .filter_map(|name| headers.get(name))
+    .filter_map(|value| value.to_str().ok())
+    .map(str::trim)
+    .find(|id| !id.is_empty())
+    .map(str::to_string)
+}
+
+// honours `Accept: text/plain` only when its q-value is preferred over json, json stays the default
+pub(crate) fn accepts_text_plain(headers: &HeaderMap) -> bool {
+  let Some(accept) = headers
+    .get(header::ACCEPT)
+    .and_then(|value| value.to_str().ok())
+  else {
+    return false;
+  };
+  accept
+    .split(',')
+    .filter_map(|media_range| {
+      let mut params = media_range.split(';');
+      let media_type = params.next()?.trim();
+      let quality = params
+        .filter_map(|param| param.trim().strip_prefix("q="))
+        .find_map(|quality| quality.trim().parse::<f32>().ok())
+        .unwrap_or(1.0);
+      [mime::APPLICATION_JSON.as_ref(), mime::TEXT_PLAIN.as_ref()]
+        .contains(&media_type)
+        .then_some((media_type, quality))
+    })
+    .filter(|(_, quality)| *quality > 0.0)
+    // the highest quality wins, and the first listed on a tie
+    .fold(
+      None,
+      |preferred: Option<(&str, f32)>, (media_type, quality)| match preferred {
+        Some((_, preferred_quality)) if preferred_quality >= quality => preferred,
+        _ => Some((media_type, quality)),
+      },
+    )
+    .is_some_and(|(media_type, _)| media_type == mime::TEXT_PLAIN.as_ref())
+}
+
+// the json asked for in the prompt, without a grammar enforcing it, is checked to parse, the
+// tool calls and the error messages are passed on as they are
+fn check_json_content(message: &str) -> Result<(), OpenAIApiError> {
+  let Ok(value) = serde_json::from_str::<Value>(message) else {
+    return Ok(());
+  };
+  let Some(content) = value["choices"][0]["message"]["content"].as_str() else {
+    return Ok(());
+  };
+  serde_json::from_str::<Value>(content)
+    .map(|_| ())
+    .map_err(|err| {
+      OpenAIApiError::InternalServer(format!(
+        "generated content is not valid json for the response_format: {err}"
+      ))
+    })
+}
+
+// generated text without the json envelope, with the usage and finish reason as headers
+fn text_response(message: &str) -> Result<Response, OpenAIApiError> {
+  let value = serde_json::from_str::<Value>(message)
+    .map_err(|err| OpenAIApiError::InternalServer(err.to_string()))?;
+  let content = value["choices"][0]["message"]["content"]
+    .as_str()
+    .unwrap_or_default()
+    .to_string();
+  let mut builder = Response::builder().status(StatusCode::OK).header(
+    header::CONTENT_TYPE,
+    HeaderValue::from_static(mime::TEXT_PLAIN_UTF_8.as_ref()),
+  );
+  for (name, field) in [
+    (X_BODHI_PROMPT_TOKENS, "prompt_tokens"),
+    (X_BODHI_COMPLETION_TOKENS, "completion_tokens"),
+    (X_BODHI_TOTAL_TOKENS, "total_tokens"),
+  ] {
+    if let Some(tokens) = value["usage"][field].as_u64() {
+      builder = builder.header(name, tokens);
+    }
+  }
+  if let Some(finish_reason) = value["choices"][0]["finish_reason"].as_str() {
+    builder = builder.header(X_BODHI_FINISH_REASON, finish_reason);
+  }
+  builder
+    .body(Body::from(content))
+    .map_err(|err| OpenAIApiError::InternalServer(err.to_string()))
+}
+
 #[cfg(test)]
 mod test {
+  use super::{
+    accepts_text_plain, conversation_id, X_BODHI_COMPLETION_TOKENS, X_BODHI_CONVERSATION_ID,
+    X_BODHI_FINISH_REASON, X_BODHI_PROMPT_TOKENS, X_BODHI_TOTAL_TOKENS,
+  };
   use crate::{
-    server::routes_chat::chat_completions_handler,
-    test_utils::{MockRouterState, RequestTestExt, ResponseTestExt},
+    oai::{ApiError, BodhiChatRequest, OpenAIApiError},
+    server::{
+      routes_chat::{chat_completions_handler, chat_stream_route},
+      RouterState,
+    },
+    service::{MockDataService, MockEnvServiceFn, MockHubService},
+    test_utils::{
+      AppServiceStubMock, MockDbService, MockRouterState, MockSharedContext, RequestTestExt,
+      ResponseTestExt,
+    },
   };
   use anyhow_trace::anyhow_trace;
   use async_openai::types::{
@@ -75,13 +353,23 @@ mod test {
     CreateChatCompletionRequestArgs, CreateChatCompletionResponse,
     CreateChatCompletionStreamResponse,
   };
-  use axum::{extract::Request, routing::post, Router};
+  use axum::{
+    extract::Request,
+    http::{
+      header::{ACCEPT, CONTENT_TYPE, RETRY_AFTER},
+      HeaderMap,
+    },
+    routing::post,
+    Router,
+  };
+  use futures_util::{SinkExt, StreamExt};
   use mockall::predicate::always;
   use reqwest::StatusCode;
   use rstest::rstest;
-  use serde_json::json;
+  use serde_json::{json, Value};
   use std::sync::Arc;
   use tokio::sync::mpsc::Sender;
+  use tokio_tungstenite::tungstenite::Message as WsMessage;
   use tower::ServiceExt;
 
   #[rstest]
@@ -97,6 +385,7 @@ mod test {
           .build()?,
       )])
       .build()?;
+    router_state.expect_is_paused().return_const(false);
     router_state
       .expect_chat_completions()
       .with(always(), always())
@@ -158,6 +447,7 @@ mod test {
           .build()?,
       )])
       .build()?;
+    router_state.expect_is_paused().return_const(false);
     router_state
       .expect_chat_completions()
       .with(always(), always())
@@ -218,4 +508,518 @@ mod test {
     assert_eq!("  After Monday, the next day is Tuesday.", content);
     Ok(())
   }
+
+  #[rstest]
+  #[case(true, 3)]
+  #[case(false, 2)]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_chat_completions_stream_include_usage(
+    #[case] include_usage: bool,
+    #[case] n_chunks: usize,
+  ) -> anyhow::Result<()> {
+    let mut router_state = MockRouterState::new();
+    router_state.expect_is_paused().return_const(false);
+    router_state
+      .expect_chat_completions()
+      .with(always(), always())
+      .return_once(|_, sender: Sender<String>| {
+        tokio::spawn(async move {
+          let delta = r#"{"choices":[{"finish_reason":null,"index":0,"delta":{"content":"Tuesday"}}],"created":1717317061,"id":"testid","model":"testalias:instruct","object":"chat.completion.chunk"}"#;
+          let end_delta = r#"{"choices":[{"finish_reason":"stop","index":0,"delta":{}}],"created":1717317061,"id":"testid","model":"testalias:instruct","object":"chat.completion.chunk","usage":{"completion_tokens":13,"prompt_tokens":15,"total_tokens":28}}"#;
+          for chunk in [delta, end_delta] {
+            _ = sender.send(format!("data: {chunk}\n\n")).await;
+          }
+        });
+        Ok(())
+      });
+    let app = Router::new()
+      .route("/v1/chat/completions", post(chat_completions_handler))
+      .with_state(Arc::new(router_state));
+    let request = json! {{
+      "model": "testalias:instruct",
+      "stream": true,
+      "stream_options": {"include_usage": include_usage},
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+    }};
+    let response = app
+      .oneshot(Request::post("/v1/chat/completions").json(request)?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let chunks: Vec<Value> = response.sse().await?;
+    assert_eq!(n_chunks, chunks.len());
+    let last = chunks.last().unwrap();
+    assert_eq!(Some(28), last["usage"]["total_tokens"].as_u64());
+    if include_usage {
+      assert_eq!(&json!([]), &last["choices"]);
+      assert!(chunks[..2].iter().all(|chunk| chunk.get("usage").is_none()));
+    }
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_chat_completions_paused_returns_service_unavailable() -> anyhow::Result<()> {
+    let mut router_state = MockRouterState::new();
+    router_state.expect_is_paused().return_const(true);
+    router_state.expect_chat_completions().never();
+    let request = CreateChatCompletionRequestArgs::default()
+      .model("testalias:instruct")
+      .messages(vec![ChatCompletionRequestMessage::User(
+        ChatCompletionRequestUserMessageArgs::default()
+          .content("What day comes after Monday?")
+          .build()?,
+      )])
+      .build()?;
+    let app = Router::new()
+      .route("/v1/chat/completions", post(chat_completions_handler))
+      .with_state(Arc::new(router_state));
+    let response = app
+      .oneshot(Request::post("/v1/chat/completions").json(request).unwrap())
+      .await
+      .unwrap();
+    assert_eq!(StatusCode::SERVICE_UNAVAILABLE, response.status());
+    assert_eq!("60", response.headers().get(RETRY_AFTER).unwrap());
+    let response: ApiError = response.json().await.unwrap();
+    assert_eq!("inference_paused", response.code);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(false)]
+  #[case(true)]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_chat_completions_queue_full_returns_service_unavailable(
+    #[case] stream: bool,
+  ) -> anyhow::Result<()> {
+    let mut router_state = MockRouterState::new();
+    router_state.expect_is_paused().return_const(false);
+    router_state
+      .expect_chat_completions()
+      .with(always(), always())
+      .return_once(|_, _| Err(OpenAIApiError::QueueFull("testalias:instruct".to_string())));
+    let request = json! {{
+      "model": "testalias:instruct",
+      "stream": stream,
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+    }};
+    let app = Router::new()
+      .route("/v1/chat/completions", post(chat_completions_handler))
+      .with_state(Arc::new(router_state));
+    let response = app
+      .oneshot(Request::post("/v1/chat/completions").json(request)?)
+      .await?;
+    assert_eq!(StatusCode::SERVICE_UNAVAILABLE, response.status());
+    assert_eq!("5", response.headers().get(RETRY_AFTER).unwrap());
+    let response: ApiError = response.json().await?;
+    assert_eq!("queue_full", response.code);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_chat_completions_insufficient_memory_returns_service_unavailable(
+  ) -> anyhow::Result<()> {
+    let mut router_state = MockRouterState::new();
+    router_state.expect_is_paused().return_const(false);
+    router_state
+      .expect_chat_completions()
+      .with(always(), always())
+      .return_once(|_, _| {
+        Err(OpenAIApiError::InsufficientMemory {
+          model: "testalias:instruct".to_string(),
+          required: 8 * 1024 * 1024 * 1024,
+          available: 4 * 1024 * 1024 * 1024,
+          suggestion: "unload a model or close other apps and retry".to_string(),
+        })
+      });
+    let request = json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+    }};
+    let app = Router::new()
+      .route("/v1/chat/completions", post(chat_completions_handler))
+      .with_state(Arc::new(router_state));
+    let response = app
+      .oneshot(Request::post("/v1/chat/completions").json(request)?)
+      .await?;
+    assert_eq!(StatusCode::SERVICE_UNAVAILABLE, response.status());
+    let response: ApiError = response.json().await?;
+    assert_eq!("insufficient_memory", response.code);
+    assert!(
+      response
+        .message
+        .starts_with("insufficient memory to load model 'testalias:instruct'"),
+      "{}",
+      response.message
+    );
+    Ok(())
+  }
+
+  // the router state rejects the invalid requests before resolving the model
+  fn router_state_app() -> Router {
+    let service = AppServiceStubMock::new(
+      MockEnvServiceFn::new(),
+      MockHubService::new(),
+      MockDataService::default(),
+    );
+    let state = RouterState::new(
+      Arc::new(MockSharedContext::default()),
+      Arc::new(service),
+      Arc::new(MockDbService::new()),
+    );
+    Router::new()
+      .route("/v1/chat/completions", post(chat_completions_handler))
+      .with_state(Arc::new(state))
+  }
+
+  #[rstest]
+  #[case(json! {{"stop": ["a", "b", "c", "d", "e"]}}, "stop can have at most 4 sequences, found 5")]
+  #[case(json! {{"stop": ["\n", ""]}}, "stop sequences cannot be empty")]
+  #[case(json! {{"logit_bias": {"hello": -100}}}, "logit_bias key 'hello' is not a token id")]
+  #[case(
+    json! {{"stream": true, "logit_bias": {"15043": 101}}},
+    "logit_bias of token '15043' should be a number between -100 and 100, found 101"
+  )]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_chat_completions_rejects_invalid_generation_params(
+    #[case] params: Value,
+    #[case] message: &str,
+  ) -> anyhow::Result<()> {
+    let mut request = json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+    }};
+    for (key, value) in params.as_object().unwrap() {
+      request[key] = value.clone();
+    }
+    let response = router_state_app()
+      .oneshot(Request::post("/v1/chat/completions").json(request)?)
+      .await?;
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    let response: ApiError = response.json().await?;
+    assert_eq!("invalid_request_error", response.code);
+    assert_eq!(message, response.message);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_chat_completions_rejects_oversized_metadata() -> anyhow::Result<()> {
+    let request = json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+      "metadata": {"trace_id": "v".repeat(513)},
+    }};
+    let response = router_state_app()
+      .oneshot(Request::post("/v1/chat/completions").json(request)?)
+      .await?;
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    let response: ApiError = response.json().await?;
+    assert_eq!(
+      "metadata value of key 'trace_id' is longer than 512 characters",
+      response.message
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("text/plain", true)]
+  #[case("text/plain; charset=utf-8", true)]
+  #[case("text/plain, application/json", true)]
+  #[case("application/json, text/plain", false)]
+  #[case("application/json", false)]
+  #[case("*/*", false)]
+  #[case("text/plain;q=0.1, application/json", false)]
+  #[case("application/json;q=0.5, text/plain", true)]
+  #[case("application/json;q=0.9, text/plain;q=0.9", false)]
+  #[case("text/plain;q=0", false)]
+  fn test_routes_chat_accepts_text_plain(#[case] accept: &str, #[case] expected: bool) {
+    let mut headers = HeaderMap::new();
+    headers.insert(ACCEPT, accept.parse().unwrap());
+    assert_eq!(expected, accepts_text_plain(&headers));
+  }
+
+  #[rstest]
+  #[case("application/json")]
+  #[case("text/plain")]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_chat_completions_non_stream_negotiates_content_type(
+    #[case] accept: &str,
+  ) -> anyhow::Result<()> {
+    let mut router_state = MockRouterState::new();
+    router_state.expect_is_paused().return_const(false);
+    router_state
+      .expect_chat_completions()
+      .with(always(), always())
+      .return_once(|_, sender: Sender<String>| {
+        let response = json! {{
+          "id": "testid",
+          "model": "testalias:instruct",
+          "choices": [
+            {
+              "index": 0,
+              "finish_reason": "stop",
+              "message": {
+                "role": "assistant",
+                "content": "The day that comes after Monday is Tuesday."
+              },
+            }],
+          "created": 1704067200,
+          "object": "chat.completion",
+          "usage": {"prompt_tokens": 15, "completion_tokens": 13, "total_tokens": 28},
+        }}
+        .to_string();
+        tokio::spawn(async move { sender.send(response).await });
+        Ok(())
+      });
+    let app = Router::new()
+      .route("/v1/chat/completions", post(chat_completions_handler))
+      .with_state(Arc::new(router_state));
+    let request = json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+    }};
+    let response = app
+      .oneshot(
+        Request::post("/v1/chat/completions")
+          .header(ACCEPT, accept)
+          .json(request)?,
+      )
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let content_type = response.headers().get(CONTENT_TYPE).unwrap().to_str()?;
+    assert!(content_type.starts_with(accept));
+    if accept == "text/plain" {
+      let headers = response.headers().clone();
+      assert_eq!("15", headers.get(X_BODHI_PROMPT_TOKENS).unwrap());
+      assert_eq!("13", headers.get(X_BODHI_COMPLETION_TOKENS).unwrap());
+      assert_eq!("28", headers.get(X_BODHI_TOTAL_TOKENS).unwrap());
+      assert_eq!("stop", headers.get(X_BODHI_FINISH_REASON).unwrap());
+      assert_eq!(
+        "The day that comes after Monday is Tuesday.",
+        response.text().await?
+      );
+    } else {
+      let result: CreateChatCompletionResponse = response.json().await?;
+      assert_eq!(Some(28), result.usage.map(|usage| usage.total_tokens));
+    }
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(Some("convo-1"), None, Some("convo-1"))]
+  #[case(Some("convo-1"), Some("session-1"), Some("session-1"))]
+  #[case(Some("  "), None, None)]
+  #[case(None, None, None)]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_chat_completions_conversation_id_as_prompt_cache_key(
+    #[case] conversation_id: Option<&str>,
+    #[case] prompt_cache_key: Option<&str>,
+    #[case] expected: Option<&str>,
+  ) -> anyhow::Result<()> {
+    let mut router_state = MockRouterState::new();
+    router_state.expect_is_paused().return_const(false);
+    let expected = expected.map(str::to_string);
+    router_state
+      .expect_chat_completions()
+      .withf(move |request: &BodhiChatRequest, _| request.prompt_cache_key == expected)
+      .return_once(|_, sender: Sender<String>| {
+        let response = json! {{
+          "id": "testid",
+          "model": "testalias:instruct",
+          "choices": [{"index": 0, "message": {"role": "assistant", "content": "Tuesday."}}],
+          "created": 1704067200,
+          "object": "chat.completion",
+        }}
+        .to_string();
+        tokio::spawn(async move { sender.send(response).await });
+        Ok(())
+      });
+    let mut request = json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+    }};
+    if let Some(prompt_cache_key) = prompt_cache_key {
+      request["prompt_cache_key"] = json!(prompt_cache_key);
+    }
+    let mut request = Request::post("/v1/chat/completions").json(request)?;
+    if let Some(conversation_id) = conversation_id {
+      request
+        .headers_mut()
+        .insert(X_BODHI_CONVERSATION_ID, conversation_id.parse()?);
+    }
+    let app = Router::new()
+      .route("/v1/chat/completions", post(chat_completions_handler))
+      .with_state(Arc::new(router_state));
+    let response = app.oneshot(request).await?;
+    assert_eq!(StatusCode::OK, response.status());
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("{\"city\": \"Paris\"}", StatusCode::OK)]
+  #[case("The weather in Paris is sunny.", StatusCode::INTERNAL_SERVER_ERROR)]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_chat_completions_checks_json_of_unconstrained_response_format(
+    #[case] content: &'static str,
+    #[case] expected: StatusCode,
+  ) -> anyhow::Result<()> {
+    let mut router_state = MockRouterState::new();
+    router_state.expect_is_paused().return_const(false);
+    router_state
+      .expect_chat_completions()
+      .with(always(), always())
+      .return_once(move |_, sender: Sender<String>| {
+        let response = json! {{
+          "id": "testid",
+          "model": "testalias:instruct",
+          "choices": [{"index": 0, "message": {"role": "assistant", "content": content}}],
+          "created": 1704067200,
+          "object": "chat.completion",
+        }}
+        .to_string();
+        tokio::spawn(async move { sender.send(response).await });
+        Ok(())
+      });
+    let request = json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What is the weather in Paris?"}],
+      "response_format": {
+        "type": "json_schema",
+        "json_schema": {
+          "name": "weather",
+          "schema": {"type": "object", "properties": {"city": {"type": "string", "pattern": "^[A-Z]"}}},
+        },
+      },
+    }};
+    let app = Router::new()
+      .route("/v1/chat/completions", post(chat_completions_handler))
+      .with_state(Arc::new(router_state));
+    let response = app
+      .oneshot(Request::post("/v1/chat/completions").json(request)?)
+      .await?;
+    assert_eq!(expected, response.status());
+    Ok(())
+  }
+
+  async fn serve_chat_stream(
+    router_state: MockRouterState,
+    strict_params: bool,
+  ) -> anyhow::Result<String> {
+    let app = Router::new()
+      .route("/v1/chat/stream", chat_stream_route(strict_params))
+      .with_state(Arc::new(router_state));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    Ok(format!("ws://{addr}/v1/chat/stream"))
+  }
+
+  // the text frames till the server closes the socket
+  async fn ws_frames(url: &str, request: String) -> anyhow::Result<Vec<Value>> {
+    let (mut socket, _) = tokio_tungstenite::connect_async(url).await?;
+    socket.send(WsMessage::Text(request)).await?;
+    let mut frames = vec![];
+    while let Some(frame) = socket.next().await {
+      match frame? {
+        WsMessage::Text(text) => frames.push(serde_json::from_str::<Value>(&text)?),
+        WsMessage::Close(_) => break,
+        _ => {}
+      }
+    }
+    Ok(frames)
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_chat_stream_sends_chunks_as_frames() -> anyhow::Result<()> {
+    let mut router_state = MockRouterState::new();
+    router_state.expect_is_paused().return_const(false);
+    router_state
+      .expect_chat_completions()
+      .with(always(), always())
+      .return_once(|request: BodhiChatRequest, sender: Sender<String>| {
+        assert_eq!(Some(true), request.request.stream);
+        tokio::spawn(async move {
+          let delta = r#"{"choices":[{"finish_reason":null,"index":0,"delta":{"content":"Tuesday"}}],"created":1717317061,"id":"testid","model":"testalias:instruct","object":"chat.completion.chunk"}"#;
+          let end_delta = r#"{"choices":[{"finish_reason":"stop","index":0,"delta":{}}],"created":1717317061,"id":"testid","model":"testalias:instruct","object":"chat.completion.chunk","usage":{"completion_tokens":13,"prompt_tokens":15,"total_tokens":28}}"#;
+          for chunk in [delta, end_delta] {
+            _ = sender.send(format!("data: {chunk}\n\n")).await;
+          }
+        });
+        Ok(())
+      });
+    let url = serve_chat_stream(router_state, true).await?;
+    let request = json! {{
+      "model": "testalias:instruct",
+      "stream_options": {"include_usage": true},
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+    }};
+    let frames = ws_frames(&url, request.to_string()).await?;
+    assert_eq!(3, frames.len());
+    assert_eq!("Tuesday", frames[0]["choices"][0]["delta"]["content"]);
+    assert_eq!("stop", frames[1]["choices"][0]["finish_reason"]);
+    assert_eq!(&json!([]), &frames[2]["choices"]);
+    assert_eq!(28, frames[2]["usage"]["total_tokens"]);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_chat_stream_sends_error_frame_for_invalid_request() -> anyhow::Result<()> {
+    let url = serve_chat_stream(MockRouterState::new(), false).await?;
+    let frames = ws_frames(&url, "What day comes after Monday?".to_string()).await?;
+    assert_eq!(1, frames.len());
+    let error: ApiError = serde_json::from_value(frames[0].clone())?;
+    assert_eq!("invalid_request_error", error.code);
+    assert!(error.message.starts_with("invalid chat request: "));
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_chat_stream_rejects_unknown_fields_when_strict() -> anyhow::Result<()> {
+    let url = serve_chat_stream(MockRouterState::new(), true).await?;
+    let request = json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+      "top_a": 0.5,
+    }};
+    let frames = ws_frames(&url, request.to_string()).await?;
+    assert_eq!(1, frames.len());
+    let error: ApiError = serde_json::from_value(frames[0].clone())?;
+    assert_eq!("invalid_request_error", error.code);
+    assert_eq!("unsupported fields in the request: top_a", error.message);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(&[("x-conversation-id", "convo-1")], Some("convo-1"))]
+  #[case(&[("x-bodhi-conversation-id", "convo-2")], Some("convo-2"))]
+  #[case(&[("x-conversation-id", " "), ("x-bodhi-conversation-id", "convo-2")], Some("convo-2"))]
+  #[case(&[("x-conversation-id", "convo-1"), ("x-bodhi-conversation-id", "convo-2")], Some("convo-1"))]
+  #[case(&[], None)]
+  fn test_routes_chat_conversation_id_from_headers(
+    #[case] headers: &[(&'static str, &str)],
+    #[case] expected: Option<&str>,
+  ) -> anyhow::Result<()> {
+    let mut header_map = HeaderMap::new();
+    for (name, value) in headers {
+      header_map.insert(*name, value.parse()?);
+    }
+    assert_eq!(expected.map(str::to_string), conversation_id(&header_map));
+    Ok(())
+  }
 }
@@ -0,0 +1,481 @@
+use super::RouterStateFn;
+use crate::oai::{BodhiChatRequest, OpenAIApiError};
+use async_openai::types::{Prompt, Stop};
+use axum::{
+  extract::State,
+  response::{sse::Event, IntoResponse, Response, Sse},
+  Json,
+};
+use futures_util::StreamExt;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{convert::Infallible, sync::Arc};
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::Instrument;
+
+// legacy text completion request, the fields not supported by llama.cpp like suffix and logprobs
+// are ignored
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct CompletionRequest {
+  pub model: String,
+  pub prompt: Prompt,
+  #[serde(default)]
+  pub max_tokens: Option<u32>,
+  #[serde(default)]
+  pub temperature: Option<f32>,
+  #[serde(default)]
+  pub top_p: Option<f32>,
+  #[serde(default)]
+  pub stop: Option<Stop>,
+  #[serde(default)]
+  pub stream: Option<bool>,
+  #[serde(default)]
+  pub presence_penalty: Option<f32>,
+  #[serde(default)]
+  pub frequency_penalty: Option<f32>,
+  #[serde(default)]
+  pub seed: Option<i64>,
+  #[serde(default)]
+  pub user: Option<String>,
+  // prepends the prompt to the completion text
+  #[serde(default)]
+  pub echo: Option<bool>,
+  #[serde(default)]
+  pub logprobs: Option<u8>,
+}
+
+pub(crate) static COMPLETION_REQUEST_FIELDS: &[&str] = &[
+  "model",
+  "prompt",
+  "max_tokens",
+  "temperature",
+  "top_p",
+  "stop",
+  "stream",
+  "presence_penalty",
+  "frequency_penalty",
+  "seed",
+  "user",
+  "echo",
+  "logprobs",
+];
+
+// the prompt is sent to llama.cpp as is, without applying the chat template
+pub(crate) async fn completions_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  Json(request): Json<CompletionRequest>,
+) -> Result<Response, OpenAIApiError> {
+  if state.is_paused() {
+    return Err(OpenAIApiError::InferencePaused);
+  }
+  let prompts = match &request.prompt {
+    Prompt::String(prompt) => vec![prompt.clone()],
+    Prompt::StringArray(prompts) if !prompts.is_empty() => prompts.clone(),
+    Prompt::StringArray(_) => {
+      return Err(OpenAIApiError::BadRequest(
+        "prompt should not be empty".to_string(),
+      ))
+    }
+    Prompt::IntegerArray(_) | Prompt::ArrayOfIntegerArray(_) => {
+      return Err(OpenAIApiError::BadRequest(
+        "token array prompts are not supported, send the prompt as text".to_string(),
+      ))
+    }
+  };
+  let echo = request.echo.unwrap_or(false);
+  // llama.cpp does not return the logprobs of the prompt tokens
+  if echo && request.logprobs.is_some() {
+    return Err(OpenAIApiError::BadRequest(
+      "logprobs are not supported along with echo, the logprobs of the prompt tokens are not available".to_string(),
+    ));
+  }
+  let requests = prompts
+    .into_iter()
+    .map(|prompt| {
+      let echoed = if echo { prompt.clone() } else { String::new() };
+      chat_request(&request, prompt).map(|request| (echoed, request))
+    })
+    .collect::<Result<Vec<_>, _>>()?;
+  if !request.stream.unwrap_or(false) {
+    // multiple prompts are completed one after the other, as a choice each
+    let mut responses = Vec::new();
+    for (echoed, request) in requests {
+      responses.push((echoed, chat_completion(state.clone(), request).await?));
+    }
+    Ok(Json(completion_response(&responses)).into_response())
+  } else {
+    let (event_tx, event_rx) = tokio::sync::mpsc::channel::<Event>(100);
+    tokio::spawn(
+      async move {
+        for (index, (mut echoed, request)) in requests.into_iter().enumerate() {
+          let (tx, mut rx) = tokio::sync::mpsc::channel::<String>(100);
+          let state = state.clone();
+          let handle = tokio::spawn(
+            async move { state.chat_completions(request, tx).await }.in_current_span(),
+          );
+          while let Some(msg) = rx.recv().await {
+            // the echoed prompt goes out with the first chunk
+            let event = completion_event(index, &msg, &std::mem::take(&mut echoed));
+            if event_tx.send(event).await.is_err() {
+              return;
+            }
+          }
+          _ = handle.await;
+        }
+      }
+      .in_current_span(),
+    );
+    let stream = ReceiverStream::new(event_rx).map(Ok::<_, Infallible>);
+    Ok(Sse::new(stream).into_response())
+  }
+}
+
+fn chat_request(
+  request: &CompletionRequest,
+  prompt: String,
+) -> Result<BodhiChatRequest, OpenAIApiError> {
+  let mut value = json! {{
+    "model": request.model,
+    "messages": [],
+    "stream": request.stream.unwrap_or(false),
+  }};
+  for (field, param) in [
+    ("max_tokens", json!(request.max_tokens)),
+    ("temperature", json!(request.temperature)),
+    ("top_p", json!(request.top_p)),
+    ("stop", json!(request.stop)),
+    ("presence_penalty", json!(request.presence_penalty)),
+    ("frequency_penalty", json!(request.frequency_penalty)),
+    ("seed", json!(request.seed)),
+    ("user", json!(request.user)),
+  ] {
+    if !param.is_null() {
+      value[field] = param;
+    }
+  }
+  let mut request = serde_json::from_value::<BodhiChatRequest>(value)
+    .map_err(|err| OpenAIApiError::BadRequest(err.to_string()))?;
+  request.prompt = Some(prompt);
+  Ok(request)
+}
+
+async fn chat_completion(
+  state: Arc<dyn RouterStateFn>,
+  request: BodhiChatRequest,
+) -> Result<Value, OpenAIApiError> {
+  let (tx, mut rx) = tokio::sync::mpsc::channel::<String>(100);
+  let handle =
+    tokio::spawn(async move { state.chat_completions(request, tx).await }.in_current_span());
+  let Some(message) = rx.recv().await else {
+    return match handle.await {
+      Ok(Err(err)) => Err(err),
+      _ => Err(OpenAIApiError::InternalServer(
+        "receiver stream abruptly closed".to_string(),
+      )),
+    };
+  };
+  drop(rx);
+  _ = handle.await;
+  serde_json::from_str::<Value>(&message)
+    .map_err(|err| OpenAIApiError::InternalServer(err.to_string()))
+}
+
+// the responses along with the prompt to echo, empty if not echoed
+fn completion_response(responses: &[(String, Value)]) -> Value {
+  let choices = responses
+    .iter()
+    .enumerate()
+    .map(|(index, (echoed, value))| {
+      let choice = &value["choices"][0];
+      let text = choice["message"]["content"].as_str().unwrap_or_default();
+      json! {{
+        "text": format!("{echoed}{text}"),
+        "index": index,
+        "logprobs": null,
+        "finish_reason": choice["finish_reason"],
+      }}
+    })
+    .collect::<Vec<_>>();
+  let tokens = |field: &str| {
+    responses
+      .iter()
+      .filter_map(|(_, value)| value["usage"][field].as_u64())
+      .sum::<u64>()
+  };
+  let first = &responses[0].1;
+  json! {{
+    "id": first["id"],
+    "object": "text_completion",
+    "created": first["created"],
+    "model": first["model"],
+    "choices": choices,
+    "usage": {
+      "prompt_tokens": tokens("prompt_tokens"),
+      "completion_tokens": tokens("completion_tokens"),
+      "total_tokens": tokens("total_tokens"),
+    },
+  }}
+}
+
+// translates a chat completion chunk to a text completion chunk, errors are forwarded as is
+fn completion_event(index: usize, msg: &str, echoed: &str) -> Event {
+  if let Some(error) = msg.strip_prefix("error: ") {
+    return Event::default().data(error.trim());
+  }
+  let data = msg.strip_prefix("data: ").unwrap_or(msg).trim();
+  let Ok(chunk) = serde_json::from_str::<Value>(data) else {
+    return Event::default().data(data);
+  };
+  let choice = &chunk["choices"][0];
+  let text = choice["delta"]["content"].as_str().unwrap_or_default();
+  let mut completion = json! {{
+    "id": chunk["id"],
+    "object": "text_completion",
+    "created": chunk["created"],
+    "model": chunk["model"],
+    "choices": [{
+      "text": format!("{echoed}{text}"),
+      "index": index,
+      "logprobs": null,
+      "finish_reason": choice["finish_reason"],
+    }],
+  }};
+  if !chunk["usage"].is_null() {
+    completion["usage"] = chunk["usage"].clone();
+  }
+  Event::default().data(completion.to_string())
+}
+
+#[cfg(test)]
+mod test {
+  use super::completions_handler;
+  use crate::{
+    oai::{ApiError, BodhiChatRequest},
+    test_utils::{MockRouterState, RequestTestExt, ResponseTestExt},
+  };
+  use anyhow_trace::anyhow_trace;
+  use async_openai::types::CreateCompletionResponse;
+  use axum::{extract::Request, routing::post, Router};
+  use reqwest::StatusCode;
+  use rstest::rstest;
+  use serde_json::json;
+  use std::sync::Arc;
+  use tokio::sync::mpsc::Sender;
+  use tower::ServiceExt;
+
+  fn app(router_state: MockRouterState) -> Router {
+    Router::new()
+      .route("/v1/completions", post(completions_handler))
+      .with_state(Arc::new(router_state))
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_completions_non_stream_completes_each_prompt() -> anyhow::Result<()> {
+    let mut router_state = MockRouterState::new();
+    router_state.expect_is_paused().return_const(false);
+    for (prompt, content) in [("Monday,", " Tuesday"), ("January,", " February")] {
+      router_state
+        .expect_chat_completions()
+        .withf(move |request: &BodhiChatRequest, _| {
+          request.prompt.as_deref() == Some(prompt)
+            && request.request.messages.is_empty()
+            && request.request.max_tokens == Some(4)
+        })
+        .times(1)
+        .return_once(move |_, sender: Sender<String>| {
+          let response = json! {{
+            "id": "testid",
+            "model": "testalias:instruct",
+            "choices": [{
+              "index": 0,
+              "finish_reason": "length",
+              "message": {"role": "assistant", "content": content},
+            }],
+            "created": 1704067200,
+            "object": "chat.completion",
+            "usage": {"prompt_tokens": 3, "completion_tokens": 4, "total_tokens": 7},
+          }}
+          .to_string();
+          tokio::spawn(async move { sender.send(response).await });
+          Ok(())
+        });
+    }
+    let request = json! {{
+      "model": "testalias:instruct",
+      "prompt": ["Monday,", "January,"],
+      "max_tokens": 4,
+    }};
+    let response = app(router_state)
+      .oneshot(Request::post("/v1/completions").json(request)?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let result: CreateCompletionResponse = response.json().await?;
+    assert_eq!("text_completion", result.object);
+    assert_eq!(
+      vec![(0, " Tuesday"), (1, " February")],
+      result
+        .choices
+        .iter()
+        .map(|choice| (choice.index, choice.text.as_str()))
+        .collect::<Vec<_>>()
+    );
+    assert_eq!(Some(14), result.usage.map(|usage| usage.total_tokens));
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_completions_stream() -> anyhow::Result<()> {
+    let mut router_state = MockRouterState::new();
+    router_state.expect_is_paused().return_const(false);
+    router_state
+      .expect_chat_completions()
+      .withf(|request: &BodhiChatRequest, _| request.prompt.as_deref() == Some("Monday,"))
+      .return_once(|_, sender: Sender<String>| {
+        tokio::spawn(async move {
+          for (content, finish_reason) in [(" Tues", None), ("day", None), ("", Some("stop"))] {
+            let chunk = json! {{
+              "id": "testid",
+              "model": "testalias:instruct",
+              "choices": [{"index": 0, "delta": {"content": content}, "finish_reason": finish_reason}],
+              "created": 1704067200,
+              "object": "chat.completion.chunk",
+            }};
+            _ = sender.send(format!("data: {chunk}\n\n")).await;
+          }
+        });
+        Ok(())
+      });
+    let request = json! {{
+      "model": "testalias:instruct",
+      "prompt": "Monday,",
+      "stream": true,
+    }};
+    let response = app(router_state)
+      .oneshot(Request::post("/v1/completions").json(request)?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let chunks: Vec<CreateCompletionResponse> = response.sse().await?;
+    let text = chunks
+      .iter()
+      .map(|chunk| chunk.choices[0].text.as_str())
+      .collect::<String>();
+    assert_eq!(" Tuesday", text);
+    assert!(chunks.iter().all(|chunk| chunk.object == "text_completion"));
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(false)]
+  #[case(true)]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_completions_echo_prepends_prompt(
+    #[case] stream: bool,
+  ) -> anyhow::Result<()> {
+    let mut router_state = MockRouterState::new();
+    router_state.expect_is_paused().return_const(false);
+    router_state
+      .expect_chat_completions()
+      .withf(|request: &BodhiChatRequest, _| request.prompt.as_deref() == Some("Monday,"))
+      .return_once(move |_, sender: Sender<String>| {
+        tokio::spawn(async move {
+          if stream {
+            for (content, finish_reason) in [(" Tues", None), ("day", Some("stop"))] {
+              let chunk = json! {{
+                "id": "testid",
+                "model": "testalias:instruct",
+                "choices": [{"index": 0, "delta": {"content": content}, "finish_reason": finish_reason}],
+                "created": 1704067200,
+                "object": "chat.completion.chunk",
+              }};
+              _ = sender.send(format!("data: {chunk}\n\n")).await;
+            }
+          } else {
+            let response = json! {{
+              "id": "testid",
+              "model": "testalias:instruct",
+              "choices": [{
+                "index": 0,
+                "finish_reason": "stop",
+                "message": {"role": "assistant", "content": " Tuesday"},
+              }],
+              "created": 1704067200,
+              "object": "chat.completion",
+            }};
+            _ = sender.send(response.to_string()).await;
+          }
+        });
+        Ok(())
+      });
+    let request = json! {{
+      "model": "testalias:instruct",
+      "prompt": "Monday,",
+      "echo": true,
+      "stream": stream,
+    }};
+    let response = app(router_state)
+      .oneshot(Request::post("/v1/completions").json(request)?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let text = if stream {
+      let chunks: Vec<CreateCompletionResponse> = response.sse().await?;
+      assert_eq!("Monday, Tues", chunks[0].choices[0].text);
+      chunks
+        .iter()
+        .map(|chunk| chunk.choices[0].text.as_str())
+        .collect::<String>()
+    } else {
+      let result: CreateCompletionResponse = response.json().await?;
+      result.choices[0].text.clone()
+    };
+    assert_eq!("Monday, Tuesday", text);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_completions_echo_with_logprobs_is_bad_request() -> anyhow::Result<()> {
+    let mut router_state = MockRouterState::new();
+    router_state.expect_is_paused().return_const(false);
+    router_state.expect_chat_completions().never();
+    let request = json! {{
+      "model": "testalias:instruct",
+      "prompt": "Monday,",
+      "echo": true,
+      "logprobs": 1,
+    }};
+    let response = app(router_state)
+      .oneshot(Request::post("/v1/completions").json(request)?)
+      .await?;
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    let response: ApiError = response.json().await?;
+    assert_eq!("invalid_request_error", response.code);
+    assert!(response.message.contains("echo"));
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_completions_token_prompt_is_bad_request() -> anyhow::Result<()> {
+    let mut router_state = MockRouterState::new();
+    router_state.expect_is_paused().return_const(false);
+    router_state.expect_chat_completions().never();
+    let request = json! {{
+      "model": "testalias:instruct",
+      "prompt": [1, 2, 3],
+    }};
+    let response = app(router_state)
+      .oneshot(Request::post("/v1/completions").json(request)?)
+      .await?;
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    let response: ApiError = response.json().await?;
+    assert_eq!("invalid_request_error", response.code);
+    Ok(())
+  }
+}
@@ -0,0 +1,161 @@
+use super::RouterStateFn;
+use axum::{
+  extract::State,
+  http::StatusCode,
+  response::{IntoResponse, Json, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HealthResponse {
+  pub status: String,
+  pub uptime_secs: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ReadyResponse {
+  pub ready: bool,
+  pub model: Option<String>,
+  pub paused: bool,
+  pub uptime_secs: i64,
+}
+
+// liveness, responds as long as the server is running
+pub(crate) async fn health_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+) -> Json<HealthResponse> {
+  Json(HealthResponse {
+    status: "ok".to_string(),
+    uptime_secs: state.uptime_secs(),
+  })
+}
+
+// readiness, only when a model is loaded and inference is not paused, the check does not load
+// a model
+pub(crate) async fn ready_handler(State(state): State<Arc<dyn RouterStateFn>>) -> Response {
+  let model = match state.loaded_model().await {
+    Ok(loaded_model) => loaded_model.map(|loaded_model| loaded_model.alias),
+    Err(err) => {
+      tracing::warn!(?err, "error checking the loaded model");
+      None
+    }
+  };
+  let paused = state.is_paused();
+  let ready = model.is_some() && !paused;
+  let status = if ready {
+    StatusCode::OK
+  } else {
+    StatusCode::SERVICE_UNAVAILABLE
+  };
+  let response = ReadyResponse {
+    ready,
+    model,
+    paused,
+    uptime_secs: state.uptime_secs(),
+  };
+  (status, Json(response)).into_response()
+}
+
+#[cfg(test)]
+mod test {
+  use super::{health_handler, ready_handler, HealthResponse, ReadyResponse};
+  use crate::{
+    oai::OpenAIApiError,
+    server::{LoadedModel, RouterStateFn},
+    test_utils::{MockRouterState, ResponseTestExt},
+  };
+  use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::get,
+    Router,
+  };
+  use rstest::rstest;
+  use std::{path::PathBuf, sync::Arc};
+  use tower::ServiceExt;
+
+  fn loaded_model() -> LoadedModel {
+    LoadedModel {
+      alias: "testalias:instruct".to_string(),
+      model_file: PathBuf::from("/models/testalias.gguf"),
+      expires_at: None,
+    }
+  }
+
+  fn router(router_state: MockRouterState) -> Router {
+    let router_state: Arc<dyn RouterStateFn> = Arc::new(router_state);
+    Router::new()
+      .route("/health", get(health_handler))
+      .route("/ready", get(ready_handler))
+      .with_state(router_state)
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_routes_health() -> anyhow::Result<()> {
+    let mut router_state = MockRouterState::new();
+    router_state.expect_uptime_secs().return_const(42);
+    let response = router(router_state)
+      .oneshot(Request::get("/health").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(
+      HealthResponse {
+        status: "ok".to_string(),
+        uptime_secs: 42,
+      },
+      response.json::<HealthResponse>().await?
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(
+    Ok(Some(loaded_model())),
+    false,
+    StatusCode::OK,
+    Some("testalias:instruct")
+  )]
+  #[case(
+    Ok(Some(loaded_model())),
+    true,
+    StatusCode::SERVICE_UNAVAILABLE,
+    Some("testalias:instruct")
+  )]
+  #[case(Ok(None), false, StatusCode::SERVICE_UNAVAILABLE, None)]
+  #[case(
+    Err(OpenAIApiError::InternalServer("lock poisoned".to_string())),
+    false,
+    StatusCode::SERVICE_UNAVAILABLE,
+    None
+  )]
+  #[tokio::test]
+  async fn test_routes_ready(
+    #[case] loaded: crate::oai::Result<Option<LoadedModel>>,
+    #[case] paused: bool,
+    #[case] status: StatusCode,
+    #[case] model: Option<&str>,
+  ) -> anyhow::Result<()> {
+    let mut router_state = MockRouterState::new();
+    router_state
+      .expect_loaded_model()
+      .return_once(move || loaded);
+    router_state.expect_is_paused().return_const(paused);
+    router_state.expect_uptime_secs().return_const(42);
+    let response = router(router_state)
+      .oneshot(Request::get("/ready").body(Body::empty())?)
+      .await?;
+    assert_eq!(status, response.status());
+    assert_eq!(
+      ReadyResponse {
+        ready: status == StatusCode::OK,
+        model: model.map(str::to_string),
+        paused,
+        uptime_secs: 42,
+      },
+      response.json::<ReadyResponse>().await?
+    );
+    Ok(())
+  }
+}
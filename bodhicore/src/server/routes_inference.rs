@@ -0,0 +1,114 @@
+use super::RouterStateFn;
+use axum::{
+  extract::State,
+  response::Json,
+  routing::{get, post},
+  Router,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct InferenceStatus {
+  pub paused: bool,
+}
+
+// pausing rejects the inference requests with 503, while rest of the endpoints stay available
+pub fn inference_router() -> Router<Arc<dyn RouterStateFn>> {
+  Router::new()
+    .route("/inference", get(inference_status_handler))
+    .route("/inference/pause", post(inference_pause_handler))
+    .route("/inference/resume", post(inference_resume_handler))
+}
+
+async fn inference_status_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+) -> Json<InferenceStatus> {
+  Json(InferenceStatus {
+    paused: state.is_paused(),
+  })
+}
+
+async fn inference_pause_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+) -> Json<InferenceStatus> {
+  state.set_paused(true);
+  tracing::info!("inference paused");
+  Json(InferenceStatus { paused: true })
+}
+
+async fn inference_resume_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+) -> Json<InferenceStatus> {
+  state.set_paused(false);
+  tracing::info!("inference resumed");
+  Json(InferenceStatus { paused: false })
+}
+
+#[cfg(test)]
+mod test {
+  use super::{inference_router, InferenceStatus};
+  use crate::{
+    server::{RouterState, RouterStateFn},
+    service::MockAppServiceFn,
+    test_utils::{MockDbService, MockSharedContext, ResponseTestExt},
+  };
+  use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+  };
+  use rstest::rstest;
+  use std::sync::Arc;
+  use tower::ServiceExt;
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_inference_routes_pause_and_resume() -> anyhow::Result<()> {
+    let router_state = RouterState::new(
+      Arc::new(MockSharedContext::new()),
+      Arc::new(MockAppServiceFn::new()),
+      Arc::new(MockDbService::new()),
+    );
+    let router_state: Arc<dyn RouterStateFn> = Arc::new(router_state);
+    let router = inference_router().with_state(router_state.clone());
+
+    let response = router
+      .clone()
+      .oneshot(Request::get("/inference").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(
+      InferenceStatus { paused: false },
+      response.json::<InferenceStatus>().await?
+    );
+
+    let response = router
+      .clone()
+      .oneshot(Request::post("/inference/pause").body(Body::empty())?)
+      .await?;
+    assert_eq!(
+      InferenceStatus { paused: true },
+      response.json::<InferenceStatus>().await?
+    );
+    assert!(router_state.is_paused());
+    let response = router
+      .clone()
+      .oneshot(Request::get("/inference").body(Body::empty())?)
+      .await?;
+    assert_eq!(
+      InferenceStatus { paused: true },
+      response.json::<InferenceStatus>().await?
+    );
+
+    let response = router
+      .clone()
+      .oneshot(Request::post("/inference/resume").body(Body::empty())?)
+      .await?;
+    assert_eq!(
+      InferenceStatus { paused: false },
+      response.json::<InferenceStatus>().await?
+    );
+    assert!(!router_state.is_paused());
+    Ok(())
+  }
+}
@@ -0,0 +1,469 @@
+use super::RouterStateFn;
+use crate::oai::{BodhiChatRequest, OpenAIApiError};
+use axum::{
+  extract::State,
+  response::{sse::Event, IntoResponse, Response, Sse},
+  Json,
+};
+use futures_util::StreamExt;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{convert::Infallible, sync::Arc};
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::Instrument;
+
+// Anthropic Messages API request, translated to a chat completion request for the llama.cpp server
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MessagesRequest {
+  pub model: String,
+  pub max_tokens: u32,
+  pub messages: Vec<Message>,
+  #[serde(default)]
+  pub system: Option<MessageContent>,
+  #[serde(default)]
+  pub stop_sequences: Option<Vec<String>>,
+  #[serde(default)]
+  pub stream: Option<bool>,
+  #[serde(default)]
+  pub temperature: Option<f32>,
+  #[serde(default)]
+  pub top_p: Option<f32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+  User,
+  Assistant,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Message {
+  pub role: Role,
+  pub content: MessageContent,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum MessageContent {
+  Text(String),
+  Blocks(Vec<ContentBlock>),
+}
+
+// only text blocks are supported, images and tool use are rejected as invalid requests
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentBlock {
+  Text { text: String },
+}
+
+impl MessageContent {
+  fn text(&self) -> String {
+    match self {
+      MessageContent::Text(text) => text.clone(),
+      MessageContent::Blocks(blocks) => blocks
+        .iter()
+        .map(|ContentBlock::Text { text }| text.as_str())
+        .collect::<Vec<_>>()
+        .join("\n"),
+    }
+  }
+}
+
+impl TryFrom<MessagesRequest> for BodhiChatRequest {
+  type Error = OpenAIApiError;
+
+  fn try_from(value: MessagesRequest) -> Result<Self, Self::Error> {
+    let mut messages = Vec::new();
+    if let Some(system) = &value.system {
+      messages.push(json! {{"role": "system", "content": system.text()}});
+    }
+    for message in &value.messages {
+      let role = match message.role {
+        Role::User => "user",
+        Role::Assistant => "assistant",
+      };
+      messages.push(json! {{"role": role, "content": message.content.text()}});
+    }
+    let mut request = json! {{
+      "model": value.model,
+      "messages": messages,
+      "max_tokens": value.max_tokens,
+      "stream": value.stream.unwrap_or(false),
+    }};
+    if let Some(stop_sequences) = value.stop_sequences {
+      request["stop"] = json!(stop_sequences);
+    }
+    if let Some(temperature) = value.temperature {
+      request["temperature"] = json!(temperature);
+    }
+    if let Some(top_p) = value.top_p {
+      request["top_p"] = json!(top_p);
+    }
+    serde_json::from_value::<BodhiChatRequest>(request)
+      .map_err(|err| OpenAIApiError::InternalServer(err.to_string()))
+  }
+}
+
+// llama.cpp does not report which stop sequence matched, so a stop is always an end of turn
+fn stop_reason(finish_reason: &str) -> &'static str {
+  match finish_reason {
+    "length" => "max_tokens",
+    _ => "end_turn",
+  }
+}
+
+pub(crate) async fn messages_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  Json(request): Json<MessagesRequest>,
+) -> Result<Response, OpenAIApiError> {
+  if state.is_paused() {
+    return Err(OpenAIApiError::InferencePaused);
+  }
+  let stream = request.stream.unwrap_or(false);
+  let model = request.model.clone();
+  let request = BodhiChatRequest::try_from(request)?;
+  let (tx, mut rx) = tokio::sync::mpsc::channel::<String>(100);
+  let handle =
+    tokio::spawn(async move { state.chat_completions(request, tx).await }.in_current_span());
+  if !stream {
+    let Some(message) = rx.recv().await else {
+      return Err(OpenAIApiError::InternalServer(
+        "receiver stream abruptly closed".to_string(),
+      ));
+    };
+    drop(rx);
+    _ = handle.await;
+    Ok(Json(message_response(&model, &message)?).into_response())
+  } else {
+    let (event_tx, event_rx) = tokio::sync::mpsc::channel::<Event>(100);
+    tokio::spawn(async move {
+      let mut stream = MessagesStream::new(model);
+      while let Some(msg) = rx.recv().await {
+        for event in stream.events(&msg) {
+          if event_tx.send(event).await.is_err() {
+            return;
+          }
+        }
+      }
+      for event in stream.finish() {
+        _ = event_tx.send(event).await;
+      }
+    });
+    let stream = ReceiverStream::new(event_rx).map(Ok::<_, Infallible>);
+    Ok(Sse::new(stream).into_response())
+  }
+}
+
+fn message_response(model: &str, message: &str) -> Result<Value, OpenAIApiError> {
+  let value = serde_json::from_str::<Value>(message)
+    .map_err(|err| OpenAIApiError::InternalServer(err.to_string()))?;
+  let choice = &value["choices"][0];
+  Ok(json! {{
+    "id": value["id"],
+    "type": "message",
+    "role": "assistant",
+    "model": model,
+    "content": [{"type": "text", "text": choice["message"]["content"].as_str().unwrap_or_default()}],
+    "stop_reason": choice["finish_reason"].as_str().map(stop_reason),
+    "stop_sequence": null,
+    "usage": {
+      "input_tokens": value["usage"]["prompt_tokens"].as_u64().unwrap_or_default(),
+      "output_tokens": value["usage"]["completion_tokens"].as_u64().unwrap_or_default(),
+    },
+  }})
+}
+
+// translates the chat completion chunks to the Anthropic stream events, a single text content
+// block is opened on the first chunk and closed along with the message on the finish reason
+struct MessagesStream {
+  model: String,
+  started: bool,
+  stopped: bool,
+}
+
+impl MessagesStream {
+  fn new(model: String) -> Self {
+    Self {
+      model,
+      started: false,
+      stopped: false,
+    }
+  }
+
+  fn events(&mut self, msg: &str) -> Vec<Event> {
+    if self.stopped {
+      return vec![];
+    }
+    if let Some(error) = msg.strip_prefix("error: ") {
+      self.stopped = true;
+      return vec![event(
+        "error",
+        json! {{"type": "error", "error": {"type": "api_error", "message": error.trim()}}},
+      )];
+    }
+    let data = msg.strip_prefix("data: ").unwrap_or(msg).trim();
+    let Ok(chunk) = serde_json::from_str::<Value>(data) else {
+      return vec![];
+    };
+    let mut events = Vec::new();
+    if !self.started {
+      self.started = true;
+      events.push(event(
+        "message_start",
+        json! {{
+          "type": "message_start",
+          "message": {
+            "id": chunk["id"],
+            "type": "message",
+            "role": "assistant",
+            "model": self.model,
+            "content": [],
+            "stop_reason": null,
+            "stop_sequence": null,
+            "usage": {"input_tokens": 0, "output_tokens": 0},
+          },
+        }},
+      ));
+      events.push(event(
+        "content_block_start",
+        json! {{"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}},
+      ));
+    }
+    let choice = &chunk["choices"][0];
+    if let Some(text) = choice["delta"]["content"].as_str() {
+      if !text.is_empty() {
+        events.push(event(
+          "content_block_delta",
+          json! {{"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": text}}},
+        ));
+      }
+    }
+    if let Some(finish_reason) = choice["finish_reason"].as_str() {
+      events.extend(self.stop(stop_reason(finish_reason), &chunk["usage"]));
+    }
+    events
+  }
+
+  // closes the message if the stream ended without a finish reason
+  fn finish(&mut self) -> Vec<Event> {
+    if self.started && !self.stopped {
+      self.stop("end_turn", &Value::Null)
+    } else {
+      vec![]
+    }
+  }
+
+  fn stop(&mut self, stop_reason: &str, usage: &Value) -> Vec<Event> {
+    self.stopped = true;
+    vec![
+      event(
+        "content_block_stop",
+        json! {{"type": "content_block_stop", "index": 0}},
+      ),
+      event(
+        "message_delta",
+        json! {{
+          "type": "message_delta",
+          "delta": {"stop_reason": stop_reason, "stop_sequence": null},
+          "usage": {
+            "input_tokens": usage["prompt_tokens"].as_u64().unwrap_or_default(),
+            "output_tokens": usage["completion_tokens"].as_u64().unwrap_or_default(),
+          },
+        }},
+      ),
+      event("message_stop", json! {{"type": "message_stop"}}),
+    ]
+  }
+}
+
+fn event(name: &str, data: Value) -> Event {
+  Event::default().event(name).data(data.to_string())
+}
+
+#[cfg(test)]
+mod test {
+  use super::{messages_handler, MessagesRequest};
+  use crate::{
+    oai::BodhiChatRequest,
+    test_utils::{MockRouterState, RequestTestExt, ResponseTestExt},
+  };
+  use anyhow_trace::anyhow_trace;
+  use axum::{extract::Request, routing::post, Router};
+  use mockall::predicate::always;
+  use reqwest::StatusCode;
+  use rstest::rstest;
+  use serde_json::{json, Value};
+  use std::sync::Arc;
+  use tokio::sync::mpsc::Sender;
+  use tower::ServiceExt;
+
+  fn messages_request(stream: bool) -> Value {
+    json! {{
+      "model": "testalias:instruct",
+      "max_tokens": 256,
+      "system": [{"type": "text", "text": "You are a helpful assistant."}],
+      "messages": [
+        {"role": "user", "content": "What day comes after Monday?"},
+        {"role": "assistant", "content": [{"type": "text", "text": "Tuesday."}]},
+        {"role": "user", "content": "And after that?"},
+      ],
+      "stop_sequences": ["\n\nHuman:"],
+      "stream": stream,
+    }}
+  }
+
+  fn app(router_state: MockRouterState) -> Router {
+    Router::new()
+      .route("/v1/messages", post(messages_handler))
+      .with_state(Arc::new(router_state))
+  }
+
+  #[rstest]
+  fn test_messages_request_to_chat_request() -> anyhow::Result<()> {
+    let request = serde_json::from_value::<MessagesRequest>(messages_request(false))?;
+    let request = BodhiChatRequest::try_from(request)?;
+    let expected = serde_json::from_value::<BodhiChatRequest>(json! {{
+      "model": "testalias:instruct",
+      "messages": [
+        {"role": "system", "content": "You are a helpful assistant."},
+        {"role": "user", "content": "What day comes after Monday?"},
+        {"role": "assistant", "content": "Tuesday."},
+        {"role": "user", "content": "And after that?"},
+      ],
+      "max_tokens": 256,
+      "stop": ["\n\nHuman:"],
+      "stream": false,
+    }})?;
+    assert_eq!(expected, request);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("stop", "end_turn")]
+  #[case("length", "max_tokens")]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_messages_non_stream(
+    #[case] finish_reason: &'static str,
+    #[case] stop_reason: &str,
+  ) -> anyhow::Result<()> {
+    let mut router_state = MockRouterState::new();
+    router_state.expect_is_paused().return_const(false);
+    router_state
+      .expect_chat_completions()
+      .with(always(), always())
+      .return_once(move |_, sender: Sender<String>| {
+        let response = json! {{
+          "id": "chatcmpl-testid",
+          "model": "testalias:instruct",
+          "choices": [{
+            "index": 0,
+            "message": {"role": "assistant", "content": "Wednesday."},
+            "finish_reason": finish_reason,
+          }],
+          "created": 1704067200,
+          "object": "chat.completion",
+          "usage": {"prompt_tokens": 32, "completion_tokens": 3, "total_tokens": 35},
+        }};
+        tokio::spawn(async move { sender.send(response.to_string()).await });
+        Ok(())
+      });
+    let response = app(router_state)
+      .oneshot(Request::post("/v1/messages").json(messages_request(false))?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response = response.json::<Value>().await?;
+    assert_eq!(
+      json! {{
+        "id": "chatcmpl-testid",
+        "type": "message",
+        "role": "assistant",
+        "model": "testalias:instruct",
+        "content": [{"type": "text", "text": "Wednesday."}],
+        "stop_reason": stop_reason,
+        "stop_sequence": null,
+        "usage": {"input_tokens": 32, "output_tokens": 3},
+      }},
+      response
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_messages_stream() -> anyhow::Result<()> {
+    let mut router_state = MockRouterState::new();
+    router_state.expect_is_paused().return_const(false);
+    router_state
+      .expect_chat_completions()
+      .with(always(), always())
+      .return_once(|_, sender: Sender<String>| {
+        tokio::spawn(async move {
+          for value in ["Wed", "nes", "day", "."] {
+            let chunk = json! {{
+              "id": "chatcmpl-testid",
+              "model": "testalias:instruct",
+              "choices": [{"index": 0, "delta": {"content": value}, "finish_reason": null}],
+              "created": 1704067200,
+              "object": "chat.completion.chunk",
+            }};
+            _ = sender.send(format!("data: {chunk}\n\n")).await;
+          }
+          let end_chunk = json! {{
+            "id": "chatcmpl-testid",
+            "model": "testalias:instruct",
+            "choices": [{"index": 0, "delta": {}, "finish_reason": "stop"}],
+            "created": 1704067200,
+            "object": "chat.completion.chunk",
+            "usage": {"prompt_tokens": 32, "completion_tokens": 4, "total_tokens": 36},
+          }};
+          _ = sender.send(format!("data: {end_chunk}\n\n")).await;
+        });
+        Ok(())
+      });
+    let response = app(router_state)
+      .oneshot(Request::post("/v1/messages").json(messages_request(true))?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let text = response.text().await?;
+    let event_names = text
+      .lines()
+      .filter_map(|line| line.strip_prefix("event: "))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      vec![
+        "message_start",
+        "content_block_start",
+        "content_block_delta",
+        "content_block_delta",
+        "content_block_delta",
+        "content_block_delta",
+        "content_block_stop",
+        "message_delta",
+        "message_stop",
+      ],
+      event_names
+    );
+    let events = text
+      .lines()
+      .filter_map(|line| line.strip_prefix("data: "))
+      .map(serde_json::from_str::<Value>)
+      .collect::<Result<Vec<_>, _>>()?;
+    let content = events
+      .iter()
+      .filter_map(|event| event["delta"]["text"].as_str())
+      .collect::<String>();
+    assert_eq!("Wednesday.", content);
+    assert_eq!(
+      json! {{
+        "type": "message_delta",
+        "delta": {"stop_reason": "end_turn", "stop_sequence": null},
+        "usage": {"input_tokens": 32, "output_tokens": 4},
+      }},
+      events[7]
+    );
+    Ok(())
+  }
+}
@@ -0,0 +1,95 @@
+use super::{metrics::HTTP_REQUESTS_TOTAL, RouterStateFn};
+use axum::{
+  extract::{MatchedPath, Request, State},
+  http::header::CONTENT_TYPE,
+  middleware::Next,
+  response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+
+pub(crate) static PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+pub(crate) async fn metrics_handler(State(state): State<Arc<dyn RouterStateFn>>) -> Response {
+  (
+    [(CONTENT_TYPE, PROMETHEUS_CONTENT_TYPE)],
+    state.metrics().render(),
+  )
+    .into_response()
+}
+
+// counts the requests by the route template, not the uri, so the path params do not add series
+pub(crate) async fn track_requests(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  request: Request,
+  next: Next,
+) -> Response {
+  let route = request
+    .extensions()
+    .get::<MatchedPath>()
+    .map(|path| path.as_str().to_string())
+    .unwrap_or_else(|| request.uri().path().to_string());
+  let response = next.run(request).await;
+  state.metrics().increment(
+    HTTP_REQUESTS_TOTAL,
+    vec![
+      ("route", route),
+      ("status", response.status().as_u16().to_string()),
+    ],
+    1,
+  );
+  response
+}
+
+#[cfg(test)]
+mod test {
+  use super::{metrics_handler, track_requests, PROMETHEUS_CONTENT_TYPE};
+  use crate::{
+    server::{metrics::Metrics, RouterStateFn},
+    test_utils::{MockRouterState, ResponseTestExt},
+  };
+  use axum::{
+    body::Body,
+    http::{header::CONTENT_TYPE, Request, StatusCode},
+    middleware::from_fn_with_state,
+    routing::get,
+    Router,
+  };
+  use rstest::rstest;
+  use std::sync::Arc;
+  use tower::ServiceExt;
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_routes_metrics_counts_requests_by_route() -> anyhow::Result<()> {
+    let metrics = Arc::new(Metrics::default());
+    let mut router_state = MockRouterState::new();
+    router_state
+      .expect_metrics()
+      .returning(move || metrics.clone());
+    let router_state: Arc<dyn RouterStateFn> = Arc::new(router_state);
+    let router = Router::new()
+      .route("/chats/:id", get(|| async { "ok" }))
+      .route("/missing", get(|| async { StatusCode::NOT_FOUND }))
+      .route("/metrics", get(metrics_handler))
+      .route_layer(from_fn_with_state(router_state.clone(), track_requests))
+      .with_state(router_state);
+    for uri in ["/chats/1", "/chats/2", "/missing"] {
+      router
+        .clone()
+        .oneshot(Request::get(uri).body(Body::empty())?)
+        .await?;
+    }
+    let response = router
+      .oneshot(Request::get("/metrics").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(
+      PROMETHEUS_CONTENT_TYPE,
+      response.headers().get(CONTENT_TYPE).unwrap().to_str()?
+    );
+    let output = response.text().await?;
+    assert!(output.contains("bodhi_http_requests_total{route=\"/chats/:id\",status=\"200\"} 2\n"));
+    assert!(output.contains("bodhi_http_requests_total{route=\"/missing\",status=\"404\"} 1\n"));
+    Ok(())
+  }
+}
@@ -0,0 +1,361 @@
+use async_openai::types::{ListModelResponse, Model};
+use axum::{
+  extract::{Path, State},
+  response::{sse::Event, IntoResponse, Response, Sse},
+  routing::{get, post},
+  Json, Router,
+};
+use chrono::Utc;
+use futures_util::StreamExt;
+use serde_json::{json, Value};
+use std::{convert::Infallible, time::Duration};
+use tokio_stream::wrappers::ReceiverStream;
+
+pub(crate) static MOCK_MODEL: &str = "bodhi-mock";
+
+// canned responses for the client developers testing without a model, the text is sent word by
+// word when streaming, with the delay between the words to simulate the token generation
+#[derive(Debug, Clone)]
+pub(crate) struct MockConfig {
+  pub text: String,
+  pub token_delay: Duration,
+}
+
+pub(crate) fn mock_router<S>(config: MockConfig) -> Router<S> {
+  Router::new()
+    .route("/v1/models", get(mock_models_handler))
+    .route("/v1/models/:id", get(mock_model_handler))
+    .route("/v1/chat/completions", post(mock_chat_completions_handler))
+    .route("/v1/completions", post(mock_completions_handler))
+    .with_state(config)
+}
+
+fn mock_model(id: &str) -> Model {
+  Model {
+    id: id.to_string(),
+    object: "model".to_string(),
+    created: 0,
+    owned_by: "system".to_string(),
+  }
+}
+
+async fn mock_models_handler() -> Json<ListModelResponse> {
+  Json(ListModelResponse {
+    object: "list".to_string(),
+    data: vec![mock_model(MOCK_MODEL)],
+  })
+}
+
+// any model id is found, so the clients can keep the model names of their real setup
+async fn mock_model_handler(Path(id): Path<String>) -> Json<Model> {
+  Json(mock_model(&id))
+}
+
+async fn mock_chat_completions_handler(
+  State(config): State<MockConfig>,
+  Json(request): Json<Value>,
+) -> Response {
+  let prompt = request["messages"]
+    .as_array()
+    .map(|messages| {
+      messages
+        .iter()
+        .filter_map(|message| message["content"].as_str())
+        .collect::<Vec<_>>()
+        .join("\n")
+    })
+    .unwrap_or_default();
+  let completion = Completion::new(&request, &prompt, &config.text, "chatcmpl");
+  if !completion.stream {
+    let response = completion.response(
+      "chat.completion",
+      json! {{
+        "index": 0,
+        "message": {"role": "assistant", "content": config.text},
+        "finish_reason": "stop",
+        "logprobs": null,
+      }},
+    );
+    return Json(response).into_response();
+  }
+  let chunks = words(&config.text)
+    .into_iter()
+    .enumerate()
+    .map(|(index, word)| {
+      let delta = if index == 0 {
+        json! {{"role": "assistant", "content": word}}
+      } else {
+        json! {{"content": word}}
+      };
+      json! {{"index": 0, "delta": delta, "finish_reason": null, "logprobs": null}}
+    })
+    .chain([json! {{"index": 0, "delta": {}, "finish_reason": "stop", "logprobs": null}}])
+    .map(|choice| completion.chunk("chat.completion.chunk", choice))
+    .collect::<Vec<_>>();
+  stream_response(chunks, config.token_delay)
+}
+
+async fn mock_completions_handler(
+  State(config): State<MockConfig>,
+  Json(request): Json<Value>,
+) -> Response {
+  let prompt = match &request["prompt"] {
+    Value::String(prompt) => prompt.clone(),
+    Value::Array(prompts) => prompts
+      .iter()
+      .filter_map(Value::as_str)
+      .collect::<Vec<_>>()
+      .join("\n"),
+    _ => String::new(),
+  };
+  let completion = Completion::new(&request, &prompt, &config.text, "cmpl");
+  if !completion.stream {
+    let response = completion.response(
+      "text_completion",
+      json! {{"index": 0, "text": config.text, "finish_reason": "stop", "logprobs": null}},
+    );
+    return Json(response).into_response();
+  }
+  let chunks = words(&config.text)
+    .into_iter()
+    .map(|word| json! {{"index": 0, "text": word, "finish_reason": null, "logprobs": null}})
+    .chain([json! {{"index": 0, "text": "", "finish_reason": "stop", "logprobs": null}}])
+    .map(|choice| completion.chunk("text_completion", choice))
+    .collect::<Vec<_>>();
+  stream_response(chunks, config.token_delay)
+}
+
+struct Completion {
+  id: String,
+  created: i64,
+  model: String,
+  stream: bool,
+  prompt_tokens: usize,
+  completion_tokens: usize,
+}
+
+impl Completion {
+  // the tokens are counted as words, close enough for the usage to look realistic
+  fn new(request: &Value, prompt: &str, text: &str, id_prefix: &str) -> Self {
+    Self {
+      id: format!("{id_prefix}-mock-{}", uuid::Uuid::new_v4().simple()),
+      created: Utc::now().timestamp(),
+      model: request["model"].as_str().unwrap_or(MOCK_MODEL).to_string(),
+      stream: request["stream"].as_bool().unwrap_or(false),
+      prompt_tokens: prompt.split_whitespace().count(),
+      completion_tokens: text.split_whitespace().count(),
+    }
+  }
+
+  fn usage(&self) -> Value {
+    json! {{
+      "prompt_tokens": self.prompt_tokens,
+      "completion_tokens": self.completion_tokens,
+      "total_tokens": self.prompt_tokens + self.completion_tokens,
+    }}
+  }
+
+  fn response(&self, object: &str, choice: Value) -> Value {
+    json! {{
+      "id": self.id,
+      "object": object,
+      "created": self.created,
+      "model": self.model,
+      "choices": [choice],
+      "usage": self.usage(),
+    }}
+  }
+
+  fn chunk(&self, object: &str, choice: Value) -> Value {
+    json! {{
+      "id": self.id,
+      "object": object,
+      "created": self.created,
+      "model": self.model,
+      "choices": [choice],
+    }}
+  }
+}
+
+// the words along with their leading whitespace, so the chunks join back to the text
+fn words(text: &str) -> Vec<String> {
+  let mut words = Vec::new();
+  let mut word = String::new();
+  for c in text.chars() {
+    if c.is_whitespace() && !word.trim().is_empty() {
+      words.push(std::mem::take(&mut word));
+    }
+    word.push(c);
+  }
+  if !word.is_empty() {
+    words.push(word);
+  }
+  words
+}
+
+fn stream_response(chunks: Vec<Value>, token_delay: Duration) -> Response {
+  let (tx, rx) = tokio::sync::mpsc::channel::<String>(100);
+  tokio::spawn(async move {
+    for chunk in chunks {
+      if tx.send(chunk.to_string()).await.is_err() {
+        return;
+      }
+      tokio::time::sleep(token_delay).await;
+    }
+    _ = tx.send("[DONE]".to_string()).await;
+  });
+  let stream = ReceiverStream::new(rx).map(|data| Ok::<_, Infallible>(Event::default().data(data)));
+  Sse::new(stream).into_response()
+}
+
+#[cfg(test)]
+mod test {
+  use super::{mock_router, words, MockConfig, MOCK_MODEL};
+  use crate::test_utils::{RequestTestExt, ResponseTestExt};
+  use async_openai::types::{
+    CreateChatCompletionResponse, CreateChatCompletionStreamResponse, CreateCompletionResponse,
+    ListModelResponse,
+  };
+  use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+  };
+  use rstest::{fixture, rstest};
+  use serde_json::json;
+  use std::time::Duration;
+  use tower::ServiceExt;
+
+  #[fixture]
+  fn router() -> Router {
+    mock_router(MockConfig {
+      text: "Tuesday comes after Monday.".to_string(),
+      token_delay: Duration::from_millis(1),
+    })
+  }
+
+  #[rstest]
+  #[case("Tuesday comes after Monday.", vec!["Tuesday", " comes", " after", " Monday."])]
+  #[case(" two  words\n", vec![" two", "  words", "\n"])]
+  #[case("", vec![])]
+  fn test_routes_mock_words(#[case] text: &str, #[case] expected: Vec<&str>) {
+    assert_eq!(expected, words(text));
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_routes_mock_chat_completions(router: Router) -> anyhow::Result<()> {
+    let response = router
+      .oneshot(Request::post("/v1/chat/completions").json(json! {{
+        "model": "testalias:instruct",
+        "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+      }})?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response = response.json::<CreateChatCompletionResponse>().await?;
+    assert_eq!("testalias:instruct", response.model);
+    assert_eq!(
+      Some("Tuesday comes after Monday.".to_string()),
+      response.choices[0].message.content
+    );
+    let usage = response.usage.unwrap();
+    assert_eq!(
+      (5, 4, 9),
+      (
+        usage.prompt_tokens,
+        usage.completion_tokens,
+        usage.total_tokens
+      )
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_routes_mock_chat_completions_stream(router: Router) -> anyhow::Result<()> {
+    let response = router
+      .oneshot(Request::post("/v1/chat/completions").json(json! {{
+        "model": "testalias:instruct",
+        "stream": true,
+        "messages": [{"role": "user", "content": "What day comes after Monday?"}],
+      }})?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let text = response.text().await?;
+    let data = text
+      .lines()
+      .filter_map(|line| line.strip_prefix("data: "))
+      .collect::<Vec<_>>();
+    assert_eq!(Some(&"[DONE]"), data.last());
+    let chunks = data[..data.len() - 1]
+      .iter()
+      .map(|data| serde_json::from_str::<CreateChatCompletionStreamResponse>(data))
+      .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(5, chunks.len());
+    let content = chunks
+      .iter()
+      .filter_map(|chunk| chunk.choices[0].delta.content.clone())
+      .collect::<String>();
+    assert_eq!("Tuesday comes after Monday.", content);
+    assert!(chunks.iter().all(|chunk| chunk.id == chunks[0].id));
+    assert_eq!(
+      Some("stop"),
+      serde_json::to_value(chunks[4].choices[0].finish_reason)?.as_str()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(false)]
+  #[case(true)]
+  #[tokio::test]
+  async fn test_routes_mock_completions(
+    router: Router,
+    #[case] stream: bool,
+  ) -> anyhow::Result<()> {
+    let response = router
+      .oneshot(Request::post("/v1/completions").json(json! {{
+        "model": "testalias:instruct",
+        "prompt": "The day after Monday is",
+        "stream": stream,
+      }})?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    if !stream {
+      let response = response.json::<CreateCompletionResponse>().await?;
+      assert_eq!("Tuesday comes after Monday.", response.choices[0].text);
+      return Ok(());
+    }
+    let text = response.text().await?;
+    let content = text
+      .lines()
+      .filter_map(|line| line.strip_prefix("data: "))
+      .filter(|data| *data != "[DONE]")
+      .map(serde_json::from_str::<CreateCompletionResponse>)
+      .collect::<Result<Vec<_>, _>>()?
+      .into_iter()
+      .map(|chunk| chunk.choices[0].text.clone())
+      .collect::<String>();
+    assert_eq!("Tuesday comes after Monday.", content);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_routes_mock_models(router: Router) -> anyhow::Result<()> {
+    let response = router
+      .oneshot(Request::get("/v1/models").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response = response.json::<ListModelResponse>().await?;
+    assert_eq!(
+      vec![MOCK_MODEL],
+      response
+        .data
+        .iter()
+        .map(|model| model.id.as_str())
+        .collect::<Vec<_>>()
+    );
+    Ok(())
+  }
+}
@@ -1,43 +1,458 @@
 use super::RouterStateFn;
-use crate::{oai::OpenAIApiError, objs::Alias};
-use async_openai::types::{ListModelResponse, Model};
+use crate::{
+  gguf::GgufModelInfo,
+  oai::OpenAIApiError,
+  objs::{is_default, Alias, GptContextParams, OAIRequestParams},
+  service::{find_model_file, AppServiceFn, DataServiceError, HubService},
+};
+use async_openai::types::Model;
 use axum::{
-  extract::{Path, State},
-  Json,
+  extract::{Path, Query, State},
+  http::StatusCode,
+  routing::{delete, post},
+  Json, Router,
 };
-use std::{fs, sync::Arc, time::UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, fs, sync::Arc, time::UNIX_EPOCH};
+
+// the OpenAI model object, along with the request defaults of the alias, clients that only know
+// the OpenAI fields ignore the rest
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AliasModel {
+  #[serde(flatten)]
+  pub model: Model,
+  #[serde(default, skip_serializing_if = "is_default")]
+  pub request_params: OAIRequestParams,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub tags: Vec<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub details: Option<GgufModelInfo>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub capabilities: Option<ModelCapabilities>,
+}
+
+// what a client can use the model for, to configure its max tokens and features without trying
+// them out
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelCapabilities {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub context_length: Option<u64>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub quantization: Option<String>,
+  pub chat: bool,
+  pub embedding: bool,
+  pub tools: bool,
+  pub vision: bool,
+}
+
+impl ModelCapabilities {
+  // the context of the alias, falling back to the context the model was trained on. The tools
+  // are described in the prompt when the chat template does not render them, so any chat model
+  // supports them, while the image content is not supported
+  pub fn new(alias: &Alias, details: Option<&GgufModelInfo>) -> ModelCapabilities {
+    let context_length = alias
+      .context_params
+      .n_ctx
+      .filter(|n_ctx| *n_ctx > 0)
+      .map(|n_ctx| n_ctx as u64)
+      .or_else(|| details.and_then(|details| details.context_length));
+    let embedding = details.is_some_and(|details| details.embedding);
+    ModelCapabilities {
+      context_length,
+      quantization: details.and_then(|details| details.quantization.clone()),
+      chat: !embedding,
+      embedding,
+      tools: !embedding,
+      vision: false,
+    }
+  }
+}
+
+// the OpenAI list, along with the paging of the list when the request asks for a page
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListAliasModelResponse {
+  pub object: String,
+  pub data: Vec<AliasModel>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub total: Option<usize>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub page: Option<usize>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub page_size: Option<usize>,
+}
+
+pub static DEFAULT_MODELS_PAGE_SIZE: usize = 30;
+pub static MAX_MODELS_PAGE_SIZE: usize = 100;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+enum ModelsSort {
+  #[default]
+  Name,
+  Created,
+  Size,
+}
+
+// `?tag=chat&tag=code` lists the aliases having all the tags, and `?q=` the aliases with the
+// text in their name, both ignoring the case. The whole list is returned unless `page` or
+// `page_size` is passed, as the OpenAI clients do not page the models list
+#[derive(Debug, Default, PartialEq)]
+struct ModelsQuery {
+  tags: Vec<String>,
+  q: Option<String>,
+  sort: ModelsSort,
+  desc: bool,
+  page: Option<usize>,
+  page_size: Option<usize>,
+}
+
+impl TryFrom<Vec<(String, String)>> for ModelsQuery {
+  type Error = OpenAIApiError;
+
+  fn try_from(params: Vec<(String, String)>) -> Result<Self, Self::Error> {
+    let mut query = ModelsQuery::default();
+    for (key, value) in params {
+      match key.as_str() {
+        "tag" => query.tags.push(value),
+        "q" => query.q = Some(value.trim().to_lowercase()),
+        "sort" => {
+          query.sort = match value.as_str() {
+            "name" => ModelsSort::Name,
+            "created" => ModelsSort::Created,
+            "size" => ModelsSort::Size,
+            _ => return Err(invalid_param(&key, &value, "one of name, created or size")),
+          }
+        }
+        "sort_order" => {
+          query.desc = match value.as_str() {
+            "asc" => false,
+            "desc" => true,
+            _ => return Err(invalid_param(&key, &value, "one of asc or desc")),
+          }
+        }
+        "page" | "page_size" => {
+          let number = value
+            .parse::<usize>()
+            .ok()
+            .filter(|number| *number > 0)
+            .ok_or_else(|| invalid_param(&key, &value, "a positive number"))?;
+          if key == "page" {
+            query.page = Some(number);
+          } else {
+            query.page_size = Some(number.min(MAX_MODELS_PAGE_SIZE));
+          }
+        }
+        _ => {}
+      }
+    }
+    Ok(query)
+  }
+}
+
+fn invalid_param(key: &str, value: &str, expected: &str) -> OpenAIApiError {
+  OpenAIApiError::BadRequest(format!("invalid {key} '{value}', should be {expected}"))
+}
+
+impl ModelsQuery {
+  fn matches(&self, alias: &Alias) -> bool {
+    let matches_q = self
+      .q
+      .as_ref()
+      .map(|q| alias.alias.to_lowercase().contains(q))
+      .unwrap_or(true);
+    matches_q && self.tags.iter().all(|tag| alias.has_tag(tag))
+  }
+
+  fn paged(&self) -> bool {
+    self.page.is_some() || self.page_size.is_some()
+  }
+}
 
 pub(crate) async fn oai_models_handler(
   State(state): State<Arc<dyn RouterStateFn>>,
-) -> Result<Json<ListModelResponse>, OpenAIApiError> {
-  let models = state
+  Query(params): Query<Vec<(String, String)>>,
+) -> Result<Json<ListAliasModelResponse>, OpenAIApiError> {
+  let query = ModelsQuery::try_from(params)?;
+  let hub_service = state.app_service().hub_service();
+  let mut models = state
     .app_service()
     .data_service()
     .list_aliases()
     .map_err(|err| OpenAIApiError::InternalServer(err.to_string()))?
     .into_iter()
-    .map(|alias| to_oai_model(state.clone(), alias))
+    .filter(|alias| query.matches(alias))
+    .map(|alias| {
+      // only the size sort needs the model files looked up
+      let size = match query.sort {
+        ModelsSort::Size => model_size(hub_service.as_ref(), &alias),
+        ModelsSort::Name | ModelsSort::Created => 0,
+      };
+      (size, to_oai_model(state.clone(), alias))
+    })
     .collect::<Vec<_>>();
-  Ok(Json(ListModelResponse {
+  models.sort_by(|(size, model), (other_size, other)| {
+    let ordering = match query.sort {
+      ModelsSort::Name => Ordering::Equal,
+      ModelsSort::Created => model.model.created.cmp(&other.model.created),
+      ModelsSort::Size => size.cmp(other_size),
+    };
+    ordering.then_with(|| model.model.id.cmp(&other.model.id))
+  });
+  if query.desc {
+    models.reverse();
+  }
+  let models = models.into_iter().map(|(_, model)| model);
+  if !query.paged() {
+    return Ok(Json(ListAliasModelResponse {
+      object: "list".to_string(),
+      data: models.collect(),
+      total: None,
+      page: None,
+      page_size: None,
+    }));
+  }
+  let total = models.len();
+  let page = query.page.unwrap_or(1);
+  let page_size = query.page_size.unwrap_or(DEFAULT_MODELS_PAGE_SIZE);
+  Ok(Json(ListAliasModelResponse {
     object: "list".to_string(),
-    data: models,
+    data: models
+      .skip((page - 1).saturating_mul(page_size))
+      .take(page_size)
+      .collect(),
+    total: Some(total),
+    page: Some(page),
+    page_size: Some(page_size),
+  }))
+}
+
+// aliases without the model file in the cache sort as the smallest
+fn model_size(hub_service: &dyn HubService, alias: &Alias) -> u64 {
+  find_model_file(hub_service, alias)
+    .ok()
+    .flatten()
+    .and_then(|model_file| fs::metadata(model_file).ok())
+    .map(|metadata| metadata.len())
+    .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DeleteAliasParams {
+  #[serde(default)]
+  pub purge: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DeleteAliasResponse {
+  pub alias: String,
+  pub unloaded: bool,
+  pub purged: bool,
+}
+
+// the fields left out of the request are kept as they are, `alias` renames the alias
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct UpdateAliasRequest {
+  #[serde(default)]
+  pub alias: Option<String>,
+  #[serde(default)]
+  pub family: Option<String>,
+  #[serde(default)]
+  pub tags: Option<Vec<String>>,
+  #[serde(default)]
+  pub request_params: Option<OAIRequestParams>,
+  #[serde(default)]
+  pub context_params: Option<GptContextParams>,
+  #[serde(default)]
+  pub keep_alive_secs: Option<u64>,
+  #[serde(default)]
+  pub stop_tokens: Option<Vec<String>>,
+}
+
+// a new alias for the model of the alias, with the request params overridden
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct CopyAliasRequest {
+  pub alias: String,
+  #[serde(default)]
+  pub request_params: OAIRequestParams,
+}
+
+pub fn models_router() -> Router<Arc<dyn RouterStateFn>> {
+  Router::new()
+    .route(
+      "/models/:alias",
+      delete(delete_alias_handler).patch(update_alias_handler),
+    )
+    .route("/models/:alias/copy", post(copy_alias_handler))
+}
+
+// the copy uses the same model snapshot, so no download is needed
+async fn copy_alias_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  Path(id): Path<String>,
+  Json(request): Json<CopyAliasRequest>,
+) -> Result<(StatusCode, Json<AliasModel>), OpenAIApiError> {
+  let new_alias = request.alias.trim();
+  if new_alias.is_empty() {
+    return Err(OpenAIApiError::BadRequest(
+      "alias should not be empty".to_string(),
+    ));
+  }
+  state
+    .app_service()
+    .data_service()
+    .copy_alias(&id, new_alias, &request.request_params)
+    .map_err(data_service_error)?;
+  let alias = state
+    .app_service()
+    .data_service()
+    .find_alias(new_alias)
+    .ok_or_else(|| OpenAIApiError::ModelNotFound(new_alias.to_string()))?;
+  Ok((StatusCode::CREATED, Json(to_oai_model(state, alias))))
+}
+
+// the model file is kept in the huggingface cache unless purged, a model loaded for the alias is
+// unloaded first
+async fn delete_alias_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  Path(id): Path<String>,
+  Query(params): Query<DeleteAliasParams>,
+) -> Result<Json<DeleteAliasResponse>, OpenAIApiError> {
+  let alias = state
+    .app_service()
+    .data_service()
+    .find_alias(&id)
+    .ok_or_else(|| OpenAIApiError::ModelNotFound(id.to_string()))?;
+  let unloaded = state.unload_alias(&alias.alias).await?;
+  state
+    .app_service()
+    .data_service()
+    .delete_alias(&alias.alias)
+    .map_err(data_service_error)?;
+  let purged = if params.purge {
+    purge_model_file(state.app_service().as_ref(), &alias)?
+  } else {
+    false
+  };
+  tracing::info!(model = alias.alias, unloaded, purged, "model alias deleted");
+  Ok(Json(DeleteAliasResponse {
+    alias: alias.alias,
+    unloaded,
+    purged,
   }))
 }
 
+// a local model file outside the huggingface cache is never removed, nor a file another alias uses
+fn purge_model_file(app_service: &dyn AppServiceFn, alias: &Alias) -> Result<bool, OpenAIApiError> {
+  if alias.model_file.is_some() {
+    return Ok(false);
+  }
+  let shared = app_service
+    .data_service()
+    .list_aliases()
+    .map_err(data_service_error)?
+    .into_iter()
+    .any(|other| {
+      other.model_file.is_none()
+        && other.repo == alias.repo
+        && other.filename == alias.filename
+        && other.snapshot == alias.snapshot
+    });
+  if shared {
+    tracing::warn!(
+      model = alias.alias,
+      "model file used by another alias, skipped purging it"
+    );
+    return Ok(false);
+  }
+  app_service
+    .hub_service()
+    .delete_local_file(&alias.repo, &alias.filename, &alias.snapshot)
+    .map_err(|err| OpenAIApiError::InternalServer(err.to_string()))
+}
+
+async fn update_alias_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  Path(id): Path<String>,
+  Json(request): Json<UpdateAliasRequest>,
+) -> Result<Json<AliasModel>, OpenAIApiError> {
+  let alias = state
+    .app_service()
+    .data_service()
+    .find_alias(&id)
+    .ok_or_else(|| OpenAIApiError::ModelNotFound(id.to_string()))?;
+  let mut updated = alias.clone();
+  if let Some(new_alias) = request.alias {
+    let new_alias = new_alias.trim();
+    if new_alias.is_empty() {
+      return Err(OpenAIApiError::BadRequest(
+        "alias should not be empty".to_string(),
+      ));
+    }
+    updated.alias = new_alias.to_string();
+  }
+  if let Some(family) = request.family {
+    updated.family = Some(family);
+  }
+  if let Some(tags) = request.tags {
+    updated.tags = tags;
+  }
+  if let Some(request_params) = request.request_params {
+    updated.request_params = request_params;
+  }
+  if let Some(context_params) = request.context_params {
+    updated.context_params = context_params;
+  }
+  if let Some(keep_alive_secs) = request.keep_alive_secs {
+    updated.keep_alive_secs = Some(keep_alive_secs);
+  }
+  if let Some(stop_tokens) = request.stop_tokens {
+    updated.stop_tokens = stop_tokens;
+  }
+  // the loaded model keeps the old name and the old context params, so it is unloaded, and the
+  // next request loads it again for the updated alias
+  if updated.alias != alias.alias || updated.context_params != alias.context_params {
+    let unloaded = state.unload_alias(&alias.alias).await?;
+    tracing::info!(
+      model = alias.alias,
+      unloaded,
+      "model alias renamed or context params changed"
+    );
+  }
+  state
+    .app_service()
+    .data_service()
+    .update_alias(&alias.alias, &updated)
+    .map_err(data_service_error)?;
+  Ok(Json(to_oai_model(state, updated)))
+}
+
+fn data_service_error(err: DataServiceError) -> OpenAIApiError {
+  match err {
+    DataServiceError::AliasNotExists(alias) => OpenAIApiError::ModelNotFound(alias),
+    err @ DataServiceError::AliasExists(_) => OpenAIApiError::Conflict(err.to_string()),
+    err => OpenAIApiError::InternalServer(err.to_string()),
+  }
+}
+
 pub(crate) async fn oai_model_handler(
   State(state): State<Arc<dyn RouterStateFn>>,
   Path(id): Path<String>,
-) -> Result<Json<Model>, OpenAIApiError> {
+) -> Result<Json<AliasModel>, OpenAIApiError> {
   let alias = state
     .app_service()
     .data_service()
     .find_alias(&id)
     .ok_or_else(|| OpenAIApiError::ModelNotFound(id.to_string()))?;
-  let model = to_oai_model(state, alias);
+  let details = model_info(state.app_service().as_ref(), &alias);
+  let capabilities = ModelCapabilities::new(&alias, details.as_ref());
+  let model = AliasModel {
+    details,
+    capabilities: Some(capabilities),
+    ..to_oai_model(state, alias)
+  };
   Ok(Json(model))
 }
 
-fn to_oai_model(state: Arc<dyn RouterStateFn>, alias: Alias) -> Model {
+fn to_oai_model(state: Arc<dyn RouterStateFn>, alias: Alias) -> AliasModel {
   let bodhi_home = &state.app_service().env_service().bodhi_home();
   let path = bodhi_home.join("configs").join(alias.config_filename());
   let created = fs::metadata(path)
@@ -46,10 +461,638 @@ fn to_oai_model(state: Arc<dyn RouterStateFn>, alias: Alias) -> Model {
     .and_then(|t| t.duration_since(UNIX_EPOCH).map_err(|e| e.to_string()))
     .unwrap_or_default()
     .as_secs() as u32;
-  Model {
-    id: alias.alias,
-    object: "model".to_string(),
-    created,
-    owned_by: "system".to_string(),
+  AliasModel {
+    model: Model {
+      id: alias.alias,
+      object: "model".to_string(),
+      created,
+      owned_by: "system".to_string(),
+    },
+    request_params: alias.request_params,
+    tags: alias.tags,
+    details: None,
+    capabilities: None,
+  }
+}
+
+// the GGUF metadata of the model file, none if the file is missing or not a valid GGUF file
+pub(crate) fn model_info(app_service: &dyn AppServiceFn, alias: &Alias) -> Option<GgufModelInfo> {
+  let model_file = match find_model_file(app_service.hub_service().as_ref(), alias) {
+    Ok(Some(model_file)) => model_file,
+    Ok(None) => return None,
+    Err(err) => {
+      tracing::warn!(?err, model = alias.alias, "error finding model file");
+      return None;
+    }
+  };
+  match GgufModelInfo::cached(&model_file) {
+    Ok(info) => Some(info),
+    Err(err) => {
+      tracing::warn!(?err, model = alias.alias, "error reading model metadata");
+      None
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{models_router, oai_model_handler, oai_models_handler};
+  use crate::{
+    objs::{Alias, OAIRequestParams},
+    service::{DataServiceError, MockDataService, MockEnvServiceFn, MockHubService},
+    test_utils::{AppServiceStubMock, MockRouterState, ResponseTestExt},
+  };
+  use anyhow_trace::anyhow_trace;
+  use axum::{body::Body, extract::Request, routing::get, Router};
+  use reqwest::StatusCode;
+  use rstest::{fixture, rstest};
+  use serde_json::{json, Value};
+  use std::{fs, path::PathBuf, sync::Arc};
+  use tower::ServiceExt;
+
+  #[fixture]
+  fn aliases() -> Vec<Alias> {
+    vec![
+      Alias::testalias(),
+      Alias {
+        alias: "coder:instruct".to_string(),
+        request_params: OAIRequestParams {
+          temperature: Some(0.5),
+          system_prompt: Some("You are a coding assistant.".to_string()),
+          ..Default::default()
+        },
+        tags: vec!["code".to_string(), "chat".to_string()],
+        ..Alias::testalias()
+      },
+      Alias {
+        alias: "writer:instruct".to_string(),
+        tags: vec!["chat".to_string()],
+        ..Alias::testalias()
+      },
+    ]
+  }
+
+  fn app(aliases: Vec<Alias>) -> Router {
+    let mut mock_data_service = MockDataService::default();
+    mock_data_service
+      .expect_list_aliases()
+      .returning(move || Ok(aliases.clone()));
+    mock_data_service.expect_find_alias().returning(|_| None);
+    let mut mock_env_service = MockEnvServiceFn::new();
+    mock_env_service
+      .expect_bodhi_home()
+      .return_const(PathBuf::from("/tmp/ignored/bodhi"));
+    let mut mock_hub_service = MockHubService::new();
+    mock_hub_service
+      .expect_find_local_file()
+      .returning(|_, _, _| Ok(None));
+    let service = Arc::new(AppServiceStubMock::new(
+      mock_env_service,
+      mock_hub_service,
+      mock_data_service,
+    ));
+    let mut router_state = MockRouterState::new();
+    router_state
+      .expect_app_service()
+      .returning(move || service.clone());
+    Router::new()
+      .route("/v1/models", get(oai_models_handler))
+      .route("/v1/models/:id", get(oai_model_handler))
+      .with_state(Arc::new(router_state))
+  }
+
+  async fn list_ids(app: Router, uri: &str) -> anyhow::Result<Vec<String>> {
+    let response = app.oneshot(Request::get(uri).body(Body::empty())?).await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response: Value = response.json().await?;
+    let ids = response["data"]
+      .as_array()
+      .unwrap()
+      .iter()
+      .map(|model| model["id"].as_str().unwrap().to_string())
+      .collect::<Vec<_>>();
+    Ok(ids)
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_models_list_includes_alias_request_params(
+    aliases: Vec<Alias>,
+  ) -> anyhow::Result<()> {
+    let response = app(aliases)
+      .oneshot(Request::get("/v1/models").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response: Value = response.json().await?;
+    assert_eq!(None, response.get("total"));
+    let models = response["data"].as_array().unwrap();
+    assert_eq!(3, models.len());
+    assert_eq!("coder:instruct", models[0]["id"]);
+    assert_eq!("model", models[0]["object"]);
+    assert_eq!(
+      json! {{"temperature": 0.5, "system_prompt": "You are a coding assistant."}},
+      models[0]["request_params"]
+    );
+    assert_eq!(json! {["code", "chat"]}, models[0]["tags"]);
+    assert_eq!("testalias:instruct", models[1]["id"]);
+    assert_eq!(None, models[1].get("request_params"));
+    assert_eq!(None, models[1].get("tags"));
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("/v1/models?tag=chat", vec!["coder:instruct", "writer:instruct"])]
+  #[case("/v1/models?tag=chat&tag=CODE", vec!["coder:instruct"])]
+  #[case("/v1/models?q=Instruct&tag=chat", vec!["coder:instruct", "writer:instruct"])]
+  #[case("/v1/models?q=test", vec!["testalias:instruct"])]
+  #[case("/v1/models?tag=missing", vec![])]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_models_list_filters_by_tag_and_q(
+    aliases: Vec<Alias>,
+    #[case] uri: &str,
+    #[case] expected: Vec<&str>,
+  ) -> anyhow::Result<()> {
+    let ids = list_ids(app(aliases), uri).await?;
+    assert_eq!(expected, ids);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("/v1/models?sort=name&sort_order=desc", vec!["writer:instruct", "testalias:instruct", "coder:instruct"])]
+  #[case("/v1/models?page_size=2", vec!["coder:instruct", "testalias:instruct"])]
+  #[case("/v1/models?page=2&page_size=2", vec!["writer:instruct"])]
+  #[case("/v1/models?page=3&page_size=2", vec![])]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_models_list_sorts_and_pages(
+    aliases: Vec<Alias>,
+    #[case] uri: &str,
+    #[case] expected: Vec<&str>,
+  ) -> anyhow::Result<()> {
+    let ids = list_ids(app(aliases), uri).await?;
+    assert_eq!(expected, ids);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_models_list_page_envelope_caps_page_size(
+    aliases: Vec<Alias>,
+  ) -> anyhow::Result<()> {
+    let response = app(aliases)
+      .oneshot(Request::get("/v1/models?page_size=500").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response: Value = response.json().await?;
+    assert_eq!(3, response["total"]);
+    assert_eq!(1, response["page"]);
+    assert_eq!(100, response["page_size"]);
+    assert_eq!(3, response["data"].as_array().unwrap().len());
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_models_list_sorts_by_model_file_size() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let aliases = [
+      ("small:instruct", 10),
+      ("large:instruct", 30),
+      ("medium:instruct", 20),
+    ]
+    .into_iter()
+    .map(|(alias, size)| -> anyhow::Result<Alias> {
+      let model_file = temp_dir.path().join(format!("{alias}.gguf"));
+      fs::write(&model_file, vec![0u8; size])?;
+      Ok(Alias {
+        alias: alias.to_string(),
+        model_file: Some(model_file),
+        ..Alias::testalias()
+      })
+    })
+    .collect::<anyhow::Result<Vec<_>>>()?;
+    let ids = list_ids(app(aliases), "/v1/models?sort=size&sort_order=desc").await?;
+    assert_eq!(
+      vec!["large:instruct", "medium:instruct", "small:instruct"],
+      ids
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("/v1/models?sort=popularity")]
+  #[case("/v1/models?page=0")]
+  #[case("/v1/models?page_size=ten")]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_models_list_rejects_invalid_params(
+    aliases: Vec<Alias>,
+    #[case] uri: &str,
+  ) -> anyhow::Result<()> {
+    let response = app(aliases)
+      .oneshot(Request::get(uri).body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_models_get_unknown_alias_returns_not_found(
+    aliases: Vec<Alias>,
+  ) -> anyhow::Result<()> {
+    let response = app(aliases)
+      .oneshot(Request::get("/v1/models/unknown:instruct").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+    Ok(())
+  }
+
+  fn alias_app(
+    mock_data_service: MockDataService,
+    mock_hub_service: MockHubService,
+    unloaded: bool,
+  ) -> Router {
+    let mut mock_env_service = MockEnvServiceFn::new();
+    mock_env_service
+      .expect_bodhi_home()
+      .return_const(PathBuf::from("/tmp/ignored/bodhi"));
+    let service = Arc::new(AppServiceStubMock::new(
+      mock_env_service,
+      mock_hub_service,
+      mock_data_service,
+    ));
+    let mut router_state = MockRouterState::new();
+    router_state
+      .expect_app_service()
+      .returning(move || service.clone());
+    router_state
+      .expect_unload_alias()
+      .returning(move |_| Ok(unloaded));
+    models_router().with_state(Arc::new(router_state))
+  }
+
+  #[rstest]
+  #[case("/models/testalias:instruct", false, false)]
+  #[case("/models/testalias:instruct?purge=true", true, true)]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_models_delete_alias(
+    #[case] uri: &str,
+    #[case] purge: bool,
+    #[case] purged: bool,
+  ) -> anyhow::Result<()> {
+    let mut mock_data_service = MockDataService::default();
+    mock_data_service
+      .expect_find_alias()
+      .withf(|alias| alias == "testalias:instruct")
+      .returning(|_| Some(Alias::testalias()));
+    mock_data_service
+      .expect_delete_alias()
+      .withf(|alias| alias == "testalias:instruct")
+      .times(1)
+      .returning(|_| Ok(()));
+    mock_data_service
+      .expect_list_aliases()
+      .returning(|| Ok(vec![]));
+    let mut mock_hub_service = MockHubService::new();
+    mock_hub_service
+      .expect_delete_local_file()
+      .times(purge as usize)
+      .returning(|_, _, _| Ok(true));
+    let response = alias_app(mock_data_service, mock_hub_service, true)
+      .oneshot(Request::delete(uri).body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response: Value = response.json().await?;
+    assert_eq!(
+      json! {{"alias": "testalias:instruct", "unloaded": true, "purged": purged}},
+      response
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_models_delete_alias_skips_purging_shared_model_file() -> anyhow::Result<()> {
+    let mut mock_data_service = MockDataService::default();
+    mock_data_service
+      .expect_find_alias()
+      .returning(|_| Some(Alias::testalias()));
+    mock_data_service
+      .expect_delete_alias()
+      .returning(|_| Ok(()));
+    mock_data_service.expect_list_aliases().returning(|| {
+      Ok(vec![Alias {
+        alias: "other:instruct".to_string(),
+        ..Alias::testalias()
+      }])
+    });
+    let mut mock_hub_service = MockHubService::new();
+    mock_hub_service.expect_delete_local_file().never();
+    let response = alias_app(mock_data_service, mock_hub_service, false)
+      .oneshot(Request::delete("/models/testalias:instruct?purge=true").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response: Value = response.json().await?;
+    assert_eq!(false, response["purged"]);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_models_delete_unknown_alias_returns_not_found() -> anyhow::Result<()> {
+    let mut mock_data_service = MockDataService::default();
+    mock_data_service.expect_find_alias().returning(|_| None);
+    mock_data_service.expect_delete_alias().never();
+    let response = alias_app(mock_data_service, MockHubService::new(), false)
+      .oneshot(Request::delete("/models/unknown:instruct").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_models_update_alias_renames_and_tags() -> anyhow::Result<()> {
+    let mut mock_data_service = MockDataService::default();
+    mock_data_service
+      .expect_find_alias()
+      .returning(|_| Some(Alias::testalias()));
+    mock_data_service
+      .expect_update_alias()
+      .withf(|alias, updated| {
+        alias == "testalias:instruct"
+          && updated.alias == "renamed:instruct"
+          && updated.tags == vec!["chat".to_string()]
+      })
+      .times(1)
+      .returning(|_, _| Ok(()));
+    let response = alias_app(mock_data_service, MockHubService::new(), false)
+      .oneshot(
+        Request::patch("/models/testalias:instruct")
+          .header("content-type", "application/json")
+          .body(Body::from(
+            json! {{"alias": "renamed:instruct", "tags": ["chat"]}}.to_string(),
+          ))?,
+      )
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response: Value = response.json().await?;
+    assert_eq!("renamed:instruct", response["id"]);
+    assert_eq!(json! {["chat"]}, response["tags"]);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(json! {{"alias": "renamed:instruct"}}, 1)]
+  #[case(json! {{"context_params": {"n_ctx": 4096}}}, 1)]
+  #[case(json! {{"alias": "testalias:instruct", "tags": ["chat"]}}, 0)]
+  #[case(json! {{"request_params": {"temperature": 0.5}}}, 0)]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_models_update_alias_unloads_loaded_model(
+    #[case] body: Value,
+    #[case] unloads: usize,
+  ) -> anyhow::Result<()> {
+    let mut mock_data_service = MockDataService::default();
+    mock_data_service
+      .expect_find_alias()
+      .returning(|_| Some(Alias::testalias()));
+    mock_data_service
+      .expect_update_alias()
+      .times(1)
+      .returning(|_, _| Ok(()));
+    let mut mock_env_service = MockEnvServiceFn::new();
+    mock_env_service
+      .expect_bodhi_home()
+      .return_const(PathBuf::from("/tmp/ignored/bodhi"));
+    let service = Arc::new(AppServiceStubMock::new(
+      mock_env_service,
+      MockHubService::new(),
+      mock_data_service,
+    ));
+    let mut router_state = MockRouterState::new();
+    router_state
+      .expect_app_service()
+      .returning(move || service.clone());
+    router_state
+      .expect_unload_alias()
+      .withf(|alias| alias == "testalias:instruct")
+      .times(unloads)
+      .returning(|_| Ok(true));
+    let response = models_router()
+      .with_state(Arc::new(router_state))
+      .oneshot(
+        Request::patch("/models/testalias:instruct")
+          .header("content-type", "application/json")
+          .body(Body::from(body.to_string()))?,
+      )
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(json! {{"alias": "  "}}, StatusCode::BAD_REQUEST)]
+  #[case(json! {{"alias": "llama3:instruct"}}, StatusCode::CONFLICT)]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_models_update_alias_errors(
+    #[case] body: Value,
+    #[case] status: StatusCode,
+  ) -> anyhow::Result<()> {
+    let mut mock_data_service = MockDataService::default();
+    mock_data_service
+      .expect_find_alias()
+      .returning(|_| Some(Alias::testalias()));
+    mock_data_service
+      .expect_update_alias()
+      .returning(|_, updated| Err(DataServiceError::AliasExists(updated.alias.clone())));
+    let response = alias_app(mock_data_service, MockHubService::new(), false)
+      .oneshot(
+        Request::patch("/models/testalias:instruct")
+          .header("content-type", "application/json")
+          .body(Body::from(body.to_string()))?,
+      )
+      .await?;
+    assert_eq!(status, response.status());
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_models_copy_alias_with_request_params() -> anyhow::Result<()> {
+    let mut mock_data_service = MockDataService::default();
+    mock_data_service
+      .expect_copy_alias()
+      .withf(|alias, new_alias, overrides| {
+        alias == "testalias:instruct"
+          && new_alias == "testalias:poet"
+          && overrides.temperature == Some(0.5)
+      })
+      .times(1)
+      .returning(|_, _, _| Ok(()));
+    mock_data_service
+      .expect_find_alias()
+      .withf(|alias| alias == "testalias:poet")
+      .returning(|_| {
+        Some(Alias {
+          alias: "testalias:poet".to_string(),
+          request_params: OAIRequestParams {
+            temperature: Some(0.5),
+            ..Default::default()
+          },
+          ..Alias::testalias()
+        })
+      });
+    let response = alias_app(mock_data_service, MockHubService::new(), false)
+      .oneshot(
+        Request::post("/models/testalias:instruct/copy")
+          .header("content-type", "application/json")
+          .body(Body::from(
+            json! {{"alias": "testalias:poet", "request_params": {"temperature": 0.5}}}.to_string(),
+          ))?,
+      )
+      .await?;
+    assert_eq!(StatusCode::CREATED, response.status());
+    let response: Value = response.json().await?;
+    assert_eq!("testalias:poet", response["id"]);
+    assert_eq!(json! {{"temperature": 0.5}}, response["request_params"]);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(DataServiceError::AliasExists("testalias:poet".to_string()), StatusCode::CONFLICT)]
+  #[case(DataServiceError::AliasNotExists("testalias:instruct".to_string()), StatusCode::NOT_FOUND)]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_models_copy_alias_errors(
+    #[case] err: DataServiceError,
+    #[case] status: StatusCode,
+  ) -> anyhow::Result<()> {
+    let mut mock_data_service = MockDataService::default();
+    mock_data_service
+      .expect_copy_alias()
+      .return_once(move |_, _, _| Err(err));
+    let response = alias_app(mock_data_service, MockHubService::new(), false)
+      .oneshot(
+        Request::post("/models/testalias:instruct/copy")
+          .header("content-type", "application/json")
+          .body(Body::from(json! {{"alias": "testalias:poet"}}.to_string()))?,
+      )
+      .await?;
+    assert_eq!(status, response.status());
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(
+    "tests/data/tinyllama-15m-q8_0.gguf",
+    json! {{"architecture": "llama", "parameter_count": 24407712, "quantization": "Q8_0", "context_length": 256}}
+  )]
+  #[case("tests/data/does-not-exist.gguf", Value::Null)]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_models_get_includes_gguf_details(
+    #[case] model_file: &str,
+    #[case] expected: Value,
+  ) -> anyhow::Result<()> {
+    let alias = Alias {
+      model_file: Some(PathBuf::from(model_file)),
+      ..Alias::testalias()
+    };
+    let mut mock_data_service = MockDataService::default();
+    mock_data_service
+      .expect_find_alias()
+      .returning(move |_| Some(alias.clone()));
+    let mut mock_env_service = MockEnvServiceFn::new();
+    mock_env_service
+      .expect_bodhi_home()
+      .return_const(PathBuf::from("/tmp/ignored/bodhi"));
+    let service = Arc::new(AppServiceStubMock::new(
+      mock_env_service,
+      MockHubService::new(),
+      mock_data_service,
+    ));
+    let mut router_state = MockRouterState::new();
+    router_state
+      .expect_app_service()
+      .returning(move || service.clone());
+    let response = Router::new()
+      .route("/v1/models/:id", get(oai_model_handler))
+      .with_state(Arc::new(router_state))
+      .oneshot(Request::get("/v1/models/testalias:instruct").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response: Value = response.json().await?;
+    assert_eq!("testalias:instruct", response["id"]);
+    assert_eq!(&expected, response.get("details").unwrap_or(&Value::Null));
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(
+    "tests/data/tinyllama-15m-q8_0.gguf",
+    None,
+    json! {{"context_length": 256, "quantization": "Q8_0", "chat": true, "embedding": false, "tools": true, "vision": false}}
+  )]
+  #[case(
+    "tests/data/tinyllama-15m-q8_0.gguf",
+    Some(128),
+    json! {{"context_length": 128, "quantization": "Q8_0", "chat": true, "embedding": false, "tools": true, "vision": false}}
+  )]
+  #[case(
+    "tests/data/does-not-exist.gguf",
+    None,
+    json! {{"chat": true, "embedding": false, "tools": true, "vision": false}}
+  )]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_models_get_includes_capabilities(
+    #[case] model_file: &str,
+    #[case] n_ctx: Option<i32>,
+    #[case] expected: Value,
+  ) -> anyhow::Result<()> {
+    let mut alias = Alias {
+      model_file: Some(PathBuf::from(model_file)),
+      ..Alias::testalias()
+    };
+    alias.context_params.n_ctx = n_ctx;
+    let mut mock_data_service = MockDataService::default();
+    mock_data_service
+      .expect_find_alias()
+      .returning(move |_| Some(alias.clone()));
+    let mut mock_env_service = MockEnvServiceFn::new();
+    mock_env_service
+      .expect_bodhi_home()
+      .return_const(PathBuf::from("/tmp/ignored/bodhi"));
+    let service = Arc::new(AppServiceStubMock::new(
+      mock_env_service,
+      MockHubService::new(),
+      mock_data_service,
+    ));
+    let mut router_state = MockRouterState::new();
+    router_state
+      .expect_app_service()
+      .returning(move || service.clone());
+    let response = Router::new()
+      .route("/v1/models/:id", get(oai_model_handler))
+      .with_state(Arc::new(router_state))
+      .oneshot(Request::get("/v1/models/testalias:instruct").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response: Value = response.json().await?;
+    assert_eq!(expected, response["capabilities"]);
+    Ok(())
   }
 }
@@ -0,0 +1,750 @@
+use super::{routes_models::model_info, RouterStateFn};
+use crate::{
+  oai::{BodhiChatRequest, OpenAIApiError},
+  service::{find_model_file, AppServiceFn},
+};
+use axum::{
+  body::Body,
+  extract::State,
+  http::header::CONTENT_TYPE,
+  response::{IntoResponse, Response},
+  Json,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::{
+  convert::Infallible,
+  fs::{self, File},
+  io,
+  path::Path,
+  sync::Arc,
+  time::{Duration, Instant},
+};
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::Instrument;
+
+// Ollama generate request, the prompt is sent to llama.cpp as is, without applying the chat
+// template
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct GenerateRequest {
+  pub model: String,
+  pub prompt: String,
+  // same as Ollama, the response is streamed unless disabled
+  #[serde(default)]
+  pub stream: Option<bool>,
+  #[serde(default)]
+  pub options: Option<GenerateOptions>,
+}
+
+// the Ollama options supported by llama.cpp, the other options are ignored
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct GenerateOptions {
+  #[serde(default)]
+  pub num_predict: Option<i32>,
+  #[serde(default)]
+  pub temperature: Option<f32>,
+  #[serde(default)]
+  pub top_p: Option<f32>,
+  #[serde(default)]
+  pub top_k: Option<i32>,
+  #[serde(default)]
+  pub min_p: Option<f32>,
+  #[serde(default)]
+  pub repeat_penalty: Option<f32>,
+  #[serde(default)]
+  pub tfs_z: Option<f32>,
+  #[serde(default)]
+  pub seed: Option<i64>,
+  #[serde(default)]
+  pub stop: Option<Vec<String>>,
+}
+
+impl TryFrom<GenerateRequest> for BodhiChatRequest {
+  type Error = OpenAIApiError;
+
+  fn try_from(value: GenerateRequest) -> Result<Self, Self::Error> {
+    let options = value.options.unwrap_or_default();
+    // the usage chunk has the token counts reported with the final response
+    let mut request = json! {{
+      "model": value.model,
+      "messages": [],
+      "stream": true,
+      "stream_options": {"include_usage": true},
+    }};
+    // a negative num_predict generates till the end of the text
+    let max_tokens = options.num_predict.filter(|num_predict| *num_predict >= 0);
+    for (field, param) in [
+      ("max_tokens", json!(max_tokens)),
+      ("temperature", json!(options.temperature)),
+      ("top_p", json!(options.top_p)),
+      ("top_k", json!(options.top_k)),
+      ("min_p", json!(options.min_p)),
+      ("repeat_penalty", json!(options.repeat_penalty)),
+      ("tfs_z", json!(options.tfs_z)),
+      ("seed", json!(options.seed)),
+      ("stop", json!(options.stop)),
+    ] {
+      if !param.is_null() {
+        request[field] = param;
+      }
+    }
+    let mut request = serde_json::from_value::<BodhiChatRequest>(request)
+      .map_err(|err| OpenAIApiError::BadRequest(err.to_string()))?;
+    request.prompt = Some(value.prompt);
+    Ok(request)
+  }
+}
+
+// the completion is always streamed from llama.cpp, and aggregated into a single response when
+// streaming is disabled
+pub(crate) async fn generate_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  Json(request): Json<GenerateRequest>,
+) -> Result<Response, OpenAIApiError> {
+  if state.is_paused() {
+    return Err(OpenAIApiError::InferencePaused);
+  }
+  let stream = request.stream.unwrap_or(true);
+  let mut generate = GenerateStream::new(request.model.clone());
+  let request = BodhiChatRequest::try_from(request)?;
+  let (tx, mut rx) = tokio::sync::mpsc::channel::<String>(100);
+  let handle =
+    tokio::spawn(async move { state.chat_completions(request, tx).await }.in_current_span());
+  // errors like a model not found are returned before anything is sent
+  let Some(first) = rx.recv().await else {
+    return match handle.await {
+      Ok(Err(err)) => Err(err),
+      _ => Err(OpenAIApiError::InternalServer(
+        "receiver stream abruptly closed".to_string(),
+      )),
+    };
+  };
+  if !stream {
+    let mut text = String::new();
+    let mut next = Some(first);
+    while let Some(msg) = next {
+      if let Some(chunk) = generate.chunk(&msg) {
+        if let Some(error) = chunk["error"].as_str() {
+          return Err(OpenAIApiError::InternalServer(error.to_string()));
+        }
+        text.push_str(chunk["response"].as_str().unwrap_or_default());
+      }
+      next = rx.recv().await;
+    }
+    _ = handle.await;
+    let mut response = generate.finish();
+    response["response"] = json!(text);
+    Ok(Json(response).into_response())
+  } else {
+    let (line_tx, line_rx) = tokio::sync::mpsc::channel::<Result<String, Infallible>>(100);
+    tokio::spawn(async move {
+      let mut next = Some(first);
+      while let Some(msg) = next {
+        if let Some(chunk) = generate.chunk(&msg) {
+          if line_tx.send(Ok(format!("{chunk}\n"))).await.is_err() {
+            return;
+          }
+        }
+        next = rx.recv().await;
+      }
+      if !generate.stopped {
+        _ = line_tx.send(Ok(format!("{}\n", generate.finish()))).await;
+      }
+      _ = handle.await;
+    });
+    let body = Body::from_stream(ReceiverStream::new(line_rx));
+    Ok(([(CONTENT_TYPE, "application/x-ndjson")], body).into_response())
+  }
+}
+
+// translates the chat completion chunks to the Ollama JSON lines, with the token counts and the
+// timings sent in the final line, the prompt eval is timed till the first token
+struct GenerateStream {
+  model: String,
+  started_at: Instant,
+  first_token_at: Option<Instant>,
+  done_reason: Option<String>,
+  usage: Value,
+  stopped: bool,
+}
+
+impl GenerateStream {
+  fn new(model: String) -> Self {
+    Self {
+      model,
+      started_at: Instant::now(),
+      first_token_at: None,
+      done_reason: None,
+      usage: Value::Null,
+      stopped: false,
+    }
+  }
+
+  fn chunk(&mut self, msg: &str) -> Option<Value> {
+    if self.stopped {
+      return None;
+    }
+    if let Some(error) = msg.strip_prefix("error: ") {
+      self.stopped = true;
+      return Some(json! {{"error": error.trim()}});
+    }
+    let data = msg.strip_prefix("data: ").unwrap_or(msg).trim();
+    let Ok(chunk) = serde_json::from_str::<Value>(data) else {
+      return None;
+    };
+    if !chunk["usage"].is_null() {
+      self.usage = chunk["usage"].clone();
+    }
+    let choice = &chunk["choices"][0];
+    if let Some(finish_reason) = choice["finish_reason"].as_str() {
+      self.done_reason = Some(finish_reason.to_string());
+    }
+    let text = choice["delta"]["content"].as_str().unwrap_or_default();
+    if text.is_empty() {
+      return None;
+    }
+    self.first_token_at.get_or_insert_with(Instant::now);
+    Some(json! {{
+      "model": self.model,
+      "created_at": created_at(),
+      "response": text,
+      "done": false,
+    }})
+  }
+
+  fn finish(&self) -> Value {
+    let finished_at = Instant::now();
+    let first_token_at = self.first_token_at.unwrap_or(finished_at);
+    json! {{
+      "model": self.model,
+      "created_at": created_at(),
+      "response": "",
+      "done": true,
+      "done_reason": self.done_reason.as_deref().unwrap_or("stop"),
+      "total_duration": nanos(finished_at - self.started_at),
+      "load_duration": 0,
+      "prompt_eval_count": self.usage["prompt_tokens"].as_u64().unwrap_or_default(),
+      "prompt_eval_duration": nanos(first_token_at - self.started_at),
+      "eval_count": self.usage["completion_tokens"].as_u64().unwrap_or_default(),
+      "eval_duration": nanos(finished_at - first_token_at),
+    }}
+  }
+}
+
+// the models with the model file in place, hashing a model file outside the huggingface cache
+// reads the whole file, so the models are listed on a blocking thread
+pub(crate) async fn tags_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+) -> Result<Json<Value>, OpenAIApiError> {
+  let app_service = state.app_service();
+  let models = tokio::task::spawn_blocking(move || tags(app_service.as_ref()))
+    .await
+    .map_err(|err| OpenAIApiError::InternalServer(err.to_string()))??;
+  Ok(Json(json! {{"models": models}}))
+}
+
+fn tags(app_service: &dyn AppServiceFn) -> Result<Vec<Value>, OpenAIApiError> {
+  let aliases = app_service
+    .data_service()
+    .list_aliases()
+    .map_err(|err| OpenAIApiError::InternalServer(err.to_string()))?;
+  let mut models = Vec::new();
+  for alias in aliases {
+    let model_file = find_model_file(app_service.hub_service().as_ref(), &alias)
+      .map_err(|err| OpenAIApiError::InternalServer(err.to_string()))?;
+    let Some(model_file) = model_file else {
+      continue;
+    };
+    let (metadata, digest) = match fs::metadata(&model_file).and_then(|metadata| {
+      let digest = digest(&model_file)?;
+      Ok((metadata, digest))
+    }) {
+      Ok(result) => result,
+      Err(err) => {
+        tracing::warn!(?err, model = alias.alias, "error reading model file");
+        continue;
+      }
+    };
+    let modified_at = metadata
+      .modified()
+      .map(|modified| DateTime::<Utc>::from(modified).to_rfc3339_opts(SecondsFormat::Micros, true))
+      .unwrap_or_else(|_| created_at());
+    models.push(json! {{
+      "name": alias.alias,
+      "model": alias.alias,
+      "modified_at": modified_at,
+      "size": metadata.len(),
+      "digest": digest,
+      "details": {
+        "format": "gguf",
+        "family": alias.family,
+      },
+    }});
+  }
+  Ok(models)
+}
+
+// Ollama show request, the older clients send the model as `name`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ShowRequest {
+  #[serde(alias = "name")]
+  pub model: String,
+}
+
+// the details of the model from the GGUF metadata of the model file, the keys missing in the
+// model file are left out
+pub(crate) async fn show_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  Json(request): Json<ShowRequest>,
+) -> Result<Json<Value>, OpenAIApiError> {
+  let app_service = state.app_service();
+  let alias = app_service
+    .data_service()
+    .find_alias(&request.model)
+    .ok_or_else(|| OpenAIApiError::ModelNotFound(request.model.clone()))?;
+  let info = model_info(app_service.as_ref(), &alias).unwrap_or_default();
+  let mut model_info = Map::new();
+  if let Some(architecture) = &info.architecture {
+    model_info.insert("general.architecture".to_string(), json!(architecture));
+    if let Some(context_length) = info.context_length {
+      model_info.insert(
+        format!("{architecture}.context_length"),
+        json!(context_length),
+      );
+    }
+  }
+  if let Some(parameter_count) = info.parameter_count {
+    model_info.insert(
+      "general.parameter_count".to_string(),
+      json!(parameter_count),
+    );
+  }
+  let family = alias.family.clone().or_else(|| info.architecture.clone());
+  Ok(Json(json! {{
+    "details": {
+      "format": "gguf",
+      "family": family,
+      "families": family.as_ref().map(|family| vec![family]),
+      "parameter_size": info.parameter_size(),
+      "quantization_level": info.quantization,
+    },
+    "model_info": model_info,
+  }}))
+}
+
+// the model loaded in llama.cpp, with its keep alive expiry, an empty list if no model is loaded
+pub(crate) async fn ps_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+) -> Result<Json<Value>, OpenAIApiError> {
+  let models = match state.loaded_model().await? {
+    Some(loaded_model) => {
+      let size = fs::metadata(&loaded_model.model_file)
+        .map(|metadata| metadata.len())
+        .unwrap_or_default();
+      vec![json! {{
+        "name": loaded_model.alias,
+        "model": loaded_model.alias,
+        "size": size,
+        "details": {"format": "gguf"},
+        "expires_at": loaded_model
+          .expires_at
+          .map(|expires_at| expires_at.to_rfc3339_opts(SecondsFormat::Micros, true)),
+      }}]
+    }
+    None => vec![],
+  };
+  Ok(Json(json! {{"models": models}}))
+}
+
+// the huggingface cache stores a LFS file as a blob named by its sha256
+fn digest(model_file: &Path) -> io::Result<String> {
+  let blob = fs::canonicalize(model_file)?;
+  let blob_name = blob
+    .file_name()
+    .map(|blob_name| blob_name.to_string_lossy().to_lowercase())
+    .unwrap_or_default();
+  if blob_name.len() == 64 && blob_name.chars().all(|c| c.is_ascii_hexdigit()) {
+    return Ok(blob_name);
+  }
+  let mut hasher = Sha256::new();
+  io::copy(&mut File::open(&blob)?, &mut hasher)?;
+  Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn created_at() -> String {
+  Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+fn nanos(duration: Duration) -> u64 {
+  duration.as_nanos() as u64
+}
+
+#[cfg(test)]
+mod test {
+  use super::{generate_handler, ps_handler, show_handler, tags_handler};
+  use crate::{
+    oai::{BodhiChatRequest, OpenAIApiError},
+    objs::Alias,
+    server::LoadedModel,
+    service::{MockDataService, MockEnvServiceFn, MockHubService},
+    test_utils::{AppServiceStubMock, MockRouterState, RequestTestExt, ResponseTestExt},
+  };
+  use anyhow_trace::anyhow_trace;
+  use axum::{
+    extract::Request,
+    routing::{get, post},
+    Router,
+  };
+  use chrono::{TimeZone, Utc};
+  use reqwest::StatusCode;
+  use rstest::rstest;
+  use serde_json::{json, Value};
+  use std::{fs, path::PathBuf, sync::Arc};
+  use tokio::sync::mpsc::Sender;
+  use tower::ServiceExt;
+
+  fn app(router_state: MockRouterState) -> Router {
+    Router::new()
+      .route("/api/generate", post(generate_handler))
+      .route("/api/tags", get(tags_handler))
+      .route("/api/ps", get(ps_handler))
+      .route("/api/show", post(show_handler))
+      .with_state(Arc::new(router_state))
+  }
+
+  fn expect_completion(router_state: &mut MockRouterState) {
+    router_state
+      .expect_chat_completions()
+      .withf(|request: &BodhiChatRequest, _| {
+        request.prompt.as_deref() == Some("Monday,")
+          && request.request.messages.is_empty()
+          && request.request.max_tokens == Some(4)
+          && request.request.temperature == Some(0.0)
+          && request.sampling_params.top_k == Some(1)
+      })
+      .return_once(|_, sender: Sender<String>| {
+        tokio::spawn(async move {
+          for (content, finish_reason) in [(" Tues", None), ("day", None), ("", Some("stop"))] {
+            let chunk = json! {{
+              "id": "testid",
+              "model": "testalias:instruct",
+              "choices": [{"index": 0, "delta": {"content": content}, "finish_reason": finish_reason}],
+              "created": 1704067200,
+              "object": "chat.completion.chunk",
+            }};
+            _ = sender.send(format!("data: {chunk}\n\n")).await;
+          }
+          let usage = json! {{
+            "id": "testid",
+            "model": "testalias:instruct",
+            "choices": [],
+            "created": 1704067200,
+            "object": "chat.completion.chunk",
+            "usage": {"prompt_tokens": 3, "completion_tokens": 2, "total_tokens": 5},
+          }};
+          _ = sender.send(format!("data: {usage}\n\n")).await;
+        });
+        Ok(())
+      });
+  }
+
+  fn generate_request(stream: Option<bool>) -> Value {
+    let mut request = json! {{
+      "model": "testalias:instruct",
+      "prompt": "Monday,",
+      "options": {"num_predict": 4, "temperature": 0.0, "top_k": 1, "num_ctx": 2048},
+    }};
+    if let Some(stream) = stream {
+      request["stream"] = json!(stream);
+    }
+    request
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_ollama_generate_streams_json_lines() -> anyhow::Result<()> {
+    let mut router_state = MockRouterState::new();
+    router_state.expect_is_paused().return_const(false);
+    expect_completion(&mut router_state);
+    let response = app(router_state)
+      .oneshot(Request::post("/api/generate").json(generate_request(None))?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(
+      "application/x-ndjson",
+      response.headers().get("content-type").unwrap()
+    );
+    let lines = response
+      .text()
+      .await?
+      .lines()
+      .map(serde_json::from_str::<Value>)
+      .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(3, lines.len());
+    assert_eq!(
+      vec![" Tues", "day", ""],
+      lines
+        .iter()
+        .map(|line| line["response"].as_str().unwrap())
+        .collect::<Vec<_>>()
+    );
+    assert_eq!(
+      vec![false, false, true],
+      lines
+        .iter()
+        .map(|line| line["done"].as_bool().unwrap())
+        .collect::<Vec<_>>()
+    );
+    let done = &lines[2];
+    assert_eq!("testalias:instruct", done["model"]);
+    assert_eq!("stop", done["done_reason"]);
+    assert_eq!(3, done["prompt_eval_count"]);
+    assert_eq!(2, done["eval_count"]);
+    assert!(done["total_duration"].is_u64());
+    assert!(done["eval_duration"].is_u64());
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_ollama_generate_non_stream_aggregates_response() -> anyhow::Result<()> {
+    let mut router_state = MockRouterState::new();
+    router_state.expect_is_paused().return_const(false);
+    expect_completion(&mut router_state);
+    let response = app(router_state)
+      .oneshot(Request::post("/api/generate").json(generate_request(Some(false)))?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response: Value = response.json().await?;
+    assert_eq!(" Tuesday", response["response"]);
+    assert_eq!(true, response["done"]);
+    assert_eq!("stop", response["done_reason"]);
+    assert_eq!(3, response["prompt_eval_count"]);
+    assert_eq!(2, response["eval_count"]);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_ollama_generate_model_not_found() -> anyhow::Result<()> {
+    let mut router_state = MockRouterState::new();
+    router_state.expect_is_paused().return_const(false);
+    router_state
+      .expect_chat_completions()
+      .return_once(|_, _| Err(OpenAIApiError::ModelNotFound("not-found".to_string())));
+    let request = json! {{"model": "not-found", "prompt": "Monday,"}};
+    let response = app(router_state)
+      .oneshot(Request::post("/api/generate").json(request)?)
+      .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  #[cfg(target_family = "unix")]
+  async fn test_routes_ollama_tags_lists_size_and_digest() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let local_file = temp_dir.path().join("mymodel.gguf");
+    fs::write(&local_file, "hello world")?;
+    let blob_name = "c22e92d054f01229c02d1b23ae2dbf0a3a0e2a7c935ce8f0a81c9d3fd3b3b0f4";
+    let blob = temp_dir.path().join(blob_name);
+    fs::write(&blob, "not hashed")?;
+    let pointer = temp_dir.path().join("pointer.gguf");
+    std::os::unix::fs::symlink(&blob, &pointer)?;
+    let aliases = vec![
+      Alias {
+        alias: "mymodel".to_string(),
+        family: Some("llama".to_string()),
+        model_file: Some(local_file),
+        ..Alias::testalias()
+      },
+      Alias {
+        alias: "cached".to_string(),
+        model_file: Some(pointer),
+        ..Alias::testalias()
+      },
+      Alias {
+        alias: "missing".to_string(),
+        model_file: Some(PathBuf::from("/does/not/exist.gguf")),
+        ..Alias::testalias()
+      },
+    ];
+    let mut mock_data_service = MockDataService::default();
+    mock_data_service
+      .expect_list_aliases()
+      .return_once(move || Ok(aliases));
+    let service = AppServiceStubMock::new(
+      MockEnvServiceFn::new(),
+      MockHubService::new(),
+      mock_data_service,
+    );
+    let mut router_state = MockRouterState::new();
+    router_state
+      .expect_app_service()
+      .return_once(move || Arc::new(service));
+    let response = app(router_state)
+      .oneshot(Request::get("/api/tags").body(axum::body::Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response: Value = response.json().await?;
+    let models = response["models"].as_array().unwrap();
+    assert_eq!(2, models.len());
+    assert_eq!("mymodel", models[0]["name"]);
+    assert_eq!(11, models[0]["size"]);
+    assert_eq!(
+      "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9",
+      models[0]["digest"]
+    );
+    assert_eq!("llama", models[0]["details"]["family"]);
+    assert!(models[0]["modified_at"].is_string());
+    assert_eq!("cached", models[1]["name"]);
+    assert_eq!(10, models[1]["size"]);
+    assert_eq!(blob_name, models[1]["digest"]);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(false)]
+  #[case(true)]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_ollama_ps_lists_loaded_model(#[case] loaded: bool) -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let model_file = temp_dir.path().join("testalias.gguf");
+    fs::write(&model_file, "hello world")?;
+    let loaded_model = loaded.then(|| LoadedModel {
+      alias: "testalias:instruct".to_string(),
+      model_file,
+      expires_at: Some(Utc.with_ymd_and_hms(2024, 1, 1, 0, 5, 0).unwrap()),
+    });
+    let mut router_state = MockRouterState::new();
+    router_state
+      .expect_loaded_model()
+      .return_once(move || Ok(loaded_model));
+    let response = app(router_state)
+      .oneshot(Request::get("/api/ps").body(axum::body::Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response: Value = response.json().await?;
+    let expected = if loaded {
+      json! {[{
+        "name": "testalias:instruct",
+        "model": "testalias:instruct",
+        "size": 11,
+        "details": {"format": "gguf"},
+        "expires_at": "2024-01-01T00:05:00.000000Z",
+      }]}
+    } else {
+      json! {[]}
+    };
+    assert_eq!(expected, response["models"]);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_ollama_show_reports_gguf_metadata() -> anyhow::Result<()> {
+    let mut mock_data_service = MockDataService::default();
+    mock_data_service.expect_find_alias().returning(|_| {
+      Some(Alias {
+        family: None,
+        model_file: Some(PathBuf::from("tests/data/tinyllama-15m-q8_0.gguf")),
+        ..Alias::testalias()
+      })
+    });
+    let service = AppServiceStubMock::new(
+      MockEnvServiceFn::new(),
+      MockHubService::new(),
+      mock_data_service,
+    );
+    let mut router_state = MockRouterState::new();
+    router_state
+      .expect_app_service()
+      .return_once(move || Arc::new(service));
+    let response = app(router_state)
+      .oneshot(Request::post("/api/show").json(json! {{"name": "testalias:instruct"}})?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response: Value = response.json().await?;
+    assert_eq!(
+      json! {{
+        "details": {
+          "format": "gguf",
+          "family": "llama",
+          "families": ["llama"],
+          "parameter_size": "24.4M",
+          "quantization_level": "Q8_0",
+        },
+        "model_info": {
+          "general.architecture": "llama",
+          "general.parameter_count": 24407712,
+          "llama.context_length": 256,
+        },
+      }},
+      response
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_ollama_show_partial_details_without_model_file() -> anyhow::Result<()> {
+    let mut mock_data_service = MockDataService::default();
+    mock_data_service.expect_find_alias().returning(|_| {
+      Some(Alias {
+        family: Some("llama".to_string()),
+        model_file: Some(PathBuf::from("/does/not/exist.gguf")),
+        ..Alias::testalias()
+      })
+    });
+    let service = AppServiceStubMock::new(
+      MockEnvServiceFn::new(),
+      MockHubService::new(),
+      mock_data_service,
+    );
+    let mut router_state = MockRouterState::new();
+    router_state
+      .expect_app_service()
+      .return_once(move || Arc::new(service));
+    let response = app(router_state)
+      .oneshot(Request::post("/api/show").json(json! {{"model": "testalias:instruct"}})?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response: Value = response.json().await?;
+    assert_eq!("llama", response["details"]["family"]);
+    assert_eq!(Value::Null, response["details"]["quantization_level"]);
+    assert_eq!(json! {{}}, response["model_info"]);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[anyhow_trace]
+  async fn test_routes_ollama_show_model_not_found() -> anyhow::Result<()> {
+    let mut mock_data_service = MockDataService::default();
+    mock_data_service.expect_find_alias().returning(|_| None);
+    let service = AppServiceStubMock::new(
+      MockEnvServiceFn::new(),
+      MockHubService::new(),
+      mock_data_service,
+    );
+    let mut router_state = MockRouterState::new();
+    router_state
+      .expect_app_service()
+      .return_once(move || Arc::new(service));
+    let response = app(router_state)
+      .oneshot(Request::post("/api/show").json(json! {{"model": "not-found"}})?)
+      .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+    Ok(())
+  }
+}
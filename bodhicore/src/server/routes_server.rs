@@ -0,0 +1,218 @@
+use super::{ActiveRequest, Eviction, RouterStateFn};
+use crate::service::SettingIssue;
+use axum::{
+  extract::{Query, State},
+  http::StatusCode,
+  response::{IntoResponse, Json, Response},
+  routing::{get, post},
+  Router,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ShutdownParams {
+  #[serde(default)]
+  pub force: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ShutdownResponse {
+  pub shutdown: bool,
+  pub message: String,
+  pub active_requests: Vec<ActiveRequest>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct EvictionsResponse {
+  pub evictions: Vec<Eviction>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SettingsValidationResponse {
+  pub valid: bool,
+  pub issues: Vec<SettingIssue>,
+}
+
+pub fn server_router() -> Router<Arc<dyn RouterStateFn>> {
+  Router::new()
+    .route("/server/shutdown", post(server_shutdown_handler))
+    .route("/server/evictions", get(server_evictions_handler))
+    .route(
+      "/server/settings/validate",
+      get(server_settings_validate_handler),
+    )
+}
+
+async fn server_settings_validate_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+) -> Json<SettingsValidationResponse> {
+  let issues = state.app_service().env_service().validate();
+  Json(SettingsValidationResponse {
+    valid: issues.is_empty(),
+    issues,
+  })
+}
+
+async fn server_evictions_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+) -> Json<EvictionsResponse> {
+  Json(EvictionsResponse {
+    evictions: state.evictions(),
+  })
+}
+
+// refuses to shutdown while chat completions are streaming, unless forced
+async fn server_shutdown_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  Query(params): Query<ShutdownParams>,
+) -> Response {
+  let active_requests = state.active_requests();
+  if !active_requests.is_empty() && !params.force {
+    let response = ShutdownResponse {
+      shutdown: false,
+      message: format!(
+        "{} requests are in progress, retry with force=true to shutdown anyway",
+        active_requests.len()
+      ),
+      active_requests,
+    };
+    return (StatusCode::CONFLICT, Json(response)).into_response();
+  }
+  tracing::info!(
+    force = params.force,
+    active_requests = active_requests.len(),
+    "shutdown requested"
+  );
+  state.shutdown();
+  let response = ShutdownResponse {
+    shutdown: true,
+    message: "server is shutting down".to_string(),
+    active_requests,
+  };
+  (StatusCode::ACCEPTED, Json(response)).into_response()
+}
+
+#[cfg(test)]
+mod test {
+  use super::{server_router, EvictionsResponse, SettingsValidationResponse, ShutdownResponse};
+  use crate::{
+    server::{ActiveRequest, Eviction, EvictionReason, RouterStateFn},
+    service::{MockDataService, MockEnvServiceFn, MockHubService, SettingIssue},
+    test_utils::{AppServiceStubMock, MockRouterState, ResponseTestExt},
+  };
+  use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+  };
+  use chrono::{TimeZone, Utc};
+  use rstest::rstest;
+  use std::{path::PathBuf, sync::Arc};
+  use tower::ServiceExt;
+
+  fn active_request() -> ActiveRequest {
+    ActiveRequest {
+      model: "testalias:instruct".to_string(),
+      started_at: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+    }
+  }
+
+  #[rstest]
+  #[case("/server/shutdown", vec![], StatusCode::ACCEPTED, true)]
+  #[case("/server/shutdown", vec![active_request()], StatusCode::CONFLICT, false)]
+  #[case("/server/shutdown?force=true", vec![active_request()], StatusCode::ACCEPTED, true)]
+  #[tokio::test]
+  async fn test_routes_server_shutdown(
+    #[case] uri: &str,
+    #[case] active_requests: Vec<ActiveRequest>,
+    #[case] status: StatusCode,
+    #[case] shutdown: bool,
+  ) -> anyhow::Result<()> {
+    let mut router_state = MockRouterState::new();
+    let expected = active_requests.clone();
+    router_state
+      .expect_active_requests()
+      .return_once(move || active_requests);
+    router_state
+      .expect_shutdown()
+      .times(if shutdown { 1 } else { 0 })
+      .return_const(());
+    let router_state: Arc<dyn RouterStateFn> = Arc::new(router_state);
+    let response = server_router()
+      .with_state(router_state)
+      .oneshot(Request::post(uri).body(Body::empty())?)
+      .await?;
+    assert_eq!(status, response.status());
+    let response = response.json::<ShutdownResponse>().await?;
+    assert_eq!(shutdown, response.shutdown);
+    assert_eq!(expected, response.active_requests);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_routes_server_evictions() -> anyhow::Result<()> {
+    let eviction = Eviction {
+      alias: "testalias:instruct".to_string(),
+      model_file: PathBuf::from("/models/testalias.gguf"),
+      reason: EvictionReason::KeepAlive,
+      evicted_at: Utc.with_ymd_and_hms(2024, 1, 1, 0, 5, 0).unwrap(),
+    };
+    let mut router_state = MockRouterState::new();
+    let evictions = vec![eviction.clone()];
+    router_state
+      .expect_evictions()
+      .return_once(move || evictions);
+    let router_state: Arc<dyn RouterStateFn> = Arc::new(router_state);
+    let response = server_router()
+      .with_state(router_state)
+      .oneshot(Request::get("/server/evictions").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response = response.json::<serde_json::Value>().await?;
+    assert_eq!("keep_alive", response["evictions"][0]["reason"]);
+    assert_eq!(
+      EvictionsResponse {
+        evictions: vec![eviction]
+      },
+      serde_json::from_value(response)?
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(vec![])]
+  #[case(vec![SettingIssue {
+    key: "BODHI_PORT".to_string(),
+    value: "0".to_string(),
+    message: "should be a port number between 1 and 65535".to_string(),
+  }])]
+  #[tokio::test]
+  async fn test_routes_server_settings_validate(
+    #[case] issues: Vec<SettingIssue>,
+  ) -> anyhow::Result<()> {
+    let mut env_service = MockEnvServiceFn::new();
+    let expected = issues.clone();
+    env_service.expect_validate().return_once(move || issues);
+    let service =
+      AppServiceStubMock::new(env_service, MockHubService::new(), MockDataService::new());
+    let mut router_state = MockRouterState::new();
+    router_state
+      .expect_app_service()
+      .return_once(move || Arc::new(service));
+    let router_state: Arc<dyn RouterStateFn> = Arc::new(router_state);
+    let response = server_router()
+      .with_state(router_state)
+      .oneshot(Request::get("/server/settings/validate").body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(
+      SettingsValidationResponse {
+        valid: expected.is_empty(),
+        issues: expected,
+      },
+      response.json::<SettingsValidationResponse>().await?
+    );
+    Ok(())
+  }
+}
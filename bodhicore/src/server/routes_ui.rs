@@ -1,14 +1,41 @@
 use super::{utils::ApiError, RouterStateFn};
-use crate::db::objs::Conversation;
+use crate::{
+  db::objs::Conversation,
+  oai::{BodhiChatRequest, OpenAIApiError},
+};
 use axum::{
   body::Body,
-  extract::{Path as UrlPath, State},
-  http::{header::LOCATION, status::StatusCode, Response},
-  response::Json,
+  extract::{Path as UrlPath, Request, State},
+  http::{
+    header::{CONTENT_TYPE, LOCATION},
+    status::StatusCode,
+    Response,
+  },
+  response::{IntoResponse, Json},
   routing::{delete, get, post},
   Router,
 };
-use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+  path::{Component, Path, PathBuf},
+  sync::Arc,
+};
+use tokio::sync::mpsc;
+use tower::ServiceExt;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WarmRequest {
+  pub model: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WarmResponse {
+  pub warmed: bool,
+  pub model: String,
+  // pass as the prompt_cache_key of the next chat completion request to reuse the warm cache
+  pub prompt_cache_key: String,
+}
 
 pub fn chats_router() -> Router<Arc<dyn RouterStateFn>> {
   Router::new()
@@ -17,6 +44,7 @@ pub fn chats_router() -> Router<Arc<dyn RouterStateFn>> {
     .route("/chats/:id", get(ui_chat_handler))
     .route("/chats/:id", post(ui_chat_new_handler))
     .route("/chats/:id", delete(ui_chat_delete_handler))
+    .route("/chats/:id/warm", post(ui_chat_warm_handler))
 }
 
 async fn ui_chats_handler(
@@ -56,6 +84,66 @@ async fn ui_chat_new_handler(
   Ok(response)
 }
 
+// on resuming a conversation, loads its model and evaluates the stored messages into the prompt
+// cache of the conversation, so the next turn only evaluates the new message, skipped if the
+// model is already loaded
+async fn ui_chat_warm_handler(
+  State(state): State<Arc<dyn RouterStateFn>>,
+  UrlPath(id): UrlPath<String>,
+  Json(warm): Json<WarmRequest>,
+) -> Result<Json<WarmResponse>, axum::response::Response> {
+  if state.is_paused() {
+    return Err(OpenAIApiError::InferencePaused.into_response());
+  }
+  let convo = state
+    .db_service()
+    .get_conversation_with_messages(&id)
+    .await
+    .map_err(|err| ApiError::from(err).into_response())?;
+  let response = |warmed: bool| WarmResponse {
+    warmed,
+    model: warm.model.clone(),
+    prompt_cache_key: convo.id.clone(),
+  };
+  let loaded_model = state
+    .loaded_model()
+    .await
+    .map_err(IntoResponse::into_response)?;
+  let messages = convo
+    .messages
+    .iter()
+    .filter_map(|message| {
+      message
+        .content
+        .as_ref()
+        .map(|content| json! {{"role": message.role, "content": content}})
+    })
+    .collect::<Vec<_>>();
+  if messages.is_empty() || loaded_model.is_some_and(|loaded| loaded.alias == warm.model) {
+    return Ok(Json(response(false)));
+  }
+  let request = serde_json::from_value::<BodhiChatRequest>(json! {{
+    "model": warm.model,
+    "messages": messages,
+    "max_tokens": 1,
+    "prompt_cache_key": convo.id,
+  }})
+  .map_err(|err| OpenAIApiError::BadRequest(err.to_string()).into_response())?;
+  let (tx, mut rx) = mpsc::channel::<String>(100);
+  // the generated token is not used
+  tokio::spawn(async move { while rx.recv().await.is_some() {} });
+  state
+    .chat_completions(request, tx)
+    .await
+    .map_err(IntoResponse::into_response)?;
+  tracing::info!(
+    conversation = convo.id,
+    model = warm.model,
+    "warmed conversation"
+  );
+  Ok(Json(response(true)))
+}
+
 async fn ui_chats_delete_handler(
   State(state): State<Arc<dyn RouterStateFn>>,
 ) -> Result<(), ApiError> {
@@ -71,17 +159,74 @@ async fn ui_chat_delete_handler(
   Ok(())
 }
 
+// with `BODHI_UI_DIR` set, the UI files are served from the directory, falling back to the
+// embedded UI for the files not in the directory
+pub fn with_ui_dir(ui_dir: Option<PathBuf>, embedded: Router) -> Router {
+  let Some(ui_dir) = ui_dir else {
+    return embedded;
+  };
+  tracing::info!(ui_dir = %ui_dir.display(), "serving the UI from the directory");
+  let ui_dir = Arc::new(ui_dir);
+  Router::new().fallback(move |request: Request| {
+    let ui_dir = ui_dir.clone();
+    let embedded = embedded.clone();
+    async move { serve_ui_file(&ui_dir, embedded, request).await }
+  })
+}
+
+async fn serve_ui_file(ui_dir: &Path, embedded: Router, request: Request) -> Response<Body> {
+  if let Some(file) = ui_file(ui_dir, request.uri().path()) {
+    match tokio::fs::read(&file).await {
+      Ok(content) => {
+        let content_type = mime_guess::from_path(&file).first_or_octet_stream();
+        return Response::builder()
+          .status(StatusCode::OK)
+          .header(CONTENT_TYPE, content_type.as_ref())
+          .body(Body::from(content))
+          .unwrap_or_else(|err| OpenAIApiError::InternalServer(err.to_string()).into_response());
+      }
+      Err(err) => {
+        tracing::warn!(?err, file = %file.display(), "error reading the UI file, serving the embedded UI");
+      }
+    }
+  }
+  match embedded.oneshot(request).await {
+    Ok(response) => response,
+    Err(err) => match err {},
+  }
+}
+
+// the file of the url path in the UI directory, the index.html for a directory. A path with `..`,
+// or a symlink resolving outside the directory, is not served from the directory
+fn ui_file(ui_dir: &Path, path: &str) -> Option<PathBuf> {
+  let relative = Path::new(path.trim_start_matches('/'));
+  if !relative
+    .components()
+    .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+  {
+    return None;
+  }
+  let mut file = ui_dir.join(relative);
+  if file.is_dir() {
+    file.push("index.html");
+  }
+  let file = file.canonicalize().ok()?;
+  let ui_dir = ui_dir.canonicalize().ok()?;
+  (file.starts_with(&ui_dir) && file.is_file()).then_some(file)
+}
+
 #[cfg(test)]
 mod test {
-  use super::chats_router;
+  use super::{chats_router, with_ui_dir};
   use crate::{
     db::{
       objs::{Conversation, ConversationBuilder, MessageBuilder},
       DbService, DbServiceFn,
     },
-    server::RouterState,
+    oai::BodhiChatRequest,
+    server::{LoadedModel, RouterState, RouterStateFn},
     service::MockAppServiceFn,
-    test_utils::{db_service, MockSharedContext, RequestTestExt, ResponseTestExt},
+    test_utils::{db_service, MockRouterState, MockSharedContext, RequestTestExt, ResponseTestExt},
   };
   use axum::{
     body::Body,
@@ -89,8 +234,8 @@ mod test {
   };
   use chrono::{DateTime, Utc};
   use rstest::rstest;
-  use serde_json::Value;
-  use std::sync::Arc;
+  use serde_json::{json, Value};
+  use std::{path::PathBuf, sync::Arc};
   use tempfile::TempDir;
   use tower::ServiceExt;
   use uuid::Uuid;
@@ -414,4 +559,132 @@ mod test {
     );
     Ok(())
   }
+
+  #[rstest]
+  #[case(None, true)]
+  #[case(Some("llama3:instruct"), true)]
+  #[case(Some("testalias:instruct"), false)]
+  #[awt]
+  #[tokio::test]
+  async fn test_chat_routes_warm_replays_messages_if_model_not_loaded(
+    #[future] db_service: (TempDir, DateTime<Utc>, DbService),
+    #[case] loaded: Option<&str>,
+    #[case] warmed: bool,
+  ) -> anyhow::Result<()> {
+    let (_temp, _now, db_service) = db_service;
+    let mut convo = ConversationBuilder::default().title("test title").build()?;
+    convo.messages.push(
+      MessageBuilder::default()
+        .conversation_id(&convo.id)
+        .role("user")
+        .content("What day comes after Monday?")
+        .build()?,
+    );
+    convo.messages.push(
+      MessageBuilder::default()
+        .conversation_id(&convo.id)
+        .role("assistant")
+        .content("Tuesday")
+        .build()?,
+    );
+    db_service.save_conversation(&mut convo).await?;
+    let db_service: Arc<dyn DbServiceFn> = Arc::new(db_service);
+    let mut router_state = MockRouterState::new();
+    router_state.expect_is_paused().return_const(false);
+    router_state
+      .expect_db_service()
+      .returning(move || db_service.clone());
+    let loaded_model = loaded.map(|alias| LoadedModel {
+      alias: alias.to_string(),
+      model_file: PathBuf::from("/models/model.gguf"),
+      expires_at: None,
+    });
+    router_state
+      .expect_loaded_model()
+      .return_once(move || Ok(loaded_model));
+    let convo_id = convo.id.clone();
+    router_state
+      .expect_chat_completions()
+      .withf(move |request: &BodhiChatRequest, _| {
+        request.request.model == "testalias:instruct"
+          && request.request.max_tokens == Some(1)
+          && request.prompt_cache_key.as_deref() == Some(convo_id.as_str())
+          && serde_json::to_value(&request.request.messages).unwrap()
+            == json! {[
+              {"role": "user", "content": "What day comes after Monday?"},
+              {"role": "assistant", "content": "Tuesday"},
+            ]}
+      })
+      .times(if warmed { 1 } else { 0 })
+      .returning(|_, _| Ok(()));
+    let router_state: Arc<dyn RouterStateFn> = Arc::new(router_state);
+    let response = chats_router()
+      .with_state(router_state)
+      .oneshot(
+        Request::post(format!("/chats/{}/warm", convo.id)).json(json! {{
+          "model": "testalias:instruct"
+        }})?,
+      )
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let response = response.json::<Value>().await?;
+    assert_eq!(
+      json! {{
+        "warmed": warmed,
+        "model": "testalias:instruct",
+        "prompt_cache_key": convo.id,
+      }},
+      response
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_chat_routes_warm_rejected_when_paused() -> anyhow::Result<()> {
+    let mut router_state = MockRouterState::new();
+    router_state.expect_is_paused().return_const(true);
+    router_state.expect_chat_completions().never();
+    let router_state: Arc<dyn RouterStateFn> = Arc::new(router_state);
+    let response = chats_router()
+      .with_state(router_state)
+      .oneshot(Request::post("/chats/testid/warm").json(json! {{
+        "model": "testalias:instruct"
+      }})?)
+      .await?;
+    assert_eq!(StatusCode::SERVICE_UNAVAILABLE, response.status());
+    let response = response.json::<Value>().await?;
+    assert_eq!("inference_paused", response["code"]);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case("/", "text/html", "<html>custom</html>")]
+  #[case("/styles/app.css", "text/css", "body {}")]
+  #[case("/missing.js", "text/plain", "embedded")]
+  #[case("/../secret.txt", "text/plain", "embedded")]
+  #[case("/styles/../../secret.txt", "text/plain", "embedded")]
+  #[tokio::test]
+  async fn test_with_ui_dir_serves_files_falling_back_to_embedded(
+    #[case] path: &str,
+    #[case] content_type: &str,
+    #[case] expected: &str,
+  ) -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let ui_dir = temp_dir.path().join("ui");
+    std::fs::create_dir_all(ui_dir.join("styles"))?;
+    std::fs::write(ui_dir.join("index.html"), "<html>custom</html>")?;
+    std::fs::write(ui_dir.join("styles").join("app.css"), "body {}")?;
+    std::fs::write(temp_dir.path().join("secret.txt"), "secret")?;
+    let embedded = axum::Router::new().fallback(|| async { "embedded" });
+    let response = with_ui_dir(Some(ui_dir), embedded)
+      .oneshot(Request::get(path).body(Body::empty())?)
+      .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    assert!(response.headers()["content-type"]
+      .to_str()?
+      .starts_with(content_type));
+    assert_eq!(expected, response.text().await?);
+    Ok(())
+  }
 }
@@ -1,10 +1,22 @@
-use crate::error::Common;
+use super::RouterStateFn;
+use crate::error::{BodhiError, Common};
 use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
+use futures_util::{future::BoxFuture, FutureExt};
+use std::{
+  future::{Future, IntoFuture},
+  path::{Path, PathBuf},
+  sync::Arc,
+  time::Duration,
+};
 use tokio::{
   net::TcpListener,
   sync::oneshot::{self, Receiver, Sender},
 };
 
+// the time the open TLS connections get to complete on shutdown, when no drain is configured
+static TLS_SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
 /// Server encapsulates the parameters to start, broadcast ready lifecycle, and receive shutdown request for a server
 /// It contains the parameters to start the server on given host, port etc. and
 /// contains a ready sender channel to notify the requester when the server is ready to receive connection and
@@ -14,6 +26,21 @@ pub struct Server {
   port: u16,
   ready: Sender<()>,
   shutdown_rx: Receiver<()>,
+  drain: Option<Drain>,
+  tls: Option<RustlsConfig>,
+  uds: Option<Uds>,
+}
+
+// the unix socket listened on instead of the host and port, and the permissions of its file
+struct Uds {
+  path: PathBuf,
+  mode: u32,
+}
+
+// on shutdown, the in-flight requests of the router state have the grace period to complete
+struct Drain {
+  grace: Duration,
+  state: Arc<dyn RouterStateFn>,
 }
 
 #[async_trait::async_trait]
@@ -46,9 +73,30 @@ impl Server {
       port,
       ready,
       shutdown_rx,
+      drain: None,
+      tls: None,
+      uds: None,
     }
   }
 
+  // serves https, with HTTP/2 negotiated over ALPN, instead of plain http
+  pub fn with_tls(mut self, tls: RustlsConfig) -> Self {
+    self.tls = Some(tls);
+    self
+  }
+
+  // listens on the unix socket at the path instead of the host and port, the socket file is removed
+  // when the server stops
+  pub fn with_uds(mut self, path: PathBuf, mode: u32) -> Self {
+    self.uds = Some(Uds { path, mode });
+    self
+  }
+
+  pub fn with_drain(mut self, grace: Duration, state: Arc<dyn RouterStateFn>) -> Self {
+    self.drain = Some(Drain { grace, state });
+    self
+  }
+
   pub async fn start_new(
     self,
     app: Router,
@@ -59,11 +107,12 @@ impl Server {
       port,
       ready,
       shutdown_rx,
+      drain,
+      tls,
+      uds,
     } = self;
-    let addr = format!("{}:{}", host, port);
-    let listener = TcpListener::bind(&addr).await.map_err(Common::Io)?;
-    tracing::info!(addr = addr, "server started");
-    let axum_server = axum::serve(listener, app).with_graceful_shutdown(async move {
+    let (draining, draining_rx) = oneshot::channel::<()>();
+    let shutdown_signal = async move {
       match shutdown_rx.await {
         Ok(()) => {
           tracing::info!("received signal to shutdown the server");
@@ -75,25 +124,147 @@ impl Server {
           );
         }
       };
-      if let Some(callback) = callback {
-        (*callback).shutdown().await;
+      let _ = draining.send(());
+    };
+    let uds_path = uds.as_ref().map(|uds| uds.path.clone());
+    let axum_server = match (uds, tls) {
+      (Some(uds), tls) => {
+        if tls.is_some() {
+          tracing::warn!("TLS is not served on the unix socket, serving plain http");
+        }
+        serve_on_uds(uds, app, shutdown_signal)?
       }
-    });
+      (None, tls) => {
+        let addr = format!("{}:{}", host, port);
+        let listener = TcpListener::bind(&addr).await.map_err(Common::Io)?;
+        tracing::info!(addr = addr, tls = tls.is_some(), "server started");
+        let grace = drain.as_ref().map(|drain| drain.grace);
+        serve_on_tcp(listener, tls, app, shutdown_signal, grace)?
+      }
+    };
     if ready.send(()).is_err() {
       tracing::warn!("ready receiver dropped before start signal notified")
     };
-    axum_server.await.map_err(Common::Io)?;
+    match drain {
+      Some(Drain { grace, state }) => {
+        let deadline = async move {
+          if draining_rx.await.is_ok() {
+            tokio::time::sleep(grace).await;
+          } else {
+            std::future::pending::<()>().await;
+          }
+        };
+        tokio::select! {
+          result = axum_server => result.map_err(Common::Io)?,
+          _ = deadline => {
+            tracing::warn!(
+              active_requests = state.active_requests().len(),
+              grace_secs = grace.as_secs(),
+              "shutdown grace period over, stopping with requests in progress"
+            );
+          }
+        }
+      }
+      None => axum_server.await.map_err(Common::Io)?,
+    }
+    if let Some(path) = uds_path {
+      if let Err(err) = std::fs::remove_file(&path) {
+        tracing::warn!(?err, path = %path.display(), "error removing the socket file");
+      }
+    }
+    if let Some(callback) = callback {
+      (*callback).shutdown().await;
+    }
     Ok(())
   }
 }
 
+// stops accepting new connections on the signal, and waits for the open ones to complete. The
+// graceful shutdown of axum-server also waits on the idle keep-alive connections, so with TLS the
+// open connections are closed once the grace period is over
+fn serve_on_tcp(
+  listener: TcpListener,
+  tls: Option<RustlsConfig>,
+  app: Router,
+  shutdown_signal: impl Future<Output = ()> + Send + 'static,
+  grace: Option<Duration>,
+) -> crate::error::Result<BoxFuture<'static, std::io::Result<()>>> {
+  let axum_server = match tls {
+    None => axum::serve(listener, app)
+      .with_graceful_shutdown(shutdown_signal)
+      .into_future()
+      .boxed(),
+    Some(tls) => {
+      let handle = axum_server::Handle::new();
+      let shutdown_handle = handle.clone();
+      tokio::spawn(async move {
+        shutdown_signal.await;
+        shutdown_handle.graceful_shutdown(Some(grace.unwrap_or(TLS_SHUTDOWN_GRACE)));
+      });
+      axum_server::from_tcp_rustls(listener.into_std().map_err(Common::Io)?, tls)
+        .handle(handle)
+        .serve(app.into_make_service())
+        .boxed()
+    }
+  };
+  Ok(axum_server)
+}
+
+#[cfg(unix)]
+fn serve_on_uds(
+  uds: Uds,
+  app: Router,
+  shutdown_signal: impl Future<Output = ()> + Send + 'static,
+) -> crate::error::Result<BoxFuture<'static, std::io::Result<()>>> {
+  let listener = super::uds::bind_uds(&uds.path, uds.mode).map_err(Common::Io)?;
+  tracing::info!(path = %uds.path.display(), mode = format!("{:o}", uds.mode), "server started");
+  Ok(super::uds::serve_uds(listener, app, shutdown_signal).boxed())
+}
+
+#[cfg(not(unix))]
+fn serve_on_uds(
+  uds: Uds,
+  _app: Router,
+  _shutdown_signal: impl Future<Output = ()> + Send + 'static,
+) -> crate::error::Result<BoxFuture<'static, std::io::Result<()>>> {
+  let err = std::io::Error::new(
+    std::io::ErrorKind::Unsupported,
+    format!(
+      "cannot listen on '{}', unix sockets are not supported on this platform",
+      uds.path.display()
+    ),
+  );
+  Err(Common::Io(err).into())
+}
+
+// reads the certificate chain and the private key at startup, so a bad certificate fails the start
+// instead of the first connection
+pub async fn load_tls_config(cert: &Path, key: &Path) -> crate::error::Result<RustlsConfig> {
+  RustlsConfig::from_pem_file(cert, key)
+    .await
+    .map_err(|source| BodhiError::TlsConfig {
+      source,
+      cert: cert.display().to_string(),
+      key: key.display().to_string(),
+    })
+}
+
 #[cfg(test)]
 mod test {
-  use super::{build_server_handle, ServerHandle, ShutdownCallback};
+  use super::{build_server_handle, load_tls_config, ServerHandle, ShutdownCallback};
+  use crate::{
+    server::{ActiveRequest, RouterStateFn},
+    test_utils::{capture_warn_logs, MockRouterState},
+  };
   use anyhow::anyhow;
   use axum::{routing::get, Router};
+  use chrono::Utc;
   use reqwest::StatusCode;
-  use std::sync::{Arc, Mutex};
+  use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+  };
 
   struct ShutdownTestCallback {
     callback: Arc<Mutex<bool>>,
@@ -143,4 +314,184 @@ mod test {
     assert!(response.is_err());
     Ok(())
   }
+
+  #[tokio::test]
+  async fn test_server_serves_https_with_tls() -> anyhow::Result<()> {
+    let host = "localhost".to_string();
+    let port = rand::random::<u16>() % 55535 + 10000;
+    let ServerHandle {
+      server,
+      shutdown,
+      ready_rx,
+    } = build_server_handle(&host, port);
+    let tls = load_tls_config(
+      Path::new("tests/data/tls/cert.pem"),
+      Path::new("tests/data/tls/key.pem"),
+    )
+    .await?;
+    let app = Router::new().route("/ping", get(|| async { (StatusCode::OK, "pong") }));
+    let join_handle = tokio::spawn(server.with_tls(tls).start_new(app, None));
+    ready_rx.await?;
+    let client = reqwest::Client::builder()
+      .danger_accept_invalid_certs(true)
+      .build()?;
+    let response = client
+      .get(format!("https://{host}:{port}/ping"))
+      .send()
+      .await?
+      .text()
+      .await?;
+    assert_eq!("pong", response);
+    assert!(client
+      .get(format!("http://{host}:{port}/ping"))
+      .send()
+      .await
+      .is_err());
+    // the pooled keep-alive connection of the client is closed along with it
+    drop(client);
+    shutdown
+      .send(())
+      .map_err(|_| anyhow!("shutdown send failed"))?;
+    tokio::time::timeout(Duration::from_secs(5), join_handle).await???;
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_server_load_tls_config_fails_for_missing_key() -> anyhow::Result<()> {
+    let result = load_tls_config(
+      Path::new("tests/data/tls/cert.pem"),
+      Path::new("tests/data/tls/missing.pem"),
+    )
+    .await;
+    let err = result.err().unwrap().to_string();
+    assert!(
+      err.starts_with(
+        "failed to load the TLS certificate 'tests/data/tls/cert.pem' and key 'tests/data/tls/missing.pem'"
+      ),
+      "{err}"
+    );
+    Ok(())
+  }
+
+  #[cfg(unix)]
+  #[tokio::test]
+  async fn test_server_serves_on_uds_replacing_stale_socket() -> anyhow::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("bodhi.sock");
+    // the socket file left behind by a server that did not stop cleanly
+    drop(std::os::unix::net::UnixListener::bind(&path)?);
+    let ServerHandle {
+      server,
+      shutdown,
+      ready_rx,
+    } = build_server_handle("localhost", 0);
+    let app = Router::new().route("/ping", get(|| async { (StatusCode::OK, "pong") }));
+    let join_handle = tokio::spawn(server.with_uds(path.clone(), 0o660).start_new(app, None));
+    ready_rx.await?;
+    assert_eq!(
+      0o660,
+      std::fs::metadata(&path)?.permissions().mode() & 0o777
+    );
+    let mut stream = tokio::net::UnixStream::connect(&path).await?;
+    stream
+      .write_all(b"GET /ping HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+      .await?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+    assert!(response.ends_with("pong"), "{response}");
+    shutdown
+      .send(())
+      .map_err(|_| anyhow!("shutdown send failed"))?;
+    tokio::time::timeout(Duration::from_secs(5), join_handle).await???;
+    assert!(!path.exists());
+    Ok(())
+  }
+
+  #[cfg(unix)]
+  #[tokio::test]
+  async fn test_server_uds_fails_over_a_file_that_is_not_a_socket() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("bodhi.sock");
+    std::fs::write(&path, "")?;
+    let ServerHandle { server, .. } = build_server_handle("localhost", 0);
+    let app = Router::new().route("/ping", get(|| async { (StatusCode::OK, "pong") }));
+    let result = server
+      .with_uds(path.clone(), 0o600)
+      .start_new(app, None)
+      .await;
+    let err = result.err().unwrap().to_string();
+    assert!(err.contains("exists and is not a socket"), "{err}");
+    assert!(path.is_file());
+    Ok(())
+  }
+
+  fn slow_app(delay: Duration) -> Router {
+    Router::new().route(
+      "/slow",
+      get(move || async move {
+        tokio::time::sleep(delay).await;
+        "done"
+      }),
+    )
+  }
+
+  #[tokio::test]
+  async fn test_server_drains_in_flight_request_within_grace() -> anyhow::Result<()> {
+    let host = "localhost".to_string();
+    let port = rand::random::<u16>() % 55535 + 10000;
+    let ServerHandle {
+      server,
+      shutdown,
+      ready_rx,
+    } = build_server_handle(&host, port);
+    let router_state: Arc<dyn RouterStateFn> = Arc::new(MockRouterState::new());
+    let server = server.with_drain(Duration::from_secs(5), router_state);
+    let join_handle = tokio::spawn(server.start_new(slow_app(Duration::from_millis(300)), None));
+    ready_rx.await?;
+    let request = tokio::spawn(reqwest::get(format!("http://{host}:{port}/slow")));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    shutdown
+      .send(())
+      .map_err(|_| anyhow!("shutdown send failed"))?;
+    assert_eq!("done", request.await??.text().await?);
+    (join_handle.await?)?;
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_server_stops_after_grace_with_requests_in_progress() -> anyhow::Result<()> {
+    let host = "localhost".to_string();
+    let port = rand::random::<u16>() % 55535 + 10000;
+    let ServerHandle {
+      server,
+      shutdown,
+      ready_rx,
+    } = build_server_handle(&host, port);
+    let mut router_state = MockRouterState::new();
+    router_state.expect_active_requests().return_once(|| {
+      vec![ActiveRequest {
+        model: "testalias:instruct".to_string(),
+        started_at: Utc::now(),
+      }]
+    });
+    let router_state: Arc<dyn RouterStateFn> = Arc::new(router_state);
+    let server = server.with_drain(Duration::from_millis(200), router_state);
+    let (logs, _guard) = capture_warn_logs();
+    let join_handle = tokio::spawn(server.start_new(slow_app(Duration::from_secs(30)), None));
+    ready_rx.await?;
+    let _request = tokio::spawn(reqwest::get(format!("http://{host}:{port}/slow")));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    shutdown
+      .send(())
+      .map_err(|_| anyhow!("shutdown send failed"))?;
+    tokio::time::timeout(Duration::from_secs(5), join_handle).await???;
+    let logs = logs.contents();
+    assert!(logs.contains("shutdown grace period over"));
+    assert!(logs.contains("active_requests=1"));
+    Ok(())
+  }
 }
@@ -1,4 +1,15 @@
-use tokio::signal::{self, unix::SignalKind};
+use once_cell::sync::Lazy;
+use tokio::{
+  signal::{self, unix::SignalKind},
+  sync::Notify,
+};
+
+// notified by the shutdown route, stops the server the same way as SIGTERM
+static SHUTDOWN_REQUESTED: Lazy<Notify> = Lazy::new(Notify::new);
+
+pub fn request_shutdown() {
+  SHUTDOWN_REQUESTED.notify_one();
+}
 
 pub async fn shutdown_signal() {
   let ctrl_c = async {
@@ -19,8 +30,14 @@ pub async fn shutdown_signal() {
 
   #[cfg(not(unix))]
   let terminate = std::future::pending::<()>();
+  let requested = async {
+    SHUTDOWN_REQUESTED.notified().await;
+    tracing::info!("received shutdown request, stopping server");
+  };
+
   tokio::select! {
       _ = ctrl_c => {},
       _ = terminate => {},
+      _ = requested => {},
   }
 }
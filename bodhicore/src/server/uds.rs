@@ -0,0 +1,88 @@
+use axum::Router;
+use hyper_util::{
+  rt::{TokioExecutor, TokioIo},
+  server::conn::auto,
+  service::TowerToHyperService,
+};
+use std::{
+  future::Future,
+  io,
+  os::unix::{fs::FileTypeExt, fs::PermissionsExt, net::UnixStream},
+  path::Path,
+};
+use tokio::{net::UnixListener, sync::watch};
+
+// binds the socket, removing the file left behind by a server that did not stop cleanly, and
+// refusing to start over a socket a running server is still listening on, or over other files
+pub(crate) fn bind_uds(path: &Path, mode: u32) -> io::Result<UnixListener> {
+  if let Ok(metadata) = std::fs::symlink_metadata(path) {
+    if !metadata.file_type().is_socket() {
+      return Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("'{}' exists and is not a socket", path.display()),
+      ));
+    }
+    if UnixStream::connect(path).is_ok() {
+      return Err(io::Error::new(
+        io::ErrorKind::AddrInUse,
+        format!("'{}' is in use by a running server", path.display()),
+      ));
+    }
+    tracing::info!(path = %path.display(), "removing the stale socket file");
+    std::fs::remove_file(path)?;
+  }
+  let listener = UnixListener::bind(path)?;
+  std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+  Ok(listener)
+}
+
+// axum::serve only takes a tcp listener, so the connections on the socket are served with hyper,
+// the way axum::serve does, stopping to accept on the signal and waiting for the open connections
+pub(crate) async fn serve_uds(
+  listener: UnixListener,
+  app: Router,
+  signal: impl Future<Output = ()> + Send + 'static,
+) -> io::Result<()> {
+  let (signal_tx, signal_rx) = watch::channel(());
+  let (close_tx, close_rx) = watch::channel(());
+  tokio::pin!(signal);
+  loop {
+    let stream = tokio::select! {
+      conn = listener.accept() => match conn {
+        Ok((stream, _)) => stream,
+        Err(err) => {
+          tracing::warn!(?err, "error accepting a connection on the socket");
+          continue;
+        }
+      },
+      _ = &mut signal => break,
+    };
+    let service = TowerToHyperService::new(app.clone());
+    let mut signal_rx = signal_rx.clone();
+    let close_rx = close_rx.clone();
+    tokio::spawn(async move {
+      let builder = auto::Builder::new(TokioExecutor::new());
+      let conn = builder.serve_connection_with_upgrades(TokioIo::new(stream), service);
+      tokio::pin!(conn);
+      tokio::select! {
+        result = conn.as_mut() => {
+          if let Err(err) = result {
+            tracing::debug!(?err, "error serving a connection on the socket");
+          }
+        }
+        _ = signal_rx.changed() => {
+          conn.as_mut().graceful_shutdown();
+          if let Err(err) = conn.as_mut().await {
+            tracing::debug!(?err, "error serving a connection on the socket");
+          }
+        }
+      }
+      drop(close_rx);
+    });
+  }
+  drop(listener);
+  drop(close_rx);
+  let _ = signal_tx.send(());
+  close_tx.closed().await;
+  Ok(())
+}
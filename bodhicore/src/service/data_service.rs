@@ -1,7 +1,7 @@
 use super::{ALIASES_DIR, MODELS_YAML};
 use crate::{
   error::Common,
-  objs::{Alias, RemoteModel},
+  objs::{Alias, OAIRequestParams, RemoteModel},
 };
 use derive_new::new;
 use std::{collections::HashMap, fmt::Debug, fs, io, path::PathBuf};
@@ -50,10 +50,14 @@ pub trait DataService: std::fmt::Debug {
 
   fn find_remote_model(&self, alias: &str) -> Result<Option<RemoteModel>>;
 
-  fn copy_alias(&self, alias: &str, new_alias: &str) -> Result<()>;
+  // the new alias uses the model of the alias, with the request params set in the overrides
+  fn copy_alias(&self, alias: &str, new_alias: &str, overrides: &OAIRequestParams) -> Result<()>;
 
   fn delete_alias(&self, alias: &str) -> Result<()>;
 
+  // saves the updated alias in place of the alias, a changed name renames the alias
+  fn update_alias(&self, alias: &str, updated: &Alias) -> Result<()>;
+
   fn alias_filename(&self, alias: &str) -> Result<PathBuf>;
 }
 
@@ -124,7 +128,7 @@ impl DataService for LocalDataService {
     Ok(models)
   }
 
-  fn copy_alias(&self, alias: &str, new_alias: &str) -> Result<()> {
+  fn copy_alias(&self, alias: &str, new_alias: &str, overrides: &OAIRequestParams) -> Result<()> {
     let mut alias = self
       .find_alias(alias)
       .ok_or_else(|| DataServiceError::AliasNotExists(alias.to_string()))?;
@@ -132,6 +136,7 @@ impl DataService for LocalDataService {
       return Err(DataServiceError::AliasExists(new_alias.to_string()));
     }
     alias.alias = new_alias.to_string();
+    alias.request_params = alias.request_params.merge(overrides);
     self.save_alias(&alias)?;
     Ok(())
   }
@@ -146,6 +151,18 @@ impl DataService for LocalDataService {
     Ok(())
   }
 
+  fn update_alias(&self, alias: &str, updated: &Alias) -> Result<()> {
+    let filename = self.alias_filename(alias)?;
+    if updated.alias != alias && self.find_alias(&updated.alias).is_some() {
+      return Err(DataServiceError::AliasExists(updated.alias.clone()));
+    }
+    let saved = self.save_alias(updated)?;
+    if saved != filename {
+      fs::remove_file(filename).map_err(Common::from)?;
+    }
+    Ok(())
+  }
+
   fn alias_filename(&self, alias: &str) -> Result<PathBuf> {
     let (filename, _) = self
       ._list_aliases()?
@@ -217,7 +234,7 @@ impl LocalDataService {
 mod test {
   use super::DataService;
   use crate::{
-    objs::{Alias, RemoteModel},
+    objs::{Alias, OAIRequestParams, RemoteModel},
     test_utils::{data_service, DataServiceTuple},
   };
   use anyhow_trace::anyhow_trace;
@@ -346,10 +363,65 @@ filename='{models_file}'"#
     Ok(())
   }
 
+  #[rstest]
+  fn test_local_data_service_update_alias_renames(
+    data_service: DataServiceTuple,
+  ) -> anyhow::Result<()> {
+    let DataServiceTuple(_temp, bodhi_home, service) = data_service;
+    let updated = Alias {
+      alias: "tinyllama:chat".to_string(),
+      tags: vec!["chat".to_string()],
+      ..Alias::tinyllama()
+    };
+    service.update_alias("tinyllama:instruct", &updated)?;
+    assert_eq!(None, service.find_alias("tinyllama:instruct"));
+    assert_eq!(Some(updated), service.find_alias("tinyllama:chat"));
+    assert!(!bodhi_home
+      .join("aliases")
+      .join("tinyllama--instruct.yaml")
+      .exists());
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(
+    "notexists:instruct",
+    "tinyllama:chat",
+    "alias 'notexists:instruct' not found in $BODHI_HOME/aliases"
+  )]
+  #[case(
+    "tinyllama:instruct",
+    "testalias-exists:instruct",
+    "alias 'testalias-exists:instruct' already exists in $BODHI_HOME/aliases"
+  )]
+  fn test_local_data_service_update_alias_errors(
+    data_service: DataServiceTuple,
+    #[case] alias: &str,
+    #[case] new_alias: &str,
+    #[case] expected: &str,
+  ) -> anyhow::Result<()> {
+    let DataServiceTuple(_temp, _, service) = data_service;
+    let updated = Alias {
+      alias: new_alias.to_string(),
+      ..Alias::tinyllama()
+    };
+    let result = service.update_alias(alias, &updated);
+    assert_eq!(expected, result.unwrap_err().to_string());
+    assert_eq!(
+      Some(Alias::tinyllama()),
+      service.find_alias("tinyllama:instruct")
+    );
+    Ok(())
+  }
+
   #[rstest]
   fn test_local_data_service_copy_alias(data_service: DataServiceTuple) -> anyhow::Result<()> {
     let DataServiceTuple(_temp, _, service) = data_service;
-    service.copy_alias("tinyllama:instruct", "tinyllama:mymodel")?;
+    service.copy_alias(
+      "tinyllama:instruct",
+      "tinyllama:mymodel",
+      &OAIRequestParams::default(),
+    )?;
     let new_alias = service
       .find_alias("tinyllama:mymodel")
       .expect("should have created new_alias");
@@ -358,4 +430,34 @@ filename='{models_file}'"#
     assert_eq!(expected, new_alias);
     Ok(())
   }
+
+  #[rstest]
+  fn test_local_data_service_copy_alias_with_overrides(
+    data_service: DataServiceTuple,
+  ) -> anyhow::Result<()> {
+    let DataServiceTuple(_temp, _, service) = data_service;
+    let overrides = OAIRequestParams {
+      temperature: Some(0.5),
+      system_prompt: Some("You are a poet.".to_string()),
+      ..Default::default()
+    };
+    service.copy_alias("tinyllama:instruct", "tinyllama:poet", &overrides)?;
+    let new_alias = service
+      .find_alias("tinyllama:poet")
+      .expect("should have created new_alias");
+    let source = Alias::tinyllama();
+    assert_eq!(source.repo, new_alias.repo);
+    assert_eq!(source.filename, new_alias.filename);
+    assert_eq!(source.snapshot, new_alias.snapshot);
+    assert_eq!(
+      source.request_params.merge(&overrides),
+      new_alias.request_params
+    );
+    let result = service.copy_alias("tinyllama:instruct", "tinyllama:poet", &overrides);
+    assert_eq!(
+      "alias 'tinyllama:poet' already exists in $BODHI_HOME/aliases",
+      result.unwrap_err().to_string()
+    );
+    Ok(())
+  }
 }
@@ -5,27 +5,120 @@ use super::env_wrapper::EnvWrapper;
 use crate::test_utils::MockEnvWrapper as EnvWrapper;
 
 use super::DataServiceError;
+use serde::{Deserialize, Serialize};
 use std::{
-  collections::HashMap,
+  collections::{BTreeSet, HashMap, HashSet},
+  env::VarError,
+  fmt,
   fs::{self, File},
   path::{Path, PathBuf},
+  sync::{Arc, RwLock},
 };
 
 pub static PROD_DB: &str = "bodhi.sqlite";
 pub static ALIASES_DIR: &str = "aliases";
 pub static MODELS_YAML: &str = "models.yaml";
+pub static SETTINGS_YAML: &str = "settings.yaml";
 
 pub static LOGS_DIR: &str = "logs";
 pub static DEFAULT_PORT: u16 = 1135;
 pub static DEFAULT_PORT_STR: &str = "1135";
 pub static DEFAULT_HOST: &str = "127.0.0.1";
+pub static DEFAULT_SLOW_REQUEST_SECS: u64 = 60;
+// 0 keeps the loaded model in memory till another model is requested
+pub static DEFAULT_KEEP_ALIVE_SECS: u64 = 0;
+// when not strict, a model not matching an alias exactly is matched ignoring case and whitespace
+pub static DEFAULT_STRICT_ALIAS: bool = false;
+// when not strict, the request fields bodhi does not support are ignored instead of rejected
+pub static DEFAULT_STRICT_PARAMS: bool = false;
+// percent of the system memory a model load can take the memory in use to, 0 disables the check
+pub static DEFAULT_MEMORY_WATERMARK: u8 = 90;
+// seconds the in-flight requests have to complete on shutdown, before the server stops anyway
+pub static DEFAULT_SHUTDOWN_GRACE_SECS: u64 = 30;
+// the /metrics endpoint is only served when enabled
+pub static DEFAULT_METRICS_ENABLED: bool = false;
+// starts the server with inference paused, till resumed with the inference resume endpoint
+pub static DEFAULT_INFERENCE_PAUSED: bool = false;
+// the serve command lints the model aliases before starting, logging the broken ones
+pub static DEFAULT_LINT_ON_START: bool = false;
+// identical deterministic chat requests are served from the response cache only when enabled
+pub static DEFAULT_RESPONSE_CACHE_ENABLED: bool = false;
+pub static DEFAULT_RESPONSE_CACHE_TTL_SECS: u64 = 3600;
+pub static DEFAULT_RESPONSE_CACHE_MAX_ENTRIES: usize = 100;
+// the responses are compressed as accepted by the client, except the streamed responses
+pub static DEFAULT_COMPRESSION_ENABLED: bool = true;
+// 0 runs as many chat requests on a model as its slots, the n_parallel of the alias
+pub static DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 0;
+// the chat requests waiting for a free slot of a model, the requests over it get a 503
+pub static DEFAULT_MAX_QUEUED_REQUESTS: usize = 32;
+// `json` writes structured log lines, for log collectors, instead of the human readable format
+pub static DEFAULT_LOG_FORMAT: &str = "text";
+pub static LOG_FORMAT_JSON: &str = "json";
+// the level of the logs when RUST_LOG is not set, RUST_LOG allows the per module filters
+pub static DEFAULT_LOG_LEVEL: &str = "info";
+static LOG_LEVELS: [&str; 6] = ["trace", "debug", "info", "warn", "error", "off"];
+// in mock mode, the chat and text completions get canned responses without loading a model
+pub static DEFAULT_MOCK: bool = false;
+pub static DEFAULT_MOCK_TEXT: &str =
+  "This is a mock response from Bodhi App, no model was loaded to generate it.";
+pub static DEFAULT_MOCK_TOKEN_DELAY_MS: u64 = 20;
+// the permissions of the unix socket, only the user running the server can connect by default
+pub static DEFAULT_BIND_UDS_MODE: u32 = 0o600;
 
 pub static BODHI_HOME: &str = "BODHI_HOME";
 pub static BODHI_HOST: &str = "BODHI_HOST";
 pub static BODHI_PORT: &str = "BODHI_PORT";
 pub static BODHI_LOGS: &str = "BODHI_LOGS";
+pub static BODHI_SLOW_REQUEST_SECS: &str = "BODHI_SLOW_REQUEST_SECS";
+pub static BODHI_KEEP_ALIVE_SECS: &str = "BODHI_KEEP_ALIVE_SECS";
+pub static BODHI_STRICT_ALIAS: &str = "BODHI_STRICT_ALIAS";
+pub static BODHI_STRICT_PARAMS: &str = "BODHI_STRICT_PARAMS";
+pub static BODHI_MEMORY_WATERMARK: &str = "BODHI_MEMORY_WATERMARK";
+pub static BODHI_CORS_ALLOWED_ORIGINS: &str = "BODHI_CORS_ALLOWED_ORIGINS";
+pub static BODHI_SHUTDOWN_GRACE_SECS: &str = "BODHI_SHUTDOWN_GRACE_SECS";
+pub static BODHI_METRICS_ENABLED: &str = "BODHI_METRICS_ENABLED";
+pub static BODHI_INFERENCE_PAUSED: &str = "BODHI_INFERENCE_PAUSED";
+pub static BODHI_LINT_ON_START: &str = "BODHI_LINT_ON_START";
+pub static BODHI_RESPONSE_CACHE_ENABLED: &str = "BODHI_RESPONSE_CACHE_ENABLED";
+pub static BODHI_RESPONSE_CACHE_TTL_SECS: &str = "BODHI_RESPONSE_CACHE_TTL_SECS";
+pub static BODHI_RESPONSE_CACHE_MAX_ENTRIES: &str = "BODHI_RESPONSE_CACHE_MAX_ENTRIES";
+pub static BODHI_COMPRESSION_ENABLED: &str = "BODHI_COMPRESSION_ENABLED";
+pub static BODHI_MAX_CONCURRENT_REQUESTS: &str = "BODHI_MAX_CONCURRENT_REQUESTS";
+pub static BODHI_MAX_QUEUED_REQUESTS: &str = "BODHI_MAX_QUEUED_REQUESTS";
+pub static BODHI_LOG_FORMAT: &str = "BODHI_LOG_FORMAT";
+pub static BODHI_LOG_LEVEL: &str = "BODHI_LOG_LEVEL";
+// picks the `settings.{BODHI_ENV_TYPE}.yaml` overlay, e.g. dev, staging or prod
+pub static BODHI_ENV_TYPE: &str = "BODHI_ENV_TYPE";
+pub static BODHI_MOCK: &str = "BODHI_MOCK";
+pub static BODHI_MOCK_TEXT: &str = "BODHI_MOCK_TEXT";
+pub static BODHI_MOCK_TOKEN_DELAY_MS: &str = "BODHI_MOCK_TOKEN_DELAY_MS";
+// the UI files served instead of the embedded ones, for a customized or newer UI
+pub static BODHI_UI_DIR: &str = "BODHI_UI_DIR";
+// the PEM certificate chain and private key, the server serves https when both are set
+pub static BODHI_TLS_CERT: &str = "BODHI_TLS_CERT";
+pub static BODHI_TLS_KEY: &str = "BODHI_TLS_KEY";
+// the unix socket the server listens on, instead of the host and port
+pub static BODHI_BIND_UDS: &str = "BODHI_BIND_UDS";
+pub static BODHI_BIND_UDS_MODE: &str = "BODHI_BIND_UDS_MODE";
 pub static HF_HOME: &str = "HF_HOME";
 
+// the settings applied when changed in the settings files, the others need a restart
+pub static HOT_RELOAD_SETTINGS: [&str; 2] = ["BODHI_LOG_LEVEL", "BODHI_KEEP_ALIVE_SECS"];
+
+// a setting with a value that would be ignored for its default, or fail later when used
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SettingIssue {
+  pub key: String,
+  pub value: String,
+  pub message: String,
+}
+
+impl fmt::Display for SettingIssue {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}='{}': {}", self.key, self.value, self.message)
+  }
+}
+
 #[cfg_attr(test, mockall::automock)]
 pub trait EnvServiceFn: std::fmt::Debug {
   fn bodhi_home(&self) -> PathBuf;
@@ -42,9 +135,69 @@ pub trait EnvServiceFn: std::fmt::Debug {
 
   fn port(&self) -> u16;
 
+  fn slow_request_secs(&self) -> u64;
+
+  fn keep_alive_secs(&self) -> u64;
+
+  fn strict_alias(&self) -> bool;
+
+  fn strict_params(&self) -> bool;
+
+  fn memory_watermark(&self) -> u8;
+
+  // the origins allowed to call the server from the browser, `*` allows any origin, empty
+  // allows only the same origin
+  fn cors_allowed_origins(&self) -> Vec<String>;
+
+  fn shutdown_grace_secs(&self) -> u64;
+
+  fn metrics_enabled(&self) -> bool;
+
+  fn inference_paused(&self) -> bool;
+
+  fn lint_on_start(&self) -> bool;
+
+  fn response_cache_enabled(&self) -> bool;
+
+  fn response_cache_ttl_secs(&self) -> u64;
+
+  fn response_cache_max_entries(&self) -> usize;
+
+  fn compression_enabled(&self) -> bool;
+
+  fn max_concurrent_requests(&self) -> usize;
+
+  fn max_queued_requests(&self) -> usize;
+
+  fn log_format(&self) -> String;
+
+  fn log_level(&self) -> String;
+
+  fn mock(&self) -> bool;
+
+  fn mock_text(&self) -> String;
+
+  fn mock_token_delay_ms(&self) -> u64;
+
+  fn ui_dir(&self) -> Option<PathBuf>;
+
+  fn tls_cert(&self) -> Option<PathBuf>;
+
+  fn tls_key(&self) -> Option<PathBuf>;
+
+  // the scheme of the urls of the server, https when serving with TLS
+  fn scheme(&self) -> String;
+
+  fn bind_uds(&self) -> Option<PathBuf>;
+
+  fn bind_uds_mode(&self) -> u32;
+
   fn db_path(&self) -> PathBuf;
 
   fn list(&self) -> HashMap<String, String>;
+
+  // checks the settings set, the settings not set use their defaults and are always valid
+  fn validate(&self) -> Vec<SettingIssue>;
 }
 
 #[derive(Debug, Clone)]
@@ -53,6 +206,17 @@ pub struct EnvService {
   bodhi_home: Option<PathBuf>,
   hf_home: Option<PathBuf>,
   logs_dir: Option<PathBuf>,
+  // the settings set from the settings files at startup, the other env vars are not reloaded
+  settings_keys: HashSet<String>,
+  // the values reloaded from the settings files, None when removed from the files
+  reloaded: Arc<RwLock<HashMap<String, Option<String>>>>,
+}
+
+#[derive(Debug, Default)]
+struct Settings {
+  loaded: Vec<PathBuf>,
+  values: HashMap<String, String>,
+  errors: Vec<String>,
 }
 
 impl EnvServiceFn for EnvService {
@@ -95,14 +259,14 @@ impl EnvServiceFn for EnvService {
   }
 
   fn host(&self) -> String {
-    match self.env_wrapper.var(BODHI_HOST) {
+    match self.var(BODHI_HOST) {
       Ok(value) => value,
       Err(_) => DEFAULT_HOST.to_string(),
     }
   }
 
   fn port(&self) -> u16 {
-    match self.env_wrapper.var(BODHI_PORT) {
+    match self.var(BODHI_PORT) {
       Ok(value) => match value.parse::<u16>() {
         Ok(port) => port,
         Err(_) => DEFAULT_PORT,
@@ -111,6 +275,240 @@ impl EnvServiceFn for EnvService {
     }
   }
 
+  fn slow_request_secs(&self) -> u64 {
+    match self.var(BODHI_SLOW_REQUEST_SECS) {
+      Ok(value) => match value.parse::<u64>() {
+        Ok(secs) => secs,
+        Err(_) => DEFAULT_SLOW_REQUEST_SECS,
+      },
+      Err(_) => DEFAULT_SLOW_REQUEST_SECS,
+    }
+  }
+
+  fn keep_alive_secs(&self) -> u64 {
+    match self.var(BODHI_KEEP_ALIVE_SECS) {
+      Ok(value) => match value.parse::<u64>() {
+        Ok(secs) => secs,
+        Err(_) => DEFAULT_KEEP_ALIVE_SECS,
+      },
+      Err(_) => DEFAULT_KEEP_ALIVE_SECS,
+    }
+  }
+
+  fn strict_alias(&self) -> bool {
+    match self.var(BODHI_STRICT_ALIAS) {
+      Ok(value) => match value.parse::<bool>() {
+        Ok(strict) => strict,
+        Err(_) => DEFAULT_STRICT_ALIAS,
+      },
+      Err(_) => DEFAULT_STRICT_ALIAS,
+    }
+  }
+
+  fn strict_params(&self) -> bool {
+    match self.var(BODHI_STRICT_PARAMS) {
+      Ok(value) => match value.parse::<bool>() {
+        Ok(strict) => strict,
+        Err(_) => DEFAULT_STRICT_PARAMS,
+      },
+      Err(_) => DEFAULT_STRICT_PARAMS,
+    }
+  }
+
+  fn memory_watermark(&self) -> u8 {
+    match self.var(BODHI_MEMORY_WATERMARK) {
+      Ok(value) => match value.parse::<u8>() {
+        Ok(watermark) if watermark <= 100 => watermark,
+        _ => DEFAULT_MEMORY_WATERMARK,
+      },
+      Err(_) => DEFAULT_MEMORY_WATERMARK,
+    }
+  }
+
+  fn cors_allowed_origins(&self) -> Vec<String> {
+    match self.var(BODHI_CORS_ALLOWED_ORIGINS) {
+      Ok(value) => value
+        .split(',')
+        .map(|origin| origin.trim().trim_end_matches('/').to_string())
+        .filter(|origin| !origin.is_empty())
+        .collect(),
+      Err(_) => vec![],
+    }
+  }
+
+  fn shutdown_grace_secs(&self) -> u64 {
+    match self.var(BODHI_SHUTDOWN_GRACE_SECS) {
+      Ok(value) => match value.parse::<u64>() {
+        Ok(secs) => secs,
+        Err(_) => DEFAULT_SHUTDOWN_GRACE_SECS,
+      },
+      Err(_) => DEFAULT_SHUTDOWN_GRACE_SECS,
+    }
+  }
+
+  fn metrics_enabled(&self) -> bool {
+    match self.var(BODHI_METRICS_ENABLED) {
+      Ok(value) => match value.parse::<bool>() {
+        Ok(enabled) => enabled,
+        Err(_) => DEFAULT_METRICS_ENABLED,
+      },
+      Err(_) => DEFAULT_METRICS_ENABLED,
+    }
+  }
+
+  fn inference_paused(&self) -> bool {
+    match self.var(BODHI_INFERENCE_PAUSED) {
+      Ok(value) => match value.parse::<bool>() {
+        Ok(paused) => paused,
+        Err(_) => DEFAULT_INFERENCE_PAUSED,
+      },
+      Err(_) => DEFAULT_INFERENCE_PAUSED,
+    }
+  }
+
+  fn lint_on_start(&self) -> bool {
+    match self.var(BODHI_LINT_ON_START) {
+      Ok(value) => match value.parse::<bool>() {
+        Ok(lint) => lint,
+        Err(_) => DEFAULT_LINT_ON_START,
+      },
+      Err(_) => DEFAULT_LINT_ON_START,
+    }
+  }
+
+  fn response_cache_enabled(&self) -> bool {
+    match self.var(BODHI_RESPONSE_CACHE_ENABLED) {
+      Ok(value) => match value.parse::<bool>() {
+        Ok(enabled) => enabled,
+        Err(_) => DEFAULT_RESPONSE_CACHE_ENABLED,
+      },
+      Err(_) => DEFAULT_RESPONSE_CACHE_ENABLED,
+    }
+  }
+
+  fn response_cache_ttl_secs(&self) -> u64 {
+    match self.var(BODHI_RESPONSE_CACHE_TTL_SECS) {
+      Ok(value) => match value.parse::<u64>() {
+        Ok(secs) => secs,
+        Err(_) => DEFAULT_RESPONSE_CACHE_TTL_SECS,
+      },
+      Err(_) => DEFAULT_RESPONSE_CACHE_TTL_SECS,
+    }
+  }
+
+  fn response_cache_max_entries(&self) -> usize {
+    match self.var(BODHI_RESPONSE_CACHE_MAX_ENTRIES) {
+      Ok(value) => match value.parse::<usize>() {
+        Ok(max_entries) => max_entries,
+        Err(_) => DEFAULT_RESPONSE_CACHE_MAX_ENTRIES,
+      },
+      Err(_) => DEFAULT_RESPONSE_CACHE_MAX_ENTRIES,
+    }
+  }
+
+  fn compression_enabled(&self) -> bool {
+    match self.var(BODHI_COMPRESSION_ENABLED) {
+      Ok(value) => match value.parse::<bool>() {
+        Ok(enabled) => enabled,
+        Err(_) => DEFAULT_COMPRESSION_ENABLED,
+      },
+      Err(_) => DEFAULT_COMPRESSION_ENABLED,
+    }
+  }
+
+  fn max_concurrent_requests(&self) -> usize {
+    match self.var(BODHI_MAX_CONCURRENT_REQUESTS) {
+      Ok(value) => match value.parse::<usize>() {
+        Ok(max_concurrent) => max_concurrent,
+        Err(_) => DEFAULT_MAX_CONCURRENT_REQUESTS,
+      },
+      Err(_) => DEFAULT_MAX_CONCURRENT_REQUESTS,
+    }
+  }
+
+  fn max_queued_requests(&self) -> usize {
+    match self.var(BODHI_MAX_QUEUED_REQUESTS) {
+      Ok(value) => match value.parse::<usize>() {
+        Ok(max_queued) => max_queued,
+        Err(_) => DEFAULT_MAX_QUEUED_REQUESTS,
+      },
+      Err(_) => DEFAULT_MAX_QUEUED_REQUESTS,
+    }
+  }
+
+  fn log_format(&self) -> String {
+    match self.var(BODHI_LOG_FORMAT) {
+      Ok(value) if value.trim().eq_ignore_ascii_case(LOG_FORMAT_JSON) => {
+        LOG_FORMAT_JSON.to_string()
+      }
+      _ => DEFAULT_LOG_FORMAT.to_string(),
+    }
+  }
+
+  fn log_level(&self) -> String {
+    match self.var(BODHI_LOG_LEVEL) {
+      Ok(value) if is_log_level(&value) => value.trim().to_lowercase(),
+      _ => DEFAULT_LOG_LEVEL.to_string(),
+    }
+  }
+
+  fn mock(&self) -> bool {
+    match self.var(BODHI_MOCK) {
+      Ok(value) => match value.parse::<bool>() {
+        Ok(mock) => mock,
+        Err(_) => DEFAULT_MOCK,
+      },
+      Err(_) => DEFAULT_MOCK,
+    }
+  }
+
+  fn mock_text(&self) -> String {
+    match self.var(BODHI_MOCK_TEXT) {
+      Ok(value) if !value.trim().is_empty() => value,
+      _ => DEFAULT_MOCK_TEXT.to_string(),
+    }
+  }
+
+  fn mock_token_delay_ms(&self) -> u64 {
+    match self.var(BODHI_MOCK_TOKEN_DELAY_MS) {
+      Ok(value) => match value.parse::<u64>() {
+        Ok(delay) => delay,
+        Err(_) => DEFAULT_MOCK_TOKEN_DELAY_MS,
+      },
+      Err(_) => DEFAULT_MOCK_TOKEN_DELAY_MS,
+    }
+  }
+
+  fn ui_dir(&self) -> Option<PathBuf> {
+    self.path_var(BODHI_UI_DIR)
+  }
+
+  fn tls_cert(&self) -> Option<PathBuf> {
+    self.path_var(BODHI_TLS_CERT)
+  }
+
+  fn tls_key(&self) -> Option<PathBuf> {
+    self.path_var(BODHI_TLS_KEY)
+  }
+
+  fn scheme(&self) -> String {
+    match (self.tls_cert(), self.tls_key()) {
+      (Some(_), Some(_)) => "https".to_string(),
+      _ => "http".to_string(),
+    }
+  }
+
+  fn bind_uds(&self) -> Option<PathBuf> {
+    self.path_var(BODHI_BIND_UDS)
+  }
+
+  fn bind_uds_mode(&self) -> u32 {
+    match self.var(BODHI_BIND_UDS_MODE) {
+      Ok(value) => parse_mode(&value).unwrap_or(DEFAULT_BIND_UDS_MODE),
+      Err(_) => DEFAULT_BIND_UDS_MODE,
+    }
+  }
+
   fn db_path(&self) -> PathBuf {
     self.bodhi_home().join(PROD_DB)
   }
@@ -128,8 +526,310 @@ impl EnvServiceFn for EnvService {
     );
     result.insert(BODHI_HOST.to_string(), self.host());
     result.insert(BODHI_PORT.to_string(), self.port().to_string());
+    result.insert(
+      BODHI_SLOW_REQUEST_SECS.to_string(),
+      self.slow_request_secs().to_string(),
+    );
+    result.insert(
+      BODHI_KEEP_ALIVE_SECS.to_string(),
+      self.keep_alive_secs().to_string(),
+    );
+    result.insert(
+      BODHI_STRICT_ALIAS.to_string(),
+      self.strict_alias().to_string(),
+    );
+    result.insert(
+      BODHI_STRICT_PARAMS.to_string(),
+      self.strict_params().to_string(),
+    );
+    result.insert(
+      BODHI_MEMORY_WATERMARK.to_string(),
+      self.memory_watermark().to_string(),
+    );
+    result.insert(
+      BODHI_CORS_ALLOWED_ORIGINS.to_string(),
+      self.cors_allowed_origins().join(","),
+    );
+    result.insert(
+      BODHI_SHUTDOWN_GRACE_SECS.to_string(),
+      self.shutdown_grace_secs().to_string(),
+    );
+    result.insert(
+      BODHI_METRICS_ENABLED.to_string(),
+      self.metrics_enabled().to_string(),
+    );
+    result.insert(
+      BODHI_INFERENCE_PAUSED.to_string(),
+      self.inference_paused().to_string(),
+    );
+    result.insert(
+      BODHI_LINT_ON_START.to_string(),
+      self.lint_on_start().to_string(),
+    );
+    result.insert(
+      BODHI_RESPONSE_CACHE_ENABLED.to_string(),
+      self.response_cache_enabled().to_string(),
+    );
+    result.insert(
+      BODHI_RESPONSE_CACHE_TTL_SECS.to_string(),
+      self.response_cache_ttl_secs().to_string(),
+    );
+    result.insert(
+      BODHI_RESPONSE_CACHE_MAX_ENTRIES.to_string(),
+      self.response_cache_max_entries().to_string(),
+    );
+    result.insert(
+      BODHI_COMPRESSION_ENABLED.to_string(),
+      self.compression_enabled().to_string(),
+    );
+    result.insert(
+      BODHI_MAX_CONCURRENT_REQUESTS.to_string(),
+      self.max_concurrent_requests().to_string(),
+    );
+    result.insert(
+      BODHI_MAX_QUEUED_REQUESTS.to_string(),
+      self.max_queued_requests().to_string(),
+    );
+    result.insert(BODHI_LOG_FORMAT.to_string(), self.log_format());
+    result.insert(BODHI_LOG_LEVEL.to_string(), self.log_level());
+    result.insert(BODHI_MOCK.to_string(), self.mock().to_string());
+    result.insert(
+      BODHI_UI_DIR.to_string(),
+      self
+        .ui_dir()
+        .map(|ui_dir| ui_dir.display().to_string())
+        .unwrap_or_default(),
+    );
+    for (key, path) in [
+      (BODHI_TLS_CERT, self.tls_cert()),
+      (BODHI_TLS_KEY, self.tls_key()),
+    ] {
+      result.insert(
+        key.to_string(),
+        path
+          .map(|path| path.display().to_string())
+          .unwrap_or_default(),
+      );
+    }
+    result.insert(
+      BODHI_BIND_UDS.to_string(),
+      self
+        .bind_uds()
+        .map(|path| path.display().to_string())
+        .unwrap_or_default(),
+    );
+    result.insert(
+      BODHI_BIND_UDS_MODE.to_string(),
+      format!("{:o}", self.bind_uds_mode()),
+    );
     result
   }
+
+  fn validate(&self) -> Vec<SettingIssue> {
+    let checks: &[SettingCheck] = &[
+      (BODHI_HOME, is_dir_or_missing, "should be a directory"),
+      (HF_HOME, is_dir_or_missing, "should be a directory"),
+      (BODHI_LOGS, is_dir_or_missing, "should be a directory"),
+      (BODHI_UI_DIR, is_dir, "should be an existing directory"),
+      (BODHI_TLS_CERT, is_file, "should be an existing file"),
+      (BODHI_TLS_KEY, is_file, "should be an existing file"),
+      (
+        BODHI_BIND_UDS,
+        is_in_dir,
+        "should be a path in an existing directory",
+      ),
+      (
+        BODHI_BIND_UDS_MODE,
+        is_mode,
+        "should be octal file permissions, like 660",
+      ),
+      (
+        BODHI_PORT,
+        is_port,
+        "should be a port number between 1 and 65535",
+      ),
+      (
+        BODHI_SLOW_REQUEST_SECS,
+        is_u64,
+        "should be a number of seconds",
+      ),
+      (
+        BODHI_KEEP_ALIVE_SECS,
+        is_u64,
+        "should be a number of seconds",
+      ),
+      (
+        BODHI_SHUTDOWN_GRACE_SECS,
+        is_u64,
+        "should be a number of seconds",
+      ),
+      (
+        BODHI_RESPONSE_CACHE_TTL_SECS,
+        is_u64,
+        "should be a number of seconds",
+      ),
+      (
+        BODHI_RESPONSE_CACHE_MAX_ENTRIES,
+        is_u64,
+        "should be a number of entries",
+      ),
+      (
+        BODHI_MAX_CONCURRENT_REQUESTS,
+        is_u64,
+        "should be a number of requests",
+      ),
+      (
+        BODHI_MAX_QUEUED_REQUESTS,
+        is_u64,
+        "should be a number of requests",
+      ),
+      (
+        BODHI_MOCK_TOKEN_DELAY_MS,
+        is_u64,
+        "should be a number of milliseconds",
+      ),
+      (
+        BODHI_MEMORY_WATERMARK,
+        is_percent,
+        "should be a percent between 0 and 100",
+      ),
+      (BODHI_STRICT_ALIAS, is_bool, "should be true or false"),
+      (BODHI_STRICT_PARAMS, is_bool, "should be true or false"),
+      (BODHI_METRICS_ENABLED, is_bool, "should be true or false"),
+      (BODHI_INFERENCE_PAUSED, is_bool, "should be true or false"),
+      (BODHI_LINT_ON_START, is_bool, "should be true or false"),
+      (
+        BODHI_RESPONSE_CACHE_ENABLED,
+        is_bool,
+        "should be true or false",
+      ),
+      (
+        BODHI_COMPRESSION_ENABLED,
+        is_bool,
+        "should be true or false",
+      ),
+      (BODHI_MOCK, is_bool, "should be true or false"),
+      (BODHI_LOG_FORMAT, is_log_format, "should be text or json"),
+      (
+        BODHI_LOG_LEVEL,
+        is_log_level,
+        "should be one of trace, debug, info, warn, error or off",
+      ),
+      (
+        BODHI_CORS_ALLOWED_ORIGINS,
+        is_origins,
+        "should be * or a comma separated list of origins, like https://chat.example.com",
+      ),
+    ];
+    let mut issues = checks
+      .iter()
+      .filter_map(|(key, valid, message)| {
+        let value = self.var(key).ok()?;
+        (!valid(&value)).then(|| SettingIssue {
+          key: key.to_string(),
+          value,
+          message: message.to_string(),
+        })
+      })
+      .collect::<Vec<_>>();
+    // the certificate without the key, or the other way, would silently serve plain http
+    match (self.tls_cert(), self.tls_key()) {
+      (Some(cert), None) => issues.push(SettingIssue {
+        key: BODHI_TLS_CERT.to_string(),
+        value: cert.display().to_string(),
+        message: format!("should be set along with {BODHI_TLS_KEY}"),
+      }),
+      (None, Some(key)) => issues.push(SettingIssue {
+        key: BODHI_TLS_KEY.to_string(),
+        value: key.display().to_string(),
+        message: format!("should be set along with {BODHI_TLS_CERT}"),
+      }),
+      _ => {}
+    }
+    // the socket serves plain http, the certificate would be loaded and silently not used
+    if let Some(uds) = self.bind_uds() {
+      if self.tls_cert().is_some() || self.tls_key().is_some() {
+        issues.push(SettingIssue {
+          key: BODHI_BIND_UDS.to_string(),
+          value: uds.display().to_string(),
+          message: format!("should not be set along with {BODHI_TLS_CERT} and {BODHI_TLS_KEY}"),
+        });
+      }
+    }
+    issues
+  }
+}
+
+// the setting, the check of its value, and the message when the check fails
+type SettingCheck = (&'static str, fn(&str) -> bool, &'static str);
+
+fn is_bool(value: &str) -> bool {
+  value.parse::<bool>().is_ok()
+}
+
+fn is_u64(value: &str) -> bool {
+  value.parse::<u64>().is_ok()
+}
+
+fn is_port(value: &str) -> bool {
+  value.parse::<u16>().map(|port| port > 0).unwrap_or(false)
+}
+
+fn is_percent(value: &str) -> bool {
+  value
+    .parse::<u8>()
+    .map(|percent| percent <= 100)
+    .unwrap_or(false)
+}
+
+fn is_log_format(value: &str) -> bool {
+  [DEFAULT_LOG_FORMAT, LOG_FORMAT_JSON]
+    .iter()
+    .any(|format| value.trim().eq_ignore_ascii_case(format))
+}
+
+fn is_log_level(value: &str) -> bool {
+  LOG_LEVELS
+    .iter()
+    .any(|level| value.trim().eq_ignore_ascii_case(level))
+}
+
+fn is_origins(value: &str) -> bool {
+  value
+    .split(',')
+    .map(str::trim)
+    .filter(|origin| !origin.is_empty())
+    .all(|origin| origin == "*" || origin.starts_with("http://") || origin.starts_with("https://"))
+}
+
+fn is_file(value: &str) -> bool {
+  Path::new(value.trim()).is_file()
+}
+
+fn is_dir(value: &str) -> bool {
+  Path::new(value.trim()).is_dir()
+}
+
+fn is_dir_or_missing(value: &str) -> bool {
+  let path = Path::new(value);
+  !path.exists() || path.is_dir()
+}
+
+fn is_in_dir(value: &str) -> bool {
+  Path::new(value.trim())
+    .parent()
+    .map(|parent| parent.as_os_str().is_empty() || parent.is_dir())
+    .unwrap_or(false)
+}
+
+fn is_mode(value: &str) -> bool {
+  parse_mode(value).is_some()
+}
+
+fn parse_mode(value: &str) -> Option<u32> {
+  u32::from_str_radix(value.trim(), 8)
+    .ok()
+    .filter(|mode| *mode <= 0o777)
 }
 
 impl EnvService {
@@ -140,6 +840,8 @@ impl EnvService {
       bodhi_home: None,
       hf_home: None,
       logs_dir: None,
+      settings_keys: HashSet::new(),
+      reloaded: Arc::new(RwLock::new(HashMap::new())),
     }
   }
 
@@ -151,6 +853,28 @@ impl EnvService {
       bodhi_home: Some(bodhi_home),
       hf_home: Some(hf_home),
       logs_dir: Some(logs_dir),
+      settings_keys: HashSet::new(),
+      reloaded: Arc::new(RwLock::new(HashMap::new())),
+    }
+  }
+
+  // the value reloaded from the settings files, if any, takes precedence over the env var
+  fn var(&self, key: &str) -> Result<String, VarError> {
+    if let Some(value) = self
+      .reloaded
+      .read()
+      .ok()
+      .and_then(|reloaded| reloaded.get(key).cloned())
+    {
+      return value.ok_or(VarError::NotPresent);
+    }
+    self.env_wrapper.var(key)
+  }
+
+  fn path_var(&self, key: &str) -> Option<PathBuf> {
+    match self.var(key) {
+      Ok(value) if !value.trim().is_empty() => Some(PathBuf::from(value.trim())),
+      _ => None,
     }
   }
 
@@ -172,6 +896,110 @@ impl EnvService {
     }
   }
 
+  pub fn settings_files(&self) -> Vec<PathBuf> {
+    let mut files = vec![self.bodhi_home().join(SETTINGS_YAML)];
+    if let Ok(env_type) = self.env_wrapper.var(BODHI_ENV_TYPE) {
+      files.push(
+        self
+          .bodhi_home()
+          .join(format!("settings.{}.yaml", env_type.trim())),
+      );
+    }
+    files
+  }
+
+  fn read_settings(&self) -> Settings {
+    let mut merged = serde_yaml::Value::Null;
+    let mut settings = Settings::default();
+    for file in self
+      .settings_files()
+      .into_iter()
+      .filter(|file| file.exists())
+    {
+      let overlay = fs::read_to_string(&file)
+        .map_err(|err| err.to_string())
+        .and_then(|content| {
+          serde_yaml::from_str::<serde_yaml::Value>(&content).map_err(|err| err.to_string())
+        });
+      match overlay {
+        Ok(overlay) => {
+          merge_settings(&mut merged, overlay);
+          settings.loaded.push(file);
+        }
+        Err(err) => settings.errors.push(format!(
+          "error loading settings file. err: {}, path: {}",
+          err,
+          file.display()
+        )),
+      }
+    }
+    let serde_yaml::Value::Mapping(merged) = merged else {
+      return settings;
+    };
+    for (key, value) in merged {
+      let (Some(key), Some(value)) = (key.as_str(), setting_value(&value)) else {
+        eprintln!("skipping setting {key:?}, settings should be a name and a scalar value");
+        continue;
+      };
+      settings.values.insert(key.to_string(), value);
+    }
+    settings
+  }
+
+  // settings from `settings.yaml` in bodhi home, overlaid by `settings.{BODHI_ENV_TYPE}.yaml`,
+  // set as the env vars not already set, so the env vars and the .env file take precedence
+  pub fn load_settings(&mut self) -> Vec<PathBuf> {
+    let settings = self.read_settings();
+    for err in &settings.errors {
+      eprintln!("{err}");
+    }
+    for (key, value) in settings.values {
+      if std::env::var(&key).is_err() {
+        std::env::set_var(&key, value);
+        self.settings_keys.insert(key);
+      }
+    }
+    settings.loaded
+  }
+
+  // re-reads the settings files, and applies the changes to the settings in HOT_RELOAD_SETTINGS,
+  // the settings set by the env vars are kept, returns the settings changed
+  pub fn reload_settings(&self) -> Vec<String> {
+    let settings = self.read_settings();
+    if !settings.errors.is_empty() {
+      // likely a file saved half way, the next save reloads the settings
+      tracing::warn!(errors = ?settings.errors, "keeping the current settings");
+      return vec![];
+    }
+    let mut keys = settings.values.keys().cloned().collect::<BTreeSet<_>>();
+    keys.extend(self.settings_keys.iter().cloned());
+    if let Ok(reloaded) = self.reloaded.read() {
+      keys.extend(reloaded.keys().cloned());
+    }
+    let mut changed = Vec::new();
+    for key in keys {
+      if !self.settings_keys.contains(&key) && self.env_wrapper.var(&key).is_ok() {
+        continue;
+      }
+      let value = settings.values.get(&key).cloned();
+      if self.var(&key).ok() == value {
+        continue;
+      }
+      if !HOT_RELOAD_SETTINGS.contains(&key.as_str()) {
+        tracing::warn!(
+          key,
+          "setting changed in the settings files, restart to apply it"
+        );
+        continue;
+      }
+      if let Ok(mut reloaded) = self.reloaded.write() {
+        reloaded.insert(key.clone(), value);
+        changed.push(key);
+      }
+    }
+    changed
+  }
+
   pub fn setup_bodhi_home(&mut self) -> Result<PathBuf, DataServiceError> {
     let value = self.env_wrapper.var(BODHI_HOME);
     let bodhi_home = match value {
@@ -254,6 +1082,32 @@ impl EnvService {
   }
 }
 
+// the overlay takes precedence per key, nested maps are merged instead of replaced
+fn merge_settings(base: &mut serde_yaml::Value, overlay: serde_yaml::Value) {
+  match (base, overlay) {
+    (serde_yaml::Value::Mapping(base), serde_yaml::Value::Mapping(overlay)) => {
+      for (key, value) in overlay {
+        match base.get_mut(&key) {
+          Some(existing) => merge_settings(existing, value),
+          None => {
+            base.insert(key, value);
+          }
+        }
+      }
+    }
+    (base, overlay) => *base = overlay,
+  }
+}
+
+fn setting_value(value: &serde_yaml::Value) -> Option<String> {
+  match value {
+    serde_yaml::Value::String(value) => Some(value.clone()),
+    serde_yaml::Value::Number(value) => Some(value.to_string()),
+    serde_yaml::Value::Bool(value) => Some(value.to_string()),
+    _ => None,
+  }
+}
+
 #[cfg(test)]
 mod test {
   use super::*;
@@ -405,17 +1259,128 @@ mod test {
   }
 
   #[rstest]
-  #[case(BODHI_HOST, "localhost", EnvService::host)]
-  fn test_env_service_host_from_env_var(
-    #[case] key: &str,
-    #[case] value: String,
-    #[case] func: for<'a> fn(&'a EnvService) -> String,
+  fn test_env_service_load_settings_overlay_for_env_type(
+    bodhi_home: (TempDir, PathBuf),
   ) -> anyhow::Result<()> {
+    let (_tempdir, bodhi_home) = bodhi_home;
+    fs::write(
+      bodhi_home.join("settings.yaml"),
+      r#"
+TEST_SETTINGS_BASE_ONLY: base
+TEST_SETTINGS_OVERLAID: base
+TEST_SETTINGS_FROM_ENV: base
+"#,
+    )?;
+    fs::write(
+      bodhi_home.join("settings.staging.yaml"),
+      r#"
+TEST_SETTINGS_OVERLAID: 8080
+TEST_SETTINGS_NESTED:
+  key: value
+"#,
+    )?;
+    fs::write(
+      bodhi_home.join("settings.prod.yaml"),
+      "TEST_SETTINGS_OVERLAID: prod",
+    )?;
+    std::env::set_var("TEST_SETTINGS_FROM_ENV", "env");
+    let bodhi_home_str = bodhi_home.display().to_string();
     let mut mock = MockEnvWrapper::default();
-    let expected = value.clone();
     mock
       .expect_var()
-      .with(eq(key.to_string()))
+      .with(eq(BODHI_HOME))
+      .return_once(move |_| Ok(bodhi_home_str));
+    mock
+      .expect_var()
+      .with(eq(BODHI_ENV_TYPE))
+      .return_once(|_| Ok("staging".to_string()));
+    let mut env_service = EnvService::new(mock);
+    env_service.setup_bodhi_home()?;
+    let loaded = env_service.load_settings();
+    assert_eq!(
+      vec![
+        bodhi_home.join("settings.yaml"),
+        bodhi_home.join("settings.staging.yaml")
+      ],
+      loaded
+    );
+    assert_eq!("base", std::env::var("TEST_SETTINGS_BASE_ONLY")?);
+    assert_eq!("8080", std::env::var("TEST_SETTINGS_OVERLAID")?);
+    assert_eq!("env", std::env::var("TEST_SETTINGS_FROM_ENV")?);
+    assert!(std::env::var("TEST_SETTINGS_NESTED").is_err());
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_env_service_reload_settings_applies_hot_reload_settings(
+    bodhi_home: (TempDir, PathBuf),
+  ) -> anyhow::Result<()> {
+    let (_tempdir, bodhi_home) = bodhi_home;
+    let settings_yaml = bodhi_home.join("settings.yaml");
+    let mut mock = MockEnvWrapper::default();
+    mock.expect_var().returning(|key| match key {
+      "BODHI_LOG_LEVEL" => Ok("warn".to_string()),
+      _ => Err(VarError::NotPresent),
+    });
+    let env_service = EnvService::new_with_args(mock, bodhi_home.clone(), bodhi_home.clone());
+    fs::write(
+      &settings_yaml,
+      "BODHI_KEEP_ALIVE_SECS: 300\nBODHI_LOG_LEVEL: debug\nBODHI_PORT: 8080\n",
+    )?;
+    assert_eq!(vec![BODHI_KEEP_ALIVE_SECS], env_service.reload_settings());
+    assert_eq!(300, env_service.keep_alive_secs());
+    assert_eq!("warn", env_service.log_level());
+    assert_eq!(DEFAULT_PORT, env_service.port());
+    fs::write(&settings_yaml, "BODHI_KEEP_ALIVE_SECS: [300")?;
+    assert!(env_service.reload_settings().is_empty());
+    assert_eq!(300, env_service.keep_alive_secs());
+    fs::write(&settings_yaml, "BODHI_PORT: 8080")?;
+    assert_eq!(vec![BODHI_KEEP_ALIVE_SECS], env_service.reload_settings());
+    assert_eq!(DEFAULT_KEEP_ALIVE_SECS, env_service.keep_alive_secs());
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_env_service_merge_settings_deep_merges_maps() -> anyhow::Result<()> {
+    let mut base = serde_yaml::from_str::<serde_yaml::Value>(
+      r#"
+port: 1135
+cors:
+  origins: "*"
+  credentials: false
+"#,
+    )?;
+    let overlay = serde_yaml::from_str::<serde_yaml::Value>(
+      r#"
+cors:
+  origins: https://chat.example.com
+"#,
+    )?;
+    merge_settings(&mut base, overlay);
+    let expected = serde_yaml::from_str::<serde_yaml::Value>(
+      r#"
+port: 1135
+cors:
+  origins: https://chat.example.com
+  credentials: false
+"#,
+    )?;
+    assert_eq!(expected, base);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(BODHI_HOST, "localhost", EnvService::host)]
+  fn test_env_service_host_from_env_var(
+    #[case] key: &str,
+    #[case] value: String,
+    #[case] func: for<'a> fn(&'a EnvService) -> String,
+  ) -> anyhow::Result<()> {
+    let mut mock = MockEnvWrapper::default();
+    let expected = value.clone();
+    mock
+      .expect_var()
+      .with(eq(key.to_string()))
       .return_once(move |_| Ok(value));
     let result = func(&EnvService::new(mock));
     assert_eq!(expected, result);
@@ -458,6 +1423,521 @@ mod test {
     Ok(())
   }
 
+  #[rstest]
+  #[case(Ok("5".to_string()), 5)]
+  #[case(Ok("not-a-number".to_string()), 60)]
+  #[case(Err(VarError::NotPresent), 60)]
+  fn test_env_service_slow_request_secs(
+    #[case] value: Result<String, VarError>,
+    #[case] expected: u64,
+  ) -> anyhow::Result<()> {
+    let mut mock = MockEnvWrapper::default();
+    mock
+      .expect_var()
+      .with(eq(BODHI_SLOW_REQUEST_SECS))
+      .return_once(move |_| value);
+    let result = EnvService::new(mock).slow_request_secs();
+    assert_eq!(expected, result);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(Ok("300".to_string()), 300)]
+  #[case(Ok("-1".to_string()), 0)]
+  #[case(Err(VarError::NotPresent), 0)]
+  fn test_env_service_keep_alive_secs(
+    #[case] value: Result<String, VarError>,
+    #[case] expected: u64,
+  ) -> anyhow::Result<()> {
+    let mut mock = MockEnvWrapper::default();
+    mock
+      .expect_var()
+      .with(eq(BODHI_KEEP_ALIVE_SECS))
+      .return_once(move |_| value);
+    let result = EnvService::new(mock).keep_alive_secs();
+    assert_eq!(expected, result);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(Ok("true".to_string()), true)]
+  #[case(Ok("yes".to_string()), false)]
+  #[case(Err(VarError::NotPresent), false)]
+  fn test_env_service_strict_alias(
+    #[case] value: Result<String, VarError>,
+    #[case] expected: bool,
+  ) -> anyhow::Result<()> {
+    let mut mock = MockEnvWrapper::default();
+    mock
+      .expect_var()
+      .with(eq(BODHI_STRICT_ALIAS))
+      .return_once(move |_| value);
+    let result = EnvService::new(mock).strict_alias();
+    assert_eq!(expected, result);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(Ok("true".to_string()), true)]
+  #[case(Ok("strict".to_string()), false)]
+  #[case(Err(VarError::NotPresent), false)]
+  fn test_env_service_strict_params(
+    #[case] value: Result<String, VarError>,
+    #[case] expected: bool,
+  ) -> anyhow::Result<()> {
+    let mut mock = MockEnvWrapper::default();
+    mock
+      .expect_var()
+      .with(eq(BODHI_STRICT_PARAMS))
+      .return_once(move |_| value);
+    let result = EnvService::new(mock).strict_params();
+    assert_eq!(expected, result);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(Ok("80".to_string()), 80)]
+  #[case(Ok("0".to_string()), 0)]
+  #[case(Ok("120".to_string()), 90)]
+  #[case(Err(VarError::NotPresent), 90)]
+  fn test_env_service_memory_watermark(
+    #[case] value: Result<String, VarError>,
+    #[case] expected: u8,
+  ) -> anyhow::Result<()> {
+    let mut mock = MockEnvWrapper::default();
+    mock
+      .expect_var()
+      .with(eq(BODHI_MEMORY_WATERMARK))
+      .return_once(move |_| value);
+    let result = EnvService::new(mock).memory_watermark();
+    assert_eq!(expected, result);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(Ok("*".to_string()), vec!["*"])]
+  #[case(
+    Ok("https://chat.example.com/, http://localhost:3000".to_string()),
+    vec!["https://chat.example.com", "http://localhost:3000"]
+  )]
+  #[case(Ok(" , ".to_string()), vec![])]
+  #[case(Err(VarError::NotPresent), vec![])]
+  fn test_env_service_cors_allowed_origins(
+    #[case] value: Result<String, VarError>,
+    #[case] expected: Vec<&str>,
+  ) -> anyhow::Result<()> {
+    let mut mock = MockEnvWrapper::default();
+    mock
+      .expect_var()
+      .with(eq(BODHI_CORS_ALLOWED_ORIGINS))
+      .return_once(move |_| value);
+    let result = EnvService::new(mock).cors_allowed_origins();
+    assert_eq!(expected, result);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(Ok("5".to_string()), 5)]
+  #[case(Ok("0".to_string()), 0)]
+  #[case(Ok("soon".to_string()), 30)]
+  #[case(Err(VarError::NotPresent), 30)]
+  fn test_env_service_shutdown_grace_secs(
+    #[case] value: Result<String, VarError>,
+    #[case] expected: u64,
+  ) -> anyhow::Result<()> {
+    let mut mock = MockEnvWrapper::default();
+    mock
+      .expect_var()
+      .with(eq(BODHI_SHUTDOWN_GRACE_SECS))
+      .return_once(move |_| value);
+    let result = EnvService::new(mock).shutdown_grace_secs();
+    assert_eq!(expected, result);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(Ok("true".to_string()), true)]
+  #[case(Ok("1".to_string()), false)]
+  #[case(Err(VarError::NotPresent), false)]
+  fn test_env_service_metrics_enabled(
+    #[case] value: Result<String, VarError>,
+    #[case] expected: bool,
+  ) -> anyhow::Result<()> {
+    let mut mock = MockEnvWrapper::default();
+    mock
+      .expect_var()
+      .with(eq(BODHI_METRICS_ENABLED))
+      .return_once(move |_| value);
+    let result = EnvService::new(mock).metrics_enabled();
+    assert_eq!(expected, result);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(Ok("true".to_string()), true)]
+  #[case(Ok("paused".to_string()), false)]
+  #[case(Err(VarError::NotPresent), false)]
+  fn test_env_service_inference_paused(
+    #[case] value: Result<String, VarError>,
+    #[case] expected: bool,
+  ) -> anyhow::Result<()> {
+    let mut mock = MockEnvWrapper::default();
+    mock
+      .expect_var()
+      .with(eq(BODHI_INFERENCE_PAUSED))
+      .return_once(move |_| value);
+    let result = EnvService::new(mock).inference_paused();
+    assert_eq!(expected, result);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(Ok("true".to_string()), true)]
+  #[case(Ok("lint".to_string()), false)]
+  #[case(Err(VarError::NotPresent), false)]
+  fn test_env_service_lint_on_start(
+    #[case] value: Result<String, VarError>,
+    #[case] expected: bool,
+  ) -> anyhow::Result<()> {
+    let mut mock = MockEnvWrapper::default();
+    mock
+      .expect_var()
+      .with(eq(BODHI_LINT_ON_START))
+      .return_once(move |_| value);
+    let result = EnvService::new(mock).lint_on_start();
+    assert_eq!(expected, result);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(Ok("true".to_string()), true)]
+  #[case(Ok("yes".to_string()), false)]
+  #[case(Err(VarError::NotPresent), false)]
+  fn test_env_service_response_cache_enabled(
+    #[case] value: Result<String, VarError>,
+    #[case] expected: bool,
+  ) -> anyhow::Result<()> {
+    let mut mock = MockEnvWrapper::default();
+    mock
+      .expect_var()
+      .with(eq(BODHI_RESPONSE_CACHE_ENABLED))
+      .return_once(move |_| value);
+    let result = EnvService::new(mock).response_cache_enabled();
+    assert_eq!(expected, result);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(Ok("600".to_string()), 600, Ok("10".to_string()), 10)]
+  #[case(Ok("an hour".to_string()), 3600, Ok("-1".to_string()), 100)]
+  #[case(Err(VarError::NotPresent), 3600, Err(VarError::NotPresent), 100)]
+  fn test_env_service_response_cache_limits(
+    #[case] ttl: Result<String, VarError>,
+    #[case] expected_ttl: u64,
+    #[case] max_entries: Result<String, VarError>,
+    #[case] expected_max_entries: usize,
+  ) -> anyhow::Result<()> {
+    let mut mock = MockEnvWrapper::default();
+    mock
+      .expect_var()
+      .with(eq(BODHI_RESPONSE_CACHE_TTL_SECS))
+      .return_once(move |_| ttl);
+    mock
+      .expect_var()
+      .with(eq(BODHI_RESPONSE_CACHE_MAX_ENTRIES))
+      .return_once(move |_| max_entries);
+    let env_service = EnvService::new(mock);
+    assert_eq!(expected_ttl, env_service.response_cache_ttl_secs());
+    assert_eq!(
+      expected_max_entries,
+      env_service.response_cache_max_entries()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(Ok("4".to_string()), 4, Ok("0".to_string()), 0)]
+  #[case(Ok("many".to_string()), 0, Ok("-1".to_string()), 32)]
+  #[case(Err(VarError::NotPresent), 0, Err(VarError::NotPresent), 32)]
+  fn test_env_service_request_queue_limits(
+    #[case] max_concurrent: Result<String, VarError>,
+    #[case] expected_max_concurrent: usize,
+    #[case] max_queued: Result<String, VarError>,
+    #[case] expected_max_queued: usize,
+  ) -> anyhow::Result<()> {
+    let mut mock = MockEnvWrapper::default();
+    mock
+      .expect_var()
+      .with(eq(BODHI_MAX_CONCURRENT_REQUESTS))
+      .return_once(move |_| max_concurrent);
+    mock
+      .expect_var()
+      .with(eq(BODHI_MAX_QUEUED_REQUESTS))
+      .return_once(move |_| max_queued);
+    let env_service = EnvService::new(mock);
+    assert_eq!(
+      expected_max_concurrent,
+      env_service.max_concurrent_requests()
+    );
+    assert_eq!(expected_max_queued, env_service.max_queued_requests());
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(Ok("json".to_string()), "json")]
+  #[case(Ok(" JSON ".to_string()), "json")]
+  #[case(Ok("logfmt".to_string()), "text")]
+  #[case(Err(VarError::NotPresent), "text")]
+  fn test_env_service_log_format(
+    #[case] value: Result<String, VarError>,
+    #[case] expected: &str,
+  ) -> anyhow::Result<()> {
+    let mut mock = MockEnvWrapper::default();
+    mock
+      .expect_var()
+      .with(eq(BODHI_LOG_FORMAT))
+      .return_once(move |_| value);
+    let result = EnvService::new(mock).log_format();
+    assert_eq!(expected, result);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(Ok("false".to_string()), false)]
+  #[case(Ok("off".to_string()), true)]
+  #[case(Err(VarError::NotPresent), true)]
+  fn test_env_service_compression_enabled(
+    #[case] value: Result<String, VarError>,
+    #[case] expected: bool,
+  ) -> anyhow::Result<()> {
+    let mut mock = MockEnvWrapper::default();
+    mock
+      .expect_var()
+      .with(eq(BODHI_COMPRESSION_ENABLED))
+      .return_once(move |_| value);
+    let result = EnvService::new(mock).compression_enabled();
+    assert_eq!(expected, result);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(Ok("true".to_string()), true)]
+  #[case(Ok("on".to_string()), false)]
+  #[case(Err(VarError::NotPresent), false)]
+  fn test_env_service_mock(
+    #[case] value: Result<String, VarError>,
+    #[case] expected: bool,
+  ) -> anyhow::Result<()> {
+    let mut mock = MockEnvWrapper::default();
+    mock
+      .expect_var()
+      .with(eq(BODHI_MOCK))
+      .return_once(move |_| value);
+    let result = EnvService::new(mock).mock();
+    assert_eq!(expected, result);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(Ok("/opt/bodhi/ui ".to_string()), Some("/opt/bodhi/ui"))]
+  #[case(Ok(" ".to_string()), None)]
+  #[case(Err(VarError::NotPresent), None)]
+  fn test_env_service_ui_dir(
+    #[case] value: Result<String, VarError>,
+    #[case] expected: Option<&str>,
+  ) -> anyhow::Result<()> {
+    let mut mock = MockEnvWrapper::default();
+    mock
+      .expect_var()
+      .with(eq(BODHI_UI_DIR))
+      .return_once(move |_| value);
+    let result = EnvService::new(mock).ui_dir();
+    assert_eq!(expected.map(PathBuf::from), result);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(Ok("Tuesday.".to_string()), "Tuesday.")]
+  #[case(Ok(" ".to_string()), DEFAULT_MOCK_TEXT)]
+  #[case(Err(VarError::NotPresent), DEFAULT_MOCK_TEXT)]
+  fn test_env_service_mock_text(
+    #[case] value: Result<String, VarError>,
+    #[case] expected: &str,
+  ) -> anyhow::Result<()> {
+    let mut mock = MockEnvWrapper::default();
+    mock
+      .expect_var()
+      .with(eq(BODHI_MOCK_TEXT))
+      .return_once(move |_| value);
+    let result = EnvService::new(mock).mock_text();
+    assert_eq!(expected, result);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(Ok("0".to_string()), 0)]
+  #[case(Ok("fast".to_string()), 20)]
+  #[case(Err(VarError::NotPresent), 20)]
+  fn test_env_service_mock_token_delay_ms(
+    #[case] value: Result<String, VarError>,
+    #[case] expected: u64,
+  ) -> anyhow::Result<()> {
+    let mut mock = MockEnvWrapper::default();
+    mock
+      .expect_var()
+      .with(eq(BODHI_MOCK_TOKEN_DELAY_MS))
+      .return_once(move |_| value);
+    let result = EnvService::new(mock).mock_token_delay_ms();
+    assert_eq!(expected, result);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(Some("cert.pem"), Some("key.pem"), "https", vec![])]
+  #[case(
+    Some("cert.pem"),
+    None,
+    "http",
+    vec!["BODHI_TLS_CERT='{dir}/cert.pem': should be set along with BODHI_TLS_KEY"]
+  )]
+  #[case(
+    Some("missing.pem"),
+    Some("key.pem"),
+    "https",
+    vec!["BODHI_TLS_CERT='{dir}/missing.pem': should be an existing file"]
+  )]
+  #[case(None, None, "http", vec![])]
+  fn test_env_service_tls_scheme_and_validate(
+    #[case] cert: Option<&str>,
+    #[case] key: Option<&str>,
+    #[case] scheme: &str,
+    #[case] issues: Vec<&str>,
+  ) -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let dir = temp_dir.path().display().to_string();
+    fs::write(temp_dir.path().join("cert.pem"), "")?;
+    fs::write(temp_dir.path().join("key.pem"), "")?;
+    let mut envs = HashMap::new();
+    if let Some(cert) = cert {
+      envs.insert(BODHI_TLS_CERT, format!("{dir}/{cert}"));
+    }
+    if let Some(key) = key {
+      envs.insert(BODHI_TLS_KEY, format!("{dir}/{key}"));
+    }
+    let mut mock = MockEnvWrapper::default();
+    mock
+      .expect_var()
+      .returning(move |key| envs.get(key).cloned().ok_or(VarError::NotPresent));
+    let env_service = EnvService::new(mock);
+    assert_eq!(scheme, env_service.scheme());
+    assert_eq!(
+      issues
+        .iter()
+        .map(|issue| issue.replace("{dir}", &dir))
+        .collect::<Vec<_>>(),
+      env_service
+        .validate()
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(true, vec!["BODHI_BIND_UDS='{dir}/bodhi.sock': should not be set along with BODHI_TLS_CERT and BODHI_TLS_KEY"])]
+  #[case(false, vec![])]
+  fn test_env_service_validate_bind_uds_with_tls(
+    #[case] tls: bool,
+    #[case] issues: Vec<&str>,
+  ) -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let dir = temp_dir.path().display().to_string();
+    fs::write(temp_dir.path().join("cert.pem"), "")?;
+    fs::write(temp_dir.path().join("key.pem"), "")?;
+    let mut envs = HashMap::from([(BODHI_BIND_UDS, format!("{dir}/bodhi.sock"))]);
+    if tls {
+      envs.insert(BODHI_TLS_CERT, format!("{dir}/cert.pem"));
+      envs.insert(BODHI_TLS_KEY, format!("{dir}/key.pem"));
+    }
+    let mut mock = MockEnvWrapper::default();
+    mock
+      .expect_var()
+      .returning(move |key| envs.get(key).cloned().ok_or(VarError::NotPresent));
+    assert_eq!(
+      issues
+        .iter()
+        .map(|issue| issue.replace("{dir}", &dir))
+        .collect::<Vec<_>>(),
+      EnvService::new(mock)
+        .validate()
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(Ok("660".to_string()), 0o660)]
+  #[case(Ok("0640".to_string()), 0o640)]
+  #[case(Ok("1777".to_string()), 0o600)]
+  #[case(Ok("rw-rw----".to_string()), 0o600)]
+  #[case(Err(VarError::NotPresent), 0o600)]
+  fn test_env_service_bind_uds_mode(
+    #[case] value: Result<String, VarError>,
+    #[case] expected: u32,
+  ) -> anyhow::Result<()> {
+    let mut mock = MockEnvWrapper::default();
+    mock
+      .expect_var()
+      .with(eq(BODHI_BIND_UDS_MODE))
+      .return_once(move |_| value);
+    let result = EnvService::new(mock).bind_uds_mode();
+    assert_eq!(expected, result);
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_env_service_validate_reports_invalid_settings(
+    bodhi_home: (TempDir, PathBuf),
+  ) -> anyhow::Result<()> {
+    let (_tempdir, bodhi_home) = bodhi_home;
+    let not_a_dir = bodhi_home.join("bodhi.log");
+    fs::write(&not_a_dir, "")?;
+    let envs = HashMap::from([
+      (BODHI_LOGS, not_a_dir.display().to_string()),
+      (BODHI_HOME, bodhi_home.display().to_string()),
+      (BODHI_PORT, "0".to_string()),
+      (BODHI_KEEP_ALIVE_SECS, "300".to_string()),
+      (BODHI_MEMORY_WATERMARK, "120".to_string()),
+      (BODHI_STRICT_ALIAS, "yes".to_string()),
+      (BODHI_INFERENCE_PAUSED, "paused".to_string()),
+      (BODHI_LOG_FORMAT, "JSON".to_string()),
+      (
+        BODHI_CORS_ALLOWED_ORIGINS,
+        "https://chat.example.com, localhost:3000".to_string(),
+      ),
+    ]);
+    let mut mock = MockEnvWrapper::default();
+    mock
+      .expect_var()
+      .returning(move |key| envs.get(key).cloned().ok_or(VarError::NotPresent));
+    let issues = EnvService::new(mock).validate();
+    assert_eq!(
+      vec![
+        format!("BODHI_LOGS='{}': should be a directory", not_a_dir.display()),
+        "BODHI_PORT='0': should be a port number between 1 and 65535".to_string(),
+        "BODHI_MEMORY_WATERMARK='120': should be a percent between 0 and 100".to_string(),
+        "BODHI_STRICT_ALIAS='yes': should be true or false".to_string(),
+        "BODHI_INFERENCE_PAUSED='paused': should be true or false".to_string(),
+        "BODHI_CORS_ALLOWED_ORIGINS='https://chat.example.com, localhost:3000': should be * or a comma separated list of origins, like https://chat.example.com".to_string(),
+      ],
+      issues.iter().map(ToString::to_string).collect::<Vec<_>>()
+    );
+    Ok(())
+  }
+
   #[rstest]
   fn test_env_service_list() -> anyhow::Result<()> {
     let mut mock = MockEnvWrapper::default();
@@ -469,6 +1949,102 @@ mod test {
       .expect_var()
       .with(eq(BODHI_PORT))
       .return_once(move |_| Ok("8080".to_string()));
+    mock
+      .expect_var()
+      .with(eq(BODHI_SLOW_REQUEST_SECS))
+      .return_once(move |_| Err(VarError::NotPresent));
+    mock
+      .expect_var()
+      .with(eq(BODHI_KEEP_ALIVE_SECS))
+      .return_once(move |_| Ok("300".to_string()));
+    mock
+      .expect_var()
+      .with(eq(BODHI_STRICT_ALIAS))
+      .return_once(move |_| Ok("true".to_string()));
+    mock
+      .expect_var()
+      .with(eq(BODHI_STRICT_PARAMS))
+      .return_once(move |_| Err(VarError::NotPresent));
+    mock
+      .expect_var()
+      .with(eq(BODHI_MEMORY_WATERMARK))
+      .return_once(move |_| Err(VarError::NotPresent));
+    mock
+      .expect_var()
+      .with(eq(BODHI_CORS_ALLOWED_ORIGINS))
+      .return_once(move |_| Ok("https://chat.example.com".to_string()));
+    mock
+      .expect_var()
+      .with(eq(BODHI_SHUTDOWN_GRACE_SECS))
+      .return_once(move |_| Ok("5".to_string()));
+    mock
+      .expect_var()
+      .with(eq(BODHI_METRICS_ENABLED))
+      .return_once(move |_| Ok("true".to_string()));
+    mock
+      .expect_var()
+      .with(eq(BODHI_INFERENCE_PAUSED))
+      .return_once(move |_| Err(VarError::NotPresent));
+    mock
+      .expect_var()
+      .with(eq(BODHI_LINT_ON_START))
+      .return_once(move |_| Err(VarError::NotPresent));
+    mock
+      .expect_var()
+      .with(eq(BODHI_RESPONSE_CACHE_ENABLED))
+      .return_once(move |_| Ok("true".to_string()));
+    mock
+      .expect_var()
+      .with(eq(BODHI_RESPONSE_CACHE_TTL_SECS))
+      .return_once(move |_| Err(VarError::NotPresent));
+    mock
+      .expect_var()
+      .with(eq(BODHI_RESPONSE_CACHE_MAX_ENTRIES))
+      .return_once(move |_| Ok("500".to_string()));
+    mock
+      .expect_var()
+      .with(eq(BODHI_COMPRESSION_ENABLED))
+      .return_once(move |_| Ok("false".to_string()));
+    mock
+      .expect_var()
+      .with(eq(BODHI_MAX_CONCURRENT_REQUESTS))
+      .return_once(move |_| Ok("2".to_string()));
+    mock
+      .expect_var()
+      .with(eq(BODHI_MAX_QUEUED_REQUESTS))
+      .return_once(move |_| Err(VarError::NotPresent));
+    mock
+      .expect_var()
+      .with(eq(BODHI_LOG_FORMAT))
+      .return_once(move |_| Ok("json".to_string()));
+    mock
+      .expect_var()
+      .with(eq(BODHI_LOG_LEVEL))
+      .return_once(move |_| Ok("DEBUG".to_string()));
+    mock
+      .expect_var()
+      .with(eq(BODHI_MOCK))
+      .return_once(move |_| Err(VarError::NotPresent));
+    mock
+      .expect_var()
+      .with(eq(BODHI_UI_DIR))
+      .return_once(move |_| Ok("/opt/bodhi/ui".to_string()));
+    mock
+      .expect_var()
+      .with(eq(BODHI_TLS_CERT))
+      .return_once(move |_| Ok("/etc/bodhi/cert.pem".to_string()));
+    mock
+      .expect_var()
+      .with(eq(BODHI_TLS_KEY))
+      .return_once(move |_| Err(VarError::NotPresent));
+    mock
+      .expect_var()
+      .with(eq(BODHI_BIND_UDS))
+      .return_once(move |_| Ok("/run/bodhi.sock".to_string()));
+    mock
+      .expect_var()
+      .with(eq(BODHI_BIND_UDS_MODE))
+      .return_once(move |_| Ok("660".to_string()));
     let result = EnvService::new_with_args(
       mock,
       PathBuf::from("/tmp/bodhi_home"),
@@ -481,6 +2057,45 @@ mod test {
     expected.insert("BODHI_LOGS".to_string(), "/tmp/hf_home/logs".to_string());
     expected.insert("BODHI_HOST".to_string(), "0.0.0.0".to_string());
     expected.insert("BODHI_PORT".to_string(), "8080".to_string());
+    expected.insert("BODHI_SLOW_REQUEST_SECS".to_string(), "60".to_string());
+    expected.insert("BODHI_KEEP_ALIVE_SECS".to_string(), "300".to_string());
+    expected.insert("BODHI_STRICT_ALIAS".to_string(), "true".to_string());
+    expected.insert("BODHI_STRICT_PARAMS".to_string(), "false".to_string());
+    expected.insert("BODHI_MEMORY_WATERMARK".to_string(), "90".to_string());
+    expected.insert(
+      "BODHI_CORS_ALLOWED_ORIGINS".to_string(),
+      "https://chat.example.com".to_string(),
+    );
+    expected.insert("BODHI_SHUTDOWN_GRACE_SECS".to_string(), "5".to_string());
+    expected.insert("BODHI_METRICS_ENABLED".to_string(), "true".to_string());
+    expected.insert("BODHI_INFERENCE_PAUSED".to_string(), "false".to_string());
+    expected.insert("BODHI_LINT_ON_START".to_string(), "false".to_string());
+    expected.insert(
+      "BODHI_RESPONSE_CACHE_ENABLED".to_string(),
+      "true".to_string(),
+    );
+    expected.insert(
+      "BODHI_RESPONSE_CACHE_TTL_SECS".to_string(),
+      "3600".to_string(),
+    );
+    expected.insert(
+      "BODHI_RESPONSE_CACHE_MAX_ENTRIES".to_string(),
+      "500".to_string(),
+    );
+    expected.insert("BODHI_COMPRESSION_ENABLED".to_string(), "false".to_string());
+    expected.insert("BODHI_MAX_CONCURRENT_REQUESTS".to_string(), "2".to_string());
+    expected.insert("BODHI_MAX_QUEUED_REQUESTS".to_string(), "32".to_string());
+    expected.insert("BODHI_LOG_FORMAT".to_string(), "json".to_string());
+    expected.insert("BODHI_LOG_LEVEL".to_string(), "debug".to_string());
+    expected.insert("BODHI_MOCK".to_string(), "false".to_string());
+    expected.insert("BODHI_UI_DIR".to_string(), "/opt/bodhi/ui".to_string());
+    expected.insert(
+      "BODHI_TLS_CERT".to_string(),
+      "/etc/bodhi/cert.pem".to_string(),
+    );
+    expected.insert("BODHI_TLS_KEY".to_string(), "".to_string());
+    expected.insert("BODHI_BIND_UDS".to_string(), "/run/bodhi.sock".to_string());
+    expected.insert("BODHI_BIND_UDS_MODE".to_string(), "660".to_string());
     assert_eq!(expected.len(), actual.len());
     for key in expected.keys() {
       assert_eq!(
@@ -0,0 +1,282 @@
+use hf_hub::{api::sync::ApiError, Cache};
+use indicatif::{ProgressBar, ProgressStyle};
+use std::{
+  fs::{self, OpenOptions},
+  io,
+  path::{Path, PathBuf},
+};
+use ureq::{Agent, AgentBuilder, Request};
+
+const HF_ENDPOINT: &str = "https://huggingface.co";
+const USER_AGENT: &str = concat!("bodhi/", env!("CARGO_PKG_VERSION"), "; rust/unknown");
+
+#[derive(Debug)]
+struct Metadata {
+  commit_hash: String,
+  etag: String,
+  size: u64,
+}
+
+// downloads a file into the huggingface cache in the same layout as hf-hub, the file is written
+// to a .part file next to its blob, so an interrupted download resumes from the bytes received
+pub(crate) fn download_resumable(
+  cache: &Cache,
+  token: Option<&str>,
+  repo: &str,
+  filename: &str,
+  progress_bar: bool,
+  force: bool,
+) -> Result<PathBuf, ApiError> {
+  let url = format!("{HF_ENDPOINT}/{repo}/resolve/main/{filename}");
+  let metadata = metadata(token, &url)?;
+  let repo_dir = cache
+    .path()
+    .join(hf_hub::Repo::model(repo.to_string()).folder_name());
+  let blob_path = repo_dir.join("blobs").join(&metadata.etag);
+  let part_path = repo_dir
+    .join("blobs")
+    .join(format!("{}.part", metadata.etag));
+  fs::create_dir_all(repo_dir.join("blobs"))?;
+  if force {
+    _ = fs::remove_file(&blob_path);
+    _ = fs::remove_file(&part_path);
+  }
+  if !blob_path.exists() {
+    let progress = progress_bar.then(|| progress(filename, metadata.size));
+    download_part(
+      &AgentBuilder::new().build(),
+      token,
+      &url,
+      &part_path,
+      progress,
+    )?;
+    fs::rename(&part_path, &blob_path)?;
+  }
+  let pointer_path = repo_dir
+    .join("snapshots")
+    .join(&metadata.commit_hash)
+    .join(filename);
+  if let Some(parent) = pointer_path.parent() {
+    fs::create_dir_all(parent)?;
+  }
+  if !pointer_path.exists() {
+    // relative to the snapshot dir, going up the snapshot and the dirs in the filename
+    let depth = Path::new(filename).components().count() + 1;
+    let blob_relative = PathBuf::from("../".repeat(depth))
+      .join("blobs")
+      .join(&metadata.etag);
+    symlink_or_copy(&blob_relative, &blob_path, &pointer_path)?;
+  }
+  cache
+    .model(repo.to_string())
+    .create_ref(&metadata.commit_hash)?;
+  Ok(pointer_path)
+}
+
+// sends a range request for the bytes after the ones in the part file, a server that does not
+// support range requests responds with the whole file, and the part file is written again
+fn download_part(
+  agent: &Agent,
+  token: Option<&str>,
+  url: &str,
+  part_path: &Path,
+  progress: Option<ProgressBar>,
+) -> Result<(), ApiError> {
+  let offset = fs::metadata(part_path).map(|m| m.len()).unwrap_or(0);
+  let mut request = get(agent, token, url);
+  if offset > 0 {
+    request = request.set("Range", &format!("bytes={offset}-"));
+  }
+  let response = match request.call() {
+    Ok(response) => response,
+    // the part file already has all the bytes of the file
+    Err(ureq::Error::Status(416, _)) if offset > 0 => return Ok(()),
+    Err(err) => return Err(ApiError::RequestError(Box::new(err))),
+  };
+  let resumed = offset > 0 && response.status() == 206;
+  if offset > 0 && !resumed {
+    tracing::info!(
+      url,
+      offset,
+      "server does not support range requests, downloading again"
+    );
+  }
+  let mut file = OpenOptions::new()
+    .create(true)
+    .write(true)
+    .append(resumed)
+    .truncate(!resumed)
+    .open(part_path)?;
+  let mut reader = response.into_reader();
+  if let Some(progress) = &progress {
+    progress.set_position(if resumed { offset } else { 0 });
+    reader = Box::new(progress.wrap_read(reader));
+  }
+  io::copy(&mut reader, &mut file)?;
+  if let Some(progress) = progress {
+    progress.finish();
+  }
+  Ok(())
+}
+
+// the etag and the commit are read from the response of the huggingface endpoint, the size from
+// the response of the storage it redirects to for a LFS file
+fn metadata(token: Option<&str>, url: &str) -> Result<Metadata, ApiError> {
+  let no_redirect = AgentBuilder::new().redirects(0).build();
+  let response = get(&no_redirect, token, url)
+    .set("Range", "bytes=0-0")
+    .call()
+    .map_err(Box::new)?;
+  let etag = response
+    .header("x-linked-etag")
+    .or_else(|| response.header("etag"))
+    .ok_or(ApiError::MissingHeader("etag"))?
+    .replace('"', "");
+  let commit_hash = response
+    .header("x-repo-commit")
+    .ok_or(ApiError::MissingHeader("x-repo-commit"))?
+    .to_string();
+  let response = if (300..400).contains(&response.status()) {
+    let location = response
+      .header("Location")
+      .ok_or(ApiError::MissingHeader("Location"))?;
+    let location = if location.starts_with('/') {
+      format!("{HF_ENDPOINT}{location}")
+    } else {
+      location.to_string()
+    };
+    get(&AgentBuilder::new().build(), token, &location)
+      .set("Range", "bytes=0-0")
+      .call()
+      .map_err(Box::new)?
+  } else {
+    response
+  };
+  let size = response
+    .header("Content-Range")
+    .ok_or(ApiError::MissingHeader("Content-Range"))?
+    .rsplit('/')
+    .next()
+    .ok_or(ApiError::InvalidHeader("Content-Range"))?
+    .parse()?;
+  Ok(Metadata {
+    commit_hash,
+    etag,
+    size,
+  })
+}
+
+fn get(agent: &Agent, token: Option<&str>, url: &str) -> Request {
+  let request = agent.get(url).set("User-Agent", USER_AGENT);
+  match token {
+    Some(token) => request.set("Authorization", &format!("Bearer {token}")),
+    None => request,
+  }
+}
+
+fn progress(filename: &str, size: u64) -> ProgressBar {
+  let progress = ProgressBar::new(size);
+  progress.set_style(
+    ProgressStyle::with_template(
+      "{msg} [{elapsed_precise}] [{wide_bar}] {bytes}/{total_bytes} {bytes_per_sec} ({eta})",
+    )
+    .unwrap(),
+  );
+  let message = match filename.char_indices().rev().nth(29) {
+    Some((index, _)) => format!("..{}", &filename[index..]),
+    None => filename.to_string(),
+  };
+  progress.set_message(message);
+  progress
+}
+
+#[cfg(target_family = "unix")]
+fn symlink_or_copy(blob_relative: &Path, _blob_path: &Path, pointer_path: &Path) -> io::Result<()> {
+  std::os::unix::fs::symlink(blob_relative, pointer_path)
+}
+
+#[cfg(not(target_family = "unix"))]
+fn symlink_or_copy(blob_relative: &Path, blob_path: &Path, pointer_path: &Path) -> io::Result<()> {
+  if std::os::windows::fs::symlink_file(blob_relative, pointer_path).is_err() {
+    fs::copy(blob_path, pointer_path)?;
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use super::download_part;
+  use rstest::rstest;
+  use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    thread::{self, JoinHandle},
+  };
+  use ureq::AgentBuilder;
+
+  // serves a single request, responding with the status and body, returns the request headers
+  fn serve_once(status: &'static str, body: &'static str) -> (String, JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/model.gguf", listener.local_addr().unwrap());
+    let handle = thread::spawn(move || {
+      let (mut stream, _) = listener.accept().unwrap();
+      let headers = BufReader::new(stream.try_clone().unwrap())
+        .lines()
+        .map(|line| line.unwrap())
+        .take_while(|line| !line.is_empty())
+        .collect::<Vec<_>>();
+      write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+      )
+      .unwrap();
+      headers
+    });
+    (url, handle)
+  }
+
+  #[rstest]
+  #[case(None, "200 OK", "hello world", None, "hello world")]
+  #[case(
+    Some("hello "),
+    "206 Partial Content",
+    "world",
+    Some("range: bytes=6-"),
+    "hello world"
+  )]
+  #[case(
+    Some("hello "),
+    "200 OK",
+    "hello world",
+    Some("range: bytes=6-"),
+    "hello world"
+  )]
+  fn test_hub_download_part_resumes_from_part_file(
+    #[case] part: Option<&str>,
+    #[case] status: &'static str,
+    #[case] body: &'static str,
+    #[case] range: Option<&str>,
+    #[case] expected: &str,
+  ) -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let part_path = temp_dir.path().join("c22e92d054f01229.part");
+    if let Some(part) = part {
+      fs::write(&part_path, part)?;
+    }
+    let (url, handle) = serve_once(status, body);
+    download_part(&AgentBuilder::new().build(), None, &url, &part_path, None)?;
+    let headers = handle.join().unwrap();
+    assert_eq!(
+      range,
+      headers
+        .iter()
+        .map(|header| header.to_lowercase())
+        .find(|header| header.starts_with("range:"))
+        .as_deref()
+    );
+    assert_eq!(expected, fs::read_to_string(part_path)?);
+    Ok(())
+  }
+}
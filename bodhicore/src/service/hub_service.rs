@@ -1,9 +1,15 @@
-use crate::objs::{HubFile, ObjError, Repo, REFS, REFS_MAIN};
+use super::hub_download::download_resumable;
+use crate::{
+  gguf::{GgufError, GgufReader},
+  objs::{Alias, HubFile, ObjError, Repo, REFS, REFS_MAIN},
+};
 use hf_hub::{api::sync::ApiError, Cache};
+use sha2::{Digest, Sha256};
 use std::{
   fmt::{Debug, Formatter},
-  fs,
-  path::PathBuf,
+  fs::{self, File},
+  io::{self, BufReader},
+  path::{Path, PathBuf},
 };
 use walkdir::WalkDir;
 
@@ -46,6 +52,30 @@ Check Huggingface Home is set correctly using environment variable $HF_HOME or u
 
   #[error("chat_template not found in tokenizer_config.json")]
   ChatTemplate,
+  #[error(
+    r#"downloaded file '{filename}' is corrupt, expected sha256 '{expected}', found '{actual}'.
+The download may have been interrupted, run the pull again with --force to download the file again."#
+  )]
+  ChecksumMismatch {
+    filename: String,
+    expected: String,
+    actual: String,
+  },
+  #[error(
+    r#"downloaded file '{filename}' is not a valid GGUF file: {source}
+The download may have been interrupted, run the pull again with --force to download the file again."#
+  )]
+  InvalidGguf {
+    filename: String,
+    #[source]
+    source: GgufError,
+  },
+  #[error("error reading downloaded file '{filename}': {source}")]
+  IoError {
+    filename: String,
+    #[source]
+    source: io::Error,
+  },
 }
 
 type Result<T> = std::result::Result<T, HubServiceError>;
@@ -60,6 +90,58 @@ pub trait HubService: std::fmt::Debug {
     -> Result<Option<HubFile>>;
 
   fn model_file_path(&self, repo: &Repo, filename: &str, snapshot: &str) -> PathBuf;
+
+  // removes the file from the snapshot, along with its blob unless another snapshot links to it,
+  // returns false if the file is not in the cache
+  fn delete_local_file(&self, repo: &Repo, filename: &str, snapshot: &str) -> Result<bool>;
+}
+
+// an alias with a local model file is looked up on the filesystem, skipping the huggingface cache
+pub fn find_model_file(hub_service: &dyn HubService, alias: &Alias) -> Result<Option<PathBuf>> {
+  match &alias.model_file {
+    Some(model_file) => Ok(model_file.is_file().then(|| model_file.clone())),
+    None => Ok(
+      hub_service
+        .find_local_file(&alias.repo, &alias.filename, &alias.snapshot)?
+        .map(|hub_file| hub_file.path()),
+    ),
+  }
+}
+
+// the huggingface cache stores a LFS file as a blob named by its sha256, a downloaded file
+// without one is only checked for a valid GGUF header
+fn verify_download(path: &Path) -> Result<()> {
+  let filename = path
+    .file_name()
+    .map(|filename| filename.to_string_lossy().into_owned())
+    .unwrap_or_default();
+  let io_error = |source| HubServiceError::IoError {
+    filename: filename.clone(),
+    source,
+  };
+  let blob = fs::canonicalize(path).map_err(io_error)?;
+  let expected = blob
+    .file_name()
+    .map(|blob| blob.to_string_lossy().into_owned())
+    .unwrap_or_default();
+  if expected.len() == 64 && expected.chars().all(|c| c.is_ascii_hexdigit()) {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(&blob).map_err(io_error)?, &mut hasher).map_err(io_error)?;
+    let actual = format!("{:x}", hasher.finalize());
+    if !actual.eq_ignore_ascii_case(&expected) {
+      return Err(HubServiceError::ChecksumMismatch {
+        filename,
+        expected,
+        actual,
+      });
+    }
+  } else if filename.ends_with(".gguf") {
+    let file = File::open(&blob).map_err(io_error)?;
+    if let Err(source) = GgufReader::from_reader(BufReader::new(file)) {
+      return Err(HubServiceError::InvalidGguf { filename, source });
+    }
+  }
+  Ok(())
 }
 
 impl HfHubService {
@@ -83,7 +165,11 @@ impl HubService for HfHubService {
     let from_cache = hf_repo.get(filename);
     let path = match from_cache {
       Some(path) if !force => path,
-      Some(_) | None => self.download_sync(repo, filename)?,
+      Some(_) | None => {
+        let path = self.download_sync(repo, filename, force)?;
+        verify_download(&path)?;
+        path
+      }
     };
     let result = HubFile::try_from(path)?;
     Ok(result)
@@ -183,6 +269,31 @@ impl HubService for HfHubService {
       .join(snapshot)
       .join(filename)
   }
+
+  fn delete_local_file(&self, repo: &Repo, filename: &str, snapshot: &str) -> Result<bool> {
+    let Some(hub_file) = self.find_local_file(repo, filename, snapshot)? else {
+      return Ok(false);
+    };
+    let path = hub_file.path();
+    let io_error = |source| HubServiceError::IoError {
+      filename: filename.to_string(),
+      source,
+    };
+    let blob = fs::canonicalize(&path).map_err(io_error)?;
+    fs::remove_file(&path).map_err(io_error)?;
+    if blob == path {
+      return Ok(true);
+    }
+    let snapshots_dir = self.hf_cache().join(repo.path()).join("snapshots");
+    let linked = WalkDir::new(snapshots_dir)
+      .into_iter()
+      .filter_map(|entry| entry.ok())
+      .any(|entry| fs::canonicalize(entry.path()).ok().as_ref() == Some(&blob));
+    if !linked {
+      fs::remove_file(&blob).map_err(io_error)?;
+    }
+    Ok(true)
+  }
 }
 
 #[derive(Clone)]
@@ -239,15 +350,16 @@ impl HfHubService {
     self.progress_bar = progress_bar;
   }
 
-  fn download_sync(&self, repo: &str, filename: &str) -> Result<PathBuf> {
-    use hf_hub::api::sync::{ApiBuilder, ApiError};
-
-    let api = ApiBuilder::from_cache(self.cache.clone())
-      .with_progress(self.progress_bar)
-      .with_token(self.token.clone())
-      .build()?;
+  fn download_sync(&self, repo: &str, filename: &str, force: bool) -> Result<PathBuf> {
     tracing::info!("Downloading from repo {repo}, file {filename}:");
-    let path = match api.model(repo.to_string()).download(filename) {
+    let path = match download_resumable(
+      &self.cache,
+      self.token.as_deref(),
+      repo,
+      filename,
+      self.progress_bar,
+      force,
+    ) {
       Ok(path) => path,
       Err(err) => {
         let err = match err {
@@ -277,15 +389,15 @@ impl HfHubService {
 
 #[cfg(test)]
 mod test {
-  use super::{HfHubService, HubService};
+  use super::{find_model_file, verify_download, HfHubService, HubService, MockHubService};
   use crate::{
-    objs::{HubFile, Repo, REFS_MAIN},
+    objs::{Alias, HubFile, Repo, REFS_MAIN},
     test_utils::{
       hf_test_token_allowed, hf_test_token_public, hub_service, temp_hf_home, HubServiceTuple,
     },
   };
   use rstest::rstest;
-  use std::fs;
+  use std::{fs, path::PathBuf};
   use tempfile::TempDir;
 
   #[rstest]
@@ -389,6 +501,24 @@ Go to https://huggingface.co/amir36/test-gated-repo to request access to the mod
     Ok(())
   }
 
+  #[rstest]
+  fn test_hf_hub_service_delete_local_file(hub_service: HubServiceTuple) -> anyhow::Result<()> {
+    let HubServiceTuple(_temp, _, service) = hub_service;
+    let repo = Repo::try_from("meta-llama/Llama-2-70b-chat-hf")?;
+    let filename = "tokenizer_config.json";
+    let snapshot = "9ff8b00464fc439a64bb374769dec3dd627be1c2";
+    assert!(service.delete_local_file(&repo, filename, snapshot)?);
+    assert!(service
+      .find_local_file(&repo, filename, snapshot)?
+      .is_none());
+    assert!(!service.delete_local_file(&repo, filename, snapshot)?);
+    let other = service
+      .find_local_file(&repo, filename, "e9149a12809580e8602995856f8098ce973d1080")?
+      .unwrap();
+    assert_eq!("this is version 2\n", fs::read_to_string(other.path())?);
+    Ok(())
+  }
+
   #[rstest]
   fn test_hf_hub_service_find_local_model_not_present(
     hub_service: HubServiceTuple,
@@ -472,4 +602,79 @@ Go to https://huggingface.co/amir36/not-exists to request access, login via CLI,
     assert_eq!(&expected_1, models.first().unwrap());
     Ok(())
   }
+
+  #[rstest]
+  #[case("tests/data/tinyllama-15m-q8_0.gguf", true)]
+  #[case("tests/data/not-exists.gguf", false)]
+  fn test_find_model_file_for_local_model_file_skips_hf_cache(
+    #[case] model_file: &str,
+    #[case] found: bool,
+  ) -> anyhow::Result<()> {
+    let mut mock = MockHubService::new();
+    mock.expect_find_local_file().never();
+    let alias = Alias {
+      model_file: Some(model_file.into()),
+      ..Alias::default()
+    };
+    let result = find_model_file(&mock, &alias)?;
+    assert_eq!(found.then(|| model_file.into()), result);
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_find_model_file_from_hf_cache(hub_service: HubServiceTuple) -> anyhow::Result<()> {
+    let HubServiceTuple(_temp_hf_home, hf_cache, service) = hub_service;
+    let result = find_model_file(&service, &Alias::testalias())?;
+    assert_eq!(
+      Some(
+        HubFile::testalias_builder()
+          .hf_cache(hf_cache)
+          .build()?
+          .path()
+      ),
+      result
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_verify_download_checks_sha256_of_lfs_blob() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let blob = temp_dir
+      .path()
+      .join("c22e92d054f01229fa949d956e8ba4ec09c626e8fb70c576f3fdd63e2b683239");
+    fs::write(&blob, "this is not a gguf")?;
+    let result = verify_download(&blob);
+    assert!(result.is_err());
+    assert_eq!(
+      r#"downloaded file 'c22e92d054f01229fa949d956e8ba4ec09c626e8fb70c576f3fdd63e2b683239' is corrupt, expected sha256 'c22e92d054f01229fa949d956e8ba4ec09c626e8fb70c576f3fdd63e2b683239', found '0a49c44bb751ebf82f9069f4bb488b64c56add03ab827bb323c85ec6914438a3'.
+The download may have been interrupted, run the pull again with --force to download the file again."#,
+      result.unwrap_err().to_string()
+    );
+    let blob = temp_dir
+      .path()
+      .join("0a49c44bb751ebf82f9069f4bb488b64c56add03ab827bb323c85ec6914438a3");
+    fs::write(&blob, "this is not a gguf")?;
+    verify_download(&blob)?;
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_verify_download_checks_gguf_header_without_sha256() -> anyhow::Result<()> {
+    verify_download(&PathBuf::from("tests/data/tinyllama-15m-q8_0.gguf"))?;
+    let temp_dir = tempfile::tempdir()?;
+    let model_file = temp_dir.path().join("testalias.Q8_0.gguf");
+    fs::write(&model_file, "this is not a gguf")?;
+    let result = verify_download(&model_file);
+    assert!(result.is_err());
+    assert_eq!(
+      r#"downloaded file 'testalias.Q8_0.gguf' is not a valid GGUF file: gguf_invalid_magic: not a GGUF file, found magic bytes 0x73696874
+The download may have been interrupted, run the pull again with --force to download the file again."#,
+      result.unwrap_err().to_string()
+    );
+    let tokenizer_config = temp_dir.path().join("tokenizer_config.json");
+    fs::write(&tokenizer_config, "{}")?;
+    verify_download(&tokenizer_config)?;
+    Ok(())
+  }
 }
@@ -0,0 +1,158 @@
+use crate::gguf::GgufReader;
+use std::{fs::File, io::BufReader, path::Path};
+
+// total and available memory of the system, the model weights are loaded in the system memory,
+// which is also the GPU memory on Apple silicon
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SystemMemory {
+  pub total_bytes: u64,
+  pub available_bytes: u64,
+}
+
+impl SystemMemory {
+  // the memory in use once the loaded model is unloaded, along with the model to load
+  pub fn projected_bytes(&self, loaded_bytes: u64, model_bytes: u64) -> u64 {
+    let in_use = self.total_bytes.saturating_sub(self.available_bytes);
+    in_use.saturating_sub(loaded_bytes) + model_bytes
+  }
+
+  pub fn watermark_bytes(&self, watermark: u8) -> u64 {
+    (self.total_bytes as u128 * watermark as u128 / 100) as u64
+  }
+}
+
+#[cfg_attr(test, mockall::automock)]
+pub trait MemoryServiceFn: std::fmt::Debug + Send + Sync {
+  // None on the platforms where the memory is not known
+  fn system_memory(&self) -> Option<SystemMemory>;
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MemoryService;
+
+impl MemoryServiceFn for MemoryService {
+  #[cfg(target_os = "linux")]
+  fn system_memory(&self) -> Option<SystemMemory> {
+    parse_meminfo(&std::fs::read_to_string("/proc/meminfo").ok()?)
+  }
+
+  #[cfg(target_os = "macos")]
+  fn system_memory(&self) -> Option<SystemMemory> {
+    let output = std::process::Command::new("sysctl")
+      .args(["-n", "hw.memsize"])
+      .output()
+      .ok()?;
+    let total_bytes = String::from_utf8_lossy(&output.stdout)
+      .trim()
+      .parse()
+      .ok()?;
+    let output = std::process::Command::new("vm_stat").output().ok()?;
+    parse_vm_stat(total_bytes, &String::from_utf8_lossy(&output.stdout))
+  }
+
+  #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+  fn system_memory(&self) -> Option<SystemMemory> {
+    None
+  }
+}
+
+// weights along with the KV cache for the context size, n_ctx of 0 takes the context length of
+// the model, and without n_ctx llama.cpp defaults to 512
+pub fn estimated_load_bytes(model_file: &Path, n_ctx: Option<i32>) -> Option<u64> {
+  let file = File::open(model_file).ok()?;
+  let reader = GgufReader::from_reader(BufReader::new(file)).ok()?;
+  let metadata = reader.metadata();
+  let n_ctx = match n_ctx {
+    Some(n_ctx) if n_ctx > 0 => Some(n_ctx as u64),
+    Some(_) => metadata.context_length(),
+    None => Some(DEFAULT_N_CTX),
+  };
+  let kv_cache_bytes = n_ctx
+    .and_then(|n_ctx| metadata.kv_cache_bytes(n_ctx))
+    .unwrap_or_default();
+  Some(reader.estimated_size_bytes()? + kv_cache_bytes)
+}
+
+const DEFAULT_N_CTX: u64 = 512;
+
+#[cfg(any(target_os = "linux", test))]
+fn parse_meminfo(meminfo: &str) -> Option<SystemMemory> {
+  let kb = |field: &str| {
+    meminfo
+      .lines()
+      .find_map(|line| line.strip_prefix(field))
+      .and_then(|value| {
+        value
+          .trim()
+          .trim_end_matches("kB")
+          .trim()
+          .parse::<u64>()
+          .ok()
+      })
+      .map(|kb| kb * 1024)
+  };
+  Some(SystemMemory {
+    total_bytes: kb("MemTotal:")?,
+    available_bytes: kb("MemAvailable:")?,
+  })
+}
+
+// the free, inactive and speculative pages can be reclaimed for a model load
+#[cfg(any(target_os = "macos", test))]
+fn parse_vm_stat(total_bytes: u64, vm_stat: &str) -> Option<SystemMemory> {
+  let page_size = vm_stat
+    .lines()
+    .next()?
+    .split("page size of ")
+    .nth(1)?
+    .split_whitespace()
+    .next()?
+    .parse::<u64>()
+    .ok()?;
+  let pages = |field: &str| {
+    vm_stat
+      .lines()
+      .find_map(|line| line.strip_prefix(field))
+      .and_then(|value| value.trim().trim_end_matches('.').parse::<u64>().ok())
+      .unwrap_or_default()
+  };
+  let available_pages =
+    pages("Pages free:") + pages("Pages inactive:") + pages("Pages speculative:");
+  Some(SystemMemory {
+    total_bytes,
+    available_bytes: available_pages * page_size,
+  })
+}
+
+#[cfg(test)]
+mod test {
+  use super::{parse_meminfo, parse_vm_stat, SystemMemory};
+  use rstest::rstest;
+
+  #[rstest]
+  fn test_memory_service_parses_meminfo() -> anyhow::Result<()> {
+    let meminfo =
+      "MemTotal:       16318472 kB\nMemFree:         1024000 kB\nMemAvailable:    8159236 kB\n";
+    assert_eq!(
+      Some(SystemMemory {
+        total_bytes: 16318472 * 1024,
+        available_bytes: 8159236 * 1024,
+      }),
+      parse_meminfo(meminfo)
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_memory_service_parses_vm_stat() -> anyhow::Result<()> {
+    let vm_stat = "Mach Virtual Memory Statistics: (page size of 16384 bytes)\nPages free:                                3000.\nPages active:                             50000.\nPages inactive:                            2000.\nPages speculative:                         1000.\n";
+    assert_eq!(
+      Some(SystemMemory {
+        total_bytes: 1 << 34,
+        available_bytes: 6000 * 16384,
+      }),
+      parse_vm_stat(1 << 34, vm_stat)
+    );
+    Ok(())
+  }
+}
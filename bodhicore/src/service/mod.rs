@@ -1,10 +1,15 @@
 mod app_service;
 mod data_service;
 pub mod env_wrapper;
+mod hub_download;
 mod hub_service;
+mod memory_service;
+mod settings_watcher;
 mod env_service;
 
 pub use app_service::*;
 pub use data_service::*;
 pub use hub_service::*;
+pub use memory_service::*;
+pub use settings_watcher::*;
 pub use env_service::*;
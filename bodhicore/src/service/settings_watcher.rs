@@ -0,0 +1,136 @@
+use super::{EnvService, EnvServiceFn};
+use std::{fs, sync::Arc, time::Duration, time::SystemTime};
+use tokio::sync::watch;
+
+// the settings applied without a restart, the consumers subscribe to the changes
+#[derive(Debug, Clone, PartialEq)]
+pub struct HotSettings {
+  pub log_level: String,
+  pub keep_alive_secs: u64,
+}
+
+impl HotSettings {
+  pub fn from_env(env_service: &dyn EnvServiceFn) -> Self {
+    Self {
+      log_level: env_service.log_level(),
+      keep_alive_secs: env_service.keep_alive_secs(),
+    }
+  }
+}
+
+type Fingerprint = Vec<Option<(SystemTime, u64)>>;
+
+// polls the settings files for changes, an editor saving a file, or a deploy tool writing the
+// overlays one after the other, fires a burst of changes, so the settings are reloaded only once
+// the files are unchanged for a full interval
+#[derive(Debug)]
+pub struct SettingsWatcher {
+  env_service: Arc<EnvService>,
+  interval: Duration,
+  tx: watch::Sender<HotSettings>,
+  // the files as loaded, the changes made before watch starts are reloaded too
+  loaded: Fingerprint,
+}
+
+impl SettingsWatcher {
+  pub fn new(env_service: Arc<EnvService>, interval: Duration) -> Self {
+    let (tx, _) = watch::channel(HotSettings::from_env(env_service.as_ref()));
+    let loaded = fingerprint(&env_service);
+    Self {
+      env_service,
+      interval,
+      tx,
+      loaded,
+    }
+  }
+
+  pub fn subscribe(&self) -> watch::Receiver<HotSettings> {
+    self.tx.subscribe()
+  }
+
+  pub async fn watch(self) {
+    let mut current = self.loaded.clone();
+    let mut pending = None;
+    loop {
+      tokio::time::sleep(self.interval).await;
+      let latest = fingerprint(&self.env_service);
+      if latest == current {
+        pending = None;
+        continue;
+      }
+      if pending.as_ref() != Some(&latest) {
+        pending = Some(latest);
+        continue;
+      }
+      current = latest;
+      pending = None;
+      self.reload();
+    }
+  }
+
+  fn reload(&self) {
+    let changed = self.env_service.reload_settings();
+    if changed.is_empty() {
+      return;
+    }
+    tracing::info!(?changed, "settings reloaded");
+    let settings = HotSettings::from_env(self.env_service.as_ref());
+    self.tx.send_if_modified(|current| {
+      if *current == settings {
+        return false;
+      }
+      *current = settings;
+      true
+    });
+  }
+}
+
+fn fingerprint(env_service: &EnvService) -> Fingerprint {
+  env_service
+    .settings_files()
+    .iter()
+    .map(|file| {
+      let metadata = fs::metadata(file).ok()?;
+      Some((metadata.modified().ok()?, metadata.len()))
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+  use super::{HotSettings, SettingsWatcher};
+  use crate::{
+    service::{EnvService, DEFAULT_KEEP_ALIVE_SECS},
+    test_utils::MockEnvWrapper,
+  };
+  use rstest::rstest;
+  use std::{env::VarError, fs, sync::Arc, time::Duration};
+
+  #[rstest]
+  #[tokio::test]
+  async fn test_settings_watcher_publishes_reloaded_settings() -> anyhow::Result<()> {
+    let tempdir = tempfile::tempdir()?;
+    let bodhi_home = tempdir.path().to_path_buf();
+    fs::write(bodhi_home.join("settings.yaml"), "BODHI_PORT: 8080")?;
+    let mut mock = MockEnvWrapper::default();
+    mock.expect_var().returning(|_| Err(VarError::NotPresent));
+    let env_service = EnvService::new_with_args(mock, bodhi_home.clone(), bodhi_home.clone());
+    let watcher = SettingsWatcher::new(Arc::new(env_service), Duration::from_millis(10));
+    let mut settings = watcher.subscribe();
+    assert_eq!(DEFAULT_KEEP_ALIVE_SECS, settings.borrow().keep_alive_secs);
+    tokio::spawn(watcher.watch());
+    fs::write(
+      bodhi_home.join("settings.yaml"),
+      "BODHI_KEEP_ALIVE_SECS: 120\nBODHI_LOG_LEVEL: debug\n",
+    )?;
+    tokio::time::timeout(Duration::from_secs(5), settings.changed()).await??;
+    assert_eq!(
+      HotSettings {
+        log_level: "debug".to_string(),
+        keep_alive_secs: 120,
+      },
+      *settings.borrow()
+    );
+    Ok(())
+  }
+}
@@ -5,23 +5,28 @@ use crate::test_utils::MockBodhiServerContext as BodhiServerContext;
 
 use validator::{Validate, ValidationErrors};
 use crate::error::Common;
+use crate::oai::{logit_bias_pairs, BodhiChatRequest, OutputConstraint, ResponseFormat};
 use crate::objs::{Alias, HubFile, ObjError};
 use crate::service::DataServiceError;
 use tokio::sync::mpsc::Sender;
+use crate::slot_affinity::SlotAffinity;
 use crate::tokenizer_config::TokenizerConfig;
-use async_openai::types::CreateChatCompletionRequest;
+use crate::tool_calls::ChatTools;
 use llama_server_bindings::{LlamaCppError, GptParams, GptParamsBuilder, GptParamsBuilderError};
 use std::ffi::{c_char, c_void};
+use std::path::PathBuf;
 use std::slice;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use thiserror::Error;
 use tokio::sync::RwLock;
 
 #[derive(Debug)]
 pub struct SharedContextRw {
   ctx: RwLock<Option<BodhiServerContext>>,
+  slots: Mutex<SlotAffinity>,
+  last_used: Mutex<Instant>,
 }
 
 #[derive(Debug, Error)]
@@ -84,11 +89,15 @@ pub trait SharedContextRwFn: std::fmt::Debug + Send + Sync {
 
   async fn get_gpt_params(&self) -> Result<Option<GptParams>>;
 
+  // unloads the model if no request used it for the keep_alive duration,
+  // returns true if the model was unloaded
+  async fn unload_if_idle(&self, keep_alive: Duration) -> Result<bool>;
+
   async fn chat_completions(
     &self,
-    mut request: CreateChatCompletionRequest,
+    request: BodhiChatRequest,
     alias: Alias,
-    model_file: HubFile,
+    model_file: PathBuf,
     tokenizer_file: HubFile,
     userdata: Sender<String>,
   ) -> Result<()>;
@@ -101,6 +110,8 @@ impl SharedContextRw {
   {
     let ctx = SharedContextRw {
       ctx: RwLock::new(None),
+      slots: Mutex::new(SlotAffinity::default()),
+      last_used: Mutex::new(Instant::now()),
     };
     ctx.reload(gpt_params).await?;
     Ok(ctx)
@@ -149,27 +160,102 @@ impl SharedContextRwFn for SharedContextRw {
     }
   }
 
+  async fn unload_if_idle(&self, keep_alive: Duration) -> crate::shared_rw::Result<bool> {
+    // a request in flight holds the read lock, the model is in use
+    let Ok(mut lock) = self.ctx.try_write() else {
+      return Ok(false);
+    };
+    if lock.is_none() {
+      return Ok(false);
+    }
+    let idle = self
+      .last_used
+      .lock()
+      .map_err(|err| ContextError::Unreachable(err.to_string()))?
+      .elapsed();
+    if idle < keep_alive {
+      return Ok(false);
+    }
+    try_stop_with(&mut lock)?;
+    Ok(true)
+  }
+
   async fn chat_completions(
     &self,
-    mut request: CreateChatCompletionRequest,
+    request: BodhiChatRequest,
     alias: Alias,
-    model_file: HubFile,
+    model_file: PathBuf,
     tokenizer_file: HubFile,
     userdata: Sender<String>,
   ) -> crate::shared_rw::Result<()> {
+    let _in_use = LastUsedGuard::new(&self.last_used);
+    let BodhiChatRequest {
+      mut request,
+      prompt_cache_key,
+      stream_options: _,
+      mut sampling_params,
+      profile,
+      keep_alive_secs: _,
+      metadata: _,
+      prompt,
+      response_format,
+    } = request;
     let lock = self.ctx.read().await;
     let ctx = lock.as_ref();
-    let loaded_model = ctx.map(|ctx| ctx.get_gpt_params().model.clone());
-    let request_model = model_file.path().display().to_string();
-    let chat_template: TokenizerConfig = TokenizerConfig::try_from(tokenizer_file)?;
-    chat_template.validate()?;
+    let loaded_params = ctx.map(|ctx| ctx.get_gpt_params());
+    let loaded_model = loaded_params.as_ref().map(|params| params.model.clone());
+    let request_model = model_file.display().to_string();
+    let strategy = ModelLoadStrategy::choose(&loaded_model, &request_model);
+    if let Some(profile) = profile {
+      profile.update(&mut request, &mut sampling_params);
+    }
     alias.request_params.update(&mut request);
-    let prompt = chat_template.apply_chat_template(&request.messages)?;
+    alias.merge_stop_tokens(&mut request);
+    let constraint = response_format.as_ref().and_then(ResponseFormat::constraint);
+    let prompt = match prompt {
+      Some(prompt) => prompt,
+      None => {
+        let chat_template: TokenizerConfig = TokenizerConfig::try_from(tokenizer_file)?;
+        chat_template.validate()?;
+        let tools = ChatTools::from_request(&request);
+        let instructions = match &constraint {
+          Some(OutputConstraint::Instructions(instructions)) => Some(instructions.as_str()),
+          _ => None,
+        };
+        chat_template.apply_chat_template_with_tools(&request.messages, tools.as_ref(), instructions)?
+      }
+    };
     let mut input_value = serde_json::to_value(request).map_err(Common::SerdeJsonDeserialize)?;
     input_value["prompt"] = serde_json::Value::String(prompt);
+    // the tools are in the prompt, and the calls are parsed from the generated text
+    if let Some(input) = input_value.as_object_mut() {
+      input.remove("tools");
+      input.remove("tool_choice");
+      input.remove("response_format");
+    }
+    if let Some(logit_bias) = input_value.get("logit_bias").and_then(|value| value.as_object()).map(logit_bias_pairs) {
+      input_value["logit_bias"] = logit_bias;
+    }
+    if let Some(OutputConstraint::Grammar(grammar)) = constraint {
+      input_value["grammar"] = serde_json::Value::String(grammar);
+    }
+    let sampling_params =
+      serde_json::to_value(sampling_params).map_err(Common::SerdeJsonDeserialize)?;
+    for (field, value) in sampling_params.as_object().into_iter().flatten() {
+      input_value[field] = value.clone();
+    }
+    if let Some(prompt_cache_key) = prompt_cache_key {
+      let n_slots = match strategy {
+        ModelLoadStrategy::Continue => loaded_params.and_then(|params| params.n_parallel),
+        ModelLoadStrategy::DropAndLoad | ModelLoadStrategy::Load => alias.context_params.n_parallel,
+      };
+      let id_slot = self.slot_for(&prompt_cache_key, n_slots.unwrap_or(1), &strategy)?;
+      input_value["cache_prompt"] = serde_json::Value::Bool(true);
+      input_value["id_slot"] = serde_json::Value::from(id_slot);
+    }
     let input = serde_json::to_string(&input_value).map_err(Common::SerdeJsonDeserialize)?;
     let callback_userdata = (userdata, Arc::new(AtomicBool::new(true)));
-    match ModelLoadStrategy::choose(&loaded_model, &request_model) {
+    match strategy {
       ModelLoadStrategy::Continue => {
         ctx
           .ok_or_else(||ContextError::Unreachable(
@@ -210,6 +296,44 @@ impl SharedContextRwFn for SharedContextRw {
   }
 }
 
+impl SharedContextRw {
+  // a model reload starts with empty slots, so the keys assigned till now are forgotten
+  fn slot_for(&self, key: &str, n_slots: i32, strategy: &ModelLoadStrategy) -> Result<i32> {
+    let mut slots = self
+      .slots
+      .lock()
+      .map_err(|err| ContextError::Unreachable(err.to_string()))?;
+    if strategy != &ModelLoadStrategy::Continue {
+      slots.clear();
+    }
+    Ok(slots.slot_for(key, n_slots))
+  }
+}
+
+// marks the model as used when the request starts and again when it completes,
+// the idle time for keep alive is counted from the end of the last request
+struct LastUsedGuard<'a>(&'a Mutex<Instant>);
+
+impl<'a> LastUsedGuard<'a> {
+  fn new(last_used: &'a Mutex<Instant>) -> Self {
+    let guard = Self(last_used);
+    guard.touch();
+    guard
+  }
+
+  fn touch(&self) {
+    if let Ok(mut last_used) = self.0.lock() {
+      *last_used = Instant::now();
+    }
+  }
+}
+
+impl Drop for LastUsedGuard<'_> {
+  fn drop(&mut self) {
+    self.touch();
+  }
+}
+
 fn try_stop_with(
   lock: &mut tokio::sync::RwLockWriteGuard<'_, Option<BodhiServerContext>>,
 ) -> Result<()> {
@@ -247,22 +371,24 @@ impl ModelLoadStrategy {
 #[cfg(test)]
 mod test {
   use crate::{
+    oai::BodhiChatRequest,
     objs::{Alias, HubFile},
     shared_rw::{ModelLoadStrategy, SharedContextRw, SharedContextRwFn},
     test_utils::{hf_cache, test_channel, MockBodhiServerContext},
   };
   use anyhow::anyhow;
   use anyhow_trace::anyhow_trace;
-  use async_openai::types::{CreateChatCompletionRequest, CreateChatCompletionResponse};
+  use async_openai::types::CreateChatCompletionResponse;
   use llama_server_bindings::{
     bindings::llama_server_disable_logging, disable_llama_log, GptParams, GptParamsBuilder,
   };
-  use mockall::predicate::{always, eq};
+  use mockall::predicate::{always, eq, function};
   use rstest::{fixture, rstest};
   use serde_json::json;
   use std::{
     ffi::{c_char, c_void},
     path::PathBuf, slice,
+    time::Duration,
   };
   use tempfile::TempDir;
   use serial_test::serial;
@@ -472,13 +598,217 @@ mod test {
     ctx.expect().with(eq(gpt_params.clone())).return_once(move |_| Ok(mock));
 
     let shared_ctx = SharedContextRw::new_shared_rw(Some(gpt_params)).await?;
-    let request = serde_json::from_value::<CreateChatCompletionRequest>(json! {{
+    let request = serde_json::from_value::<BodhiChatRequest>(json! {{
       "model": "testalias:instruct",
       "messages": [{"role": "user", "content": "What day comes after Monday?"}]
     }})?;
     let (tx, _rx) = test_channel();
     shared_ctx
-      .chat_completions(request, Alias::testalias(), model_file, tokenizer_file, tx)
+      .chat_completions(request, Alias::testalias(), model_file.path(), tokenizer_file, tx)
+      .await?;
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[serial(BodhiServerContext)]
+  #[anyhow_trace]
+  async fn test_chat_completions_raw_prompt_skips_chat_template(
+    hf_cache: (TempDir, PathBuf),
+  ) -> anyhow::Result<()> {
+    let (_temp, hf_cache) = hf_cache;
+    let model_file = HubFile::testalias_builder()
+      .hf_cache(hf_cache.clone())
+      .build()
+      .unwrap();
+    let model_filepath = model_file.path().display().to_string();
+    let tokenizer_file = HubFile::testalias_tokenizer_builder()
+      .hf_cache(hf_cache.clone())
+      .build()
+      .unwrap();
+    let mut mock = MockBodhiServerContext::default();
+    let expected_input =
+      "{\"messages\":[],\"model\":\"testalias:instruct\",\"prompt\":\"Monday, Tuesday,\"}";
+    mock.expect_init().with().return_once(|| Ok(()));
+    mock.expect_start_event_loop().with().return_once(|| Ok(()));
+    mock
+      .expect_completions()
+      .with(eq(expected_input), eq(""), always(), always())
+      .return_once(|_, _, _, _| Ok(()));
+    let gpt_params = GptParamsBuilder::default().model(model_filepath).build()?;
+    let gpt_params_cl = gpt_params.clone();
+    mock.expect_get_gpt_params().return_once(move || gpt_params_cl);
+
+    let ctx = MockBodhiServerContext::new_context();
+    ctx.expect().with(eq(gpt_params.clone())).return_once(move |_| Ok(mock));
+
+    let shared_ctx = SharedContextRw::new_shared_rw(Some(gpt_params)).await?;
+    let mut request = serde_json::from_value::<BodhiChatRequest>(json! {{
+      "model": "testalias:instruct",
+      "messages": []
+    }})?;
+    request.prompt = Some("Monday, Tuesday,".to_string());
+    let (tx, _rx) = test_channel();
+    shared_ctx
+      .chat_completions(request, Alias::testalias(), model_file.path(), tokenizer_file, tx)
+      .await?;
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[serial(BodhiServerContext)]
+  #[anyhow_trace]
+  async fn test_chat_completions_forwards_response_format_as_grammar(
+    hf_cache: (TempDir, PathBuf),
+  ) -> anyhow::Result<()> {
+    let (_temp, hf_cache) = hf_cache;
+    let model_file = HubFile::testalias_builder()
+      .hf_cache(hf_cache.clone())
+      .build()
+      .unwrap();
+    let model_filepath = model_file.path().display().to_string();
+    let tokenizer_file = HubFile::testalias_tokenizer_builder()
+      .hf_cache(hf_cache.clone())
+      .build()
+      .unwrap();
+    let mut mock = MockBodhiServerContext::default();
+    mock.expect_init().with().return_once(|| Ok(()));
+    mock.expect_start_event_loop().with().return_once(|| Ok(()));
+    mock
+      .expect_completions()
+      .with(
+        function(|input: &str| {
+          let input = serde_json::from_str::<serde_json::Value>(input).unwrap();
+          input.get("response_format").is_none()
+            && input["grammar"]
+              .as_str()
+              .is_some_and(|grammar| grammar.starts_with("root ::= object\n"))
+        }),
+        eq(""),
+        always(),
+        always(),
+      )
+      .return_once(|_, _, _, _| Ok(()));
+    let gpt_params = GptParamsBuilder::default().model(model_filepath).build()?;
+    let gpt_params_cl = gpt_params.clone();
+    mock.expect_get_gpt_params().return_once(move || gpt_params_cl);
+
+    let ctx = MockBodhiServerContext::new_context();
+    ctx.expect().with(eq(gpt_params.clone())).return_once(move |_| Ok(mock));
+
+    let shared_ctx = SharedContextRw::new_shared_rw(Some(gpt_params)).await?;
+    let request = serde_json::from_value::<BodhiChatRequest>(json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "List the weekdays as json"}],
+      "response_format": {"type": "json_object"},
+    }})?;
+    let (tx, _rx) = test_channel();
+    shared_ctx
+      .chat_completions(request, Alias::testalias(), model_file.path(), tokenizer_file, tx)
+      .await?;
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[serial(BodhiServerContext)]
+  #[anyhow_trace]
+  async fn test_chat_completions_forwards_sampling_params(
+    hf_cache: (TempDir, PathBuf),
+  ) -> anyhow::Result<()> {
+    let (_temp, hf_cache) = hf_cache;
+    let model_file = HubFile::testalias_builder()
+      .hf_cache(hf_cache.clone())
+      .build()
+      .unwrap();
+    let model_filepath = model_file.path().display().to_string();
+    let tokenizer_file = HubFile::testalias_tokenizer_builder()
+      .hf_cache(hf_cache.clone())
+      .build()
+      .unwrap();
+    let mut mock = MockBodhiServerContext::default();
+    let expected_input =
+      "{\"messages\":[],\"min_p\":0.5,\"model\":\"testalias:instruct\",\"prompt\":\"Monday, Tuesday,\",\"repeat_penalty\":1.5,\"seed\":42,\"tfs_z\":1.0,\"top_k\":40}";
+    mock.expect_init().with().return_once(|| Ok(()));
+    mock.expect_start_event_loop().with().return_once(|| Ok(()));
+    mock
+      .expect_completions()
+      .with(eq(expected_input), eq(""), always(), always())
+      .return_once(|_, _, _, _| Ok(()));
+    let gpt_params = GptParamsBuilder::default().model(model_filepath).build()?;
+    let gpt_params_cl = gpt_params.clone();
+    mock.expect_get_gpt_params().return_once(move || gpt_params_cl);
+
+    let ctx = MockBodhiServerContext::new_context();
+    ctx.expect().with(eq(gpt_params.clone())).return_once(move |_| Ok(mock));
+
+    let shared_ctx = SharedContextRw::new_shared_rw(Some(gpt_params)).await?;
+    let mut request = serde_json::from_value::<BodhiChatRequest>(json! {{
+      "model": "testalias:instruct",
+      "messages": [],
+      "seed": 42,
+      "top_k": 40,
+      "min_p": 0.5,
+      "repeat_penalty": 1.5,
+      "tfs_z": 1.0,
+      "metadata": {"trace_id": "trace-1"},
+    }})?;
+    request.prompt = Some("Monday, Tuesday,".to_string());
+    let (tx, _rx) = test_channel();
+    shared_ctx
+      .chat_completions(request, Alias::testalias(), model_file.path(), tokenizer_file, tx)
+      .await?;
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[serial(BodhiServerContext)]
+  #[anyhow_trace]
+  async fn test_chat_completions_forwards_alias_stop_tokens_with_request_stop(
+    hf_cache: (TempDir, PathBuf),
+  ) -> anyhow::Result<()> {
+    let (_temp, hf_cache) = hf_cache;
+    let model_file = HubFile::testalias_builder()
+      .hf_cache(hf_cache.clone())
+      .build()
+      .unwrap();
+    let model_filepath = model_file.path().display().to_string();
+    let tokenizer_file = HubFile::testalias_tokenizer_builder()
+      .hf_cache(hf_cache.clone())
+      .build()
+      .unwrap();
+    let mut mock = MockBodhiServerContext::default();
+    let expected_input =
+      "{\"messages\":[],\"model\":\"testalias:instruct\",\"prompt\":\"Monday, Tuesday,\",\"stop\":[\"Sunday\",\"<|im_end|>\"]}";
+    mock.expect_init().with().return_once(|| Ok(()));
+    mock.expect_start_event_loop().with().return_once(|| Ok(()));
+    mock
+      .expect_completions()
+      .with(eq(expected_input), eq(""), always(), always())
+      .return_once(|_, _, _, _| Ok(()));
+    let gpt_params = GptParamsBuilder::default().model(model_filepath).build()?;
+    let gpt_params_cl = gpt_params.clone();
+    mock.expect_get_gpt_params().return_once(move || gpt_params_cl);
+
+    let ctx = MockBodhiServerContext::new_context();
+    ctx.expect().with(eq(gpt_params.clone())).return_once(move |_| Ok(mock));
+
+    let shared_ctx = SharedContextRw::new_shared_rw(Some(gpt_params)).await?;
+    let mut request = serde_json::from_value::<BodhiChatRequest>(json! {{
+      "model": "testalias:instruct",
+      "messages": [],
+      "stop": "Sunday",
+    }})?;
+    request.prompt = Some("Monday, Tuesday,".to_string());
+    let alias = Alias {
+      stop_tokens: vec!["<|im_end|>".to_string()],
+      ..Alias::testalias()
+    };
+    let (tx, _rx) = test_channel();
+    shared_ctx
+      .chat_completions(request, alias, model_file.path(), tokenizer_file, tx)
       .await?;
     Ok(())
   }
@@ -514,13 +844,13 @@ mod test {
     ctx.expect().with(eq(GptParams{model: model_filepath, ..Default::default()})).return_once(move |_| Ok(mock));
 
     let shared_ctx = SharedContextRw::new_shared_rw(None).await?;
-    let request = serde_json::from_value::<CreateChatCompletionRequest>(json! {{
+    let request = serde_json::from_value::<BodhiChatRequest>(json! {{
       "model": "testalias:instruct",
       "messages": [{"role": "user", "content": "What day comes after Monday?"}]
     }})?;
     let (tx, _rx) = test_channel();
     shared_ctx
-      .chat_completions(request, Alias::testalias(), model_file, tokenizer_file, tx)
+      .chat_completions(request, Alias::testalias(), model_file.path(), tokenizer_file, tx)
       .await?;
     Ok(())
   }
@@ -572,13 +902,114 @@ mod test {
       .unwrap();
 
     let shared_ctx = SharedContextRw::new_shared_rw(Some(loaded_params)).await?;
-    let request = serde_json::from_value::<CreateChatCompletionRequest>(json! {{
+    let request = serde_json::from_value::<BodhiChatRequest>(json! {{
       "model": "fakemodel:instruct",
       "messages": [{"role": "user", "content": "What day comes after Monday?"}]
     }})?;
     let (tx, _rx) = test_channel();
     shared_ctx
-      .chat_completions(request, Alias::testalias(), loaded_model, tokenizer_file, tx)
+      .chat_completions(request, Alias::testalias(), loaded_model.path(), tokenizer_file, tx)
       .await?;
     Ok(())
-  }}
+  }
+  #[rstest]
+  #[tokio::test]
+  #[serial(BodhiServerContext)]
+  #[anyhow_trace]
+  async fn test_chat_completions_prompt_cache_key_pins_slot(
+    hf_cache: (TempDir, PathBuf),
+  ) -> anyhow::Result<()> {
+    let (_temp, hf_cache) = hf_cache;
+    let model_filepath = HubFile::testalias_builder()
+      .hf_cache(hf_cache.clone())
+      .build()?
+      .path()
+      .display()
+      .to_string();
+    let mut mock = MockBodhiServerContext::default();
+    mock.expect_init().with().return_once(|| Ok(()));
+    mock.expect_start_event_loop().with().return_once(|| Ok(()));
+    mock
+      .expect_completions()
+      .withf(|input, _, _, _| input.contains(r#""cache_prompt":true,"id_slot":0,"#))
+      .times(2)
+      .returning(|_, _, _, _| Ok(()));
+    mock
+      .expect_completions()
+      .withf(|input, _, _, _| input.contains(r#""cache_prompt":true,"id_slot":1,"#))
+      .times(1)
+      .returning(|_, _, _, _| Ok(()));
+    let gpt_params = GptParams {
+      n_parallel: Some(2),
+      ..GptParamsBuilder::default().model(model_filepath).build()?
+    };
+    let gpt_params_cl = gpt_params.clone();
+    mock.expect_get_gpt_params().returning(move || gpt_params_cl.clone());
+
+    let ctx = MockBodhiServerContext::new_context();
+    ctx.expect().with(eq(gpt_params.clone())).return_once(move |_| Ok(mock));
+
+    let shared_ctx = SharedContextRw::new_shared_rw(Some(gpt_params)).await?;
+    for key in ["session-1", "session-2", "session-1"] {
+      let request = serde_json::from_value::<BodhiChatRequest>(json! {{
+        "model": "testalias:instruct",
+        "prompt_cache_key": key,
+        "messages": [{"role": "user", "content": "What day comes after Monday?"}]
+      }})?;
+      let model_file = HubFile::testalias_builder()
+        .hf_cache(hf_cache.clone())
+        .build()?;
+      let tokenizer_file = HubFile::testalias_tokenizer_builder()
+        .hf_cache(hf_cache.clone())
+        .build()?;
+      let (tx, _rx) = test_channel();
+      shared_ctx
+        .chat_completions(request, Alias::testalias(), model_file.path(), tokenizer_file, tx)
+        .await?;
+    }
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(Duration::ZERO, true)]
+  #[case(Duration::from_secs(300), false)]
+  #[tokio::test]
+  #[serial(BodhiServerContext)]
+  async fn test_unload_if_idle(
+    #[case] keep_alive: Duration,
+    #[case] unloaded: bool,
+  ) -> anyhow::Result<()> {
+    let mut mock = MockBodhiServerContext::default();
+    mock.expect_init().with().return_once(|| Ok(()));
+    mock.expect_start_event_loop().with().return_once(|| Ok(()));
+    mock.expect_stop().times(unloaded as usize).returning(|| Ok(()));
+    let gpt_params = GptParamsBuilder::default().model("testalias.Q8_0.gguf").build()?;
+    let ctx = MockBodhiServerContext::new_context();
+    ctx.expect().with(eq(gpt_params.clone())).return_once(move |_| Ok(mock));
+
+    let shared_ctx = SharedContextRw::new_shared_rw(Some(gpt_params)).await?;
+    assert_eq!(unloaded, shared_ctx.unload_if_idle(keep_alive).await?);
+    assert_eq!(!unloaded, shared_ctx.has_model().await);
+    Ok(())
+  }
+
+  #[rstest]
+  #[tokio::test]
+  #[serial(BodhiServerContext)]
+  async fn test_unload_if_idle_skips_model_in_use() -> anyhow::Result<()> {
+    let mut mock = MockBodhiServerContext::default();
+    mock.expect_init().with().return_once(|| Ok(()));
+    mock.expect_start_event_loop().with().return_once(|| Ok(()));
+    mock.expect_stop().never();
+    let gpt_params = GptParamsBuilder::default().model("testalias.Q8_0.gguf").build()?;
+    let ctx = MockBodhiServerContext::new_context();
+    ctx.expect().with(eq(gpt_params.clone())).return_once(move |_| Ok(mock));
+
+    let shared_ctx = SharedContextRw::new_shared_rw(Some(gpt_params)).await?;
+    let in_flight = shared_ctx.ctx.read().await;
+    assert!(!shared_ctx.unload_if_idle(Duration::ZERO).await?);
+    drop(in_flight);
+    assert!(shared_ctx.has_model().await);
+    Ok(())
+  }
+}
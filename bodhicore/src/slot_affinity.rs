@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+// Maps the client provided prompt cache key to a llama.cpp slot, so requests sharing
+// a prompt prefix land on the slot that already has the prefix in its kv cache.
+// Keys are assigned to free slots first, and once all slots are taken, the least recently
+// used key gives up its slot.
+#[derive(Debug, Default)]
+pub(crate) struct SlotAffinity {
+  keys: HashMap<String, (i32, u64)>,
+  tick: u64,
+}
+
+impl SlotAffinity {
+  pub(crate) fn slot_for(&mut self, key: &str, n_slots: i32) -> i32 {
+    let n_slots = n_slots.max(1);
+    self.tick += 1;
+    if let Some((slot, last_used)) = self.keys.get_mut(key) {
+      *last_used = self.tick;
+      return *slot;
+    }
+    let slot = if (self.keys.len() as i32) < n_slots {
+      (0..n_slots)
+        .find(|slot| !self.keys.values().any(|(used, _)| used == slot))
+        .unwrap_or_default()
+    } else {
+      let lru_key = self
+        .keys
+        .iter()
+        .min_by_key(|(_, (_, last_used))| *last_used)
+        .map(|(key, _)| key.clone())
+        .unwrap_or_default();
+      self
+        .keys
+        .remove(&lru_key)
+        .map(|(slot, _)| slot)
+        .unwrap_or_default()
+    };
+    self.keys.insert(key.to_string(), (slot, self.tick));
+    slot
+  }
+
+  pub(crate) fn clear(&mut self) {
+    self.keys.clear();
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::SlotAffinity;
+  use rstest::rstest;
+
+  #[rstest]
+  fn test_slot_affinity_same_key_reuses_slot() {
+    let mut affinity = SlotAffinity::default();
+    let slot = affinity.slot_for("session-1", 4);
+    affinity.slot_for("session-2", 4);
+    assert_eq!(slot, affinity.slot_for("session-1", 4));
+  }
+
+  #[rstest]
+  fn test_slot_affinity_different_keys_get_different_slots() {
+    let mut affinity = SlotAffinity::default();
+    let slots = ["a", "b", "c", "d"]
+      .iter()
+      .map(|key| affinity.slot_for(key, 4))
+      .collect::<Vec<_>>();
+    assert_eq!(vec![0, 1, 2, 3], slots);
+  }
+
+  #[rstest]
+  fn test_slot_affinity_evicts_least_recently_used_key() {
+    let mut affinity = SlotAffinity::default();
+    assert_eq!(0, affinity.slot_for("a", 2));
+    assert_eq!(1, affinity.slot_for("b", 2));
+    assert_eq!(0, affinity.slot_for("a", 2));
+    assert_eq!(1, affinity.slot_for("c", 2));
+    assert_eq!(0, affinity.slot_for("a", 2));
+  }
+
+  #[rstest]
+  #[case(0)]
+  #[case(1)]
+  fn test_slot_affinity_single_slot(#[case] n_slots: i32) {
+    let mut affinity = SlotAffinity::default();
+    assert_eq!(0, affinity.slot_for("a", n_slots));
+    assert_eq!(0, affinity.slot_for("b", n_slots));
+  }
+
+  #[rstest]
+  fn test_slot_affinity_clear_releases_slots() {
+    let mut affinity = SlotAffinity::default();
+    affinity.slot_for("a", 2);
+    affinity.slot_for("b", 2);
+    affinity.clear();
+    assert_eq!(0, affinity.slot_for("b", 2));
+  }
+}
@@ -0,0 +1,122 @@
+use crate::gguf::{GgufValue, GGUF_MAGIC};
+use std::{fs, io, path::Path};
+
+// writes a GGUF file header, metadata and tensor infos in memory, for tests that need specific
+// metadata, the tensor data itself is not written
+pub struct GgufBytes {
+  version: u32,
+  kv: Vec<(String, GgufValue)>,
+  tensors: Vec<(String, Vec<u64>, u32, u64)>,
+}
+
+impl Default for GgufBytes {
+  fn default() -> Self {
+    Self {
+      version: 3,
+      kv: Vec::new(),
+      tensors: Vec::new(),
+    }
+  }
+}
+
+impl GgufBytes {
+  pub fn version(mut self, version: u32) -> Self {
+    self.version = version;
+    self
+  }
+
+  pub fn kv(mut self, key: &str, value: GgufValue) -> Self {
+    self.kv.push((key.to_string(), value));
+    self
+  }
+
+  pub fn tensor(mut self, name: &str, dimensions: &[u64], ggml_type: u32, offset: u64) -> Self {
+    self
+      .tensors
+      .push((name.to_string(), dimensions.to_vec(), ggml_type, offset));
+    self
+  }
+
+  pub fn build(&self) -> Vec<u8> {
+    let mut buf = Vec::new();
+    buf.extend(GGUF_MAGIC.to_le_bytes());
+    buf.extend(self.version.to_le_bytes());
+    buf.extend((self.tensors.len() as u64).to_le_bytes());
+    buf.extend((self.kv.len() as u64).to_le_bytes());
+    for (key, value) in &self.kv {
+      write_string(&mut buf, key);
+      buf.extend(value_type(value).to_le_bytes());
+      write_value(&mut buf, value);
+    }
+    for (name, dimensions, ggml_type, offset) in &self.tensors {
+      write_string(&mut buf, name);
+      buf.extend((dimensions.len() as u32).to_le_bytes());
+      for dimension in dimensions {
+        buf.extend(dimension.to_le_bytes());
+      }
+      buf.extend(ggml_type.to_le_bytes());
+      buf.extend(offset.to_le_bytes());
+    }
+    buf
+  }
+}
+
+// writes a GGUF file with an F32 tensor of each of the given shapes, no tensors writes the
+// smallest valid file, the header alone
+pub fn write_gguf(path: &Path, shapes: &[&[u64]]) -> io::Result<()> {
+  let mut gguf = GgufBytes::default();
+  let mut offset = 0;
+  for (i, shape) in shapes.iter().enumerate() {
+    gguf = gguf.tensor(&format!("weight.{i}"), shape, 0, offset);
+    offset += shape.iter().product::<u64>() * 4;
+  }
+  fs::write(path, gguf.build())
+}
+
+fn value_type(value: &GgufValue) -> u32 {
+  match value {
+    GgufValue::U8(_) => 0,
+    GgufValue::I8(_) => 1,
+    GgufValue::U16(_) => 2,
+    GgufValue::I16(_) => 3,
+    GgufValue::U32(_) => 4,
+    GgufValue::I32(_) => 5,
+    GgufValue::F32(_) => 6,
+    GgufValue::Bool(_) => 7,
+    GgufValue::String(_) => 8,
+    GgufValue::Array(_) => 9,
+    GgufValue::U64(_) => 10,
+    GgufValue::I64(_) => 11,
+    GgufValue::F64(_) => 12,
+  }
+}
+
+fn write_string(buf: &mut Vec<u8>, value: &str) {
+  buf.extend((value.len() as u64).to_le_bytes());
+  buf.extend(value.as_bytes());
+}
+
+fn write_value(buf: &mut Vec<u8>, value: &GgufValue) {
+  match value {
+    GgufValue::U8(value) => buf.extend(value.to_le_bytes()),
+    GgufValue::I8(value) => buf.extend(value.to_le_bytes()),
+    GgufValue::U16(value) => buf.extend(value.to_le_bytes()),
+    GgufValue::I16(value) => buf.extend(value.to_le_bytes()),
+    GgufValue::U32(value) => buf.extend(value.to_le_bytes()),
+    GgufValue::I32(value) => buf.extend(value.to_le_bytes()),
+    GgufValue::U64(value) => buf.extend(value.to_le_bytes()),
+    GgufValue::I64(value) => buf.extend(value.to_le_bytes()),
+    GgufValue::F32(value) => buf.extend(value.to_le_bytes()),
+    GgufValue::F64(value) => buf.extend(value.to_le_bytes()),
+    GgufValue::Bool(value) => buf.push(*value as u8),
+    GgufValue::String(value) => write_string(buf, value),
+    GgufValue::Array(items) => {
+      let item_type = items.first().map(value_type).unwrap_or(0);
+      buf.extend(item_type.to_le_bytes());
+      buf.extend((items.len() as u64).to_le_bytes());
+      for item in items {
+        write_value(buf, item);
+      }
+    }
+  }
+}
@@ -2,8 +2,8 @@ use super::SNAPSHOT;
 use crate::{
   cli::create::CreateCommandBuilder,
   objs::{
-    Alias, AliasBuilder, ChatTemplate, ChatTemplateId, GptContextParams, HubFile,
-    HubFileBuilder, OAIRequestParams, RemoteModel, Repo, TOKENIZER_CONFIG_JSON,
+    Alias, AliasBuilder, ChatTemplate, ChatTemplateId, GptContextParams, HubFile, HubFileBuilder,
+    OAIRequestParams, RemoteModel, Repo, TOKENIZER_CONFIG_JSON,
   },
   CreateCommand,
 };
@@ -129,9 +129,11 @@ impl CreateCommand {
       .alias("testalias:instruct".to_string())
       .repo(Repo::try_from("MyFactory/testalias-gguf").unwrap())
       .filename("testalias.Q8_0.gguf".to_string())
+      .model_file(None)
       .chat_template(ChatTemplate::Id(ChatTemplateId::Llama3))
       .family(Some("testalias".to_string()))
       .force(false)
+      .tags(vec![])
       .oai_request_params(OAIRequestParams::default())
       .context_params(GptContextParams::default())
       .to_owned()
@@ -1,7 +1,6 @@
-use crate::{objs::*, SharedContextRwFn};
-use async_openai::types::CreateChatCompletionRequest;
+use crate::{oai::BodhiChatRequest, objs::*, SharedContextRwFn};
 use llama_server_bindings::{Callback, GptParams};
-use std::ffi::c_void;
+use std::{ffi::c_void, path::PathBuf, time::Duration};
 use tokio::sync::mpsc::Sender;
 
 mockall::mock! {
@@ -29,11 +28,13 @@ mockall::mock! {
 
     async fn get_gpt_params(&self) -> crate::shared_rw::Result<Option<GptParams>>;
 
+    async fn unload_if_idle(&self, keep_alive: Duration) -> crate::shared_rw::Result<bool>;
+
     async fn chat_completions(
       &self,
-      mut request: CreateChatCompletionRequest,
+      request: BodhiChatRequest,
       alias: Alias,
-      model_file: HubFile,
+      model_file: PathBuf,
       tokenizer_file: HubFile,
       userdata: Sender<String>,
     ) -> crate::shared_rw::Result<()>;
@@ -1,5 +1,10 @@
-use crate::{db::DbServiceFn, server::RouterStateFn, service::AppServiceFn};
-use async_openai::types::CreateChatCompletionRequest;
+use crate::{
+  db::DbServiceFn,
+  oai::BodhiChatRequest,
+  objs::Alias,
+  server::{ActiveRequest, Eviction, LoadedModel, Metrics, RouterStateFn},
+  service::AppServiceFn,
+};
 use std::sync::Arc;
 use tokio::sync::mpsc::Sender;
 
@@ -13,9 +18,29 @@ mockall::mock! {
 
     fn db_service(&self) -> Arc<dyn DbServiceFn> ;
 
+    fn is_paused(&self) -> bool;
+
+    fn set_paused(&self, paused: bool);
+
+    fn active_requests(&self) -> Vec<ActiveRequest>;
+
+    fn uptime_secs(&self) -> i64;
+
+    fn shutdown(&self);
+
+    async fn loaded_model(&self) -> crate::oai::Result<Option<LoadedModel>>;
+
+    async fn unload_alias(&self, alias: &str) -> crate::oai::Result<bool>;
+
+    fn evictions(&self) -> Vec<Eviction>;
+
+    fn find_alias(&self, model: &str) -> Option<Alias>;
+
+    fn metrics(&self) -> Arc<Metrics>;
+
     async fn chat_completions(
       &self,
-      request: CreateChatCompletionRequest,
+      request: BodhiChatRequest,
       userdata: Sender<String>,
     ) -> crate::oai::Result<()>;
   }
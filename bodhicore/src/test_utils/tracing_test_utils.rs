@@ -1,4 +1,9 @@
-use tracing_subscriber::{fmt, EnvFilter};
+use std::{
+  io::{self, Write},
+  sync::{Arc, Mutex},
+};
+use tracing::{subscriber::DefaultGuard, Level};
+use tracing_subscriber::{fmt, fmt::MakeWriter, EnvFilter};
 
 #[allow(unused)]
 pub fn init_test_tracing() {
@@ -8,3 +13,49 @@ pub fn init_test_tracing() {
     .finish();
   let _ = tracing::subscriber::set_global_default(subscriber);
 }
+
+#[derive(Debug, Clone, Default)]
+pub struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+impl CapturedLogs {
+  pub fn contents(&self) -> String {
+    String::from_utf8_lossy(&self.0.lock().unwrap()).to_string()
+  }
+}
+
+impl Write for CapturedLogs {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.0.lock().unwrap().extend_from_slice(buf);
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+impl<'a> MakeWriter<'a> for CapturedLogs {
+  type Writer = CapturedLogs;
+
+  fn make_writer(&'a self) -> Self::Writer {
+    self.clone()
+  }
+}
+
+// captures logs at warn and above emitted on the current thread, till the guard is dropped
+#[allow(unused)]
+pub fn capture_warn_logs() -> (CapturedLogs, DefaultGuard) {
+  capture_logs(Level::WARN)
+}
+
+#[allow(unused)]
+pub fn capture_logs(level: Level) -> (CapturedLogs, DefaultGuard) {
+  let logs = CapturedLogs::default();
+  let subscriber = fmt::Subscriber::builder()
+    .with_max_level(level)
+    .with_ansi(false)
+    .with_writer(logs.clone())
+    .finish();
+  let guard = tracing::subscriber::set_default(subscriber);
+  (logs, guard)
+}
@@ -8,10 +8,14 @@ use serde::{
   de::{self, MapAccess, Visitor},
   Deserialize, Deserializer, Serialize,
 };
+use serde_json::{json, Value};
 use std::{fmt, ops::Deref};
 use validator::{Validate, ValidationError};
 
-use crate::objs::{validation_errors, HubFile, ObjError};
+use crate::{
+  objs::{validation_errors, HubFile, ObjError},
+  tool_calls::ChatTools,
+};
 
 pub fn raise_exception(err_text: String) -> Result<String, minijinja::Error> {
   Err(minijinja::Error::new(ErrorKind::SyntaxError, err_text))
@@ -21,6 +25,10 @@ pub fn raise_exception(err_text: String) -> Result<String, minijinja::Error> {
 pub struct ChatMessage {
   role: Option<String>,
   content: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  tool_calls: Option<Vec<Value>>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  tool_call_id: Option<String>,
 }
 
 impl<'a> From<&'a ChatMessage> for ChatMessage {
@@ -31,6 +39,8 @@ impl<'a> From<&'a ChatMessage> for ChatMessage {
 
 impl<'a> From<&'a ChatCompletionRequestMessage> for ChatMessage {
   fn from(value: &'a ChatCompletionRequestMessage) -> Self {
+    let mut tool_calls = None;
+    let mut tool_call_id = None;
     let (role, content) = match value {
       ChatCompletionRequestMessage::System(m) => (m.role.to_string(), Some(m.content.clone())),
       ChatCompletionRequestMessage::User(m) => match &m.content {
@@ -50,13 +60,35 @@ impl<'a> From<&'a ChatCompletionRequestMessage> for ChatMessage {
           (m.role.to_string().clone(), Some(fold))
         }
       },
-      ChatCompletionRequestMessage::Assistant(m) => (m.role.to_string().clone(), m.content.clone()),
-      ChatCompletionRequestMessage::Tool(_) => unimplemented!(),
+      ChatCompletionRequestMessage::Assistant(m) => {
+        // the chat templates expect the arguments of the calls as an object, not as json text
+        tool_calls = m.tool_calls.as_ref().map(|calls| {
+          calls
+            .iter()
+            .map(|call| {
+              let arguments = serde_json::from_str::<Value>(&call.function.arguments)
+                .unwrap_or_else(|_| Value::String(call.function.arguments.clone()));
+              json! {{
+                "id": call.id,
+                "type": "function",
+                "function": {"name": call.function.name, "arguments": arguments},
+              }}
+            })
+            .collect()
+        });
+        (m.role.to_string().clone(), m.content.clone())
+      }
+      ChatCompletionRequestMessage::Tool(m) => {
+        tool_call_id = Some(m.tool_call_id.clone());
+        (m.role.to_string(), Some(m.content.clone()))
+      }
       ChatCompletionRequestMessage::Function(_) => unimplemented!(),
     };
     ChatMessage {
       role: Some(role),
       content,
+      tool_calls,
+      tool_call_id,
     }
   }
 }
@@ -67,6 +99,8 @@ pub(crate) struct ChatTemplateInputs {
   bos_token: Option<String>,
   eos_token: Option<String>,
   add_generation_prompt: bool,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  tools: Option<Vec<Value>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -117,6 +151,22 @@ fn validate_chat_template(chat_template: &ChatTemplateVersions) -> Result<(), Va
 impl TokenizerConfig {
   #[allow(clippy::result_large_err)]
   pub fn apply_chat_template<T>(&self, messages: &[T]) -> crate::shared_rw::Result<String>
+  where
+    for<'a> &'a T: Into<ChatMessage>,
+  {
+    self.apply_chat_template_with_tools(messages, None, None)
+  }
+
+  // the templates rendering `tools` get the tools as they are, for the others the tools are
+  // described in the system message, or in the first message when there is no system message,
+  // the same as the instructions for the output format
+  #[allow(clippy::result_large_err)]
+  pub(crate) fn apply_chat_template_with_tools<T>(
+    &self,
+    messages: &[T],
+    tools: Option<&ChatTools>,
+    instructions: Option<&str>,
+  ) -> crate::shared_rw::Result<String>
   where
     for<'a> &'a T: Into<ChatMessage>,
   {
@@ -129,23 +179,60 @@ impl TokenizerConfig {
       })?
       .replace(".strip()", " | trim")
       .replace(".title()", " | title");
+    let renders_tools = chat_template.contains("tools");
     let mut env = Box::new(Environment::new());
     let template_str = chat_template.into_boxed_str();
     env.add_function("raise_exception", raise_exception);
     let template = Box::leak(env).template_from_str(Box::leak(template_str))?;
-    let messages: Vec<ChatMessage> = messages.iter().map(Into::into).collect();
+    let mut messages: Vec<ChatMessage> = messages.iter().map(Into::into).collect();
+    let tools = match tools {
+      Some(tools) if renders_tools => Some(tools.tools.clone()),
+      Some(tools) => {
+        add_instructions(&mut messages, &tools.instructions());
+        None
+      }
+      None => None,
+    };
+    if let Some(instructions) = instructions {
+      add_instructions(&mut messages, instructions);
+    }
 
     let inputs = ChatTemplateInputs {
       messages,
       bos_token: self.bos_token.clone(),
       eos_token: self.eos_token.clone(),
       add_generation_prompt: true,
+      tools,
     };
     let result = template.render(inputs)?;
     Ok(result)
   }
 }
 
+fn add_instructions(messages: &mut Vec<ChatMessage>, instructions: &str) {
+  let system = messages
+    .iter()
+    .position(|message| message.role.as_deref() == Some("system"));
+  match system {
+    Some(index) => {
+      let message = &mut messages[index];
+      let content = message.content.take().unwrap_or_default();
+      message.content = Some(format!("{content}\n\n{instructions}"));
+    }
+    None => match messages.first_mut() {
+      Some(message) => {
+        let content = message.content.take().unwrap_or_default();
+        message.content = Some(format!("{instructions}\n\n{content}"));
+      }
+      None => messages.push(ChatMessage {
+        role: Some("system".to_string()),
+        content: Some(instructions.to_string()),
+        ..Default::default()
+      }),
+    },
+  }
+}
+
 fn deserialize_token<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
 where
   D: Deserializer<'de>,
@@ -275,6 +362,85 @@ mod test {
     Ok(())
   }
 
+  fn weather_tools() -> ChatTools {
+    ChatTools {
+      tools: vec![json! {{
+        "type": "function",
+        "function": {"name": "get_weather", "parameters": {"type": "object"}},
+      }}],
+      required: false,
+    }
+  }
+
+  fn messages(messages: Value) -> anyhow::Result<Vec<ChatCompletionRequestMessage>> {
+    Ok(serde_json::from_value(messages)?)
+  }
+
+  #[rstest]
+  fn test_tokenizer_config_apply_chat_template_renders_tools() -> anyhow::Result<()> {
+    let config = TokenizerConfig::new(
+      ChatTemplateVersions::Single("{% for tool in tools %}{{ tool['function']['name'] }}\n{% endfor %}{% for message in messages %}{{ message['role'] }}: {{ message['content'] }}\n{% endfor %}".to_string()),
+      None,
+      None,
+    );
+    let messages =
+      messages(json! {[{"role": "user", "content": "What is the weather in Paris?"}]})?;
+    let prompt = config.apply_chat_template_with_tools(&messages, Some(&weather_tools()), None)?;
+    assert_eq!("get_weather\nuser: What is the weather in Paris?\n", prompt);
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(
+    json! {[{"role": "system", "content": "You are helpful."}, {"role": "user", "content": "Weather in Paris?"}]},
+    "system: You are helpful.\n\n{instructions}\nuser: Weather in Paris?\n"
+  )]
+  #[case(
+    json! {[{"role": "user", "content": "Weather in Paris?"}]},
+    "user: {instructions}\n\nWeather in Paris?\n"
+  )]
+  fn test_tokenizer_config_apply_chat_template_describes_tools_in_prompt(
+    #[case] input: Value,
+    #[case] expected: &str,
+  ) -> anyhow::Result<()> {
+    let config = TokenizerConfig::new(
+      ChatTemplateVersions::Single("{% for message in messages %}{{ message['role'] }}: {{ message['content'] }}\n{% endfor %}".to_string()),
+      None,
+      None,
+    );
+    let tools = weather_tools();
+    let prompt = config.apply_chat_template_with_tools(&messages(input)?, Some(&tools), None)?;
+    assert_eq!(
+      expected.replace("{instructions}", &tools.instructions()),
+      prompt
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  fn test_tokenizer_config_apply_chat_template_tool_call_messages() -> anyhow::Result<()> {
+    let config = TokenizerConfig::new(
+      ChatTemplateVersions::Single("{% for message in messages %}{% if message.tool_calls %}{{ message.tool_calls[0].function.name }}({{ message.tool_calls[0].function.arguments.city }}){% else %}{{ message.role }}: {{ message.content }}{% endif %}\n{% endfor %}".to_string()),
+      None,
+      None,
+    );
+    let messages = messages(json! {[
+      {"role": "user", "content": "What is the weather in Paris?"},
+      {"role": "assistant", "content": null, "tool_calls": [{
+        "id": "call_1",
+        "type": "function",
+        "function": {"name": "get_weather", "arguments": "{\"city\": \"Paris\"}"},
+      }]},
+      {"role": "tool", "tool_call_id": "call_1", "content": "sunny"},
+    ]})?;
+    let prompt = config.apply_chat_template(&messages)?;
+    assert_eq!(
+      "user: What is the weather in Paris?\nget_weather(Paris)\ntool: sunny\n",
+      prompt
+    );
+    Ok(())
+  }
+
   #[rstest]
   #[case("simple.json", 
   TokenizerConfig::new(
@@ -0,0 +1,653 @@
+use async_openai::types::CreateChatCompletionRequest;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+static TOOL_CALL_START: &str = "<tool_call>";
+static TOOL_CALL_END: &str = "</tool_call>";
+static PYTHON_TAG: &str = "<|python_tag|>";
+
+// the tools a chat request makes available to the model, none when the request has no tools or
+// sets `tool_choice: none`, a named tool choice leaves only the named tool
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ChatTools {
+  pub(crate) tools: Vec<Value>,
+  pub(crate) required: bool,
+}
+
+impl ChatTools {
+  pub(crate) fn from_request(request: &CreateChatCompletionRequest) -> Option<ChatTools> {
+    let tools = serde_json::to_value(request.tools.as_ref()?).ok()?;
+    let mut tools = tools.as_array()?.clone();
+    let tool_choice = serde_json::to_value(&request.tool_choice).unwrap_or(Value::Null);
+    let required = match &tool_choice {
+      Value::String(choice) if choice == "none" => return None,
+      Value::String(choice) => choice == "required",
+      Value::Object(_) => {
+        let name = &tool_choice["function"]["name"];
+        tools.retain(|tool| &tool["function"]["name"] == name);
+        true
+      }
+      _ => false,
+    };
+    (!tools.is_empty()).then_some(ChatTools { tools, required })
+  }
+
+  pub(crate) fn names(&self) -> Vec<String> {
+    self
+      .tools
+      .iter()
+      .filter_map(|tool| tool["function"]["name"].as_str())
+      .map(str::to_string)
+      .collect()
+  }
+
+  // for the chat templates not rendering the tools, tells the model about the tools in the prompt
+  pub(crate) fn instructions(&self) -> String {
+    let tools = self
+      .tools
+      .iter()
+      .map(|tool| tool["function"].to_string())
+      .collect::<Vec<_>>()
+      .join("\n");
+    let mut instructions = format!(
+      "You can call the following tools, described as JSON schemas:\n{tools}\n\nTo call a tool, \
+       respond with {TOOL_CALL_START}{{\"name\": <tool name>, \"arguments\": <arguments as a JSON \
+       object>}}{TOOL_CALL_END} for each call, and nothing else."
+    );
+    if self.required {
+      instructions.push_str(" You must call a tool to respond.");
+    }
+    instructions
+  }
+}
+
+// a tool call parsed from the generated text, the arguments are the json generated so far
+#[derive(Debug, Clone, PartialEq)]
+struct PartialCall {
+  name: Option<String>,
+  arguments: Option<String>,
+  complete: bool,
+}
+
+// the tool calls the model generated, either wrapped in <tool_call> tags like Hermes and Qwen, or
+// as the json object of the call like Llama 3.1, as a list when more than one
+fn scan_calls(text: &str) -> Vec<PartialCall> {
+  let mut calls = vec![];
+  let mut rest = text;
+  loop {
+    rest = skip_separators(rest);
+    if !rest.starts_with('{') {
+      break;
+    }
+    let (call, end) = scan_call(rest);
+    calls.push(call);
+    match end {
+      Some(end) => rest = &rest[end..],
+      None => break,
+    }
+  }
+  calls
+}
+
+fn skip_separators(mut text: &str) -> &str {
+  loop {
+    let trimmed = text.trim_start();
+    let stripped = [
+      TOOL_CALL_START,
+      TOOL_CALL_END,
+      PYTHON_TAG,
+      "[",
+      "]",
+      ",",
+      ";",
+    ]
+    .iter()
+    .find_map(|separator| trimmed.strip_prefix(separator));
+    match stripped {
+      Some(stripped) => text = stripped,
+      None => return trimmed,
+    }
+  }
+}
+
+// walks the fields of the json object of the call, the text can end anywhere in the object while
+// it is being generated, the end is the index after the object once it is complete
+fn scan_call(text: &str) -> (PartialCall, Option<usize>) {
+  let mut depth = 0usize;
+  let mut in_string = false;
+  let mut escaped = false;
+  let mut expecting_key = false;
+  let mut key_start = 0;
+  let mut key: Option<String> = None;
+  let mut awaiting_value = false;
+  let mut value_start: Option<usize> = None;
+  let mut fields: Vec<(String, &str)> = vec![];
+  let mut end = None;
+  for (i, c) in text.char_indices() {
+    if awaiting_value && !c.is_whitespace() {
+      awaiting_value = false;
+      value_start = Some(i);
+    }
+    if in_string {
+      if escaped {
+        escaped = false;
+      } else if c == '\\' {
+        escaped = true;
+      } else if c == '"' {
+        in_string = false;
+        if depth == 1 && expecting_key {
+          key = serde_json::from_str(&text[key_start..=i]).ok();
+          expecting_key = false;
+        }
+      }
+      continue;
+    }
+    match c {
+      '"' => {
+        in_string = true;
+        key_start = i;
+      }
+      ':' if depth == 1 => awaiting_value = true,
+      ',' | '}' | ']' if depth == 1 => {
+        if let (Some(key), Some(start)) = (key.take(), value_start.take()) {
+          fields.push((key, text[start..i].trim_end()));
+        }
+        if c == ',' {
+          expecting_key = true;
+        } else {
+          end = Some(i + 1);
+          break;
+        }
+      }
+      '{' | '[' => {
+        depth += 1;
+        if depth == 1 {
+          expecting_key = true;
+        }
+      }
+      '}' | ']' => depth = depth.saturating_sub(1),
+      _ => {}
+    }
+  }
+  let generating = match (end, key, value_start) {
+    (None, Some(key), Some(start)) => Some((key, text[start..].trim_end())),
+    _ => None,
+  };
+  let name = fields
+    .iter()
+    .find(|(key, _)| key == "name")
+    .and_then(|(_, value)| serde_json::from_str::<String>(value).ok());
+  let arguments = fields
+    .iter()
+    .find(|(key, _)| key == "arguments" || key == "parameters")
+    .map(|(_, value)| match serde_json::from_str::<String>(value) {
+      Ok(arguments) => arguments,
+      Err(_) => value.to_string(),
+    })
+    .or_else(|| {
+      // only the json object or list of the arguments can be sent while being generated
+      generating
+        .filter(|(key, value)| {
+          (key == "arguments" || key == "parameters")
+            && (value.starts_with('{') || value.starts_with('['))
+        })
+        .map(|(_, value)| value.to_string())
+    });
+  let call = PartialCall {
+    name,
+    arguments,
+    complete: end.is_some(),
+  };
+  (call, end)
+}
+
+// whether the generated text is a tool call, none till there is enough text to tell
+fn call_start(text: &str) -> Option<bool> {
+  let text = text.trim_start();
+  for tag in [TOOL_CALL_START, PYTHON_TAG] {
+    if text.starts_with(tag) {
+      return Some(true);
+    }
+    if tag.starts_with(text) {
+      return None;
+    }
+  }
+  let text = text.strip_prefix('[').map(str::trim_start).unwrap_or(text);
+  let Some(object) = text.strip_prefix('{') else {
+    return (!text.is_empty()).then_some(false);
+  };
+  let object = object.trim_start();
+  match object.strip_prefix("\"name\"") {
+    Some(rest) => {
+      let rest = rest.trim_start();
+      (!rest.is_empty()).then(|| rest.starts_with(':'))
+    }
+    None if "\"name\"".starts_with(object) => None,
+    None => Some(false),
+  }
+}
+
+fn tool_call(id: String, name: &str, arguments: &str) -> Value {
+  json! {{
+    "id": id,
+    "type": "function",
+    "function": {"name": name, "arguments": arguments},
+  }}
+}
+
+fn call_id() -> String {
+  format!("call_{}", Uuid::new_v4().simple())
+}
+
+// the tool calls in the generated text, with the text before them as the content, none when the
+// text is not a call of the tools of the request
+fn parse_tool_calls(content: &str, names: &[String]) -> Option<(Option<String>, Vec<Value>)> {
+  let start = if call_start(content) == Some(true) {
+    0
+  } else {
+    content.find(TOOL_CALL_START)?
+  };
+  let calls = scan_calls(&content[start..]);
+  if calls.is_empty() {
+    return None;
+  }
+  let mut tool_calls = vec![];
+  for call in calls {
+    let name = call.name.filter(|name| names.contains(name))?;
+    let arguments = call.arguments.unwrap_or_else(|| "{}".to_string());
+    if !call.complete || serde_json::from_str::<Value>(&arguments).is_err() {
+      return None;
+    }
+    tool_calls.push(tool_call(call_id(), &name, &arguments));
+  }
+  let text = content[..start].trim();
+  Some(((!text.is_empty()).then(|| text.to_string()), tool_calls))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+  Pending,
+  Text,
+  Calls,
+}
+
+// turns the tool calls in the generated text into the `tool_calls` of the response, for a stream
+// the text is held back only till it is known whether it is a tool call, and the arguments are
+// sent as they are generated
+#[derive(Debug)]
+pub(crate) struct ToolCallParser {
+  names: Vec<String>,
+  mode: Mode,
+  text: String,
+  // the id and the length of the arguments sent of each call
+  sent: Vec<(String, usize)>,
+}
+
+impl ToolCallParser {
+  pub(crate) fn new(tools: &ChatTools) -> Self {
+    Self {
+      names: tools.names(),
+      mode: Mode::Pending,
+      text: String::new(),
+      sent: vec![],
+    }
+  }
+
+  // rewrites a message of the completion, either a chunk of the stream or the whole response,
+  // none for a chunk left with nothing to send
+  pub(crate) fn rewrite(&mut self, message: String) -> Option<String> {
+    if let Some(data) = message.strip_prefix("data: ") {
+      return self
+        .rewrite_chunk(data.trim_end())
+        .map(|data| format!("data: {data}\n\n"));
+    }
+    if message.starts_with("error: ") {
+      return Some(message);
+    }
+    Some(self.rewrite_response(&message).unwrap_or(message))
+  }
+
+  fn rewrite_response(&self, response: &str) -> Option<String> {
+    let mut response = serde_json::from_str::<Value>(response).ok()?;
+    let choice = response.get_mut("choices")?.get_mut(0)?;
+    let content = choice["message"]["content"].as_str()?;
+    let (content, tool_calls) = parse_tool_calls(content, &self.names)?;
+    choice["message"]["content"] = json!(content);
+    choice["message"]["tool_calls"] = json!(tool_calls);
+    choice["finish_reason"] = json!("tool_calls");
+    Some(response.to_string())
+  }
+
+  fn rewrite_chunk(&mut self, data: &str) -> Option<String> {
+    let Ok(mut chunk) = serde_json::from_str::<Value>(data) else {
+      return Some(data.to_string());
+    };
+    let has_usage = chunk.get("usage").is_some_and(|usage| !usage.is_null());
+    let Some(choice) = chunk
+      .get_mut("choices")
+      .and_then(|choices| choices.get_mut(0))
+    else {
+      return Some(data.to_string());
+    };
+    let content = choice["delta"]["content"].as_str().unwrap_or_default();
+    let (mut content, mut tool_calls) = self.push(content);
+    let finished = !choice["finish_reason"].is_null();
+    if finished {
+      let (rest, more, called) = self.finish();
+      content.push_str(&rest);
+      tool_calls.extend(more);
+      if called {
+        choice["finish_reason"] = json!("tool_calls");
+      }
+    }
+    let mut delta = choice["delta"].as_object().cloned().unwrap_or_default();
+    delta.remove("content");
+    if !content.is_empty() {
+      delta.insert("content".to_string(), json!(content));
+    }
+    if !tool_calls.is_empty() {
+      delta.insert("tool_calls".to_string(), json!(tool_calls));
+    }
+    if delta.is_empty() && !finished && !has_usage {
+      return None;
+    }
+    choice["delta"] = Value::Object(delta);
+    Some(chunk.to_string())
+  }
+
+  // the text to send as the content, and the tool call deltas, for the generated text
+  fn push(&mut self, content: &str) -> (String, Vec<Value>) {
+    self.text.push_str(content);
+    if self.mode == Mode::Pending {
+      match call_start(&self.text) {
+        None => return (String::new(), vec![]),
+        Some(true) => self.mode = Mode::Calls,
+        Some(false) => self.mode = Mode::Text,
+      }
+    }
+    if self.mode == Mode::Text {
+      if let Some(start) = self.text.find(TOOL_CALL_START) {
+        let text = self.text.drain(..start).collect();
+        self.mode = Mode::Calls;
+        return (text, self.deltas());
+      }
+      // a tag split over the chunks is held back till it is complete
+      let held = (1..TOOL_CALL_START.len())
+        .rev()
+        .find(|len| self.text.ends_with(&TOOL_CALL_START[..*len]))
+        .unwrap_or(0);
+      let text = self.text.drain(..self.text.len() - held).collect();
+      return (text, vec![]);
+    }
+    (String::new(), self.deltas())
+  }
+
+  fn deltas(&mut self) -> Vec<Value> {
+    let mut deltas = vec![];
+    for (index, call) in scan_calls(&self.text).into_iter().enumerate() {
+      let Some(name) = call.name.filter(|name| self.names.contains(name)) else {
+        break;
+      };
+      let arguments = call.arguments.unwrap_or_default();
+      if index == self.sent.len() {
+        let id = call_id();
+        let mut delta = tool_call(id.clone(), &name, &arguments);
+        delta["index"] = json!(index);
+        deltas.push(delta);
+        self.sent.push((id, arguments.len()));
+        continue;
+      }
+      let sent = self.sent[index].1;
+      let new = arguments.get(sent..).unwrap_or_default();
+      if !new.is_empty() {
+        deltas.push(json! {{"index": index, "function": {"arguments": new}}});
+        self.sent[index].1 = arguments.len();
+      }
+    }
+    deltas
+  }
+
+  // the text held back and the last deltas once the generation is done, and whether the model
+  // called any tool, the text that turned out not to be a call is sent as the content
+  fn finish(&mut self) -> (String, Vec<Value>, bool) {
+    if self.mode != Mode::Calls {
+      return (std::mem::take(&mut self.text), vec![], false);
+    }
+    let mut deltas = self.deltas();
+    if self.sent.is_empty() {
+      self.mode = Mode::Text;
+      return (std::mem::take(&mut self.text), deltas, false);
+    }
+    for (index, (_, sent)) in self.sent.iter().enumerate() {
+      if *sent == 0 {
+        deltas.push(json! {{"index": index, "function": {"arguments": "{}"}}});
+      }
+    }
+    (String::new(), deltas, true)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{parse_tool_calls, ChatTools, ToolCallParser};
+  use async_openai::types::CreateChatCompletionRequest;
+  use rstest::rstest;
+  use serde_json::{json, Value};
+
+  fn weather_tools() -> Value {
+    json! {[
+      {"type": "function", "function": {
+        "name": "get_weather",
+        "description": "Get the current weather of a city",
+        "parameters": {"type": "object", "properties": {"city": {"type": "string"}}},
+      }},
+      {"type": "function", "function": {
+        "name": "get_time",
+        "parameters": {"type": "object", "properties": {"timezone": {"type": "string"}}},
+      }},
+    ]}
+  }
+
+  fn chat_tools() -> ChatTools {
+    ChatTools {
+      tools: weather_tools().as_array().unwrap().clone(),
+      required: false,
+    }
+  }
+
+  fn names() -> Vec<String> {
+    vec!["get_weather".to_string(), "get_time".to_string()]
+  }
+
+  // the tool calls without the generated ids
+  fn without_ids(mut tool_calls: Vec<Value>) -> Vec<Value> {
+    for tool_call in tool_calls.iter_mut() {
+      let id = tool_call.as_object_mut().unwrap().remove("id");
+      assert!(id.unwrap().as_str().unwrap().starts_with("call_"));
+    }
+    tool_calls
+  }
+
+  #[rstest]
+  #[case(json! {{}}, Some((vec!["get_weather", "get_time"], false)))]
+  #[case(json! {{"tool_choice": "auto"}}, Some((vec!["get_weather", "get_time"], false)))]
+  #[case(json! {{"tool_choice": "none"}}, None)]
+  #[case(
+    json! {{"tool_choice": {"type": "function", "function": {"name": "get_time"}}}},
+    Some((vec!["get_time"], true))
+  )]
+  fn test_chat_tools_from_request(
+    #[case] params: Value,
+    #[case] expected: Option<(Vec<&str>, bool)>,
+  ) -> anyhow::Result<()> {
+    let mut request = json! {{
+      "model": "testalias:instruct",
+      "messages": [{"role": "user", "content": "What is the weather in Paris?"}],
+      "tools": weather_tools(),
+    }};
+    for (key, value) in params.as_object().unwrap() {
+      request[key] = value.clone();
+    }
+    let request = serde_json::from_value::<CreateChatCompletionRequest>(request)?;
+    let tools = ChatTools::from_request(&request);
+    let expected = expected.map(|(names, required)| {
+      let names = names.into_iter().map(str::to_string).collect::<Vec<_>>();
+      (names, required)
+    });
+    assert_eq!(
+      expected,
+      tools.as_ref().map(|tools| (tools.names(), tools.required))
+    );
+    Ok(())
+  }
+
+  #[rstest]
+  #[case(
+    "<tool_call>\n{\"name\": \"get_weather\", \"arguments\": {\"city\": \"Paris\"}}\n</tool_call>",
+    None,
+    vec![("get_weather", "{\"city\": \"Paris\"}")]
+  )]
+  #[case(
+    "{\"name\": \"get_weather\", \"parameters\": {\"city\": \"Paris\"}}",
+    None,
+    vec![("get_weather", "{\"city\": \"Paris\"}")]
+  )]
+  #[case(
+    "<|python_tag|>{\"name\": \"get_time\", \"arguments\": \"{\\\"timezone\\\": \\\"CET\\\"}\"}",
+    None,
+    vec![("get_time", "{\"timezone\": \"CET\"}")]
+  )]
+  #[case(
+    "Let me check.\n<tool_call>{\"name\": \"get_weather\", \"arguments\": {\"city\": \"Paris\"}}</tool_call>\n<tool_call>{\"name\": \"get_time\"}</tool_call>",
+    Some("Let me check."),
+    vec![("get_weather", "{\"city\": \"Paris\"}"), ("get_time", "{}")]
+  )]
+  fn test_parse_tool_calls(
+    #[case] content: &str,
+    #[case] expected_content: Option<&str>,
+    #[case] expected: Vec<(&str, &str)>,
+  ) {
+    let (content, tool_calls) = parse_tool_calls(content, &names()).unwrap();
+    assert_eq!(expected_content.map(str::to_string), content);
+    let expected = expected
+      .into_iter()
+      .map(|(name, arguments)| {
+        json! {{"type": "function", "function": {"name": name, "arguments": arguments}}}
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(expected, without_ids(tool_calls));
+  }
+
+  #[rstest]
+  #[case("The weather in Paris is sunny.")]
+  #[case("{\"city\": \"Paris\", \"weather\": \"sunny\"}")]
+  #[case("{\"name\": \"get_stock_price\", \"arguments\": {\"symbol\": \"AAPL\"}}")]
+  #[case("<tool_call>{\"name\": \"get_weather\", \"arguments\": {\"city\": \"Par")]
+  fn test_parse_tool_calls_not_a_call(#[case] content: &str) {
+    assert_eq!(None, parse_tool_calls(content, &names()));
+  }
+
+  #[rstest]
+  fn test_tool_call_parser_rewrites_response() -> anyhow::Result<()> {
+    let response = json! {{
+      "id": "chatcmpl-1",
+      "object": "chat.completion",
+      "choices": [{
+        "index": 0,
+        "finish_reason": "stop",
+        "message": {"role": "assistant", "content": "<tool_call>{\"name\": \"get_weather\", \"arguments\": {\"city\": \"Paris\"}}</tool_call>"},
+      }],
+    }};
+    let mut parser = ToolCallParser::new(&chat_tools());
+    let rewritten = parser.rewrite(response.to_string()).unwrap();
+    let mut rewritten = serde_json::from_str::<Value>(&rewritten)?;
+    let tool_calls =
+      serde_json::from_value(rewritten["choices"][0]["message"]["tool_calls"].take())?;
+    assert_eq!(
+      vec![
+        json! {{"type": "function", "function": {"name": "get_weather", "arguments": "{\"city\": \"Paris\"}"}}}
+      ],
+      without_ids(tool_calls)
+    );
+    assert_eq!(Value::Null, rewritten["choices"][0]["message"]["content"]);
+    assert_eq!("tool_calls", rewritten["choices"][0]["finish_reason"]);
+    Ok(())
+  }
+
+  fn chunk(content: Option<&str>, finish_reason: Option<&str>) -> String {
+    let delta = match content {
+      Some(content) => json! {{"content": content}},
+      None => json! {{}},
+    };
+    let chunk = json! {{
+      "id": "chatcmpl-1",
+      "object": "chat.completion.chunk",
+      "choices": [{"index": 0, "delta": delta, "finish_reason": finish_reason}],
+    }};
+    format!("data: {chunk}\n\n")
+  }
+
+  fn rewrite_stream(chunks: Vec<String>) -> Vec<Value> {
+    let mut parser = ToolCallParser::new(&chat_tools());
+    chunks
+      .into_iter()
+      .filter_map(|chunk| parser.rewrite(chunk))
+      .map(|chunk| {
+        let data = chunk.strip_prefix("data: ").unwrap().trim_end();
+        serde_json::from_str::<Value>(data).unwrap()["choices"][0].clone()
+      })
+      .collect()
+  }
+
+  #[rstest]
+  fn test_tool_call_parser_streams_call_arguments() {
+    let chunks = [
+      "<tool",
+      "_call>{\"name\": ",
+      "\"get_weather\", \"arguments\": {\"ci",
+      "ty\": \"Par",
+      "is\"}}</tool_call>",
+    ]
+    .into_iter()
+    .map(|content| chunk(Some(content), None))
+    .chain([chunk(None, Some("stop"))])
+    .collect::<Vec<_>>();
+    let mut choices = rewrite_stream(chunks);
+    let id = choices[0]["delta"]["tool_calls"][0]
+      .as_object_mut()
+      .unwrap()
+      .remove("id")
+      .unwrap();
+    assert!(id.as_str().unwrap().starts_with("call_"));
+    assert_eq!(
+      vec![
+        json! {{"index": 0, "delta": {"tool_calls": [{"index": 0, "type": "function", "function": {"name": "get_weather", "arguments": "{\"ci"}}]}, "finish_reason": null}},
+        json! {{"index": 0, "delta": {"tool_calls": [{"index": 0, "function": {"arguments": "ty\": \"Par"}}]}, "finish_reason": null}},
+        json! {{"index": 0, "delta": {"tool_calls": [{"index": 0, "function": {"arguments": "is\"}"}}]}, "finish_reason": null}},
+        json! {{"index": 0, "delta": {}, "finish_reason": "tool_calls"}},
+      ],
+      choices
+    );
+  }
+
+  #[rstest]
+  #[case(vec!["The weather", " is sunny <", "3"], vec!["The weather", " is sunny ", "<3"])]
+  #[case(vec!["{", "\"city\": \"Paris\"}"], vec!["{\"city\": \"Paris\"}"])]
+  #[case(vec!["{\"name\": \"get_stock_price\"", "}"], vec!["{\"name\": \"get_stock_price\"}"])]
+  fn test_tool_call_parser_streams_text(#[case] contents: Vec<&str>, #[case] expected: Vec<&str>) {
+    let chunks = contents
+      .into_iter()
+      .map(|content| chunk(Some(content), None))
+      .chain([chunk(None, Some("stop"))])
+      .collect::<Vec<_>>();
+    let choices = rewrite_stream(chunks);
+    let (last, choices) = choices.split_last().unwrap();
+    let text = choices
+      .iter()
+      .chain([last])
+      .filter_map(|choice| choice["delta"]["content"].as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(expected, text);
+    assert_eq!("stop", last["finish_reason"]);
+  }
+}
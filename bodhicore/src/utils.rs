@@ -12,3 +12,7 @@ pub(crate) fn to_safe_filename(input: &str) -> String {
   }
   sanitized
 }
+
+pub(crate) fn human_size(bytes: u64) -> String {
+  format!("{:.2} GB", bytes as f64 / 2_f64.powf(30.0))
+}